@@ -0,0 +1,64 @@
+//! How long `prepare` takes on a generated workspace with many members, e.g.
+//! `cargo bench --bench prepare -- 1000`.
+//!
+//! Walking the project and parsing the manifests dominate: keep an eye on this number when
+//! touching them.
+use assert_fs::prelude::{FileTouch, FileWriteStr, PathChild};
+use assert_fs::TempDir;
+use chef::{PrepareArgs, Recipe};
+use std::time::{Duration, Instant};
+
+/// A workspace with `members` crates, each depending on the previous ones and on a few
+/// crates.io packages.
+fn workspace(members: usize) -> TempDir {
+    let directory = TempDir::new().unwrap();
+    directory
+        .child("Cargo.toml")
+        .write_str("[workspace]\nmembers = [\"crates/*\"]\n")
+        .unwrap();
+    for i in 0..members {
+        let member = directory.child("crates").child(format!("crate-{}", i));
+        let local_dependencies: String = (i.saturating_sub(3)..i)
+            .map(|j| format!("crate-{0} = {{ path = \"../crate-{0}\" }}\n", j))
+            .collect();
+        member
+            .child("Cargo.toml")
+            .write_str(&format!(
+                r#"[package]
+name = "crate-{}"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+serde = {{ version = "1", features = ["derive"] }}
+tokio = {{ version = "1", features = ["full"] }}
+{}"#,
+                i, local_dependencies
+            ))
+            .unwrap();
+        member.child("src").child("lib.rs").touch().unwrap();
+    }
+    directory
+}
+
+fn main() {
+    let members = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(1000);
+    let directory = workspace(members);
+    let mut timings: Vec<Duration> = (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            Recipe::prepare(directory.path().into(), PrepareArgs::default()).unwrap();
+            start.elapsed()
+        })
+        .collect();
+    timings.sort();
+    println!(
+        "prepare, {} members: {:?} (median of {} runs)",
+        members,
+        timings[timings.len() / 2],
+        timings.len()
+    );
+}
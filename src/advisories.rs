@@ -0,0 +1,234 @@
+//! Logic to match the packages pinned in the lockfile of a recipe against a snapshot of the
+//! RustSec advisory database, e.g. a checkout of https://github.com/rustsec/advisory-db.
+use crate::sbom::CRATES_IO_SOURCES;
+use crate::Recipe;
+use anyhow::Context;
+use fs_err as fs;
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+
+/// The advisories of a RustSec advisory database, read from its `crates` directory.
+///
+/// Both the current Markdown format (with the TOML metadata in a leading ```` ```toml ````
+/// block) and the legacy TOML files are supported. Withdrawn and informational advisories
+/// (e.g. unmaintained crates) are ignored.
+#[derive(Debug)]
+pub struct AdvisoryDatabase {
+    advisories: Vec<Advisory>,
+}
+
+#[derive(Debug)]
+struct Advisory {
+    id: String,
+    package: String,
+    title: Option<String>,
+    patched: Vec<VersionReq>,
+    unaffected: Vec<VersionReq>,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryFile {
+    advisory: AdvisoryMetadata,
+    #[serde(default)]
+    versions: Versions,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryMetadata {
+    id: String,
+    package: String,
+    title: Option<String>,
+    informational: Option<String>,
+    withdrawn: Option<toml::Value>,
+}
+
+#[derive(Deserialize, Default)]
+struct Versions {
+    #[serde(default)]
+    patched: Vec<String>,
+    #[serde(default)]
+    unaffected: Vec<String>,
+}
+
+/// A package of the lockfile affected by an advisory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vulnerability {
+    pub package: String,
+    pub version: String,
+    pub advisory_id: String,
+    pub title: Option<String>,
+    /// The version requirements that are not affected by the advisory, e.g. `>=1.2.3`.
+    pub patched: Vec<String>,
+}
+
+impl AdvisoryDatabase {
+    pub fn open(path: &Path) -> Result<Self, anyhow::Error> {
+        let crates = path.join("crates");
+        if !crates.is_dir() {
+            anyhow::bail!(
+                "{:?} is not an advisory database: it has no `crates` directory.",
+                path
+            );
+        }
+        let mut files = vec![];
+        for directory in fs::read_dir(&crates)? {
+            let directory = directory?.path();
+            if directory.is_dir() {
+                for file in fs::read_dir(&directory)? {
+                    files.push(file?.path());
+                }
+            }
+        }
+        files.sort();
+
+        let mut advisories = vec![];
+        for file in files {
+            let advisory = read_advisory(&file)
+                .with_context(|| format!("Failed to read the advisory at {:?}.", file))?;
+            advisories.extend(advisory);
+        }
+        Ok(AdvisoryDatabase { advisories })
+    }
+
+    /// The packages of the lockfile of the recipe affected by an advisory.
+    ///
+    /// Only the packages downloaded from crates.io are checked: the crates of the workspace
+    /// (with their masked version) and the git dependencies are skipped.
+    pub fn check(&self, recipe: &Recipe) -> Result<Vec<Vulnerability>, anyhow::Error> {
+        let lock_file: toml::Value = match &recipe.skeleton.lock_file {
+            Some(lock_file) => lock_file
+                .parse()
+                .context("Failed to parse the Cargo.lock of the recipe.")?,
+            None => return Ok(vec![]),
+        };
+        let mut vulnerabilities = vec![];
+        for package in lock_file
+            .get("package")
+            .and_then(|packages| packages.as_array())
+            .into_iter()
+            .flatten()
+        {
+            let get = |key: &str| package.get(key).and_then(|value| value.as_str());
+            let (name, version, source) = match (get("name"), get("version"), get("source")) {
+                (Some(name), Some(version), Some(source)) => (name, version, source),
+                _ => continue,
+            };
+            if !CRATES_IO_SOURCES.contains(&source) {
+                continue;
+            }
+            let parsed = match Version::parse(version) {
+                Ok(parsed) => parsed,
+                Err(_) => continue,
+            };
+            for advisory in self.advisories.iter().filter(|a| a.package == name) {
+                if advisory.affects(&parsed) {
+                    vulnerabilities.push(Vulnerability {
+                        package: name.to_owned(),
+                        version: version.to_owned(),
+                        advisory_id: advisory.id.clone(),
+                        title: advisory.title.clone(),
+                        patched: advisory.patched.iter().map(|req| req.to_string()).collect(),
+                    });
+                }
+            }
+        }
+        Ok(vulnerabilities)
+    }
+}
+
+impl Advisory {
+    fn affects(&self, version: &Version) -> bool {
+        !self
+            .patched
+            .iter()
+            .chain(&self.unaffected)
+            .any(|req| req.matches(version))
+    }
+}
+
+fn read_advisory(path: &Path) -> Result<Option<Advisory>, anyhow::Error> {
+    let contents = fs::read_to_string(path)?;
+    let (metadata, title) = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => (contents.as_str(), None),
+        Some("md") => {
+            let front_matter = contents
+                .trim_start()
+                .strip_prefix("```toml")
+                .and_then(|rest| rest.split_once("\n```"))
+                .context("The advisory does not start with a ```toml block.")?;
+            let title = front_matter
+                .1
+                .lines()
+                .find_map(|line| line.strip_prefix("# "))
+                .map(|title| title.trim().to_owned());
+            (front_matter.0, title)
+        }
+        _ => return Ok(None),
+    };
+    let file: AdvisoryFile = toml::from_str(metadata)?;
+    if file.advisory.withdrawn.is_some() || file.advisory.informational.is_some() {
+        return Ok(None);
+    }
+    let parse = |requirements: &[String]| -> Result<Vec<VersionReq>, anyhow::Error> {
+        requirements
+            .iter()
+            .map(|req| {
+                VersionReq::parse(req)
+                    .with_context(|| format!("`{}` is not a valid version requirement.", req))
+            })
+            .collect()
+    };
+    Ok(Some(Advisory {
+        patched: parse(&file.versions.patched)?,
+        unaffected: parse(&file.versions.unaffected)?,
+        title: file.advisory.title.or(title),
+        id: file.advisory.id,
+        package: file.advisory.package,
+    }))
+}
+
+/// A table of vulnerabilities, one per line.
+pub(crate) struct VulnerabilityTable<'a>(pub(crate) &'a [Vulnerability]);
+
+impl fmt::Display for VulnerabilityTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rows = vec![[
+            "Crate".to_owned(),
+            "Version".to_owned(),
+            "Advisory".to_owned(),
+            "Patched versions".to_owned(),
+            "Title".to_owned(),
+        ]];
+        for vulnerability in self.0 {
+            let patched = if vulnerability.patched.is_empty() {
+                "none".to_owned()
+            } else {
+                vulnerability.patched.join(" or ")
+            };
+            rows.push([
+                vulnerability.package.clone(),
+                vulnerability.version.clone(),
+                vulnerability.advisory_id.clone(),
+                patched,
+                vulnerability.title.clone().unwrap_or_default(),
+            ]);
+        }
+        let mut widths = [0; 5];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        for row in &rows {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            writeln!(f, "  {}", cells.join("  ").trim_end())?;
+        }
+        Ok(())
+    }
+}
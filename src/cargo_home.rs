@@ -0,0 +1,197 @@
+//! Logic to locate the download caches of `$CARGO_HOME`, to count what a build downloaded into
+//! them, e.g. to notice that a cache mount is shadowed and never reused, and to sweep them.
+use anyhow::Context;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Where cargo downloads the dependencies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CacheLocations {
+    pub(crate) cargo_home: PathBuf,
+    /// The `.crate` archives downloaded from registries, one directory per registry.
+    pub(crate) registry_cache: PathBuf,
+    /// The sources unpacked from the `.crate` archives, one directory per registry.
+    pub(crate) registry_src: PathBuf,
+    /// The bare clones of git dependencies.
+    pub(crate) git_db: PathBuf,
+}
+
+impl CacheLocations {
+    /// Resolved like cargo does: `CARGO_HOME` (relative to `current_directory`), or `~/.cargo`.
+    /// `None` if neither `CARGO_HOME` nor `HOME` is set.
+    pub(crate) fn resolve(current_directory: &Path) -> Option<Self> {
+        let cargo_home = cargo_home(current_directory)?;
+        Some(Self {
+            registry_cache: cargo_home.join("registry").join("cache"),
+            registry_src: cargo_home.join("registry").join("src"),
+            git_db: cargo_home.join("git").join("db"),
+            cargo_home,
+        })
+    }
+
+    /// The contents of the caches right now.
+    pub(crate) fn snapshot(&self) -> CacheSnapshot {
+        let mut crates = HashSet::new();
+        for registry in entries(&self.registry_cache) {
+            crates.extend(
+                entries(&registry)
+                    .into_iter()
+                    .filter(|archive| archive.extension() == Some("crate".as_ref()))
+                    .filter_map(|archive| Some(archive.file_name()?.to_str()?.to_owned())),
+            );
+        }
+        let git_repositories = entries(&self.git_db).into_iter().collect();
+        CacheSnapshot {
+            crates,
+            git_repositories,
+        }
+    }
+
+    /// The first of `$CARGO_HOME`, `registry`, `registry/cache` and `registry/src` that is a
+    /// mount point, e.g. a cache mount of `docker build` shared with other builds: it is on
+    /// another device than its parent directory. Outside of unix, it cannot be told: `$CARGO_HOME`
+    /// itself is returned.
+    pub(crate) fn shared_mount(&self) -> Option<PathBuf> {
+        let registry = self.cargo_home.join("registry");
+        vec![
+            &self.cargo_home,
+            &registry,
+            &self.registry_cache,
+            &self.registry_src,
+        ]
+        .into_iter()
+        .find(|directory| is_mount_point(directory))
+        .cloned()
+    }
+
+    /// Remove the `.crate` archives and the unpacked sources of the registries, keeping their
+    /// indices and the git caches. Cargo downloads them again if it needs them.
+    pub(crate) fn sweep(&self) -> Result<Sweep, anyhow::Error> {
+        let mut sweep = Sweep::default();
+        for registry in entries(&self.registry_cache) {
+            for archive in entries(&registry) {
+                if archive.extension() != Some("crate".as_ref()) {
+                    continue;
+                }
+                sweep.reclaimed_bytes += fs_err::symlink_metadata(&archive)?.len();
+                fs_err::remove_file(&archive)?;
+                sweep.archives += 1;
+            }
+        }
+        for registry in entries(&self.registry_src) {
+            for source in entries(&registry) {
+                sweep.reclaimed_bytes +=
+                    size(&source).with_context(|| format!("Failed to measure {:?}.", source))?;
+                if fs_err::symlink_metadata(&source)?.is_dir() {
+                    fs_err::remove_dir_all(&source)?;
+                } else {
+                    fs_err::remove_file(&source)?;
+                }
+                sweep.sources += 1;
+            }
+        }
+        Ok(sweep)
+    }
+}
+
+/// What [`CacheLocations::sweep`] removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Sweep {
+    pub(crate) archives: usize,
+    pub(crate) sources: usize,
+    pub(crate) reclaimed_bytes: u64,
+}
+
+#[cfg(unix)]
+fn is_mount_point(directory: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let device = |path: &Path| std::fs::metadata(path).map(|metadata| metadata.dev());
+    match (directory.parent(), device(directory)) {
+        (Some(parent), Ok(own_device)) => {
+            device(parent).is_ok_and(|parent_device| parent_device != own_device)
+        }
+        // The root of the file system, or a directory that does not exist (yet).
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_mount_point(_directory: &Path) -> bool {
+    true
+}
+
+/// The size of the files in `path`, recursively. Links are not followed.
+fn size(path: &Path) -> Result<u64, std::io::Error> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        size += self::size(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+/// `CARGO_HOME`, relative to `current_directory`, or `~/.cargo`.
+pub(crate) fn cargo_home(current_directory: &Path) -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .filter(|cargo_home| !cargo_home.is_empty())
+        .map(|cargo_home| current_directory.join(cargo_home))
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")))
+}
+
+/// The paths in `directory`, none if it does not exist (yet).
+fn entries(directory: &Path) -> Vec<PathBuf> {
+    match std::fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// The crate archives (as `<name>-<version>.crate`) and the git repositories in the caches.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CacheSnapshot {
+    crates: HashSet<String>,
+    git_repositories: HashSet<PathBuf>,
+}
+
+/// What a build added to the caches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Downloads {
+    /// The crate archives that were downloaded.
+    pub(crate) crates: usize,
+    /// The registry packages of the lockfile that were in the cache already.
+    pub(crate) cached_crates: usize,
+    /// The git repositories that were cloned.
+    pub(crate) git_repositories: usize,
+}
+
+impl CacheSnapshot {
+    /// What was downloaded since `before`, for a lockfile pinning `registry_packages` (as
+    /// `(name, version)`).
+    pub(crate) fn downloads_since(
+        &self,
+        before: &CacheSnapshot,
+        registry_packages: &[(String, String)],
+    ) -> Downloads {
+        Downloads {
+            crates: self.crates.difference(&before.crates).count(),
+            cached_crates: registry_packages
+                .iter()
+                .filter(|(name, version)| {
+                    before
+                        .crates
+                        .contains(&format!("{}-{}.crate", name, version))
+                })
+                .count(),
+            git_repositories: self
+                .git_repositories
+                .difference(&before.git_repositories)
+                .count(),
+        }
+    }
+}
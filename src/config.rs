@@ -0,0 +1,266 @@
+//! Logic to read the project-level defaults of `prepare` from `chef.toml` at the root of the
+//! workspace, or from `[workspace.metadata.chef]` in the root manifest.
+use crate::{find_workspace_root, PrepareArgs};
+use anyhow::Context;
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The name of the configuration file, at the root of the workspace.
+pub const CONFIG_FILE: &str = "chef.toml";
+
+/// The defaults of `prepare` for a project, named like its arguments. The command line and the
+/// `CHEF_*` environment variables take precedence over them.
+///
+/// ```toml
+/// mask-version = "0.0.1"
+/// no-dev-dependencies = true
+/// include = ["proto/**/*.proto"]
+/// split-members = ["api", "worker"]
+/// cook-args = ["--release"]
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct ChefConfig {
+    /// See [`PrepareArgs::mask_version`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask_version: Option<String>,
+    /// See [`PrepareArgs::no_cargo_config`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_cargo_config: Option<bool>,
+    /// See [`PrepareArgs::no_dev_dependencies`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_dev_dependencies: Option<bool>,
+    /// See [`PrepareArgs::no_lockfile_pruning`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_lockfile_pruning: Option<bool>,
+    /// See [`PrepareArgs::no_gitignore`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_gitignore: Option<bool>,
+    /// See [`PrepareArgs::keep_metadata`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_metadata: Option<bool>,
+    /// See [`PrepareArgs::keep_build_scripts`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_build_scripts: Option<bool>,
+    /// See [`PrepareArgs::include`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<Vec<String>>,
+    /// See [`PrepareArgs::split_members`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split_members: Option<Vec<String>>,
+    /// See [`PrepareArgs::cook_args`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cook_args: Option<Vec<String>>,
+}
+
+impl ChefConfig {
+    /// Read the configuration of the project in `base_path`, at the root of its workspace (the
+    /// one of `manifest_path`, relative to `base_path`, if set): the keys of `chef.toml` take
+    /// precedence over the ones of `[workspace.metadata.chef]`. Unknown keys are reported on
+    /// stderr and ignored. `None` if there is no configuration.
+    pub fn load(
+        base_path: &Path,
+        manifest_path: Option<&Path>,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        let root = workspace_root(base_path, manifest_path);
+        let mut table = BTreeMap::new();
+        let mut sources = BTreeMap::new();
+        if let Some(metadata) = workspace_metadata(&root)? {
+            for key in metadata.keys() {
+                sources.insert(key.to_owned(), "`[workspace.metadata.chef]` of Cargo.toml");
+            }
+            table.extend(metadata);
+        }
+        let config_path = root.join(CONFIG_FILE);
+        let has_config_file = config_path.is_file();
+        if has_config_file {
+            let contents = fs::read_to_string(&config_path)?;
+            let config: BTreeMap<String, toml::Value> = toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse {:?}.", config_path))?;
+            for key in config.keys() {
+                sources.insert(key.to_owned(), CONFIG_FILE);
+            }
+            table.extend(config);
+        }
+        if !has_config_file && table.is_empty() {
+            return Ok(None);
+        }
+
+        #[derive(Deserialize)]
+        struct WithUnknownKeys {
+            #[serde(flatten)]
+            config: ChefConfig,
+            #[serde(flatten)]
+            unknown: BTreeMap<String, toml::Value>,
+        }
+        let WithUnknownKeys { config, unknown } = toml::Value::Table(table.into_iter().collect())
+            .try_into()
+            .context("Failed to read the chef configuration of the project.")?;
+        for key in unknown.keys() {
+            eprintln!(
+                "warning: unknown key `{}` in {}, it is ignored.",
+                key, sources[key]
+            );
+        }
+        if let Some(mask_version) = &config.mask_version {
+            semver::Version::parse(mask_version).with_context(|| {
+                format!(
+                    "`mask-version` in {} is not a valid semver version.",
+                    sources["mask-version"]
+                )
+            })?;
+        }
+        Ok(Some(config))
+    }
+
+    /// Apply the options of the configuration to `args`, except the ones that `is_overridden`
+    /// (e.g. by the command line), given their key. The applied ones are recorded in
+    /// [`PrepareArgs::config`], except `split-members`: it selects the recipes to compute, not
+    /// their contents.
+    pub fn apply(&self, args: &mut PrepareArgs, is_overridden: impl Fn(&str) -> bool) {
+        let mut applied = ChefConfig::default();
+        if let (Some(mask_version), false) = (&self.mask_version, is_overridden("mask-version")) {
+            args.mask_version = mask_version.clone();
+            applied.mask_version = Some(mask_version.clone());
+        }
+        let flags = [
+            (
+                self.no_cargo_config,
+                "no-cargo-config",
+                &mut args.no_cargo_config,
+                &mut applied.no_cargo_config,
+            ),
+            (
+                self.no_dev_dependencies,
+                "no-dev-dependencies",
+                &mut args.no_dev_dependencies,
+                &mut applied.no_dev_dependencies,
+            ),
+            (
+                self.no_lockfile_pruning,
+                "no-lockfile-pruning",
+                &mut args.no_lockfile_pruning,
+                &mut applied.no_lockfile_pruning,
+            ),
+            (
+                self.no_gitignore,
+                "no-gitignore",
+                &mut args.no_gitignore,
+                &mut applied.no_gitignore,
+            ),
+            (
+                self.keep_metadata,
+                "keep-metadata",
+                &mut args.keep_metadata,
+                &mut applied.keep_metadata,
+            ),
+            (
+                self.keep_build_scripts,
+                "keep-build-scripts",
+                &mut args.keep_build_scripts,
+                &mut applied.keep_build_scripts,
+            ),
+        ];
+        for (flag, key, arg, applied) in flags {
+            if let (Some(flag), false) = (flag, is_overridden(key)) {
+                *arg = flag;
+                *applied = Some(flag);
+            }
+        }
+        let lists = [
+            (
+                &self.include,
+                "include",
+                &mut args.include,
+                &mut applied.include,
+            ),
+            (
+                &self.cook_args,
+                "cook-args",
+                &mut args.cook_args,
+                &mut applied.cook_args,
+            ),
+        ];
+        for (list, key, arg, applied) in lists {
+            if let (Some(list), false) = (list, is_overridden(key)) {
+                *arg = list.clone();
+                *applied = Some(list.clone());
+            }
+        }
+        if let Some(split_members) = &self.split_members {
+            args.split_members = split_members.clone();
+        }
+        args.config = applied;
+    }
+
+    /// Was nothing set?
+    pub fn is_empty(&self) -> bool {
+        self == &ChefConfig::default()
+    }
+
+    /// The keys whose values differ from the ones of `other`, with both values (`None` if it is
+    /// not set). `split-members` is not compared, since recipes do not record it.
+    pub(crate) fn differences(
+        &self,
+        other: &ChefConfig,
+    ) -> Vec<(String, Option<String>, Option<String>)> {
+        let table = |config: &ChefConfig| match toml::Value::try_from(ChefConfig {
+            split_members: None,
+            ..config.clone()
+        }) {
+            Ok(toml::Value::Table(table)) => table,
+            _ => unreachable!("The configuration is always a table"),
+        };
+        let (ours, theirs) = (table(self), table(other));
+        let mut keys: Vec<&String> = ours.keys().chain(theirs.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        keys.into_iter()
+            .filter(|key| ours.get(*key) != theirs.get(*key))
+            .map(|key| {
+                (
+                    key.to_owned(),
+                    ours.get(key).map(|value| value.to_string()),
+                    theirs.get(key).map(|value| value.to_string()),
+                )
+            })
+            .collect()
+    }
+}
+
+/// The root of the workspace of `manifest_path` (or of the `Cargo.toml` of `base_path`), like
+/// `prepare` finds it. `base_path` itself if it cannot be found: `prepare` reports why.
+fn workspace_root(base_path: &Path, manifest_path: Option<&Path>) -> PathBuf {
+    let manifest_path = match manifest_path {
+        Some(manifest_path) => base_path.join(manifest_path),
+        None => base_path.join("Cargo.toml"),
+    };
+    if !manifest_path.is_file() {
+        return base_path.to_owned();
+    }
+    find_workspace_root(&manifest_path)
+        .map(|(root, _)| root)
+        .unwrap_or_else(|_| base_path.to_owned())
+}
+
+/// `[workspace.metadata.chef]` of the root manifest, if any.
+fn workspace_metadata(root: &Path) -> Result<Option<toml::value::Table>, anyhow::Error> {
+    let contents = match fs::read_to_string(root.join("Cargo.toml")) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let manifest: toml::Value =
+        toml::from_str(&contents).context("Failed to parse the root manifest.")?;
+    let chef = manifest
+        .get("workspace")
+        .and_then(|workspace| workspace.get("metadata"))
+        .and_then(|metadata| metadata.get("chef"));
+    match chef {
+        None => Ok(None),
+        Some(toml::Value::Table(chef)) => Ok(Some(chef.clone())),
+        Some(_) => anyhow::bail!("`workspace.metadata.chef` in the root manifest must be a table."),
+    }
+}
@@ -0,0 +1,426 @@
+//! Logic to explain the differences between two recipes, e.g. to understand why the
+//! `cargo chef cook` layer was invalidated.
+use crate::skeleton::format_key_path;
+use crate::Recipe;
+use anyhow::Context;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Placeholder used in place of the masked versions of local crates, so that two recipes
+/// prepared with a different `--mask-version` are not reported as different.
+const MASKED: &str = "<masked>";
+
+/// A summary of the differences between two recipes.
+///
+/// Manifests are aligned by relative path, lockfile packages by name, version and source.
+/// Masked versions of local crates are never reported as differences.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RecipeDiff {
+    added_manifests: BTreeSet<PathBuf>,
+    removed_manifests: BTreeSet<PathBuf>,
+    /// For each modified manifest, the list of changed keys.
+    modified_manifests: BTreeMap<PathBuf, Vec<String>>,
+    config_file: Option<&'static str>,
+    rust_toolchain_file: Option<&'static str>,
+    /// The files listed in `package.metadata.chef.include`, prefixed with `+`, `-` or `~`.
+    included_files: Vec<String>,
+    lock_file: Vec<String>,
+    /// The old and new default cook arguments, if they changed.
+    default_cook_args: Option<(Vec<String>, Vec<String>)>,
+}
+
+impl RecipeDiff {
+    pub fn new(old: &Recipe, new: &Recipe) -> Result<Self, anyhow::Error> {
+        let mut diff = RecipeDiff::default();
+
+        let old_manifests = manifests(old)?;
+        let new_manifests = manifests(new)?;
+        for (path, old_contents) in &old_manifests {
+            match new_manifests.get(path) {
+                None => {
+                    diff.removed_manifests.insert(path.to_owned());
+                }
+                Some(new_contents) => {
+                    let changes = diff_entries(old_contents, new_contents);
+                    if !changes.is_empty() {
+                        diff.modified_manifests.insert(path.to_owned(), changes);
+                    }
+                }
+            }
+        }
+        for path in new_manifests.keys() {
+            if !old_manifests.contains_key(path) {
+                diff.added_manifests.insert(path.to_owned());
+            }
+        }
+
+        diff.config_file = diff_files(&old.skeleton.config_file, &new.skeleton.config_file);
+        diff.rust_toolchain_file = diff_files(
+            &old.skeleton.rust_toolchain_file,
+            &new.skeleton.rust_toolchain_file,
+        );
+
+        diff.included_files = diff_included_files(old, new);
+
+        diff.lock_file = match (lock_file(old)?, lock_file(new)?) {
+            (None, Some(_)) => vec!["added".into()],
+            (Some(_), None) => vec!["removed".into()],
+            (Some(old), Some(new)) => diff_lock_files(&old, &new),
+            (None, None) => vec![],
+        };
+
+        if old.default_cook_args != new.default_cook_args {
+            diff.default_cook_args =
+                Some((old.default_cook_args.clone(), new.default_cook_args.clone()));
+        }
+
+        Ok(diff)
+    }
+
+    /// `true` if the two recipes are equivalent.
+    pub fn is_empty(&self) -> bool {
+        self == &RecipeDiff::default()
+    }
+}
+
+impl fmt::Display for RecipeDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "The recipes are equivalent.");
+        }
+        if !(self.added_manifests.is_empty()
+            && self.removed_manifests.is_empty()
+            && self.modified_manifests.is_empty())
+        {
+            writeln!(f, "Manifests:")?;
+            for path in &self.added_manifests {
+                writeln!(f, "  + {}", path.display())?;
+            }
+            for path in &self.removed_manifests {
+                writeln!(f, "  - {}", path.display())?;
+            }
+            for (path, changes) in &self.modified_manifests {
+                writeln!(f, "  ~ {}", path.display())?;
+                for change in changes {
+                    writeln!(f, "      {}", change)?;
+                }
+            }
+        }
+        if let Some(change) = self.config_file {
+            writeln!(f, ".cargo/config.toml: {}", change)?;
+        }
+        if let Some(change) = self.rust_toolchain_file {
+            writeln!(f, "Rust toolchain file: {}", change)?;
+        }
+        if !self.included_files.is_empty() {
+            writeln!(f, "Included files:")?;
+            for change in &self.included_files {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+        if !self.lock_file.is_empty() {
+            writeln!(f, "Cargo.lock:")?;
+            for change in &self.lock_file {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+        if let Some((old, new)) = &self.default_cook_args {
+            writeln!(f, "Default cook arguments: {:?} -> {:?}", old, new)?;
+        }
+        Ok(())
+    }
+}
+
+fn diff_files<T: PartialEq>(old: &Option<T>, new: &Option<T>) -> Option<&'static str> {
+    match (old, new) {
+        (None, Some(_)) => Some("added"),
+        (Some(_), None) => Some("removed"),
+        (Some(old), Some(new)) if old != new => Some("modified"),
+        _ => None,
+    }
+}
+
+fn diff_included_files(old: &Recipe, new: &Recipe) -> Vec<String> {
+    let contents = |recipe: &Recipe| -> BTreeMap<PathBuf, String> {
+        recipe
+            .skeleton
+            .included_files
+            .iter()
+            .map(|file| {
+                (
+                    file.relative_path.clone(),
+                    file.contents.replace("\r\n", "\n"),
+                )
+            })
+            .collect()
+    };
+    let (old, new) = (contents(old), contents(new));
+    let mut changes = vec![];
+    for (path, old_contents) in &old {
+        match new.get(path) {
+            None => changes.push(format!("- {}", path.display())),
+            Some(new_contents) if new_contents != old_contents => {
+                changes.push(format!("~ {}", path.display()))
+            }
+            Some(_) => {}
+        }
+    }
+    for path in new.keys().filter(|path| !old.contains_key(*path)) {
+        changes.push(format!("+ {}", path.display()));
+    }
+    changes
+}
+
+/// Parse the manifests of a recipe and flatten them into `key path -> value` entries.
+fn manifests(
+    recipe: &Recipe,
+) -> Result<BTreeMap<PathBuf, BTreeMap<String, String>>, anyhow::Error> {
+    let mut manifests = BTreeMap::new();
+    for manifest in &recipe.skeleton.manifests {
+        let contents: toml::Value = manifest.contents.parse().with_context(|| {
+            format!(
+                "Failed to parse the manifest at {}",
+                manifest.relative_path.display()
+            )
+        })?;
+        let mut entries = BTreeMap::new();
+        flatten(
+            &contents,
+            &mut vec![],
+            &recipe.skeleton.mask_version,
+            &mut entries,
+        );
+        manifests.insert(manifest.relative_path.clone(), entries);
+    }
+    Ok(manifests)
+}
+
+fn flatten(
+    value: &toml::Value,
+    key_path: &mut Vec<String>,
+    mask_version: &str,
+    entries: &mut BTreeMap<String, String>,
+) {
+    let mut recurse = |key: String, value: &toml::Value, entries: &mut _| {
+        key_path.push(key);
+        flatten(value, key_path, mask_version, entries);
+        key_path.pop();
+    };
+    match value {
+        toml::Value::Table(table) if !table.is_empty() => {
+            for (key, value) in table {
+                recurse(key.to_owned(), value, entries);
+            }
+        }
+        toml::Value::Array(array) if array.iter().any(|value| value.is_table()) => {
+            for (i, value) in array.iter().enumerate() {
+                recurse(format!("[{}]", i), value, entries);
+            }
+        }
+        value => {
+            let key_path: Vec<&str> = key_path.iter().map(|key| key.as_str()).collect();
+            let is_masked =
+                key_path.last() == Some(&"version") && value.as_str() == Some(mask_version);
+            let value = match value {
+                _ if is_masked => MASKED.to_string(),
+                toml::Value::Table(_) => "{}".to_string(),
+                value => value.to_string(),
+            };
+            entries.insert(format_key_path(&key_path), value);
+        }
+    }
+}
+
+fn diff_entries(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) -> Vec<String> {
+    let mut changes = vec![];
+    for (key, old_value) in old {
+        match new.get(key) {
+            None => changes.push(format!("- {} = {}", key, old_value)),
+            Some(new_value) if new_value != old_value => {
+                changes.push(format!("~ {}: {} -> {}", key, old_value, new_value))
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, new_value) in new {
+        if !old.contains_key(key) {
+            changes.push(format!("+ {} = {}", key, new_value));
+        }
+    }
+    changes.sort_by(|a, b| a[2..].cmp(&b[2..]));
+    changes
+}
+
+/// A `[[package]]` entry of a lockfile.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct LockedPackage {
+    name: String,
+    source: Option<String>,
+    version: String,
+    /// All other keys (e.g. `checksum` or `dependencies`), flattened.
+    entries: BTreeMap<String, String>,
+}
+
+impl LockedPackage {
+    fn id(&self) -> String {
+        match &self.source {
+            Some(source) => format!("{} {} ({})", self.name, self.version, source),
+            None => format!("{} {}", self.name, self.version),
+        }
+    }
+}
+
+fn lock_file(recipe: &Recipe) -> Result<Option<Vec<LockedPackage>>, anyhow::Error> {
+    let lock_file = match &recipe.skeleton.lock_file {
+        Some(lock_file) => lock_file,
+        None => return Ok(None),
+    };
+    let lock_file: toml::Value = lock_file
+        .parse()
+        .context("Failed to parse the lockfile of the recipe")?;
+    let packages = lock_file
+        .get("package")
+        .and_then(|packages| packages.as_array())
+        .map(|packages| packages.as_slice())
+        .unwrap_or_default();
+
+    let mask_version = &recipe.skeleton.mask_version;
+    // Local crates have no source (or a `path+` one).
+    let is_local = |package: &toml::Value| {
+        !matches!(
+            package.get("source").and_then(|source| source.as_str()),
+            Some(source) if !source.starts_with("path+")
+        )
+    };
+    let local_package_names: BTreeSet<&str> = packages
+        .iter()
+        .filter(|package| is_local(package))
+        .filter_map(|package| package.get("name")?.as_str())
+        .collect();
+
+    let mut locked_packages = vec![];
+    for package in packages {
+        let get = |key: &str| package.get(key).and_then(|value| value.as_str());
+        let name = get("name").unwrap_or_default().to_owned();
+        let mut version = get("version").unwrap_or_default().to_owned();
+        if is_local(package) && version == *mask_version {
+            version = MASKED.to_owned();
+        }
+        let mut package = package.clone();
+        if let Some(dependencies) = package
+            .get_mut("dependencies")
+            .and_then(|dependencies| dependencies.as_array_mut())
+        {
+            for dependency in dependencies.iter_mut() {
+                if let Some(masked) = dependency
+                    .as_str()
+                    .and_then(|d| mask_dependency(d, &local_package_names, mask_version))
+                {
+                    *dependency = toml::Value::String(masked);
+                }
+            }
+        }
+        let mut entries = BTreeMap::new();
+        flatten(&package, &mut vec![], mask_version, &mut entries);
+        for key in &["name", "version", "source"] {
+            entries.remove(*key);
+        }
+        locked_packages.push(LockedPackage {
+            name,
+            source: get("source").map(|source| source.to_owned()),
+            version,
+            entries,
+        });
+    }
+    locked_packages.sort();
+    Ok(Some(locked_packages))
+}
+
+/// Replace the version in a lockfile dependency entry (e.g. `"foo 0.0.1"`) with a placeholder if
+/// it is the masked version of a local crate.
+fn mask_dependency(
+    dependency: &str,
+    local_package_names: &BTreeSet<&str>,
+    mask_version: &str,
+) -> Option<String> {
+    let mut parts = dependency.splitn(3, ' ');
+    let name = parts.next()?;
+    let version = parts.next()?;
+    if !local_package_names.contains(name) || version != mask_version {
+        return None;
+    }
+    Some(
+        std::iter::once(name)
+            .chain(std::iter::once(MASKED))
+            .chain(parts)
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+fn diff_lock_files(old: &[LockedPackage], new: &[LockedPackage]) -> Vec<String> {
+    let key = |package: &LockedPackage| {
+        (
+            package.name.clone(),
+            package.version.clone(),
+            package.source.clone(),
+        )
+    };
+    let old: BTreeMap<_, _> = old.iter().map(|package| (key(package), package)).collect();
+    let new: BTreeMap<_, _> = new.iter().map(|package| (key(package), package)).collect();
+
+    let mut removed: Vec<&LockedPackage> = vec![];
+    let mut changes = vec![];
+    for (key, old_package) in &old {
+        match new.get(key) {
+            None => removed.push(old_package),
+            Some(new_package) => {
+                let entries = diff_entries(&old_package.entries, &new_package.entries);
+                if !entries.is_empty() {
+                    changes.push(format!("~ {}", old_package.id()));
+                    changes.extend(entries.into_iter().map(|entry| format!("    {}", entry)));
+                }
+            }
+        }
+    }
+    let mut added: Vec<&LockedPackage> = new
+        .iter()
+        .filter(|(key, _)| !old.contains_key(key))
+        .map(|(_, package)| *package)
+        .collect();
+
+    // A package that was removed and added again with the same source changed version.
+    let mut removed_only = vec![];
+    for old_package in removed {
+        let same_package = added.iter().position(|new_package| {
+            new_package.name == old_package.name && new_package.source == old_package.source
+        });
+        match same_package {
+            Some(i) => {
+                let new_package = added.remove(i);
+                let name = match &old_package.source {
+                    Some(source) => format!("{} ({})", old_package.name, source),
+                    None => old_package.name.clone(),
+                };
+                changes.push(format!(
+                    "~ {}: {} -> {}",
+                    name, old_package.version, new_package.version
+                ));
+            }
+            None => removed_only.push(old_package),
+        }
+    }
+
+    let mut lines: Vec<String> = added
+        .iter()
+        .map(|package| format!("+ {}", package.id()))
+        .collect();
+    lines.extend(
+        removed_only
+            .iter()
+            .map(|package| format!("- {}", package.id())),
+    );
+    lines.extend(changes);
+    lines
+}
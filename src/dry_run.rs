@@ -0,0 +1,258 @@
+//! Logic to summarise what `cargo chef cook` would compile, from the unit graph of cargo (on
+//! nightly) or the output of `cargo tree` (on stable), without building anything.
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// How many of the crates with the most dependents are listed.
+const HEAVIEST_CRATES: usize = 20;
+
+/// The crates a build would compile.
+#[derive(Debug, Default)]
+pub(crate) struct BuildPlan {
+    /// The number of compilation units (libraries, build scripts, ...), if known.
+    units: Option<usize>,
+    crates: BTreeMap<Package, PlannedCrate>,
+}
+
+/// A name and a version.
+type Package = (String, String);
+
+#[derive(Debug, Default)]
+struct PlannedCrate {
+    /// The crates that depend on it.
+    dependents: BTreeSet<Package>,
+    features: BTreeSet<String>,
+    /// A member of the workspace.
+    is_root: bool,
+    /// A dependency of a member of the workspace.
+    is_direct: bool,
+}
+
+#[derive(Deserialize)]
+struct UnitGraph {
+    units: Vec<Unit>,
+    roots: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct Unit {
+    pkg_id: String,
+    #[serde(default)]
+    features: Vec<String>,
+    #[serde(default)]
+    dependencies: Vec<UnitDependency>,
+}
+
+#[derive(Deserialize)]
+struct UnitDependency {
+    index: usize,
+}
+
+impl BuildPlan {
+    /// Read the output of `cargo build --unit-graph -Z unstable-options`.
+    pub(crate) fn from_unit_graph(output: &str) -> Result<Self, anyhow::Error> {
+        let graph: UnitGraph =
+            serde_json::from_str(output).context("Failed to parse the unit graph of cargo.")?;
+        let packages: Vec<Package> = graph
+            .units
+            .iter()
+            .map(|unit| package_of(&unit.pkg_id))
+            .collect::<Result<_, _>>()?;
+        let mut plan = BuildPlan {
+            units: Some(graph.units.len()),
+            ..BuildPlan::default()
+        };
+        for (unit, package) in graph.units.iter().zip(&packages) {
+            plan.crates
+                .entry(package.clone())
+                .or_default()
+                .features
+                .extend(unit.features.iter().cloned());
+        }
+        for (unit, package) in graph.units.iter().zip(&packages) {
+            for dependency in &unit.dependencies {
+                let dependency = packages
+                    .get(dependency.index)
+                    .context("The unit graph of cargo refers to an unknown unit.")?;
+                if dependency != package {
+                    plan.add_dependency(package, dependency);
+                }
+            }
+        }
+        for root in graph.roots {
+            if let Some(package) = packages.get(root) {
+                plan.crates.entry(package.clone()).or_default().is_root = true;
+            }
+        }
+        plan.mark_direct_dependencies();
+        Ok(plan)
+    }
+
+    /// Read the output of `cargo tree --prefix depth --format {p}|{f}`.
+    pub(crate) fn from_tree(output: &str) -> Result<Self, anyhow::Error> {
+        let mut plan = BuildPlan::default();
+        // The ancestors of the current line, one per level of depth.
+        let mut ancestors: Vec<Package> = vec![];
+        for line in output.lines().filter(|line| !line.trim().is_empty()) {
+            let line = line.strip_suffix(" (*)").unwrap_or(line);
+            let digits = line.find(|c: char| !c.is_ascii_digit()).unwrap_or(0);
+            let depth: usize = line[..digits].parse().with_context(|| {
+                format!("Unexpected line in the output of cargo tree: {}", line)
+            })?;
+            let (package, features) = line[digits..]
+                .split_once('|')
+                .unwrap_or((&line[digits..], ""));
+            let mut words = package.split_whitespace();
+            let package: Package = match (words.next(), words.next()) {
+                (Some(name), Some(version)) => {
+                    (name.to_owned(), version.trim_start_matches('v').to_owned())
+                }
+                _ => anyhow::bail!("Unexpected line in the output of cargo tree: {}", line),
+            };
+            ancestors.truncate(depth);
+            let planned = plan.crates.entry(package.clone()).or_default();
+            planned.features.extend(
+                features
+                    .split(',')
+                    .filter(|feature| !feature.is_empty())
+                    .map(|feature| feature.to_owned()),
+            );
+            if depth == 0 {
+                planned.is_root = true;
+            }
+            if let Some(parent) = ancestors.last().cloned() {
+                plan.add_dependency(&parent, &package);
+            }
+            ancestors.push(package);
+        }
+        plan.mark_direct_dependencies();
+        Ok(plan)
+    }
+
+    fn add_dependency(&mut self, dependent: &Package, dependency: &Package) {
+        self.crates
+            .entry(dependency.clone())
+            .or_default()
+            .dependents
+            .insert(dependent.clone());
+    }
+
+    fn mark_direct_dependencies(&mut self) {
+        let roots: BTreeSet<Package> = self
+            .crates
+            .iter()
+            .filter(|(_, planned)| planned.is_root)
+            .map(|(package, _)| package.clone())
+            .collect();
+        for planned in self.crates.values_mut() {
+            planned.is_direct = !planned.is_root && !planned.dependents.is_disjoint(&roots);
+        }
+    }
+}
+
+/// The name and the version of a package id of cargo, either `<name> <version> (<source>)` or
+/// `<source>#<name>@<version>` (or `<source>#<version>`, if the name is the last segment of the
+/// source).
+fn package_of(pkg_id: &str) -> Result<Package, anyhow::Error> {
+    let unexpected = || anyhow::anyhow!("Unexpected package id in the unit graph: {}", pkg_id);
+    if let Some((source, fragment)) = pkg_id.rsplit_once('#') {
+        return Ok(match fragment.split_once('@') {
+            Some((name, version)) => (name.to_owned(), version.to_owned()),
+            None => {
+                let source = source.split('?').next().unwrap_or(source);
+                let name = source
+                    .trim_end_matches('/')
+                    .rsplit('/')
+                    .next()
+                    .ok_or_else(unexpected)?;
+                (name.to_owned(), fragment.to_owned())
+            }
+        });
+    }
+    let mut words = pkg_id.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some(name), Some(version)) => Ok((name.to_owned(), version.to_owned())),
+        _ => Err(unexpected()),
+    }
+}
+
+impl fmt::Display for BuildPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.units {
+            Some(units) => writeln!(
+                f,
+                "{} compilation units would be built, for {} crates.",
+                units,
+                self.crates.len()
+            )?,
+            None => writeln!(
+                f,
+                "{} crates would be built (use a nightly toolchain to count the compilation \
+                units).",
+                self.crates.len()
+            )?,
+        }
+
+        let mut heaviest: Vec<(&Package, &PlannedCrate)> = self
+            .crates
+            .iter()
+            .filter(|(_, planned)| !planned.is_root && !planned.dependents.is_empty())
+            .collect();
+        heaviest.sort_by(|(a, a_planned), (b, b_planned)| {
+            b_planned
+                .dependents
+                .len()
+                .cmp(&a_planned.dependents.len())
+                .then_with(|| a.cmp(b))
+        });
+        heaviest.truncate(HEAVIEST_CRATES);
+        if !heaviest.is_empty() {
+            writeln!(f, "\nCrates with the most dependents:")?;
+            let width = heaviest
+                .iter()
+                .map(|((name, version), _)| name.len() + version.len() + 2)
+                .max()
+                .unwrap_or_default();
+            for ((name, version), planned) in heaviest {
+                let dependents = planned.dependents.len();
+                writeln!(
+                    f,
+                    "  {:width$}  {} {}",
+                    format!("{} v{}", name, version),
+                    dependents,
+                    if dependents == 1 {
+                        "dependent"
+                    } else {
+                        "dependents"
+                    },
+                    width = width
+                )?;
+            }
+        }
+
+        let direct: Vec<(&Package, &PlannedCrate)> = self
+            .crates
+            .iter()
+            .filter(|(_, planned)| planned.is_direct)
+            .collect();
+        if !direct.is_empty() {
+            writeln!(f, "\nFeatures of the direct dependencies:")?;
+            for ((name, version), planned) in direct {
+                let features = if planned.features.is_empty() {
+                    "(none)".to_owned()
+                } else {
+                    planned
+                        .features
+                        .iter()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                writeln!(f, "  {} v{}: {}", name, version, features)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+//! Logic to record the toolchain a recipe was prepared with and to compare it with the one it
+//! is cooked with: the cached dependencies are useless if they were built by another compiler.
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Command;
+
+/// The toolchain `cargo chef prepare` ran with.
+///
+/// It is informative only: it is left out of [`Recipe::fingerprint`](crate::Recipe::fingerprint)
+/// and of the comparison of recipes.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Environment {
+    /// The output of `rustc -V`, e.g. `rustc 1.79.0 (129f3b996 2024-06-10)`.
+    pub rustc_version: String,
+    /// The host triple of rustc, e.g. `x86_64-unknown-linux-gnu`.
+    pub host: String,
+}
+
+impl Environment {
+    /// The toolchain cargo would use in `directory`, honouring `RUSTC` and toolchain files.
+    /// `None` if rustc cannot be invoked.
+    pub fn detect(directory: &Path) -> Option<Self> {
+        let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+        let output = Command::new(rustc)
+            .arg("-vV")
+            .current_dir(directory)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+        let output = String::from_utf8(output.stdout).ok()?;
+        let rustc_version = output.lines().next()?.trim().to_owned();
+        let host = output
+            .lines()
+            .find_map(|line| line.strip_prefix("host: "))?
+            .trim()
+            .to_owned();
+        Some(Environment {
+            rustc_version,
+            host,
+        })
+    }
+
+    /// How `current` differs from `self`, in ways that make cargo rebuild the dependencies.
+    pub fn mismatches(&self, current: &Environment) -> Vec<String> {
+        let mut mismatches = vec![];
+        if minor_version(&self.rustc_version) != minor_version(&current.rustc_version) {
+            mismatches.push(format!(
+                "the recipe was prepared with {}, but it is cooked with {}.",
+                self.rustc_version, current.rustc_version
+            ));
+        }
+        if self.host != current.host {
+            mismatches.push(format!(
+                "the recipe was prepared on {}, but it is cooked on {}.",
+                self.host, current.host
+            ));
+        }
+        mismatches
+    }
+}
+
+/// `1.79` for `rustc 1.79.0 (129f3b996 2024-06-10)`, and the channel for non-stable releases,
+/// e.g. `1.81-nightly`.
+fn minor_version(rustc_version: &str) -> Option<String> {
+    let version = rustc_version.split_whitespace().nth(1)?;
+    let (version, channel) = match version.split_once('-') {
+        Some((version, channel)) => (version, Some(channel)),
+        None => (version, None),
+    };
+    let minor: Vec<&str> = version.split('.').take(2).collect();
+    Some(match channel {
+        Some(channel) => format!("{}-{}", minor.join("."), channel),
+        None => minor.join("."),
+    })
+}
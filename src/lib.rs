@@ -1,5 +1,23 @@
+mod advisories;
+mod cargo_home;
+mod config;
+mod diff;
+mod dry_run;
+mod environment;
 mod recipe;
+mod sbom;
 mod skeleton;
+mod summary;
 
-pub use recipe::{CommandArg, CookArgs, DefaultFeatures, OptimisationProfile, Recipe, TargetArgs};
+pub use advisories::{AdvisoryDatabase, Vulnerability};
+pub use config::{ChefConfig, CONFIG_FILE};
+pub use diff::RecipeDiff;
+pub use environment::Environment;
+pub use recipe::{
+    cook, prepare, CommandArg, CookArgs, CookOptions, CookReport, DefaultFeatures, MessageFormat,
+    OptimisationProfile, PrepareArgs, Recipe, TargetArgs, WorkspaceOptions,
+    MIN_RECIPE_FORMAT_VERSION, RECIPE_FORMAT_VERSION,
+};
+pub use sbom::{Sbom, SbomFormat};
 pub use skeleton::*;
+pub use summary::{DirectDependency, RecipeSummary, RemotePackageCounts, SUMMARY_FORMAT_VERSION};
@@ -1,10 +1,18 @@
 use anyhow::{anyhow, Context};
-use chef::{CommandArg, CookArgs, DefaultFeatures, OptimisationProfile, Recipe, TargetArgs};
+use chef::{
+    ChefConfig, CommandArg, CookOptions, DefaultFeatures, MessageFormat, OptimisationProfile,
+    PrepareArgs, Recipe, RecipeDiff, RecipeSummary, SbomFormat, TargetArgs, WorkspaceOptions,
+    DEFAULT_MASK_VERSION, MIN_RECIPE_FORMAT_VERSION, RECIPE_FORMAT_VERSION,
+};
 use clap::crate_version;
-use clap::Parser;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum, ValueSource};
+use clap_complete::Shell;
 use fs_err as fs;
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Cache the dependencies of your Rust project.
 #[derive(Parser)]
@@ -23,6 +31,11 @@ pub enum CargoInvocation {
     // All `cargo` subcommands receive their name (e.g. `chef` as the first command).
     // See https://github.com/rust-lang/rustfmt/pull/3569
     Chef {
+        /// Log what `cargo-chef` does on stderr: `-v` for the manifests found, the files written
+        /// and the cargo invocation, `-vv` for the masked versions and the lockfile rewrites.
+        /// `RUST_LOG` is respected as well.
+        #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+        verbose: u8,
         #[clap(subcommand)]
         command: Command,
     },
@@ -39,136 +52,659 @@ pub enum Command {
     ///
     /// `cargo chef prepare` emits a recipe file that can be later used via
     /// `cargo chef cook --recipe <recipe-path>.json`.
-    Prepare(Prepare),
+    Prepare(Box<Prepare>),
     /// Re-hydrate the minimum project skeleton identified by `cargo chef prepare` and build
     /// it to cache dependencies.
-    Cook(Cook),
+    Cook(Box<Cook>),
+    /// Explain the differences between two recipes, e.g. to understand why the layer cache
+    /// of `cargo chef cook` was invalidated.
+    ///
+    /// It exits with status code 0 if the recipes are equivalent, 1 otherwise.
+    Diff(Diff),
+    /// Print a stable sha256 fingerprint of a recipe, e.g. to key remote caches.
+    ///
+    /// It does not change with the masked versions of local crates, the order of keys in the
+    /// manifests or line endings.
+    Hash(Hash),
+    /// Print a Software Bill of Materials of the packages pinned in the lockfile of a recipe,
+    /// without building anything or accessing the network.
+    ///
+    /// The crates of the workspace are marked as first-party components, with their masked
+    /// version flagged. It fails if the recipe has no lockfile.
+    Sbom(Sbom),
+    /// Check a recipe before cooking it: its manifests and lockfile must parse, every `path`
+    /// dependency must point to a manifest of the recipe, the lockfile must pin every other
+    /// dependency and all paths must stay within the project.
+    ///
+    /// Every violation is listed, with the path of the offending file. It exits with status
+    /// code 1 if there are any. `cook` runs the same checks.
+    Validate(Validate),
+    /// Print the completions of `cargo chef` for a shell on stdout, e.g.
+    /// `cargo chef completions fish > ~/.config/fish/conf.d/cargo-chef.fish`.
+    Completions(Completions),
+}
+
+#[derive(Parser)]
+pub struct Validate {
+    /// The filepath of the recipe. Use `-` to read it from stdin.
+    #[clap(long, default_value = "recipe.json")]
+    recipe_path: PathBuf,
+}
+
+#[derive(Parser)]
+pub struct Completions {
+    /// The shell to complete `cargo chef` in.
+    #[clap(value_enum)]
+    shell: CompletionShell,
+}
+
+/// The shells `cargo chef completions` supports.
+#[derive(Clone, Copy, ValueEnum)]
+enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl From<CompletionShell> for Shell {
+    fn from(shell: CompletionShell) -> Self {
+        match shell {
+            CompletionShell::Bash => Shell::Bash,
+            CompletionShell::Zsh => Shell::Zsh,
+            CompletionShell::Fish => Shell::Fish,
+        }
+    }
+}
+
+#[derive(Parser)]
+pub struct Sbom {
+    /// The filepath of the recipe. Use `-` to read it from stdin.
+    #[clap(long, default_value = "recipe.json")]
+    recipe_path: PathBuf,
+    /// The format of the document: `cyclonedx` (CycloneDX 1.5) or `spdx-json` (SPDX 2.3).
+    /// SPDX documents record their creation time: `SOURCE_DATE_EPOCH` is used if set.
+    #[clap(long, default_value = "cyclonedx", value_enum)]
+    format: SbomFormatArg,
+}
+
+/// The formats `cargo chef sbom --format` accepts.
+#[derive(Clone, Copy, ValueEnum)]
+enum SbomFormatArg {
+    #[clap(name = "cyclonedx")]
+    CycloneDx,
+    SpdxJson,
+}
+
+impl From<SbomFormatArg> for SbomFormat {
+    fn from(format: SbomFormatArg) -> Self {
+        match format {
+            SbomFormatArg::CycloneDx => SbomFormat::CycloneDx,
+            SbomFormatArg::SpdxJson => SbomFormat::SpdxJson,
+        }
+    }
 }
 
 #[derive(Parser)]
+pub struct Hash {
+    /// The filepath of the recipe to fingerprint. Use `-` to read it from stdin.
+    ///
+    /// If omitted, the recipe of the current directory is computed on the fly.
+    #[clap(long)]
+    recipe_path: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+pub struct Diff {
+    /// The filepath of the old recipe.
+    old_recipe_path: PathBuf,
+    /// The filepath of the new recipe.
+    new_recipe_path: PathBuf,
+}
+
+#[derive(Parser)]
+#[clap(
+    after_help = "Every option can be set with the environment variable in brackets instead, \
+    e.g. `CHEF_LOCKED=true`: the command line takes precedence over it. Lists are comma \
+    separated and flags accept `1`, `true` or `yes` (`0`, `false` or `no` to leave them unset)."
+)]
 pub struct Prepare {
-    /// The filepath used to save the computed recipe.
+    /// The filepath used to save the computed recipe. Use `-` to write it to stdout.
     ///
     /// It defaults to "recipe.json".
-    #[clap(long, default_value = "recipe.json")]
+    #[clap(long, default_value = "recipe.json", env = "CHEF_RECIPE_PATH")]
     recipe_path: PathBuf,
 
     /// When --bin is specified, `cargo-chef` will ignore all members of the workspace
     /// that are not necessary to successfully compile the specific binary.
-    #[clap(long)]
-    bin: Option<String>,
+    /// The flag can be passed multiple times to select multiple binaries.
+    #[clap(long, env = "CHEF_BIN")]
+    bin: Vec<String>,
+
+    /// Package to prepare the recipe for (see `cargo help pkgid`).
+    /// `cargo-chef` will ignore all members of the workspace that are not (transitively)
+    /// required to compile the selected package.
+    /// The flag can be passed multiple times to select multiple packages.
+    #[clap(long, short = 'p', env = "CHEF_PACKAGE")]
+    package: Vec<String>,
+
+    /// The placeholder version used to mask the versions of local crates.
+    /// It must be a valid semver version.
+    #[clap(
+        long,
+        default_value = DEFAULT_MASK_VERSION,
+        value_parser = parse_mask_version,
+        env = "CHEF_MASK_VERSION"
+    )]
+    mask_version: String,
+
+    /// Print to stderr which crates were detected as local, which versions were masked
+    /// and which local-looking dependencies were left untouched.
+    /// It does not affect the computed recipe.
+    #[clap(long, env = "CHEF_PRINT_MASKED")]
+    print_masked: bool,
+
+    /// Do not include `.cargo/config.toml` (or `.cargo/config`) in the recipe.
+    /// Useful if the config is intentionally injected only when building the actual project.
+    #[clap(long, env = "CHEF_NO_CARGO_CONFIG")]
+    no_cargo_config: bool,
+
+    /// Default arguments for `cargo chef cook`, stored in the recipe (e.g. `--cook-args
+    /// "--release --target x86_64-unknown-linux-musl"`).
+    /// Arguments passed to `cook` on the command line take precedence over them.
+    ///
+    /// It defaults to `cook-args` in `[package.metadata.chef]` (or `[workspace.metadata.chef]`)
+    /// of the root manifest, if present.
+    #[clap(long, allow_hyphen_values = true, env = "CHEF_COOK_ARGS")]
+    cook_args: Option<String>,
+
+    /// Do not write the recipe: check that the one at `--recipe-path` is up to date instead.
+    /// It exits with status code 0 if it is, 1 otherwise (printing what changed).
+    #[clap(long, env = "CHEF_CHECK_RECIPE")]
+    check: bool,
+
+    /// Fail if the project does not have a `Cargo.lock` file.
+    #[clap(long, env = "CHEF_LOCKED")]
+    locked: bool,
+
+    /// Fail if `Cargo.lock` is in a format more recent than the ones `cargo-chef` supports
+    /// (version 4). By default it is copied to the recipe as is, with a warning: the versions of
+    /// the local crates are not masked in it.
+    #[clap(long, env = "CHEF_STRICT_LOCKFILE")]
+    strict_lockfile: bool,
+
+    /// Only warn when `Cargo.lock` is out of sync with the manifests, instead of failing: by
+    /// default `prepare` fails if a dependency has no satisfying package in the lockfile (e.g. it
+    /// was added without updating it), since `cook` would have to resolve it again.
+    #[clap(long, env = "CHEF_ALLOW_STALE_LOCKFILE")]
+    allow_stale_lockfile: bool,
+
+    /// Leave dev-dependencies out of the recipe (and the lockfile packages only they need).
+    /// Useful for runtime images that never build tests, examples or benchmarks.
+    #[clap(long, env = "CHEF_NO_DEV_DEPENDENCIES")]
+    no_dev_dependencies: bool,
+
+    /// When --bin or --package is specified, the lockfile packages that are not needed by the
+    /// selected members are left out of the recipe. Use this flag to keep the whole lockfile.
+    #[clap(long, env = "CHEF_NO_LOCKFILE_PRUNING")]
+    no_lockfile_pruning: bool,
+
+    /// Path to the `Cargo.toml` to prepare the recipe for, instead of the one in the current
+    /// directory. The recipe covers the root of its workspace: if the manifest is a member,
+    /// it is the same as passing `--package <member>` from the root.
+    #[clap(long, env = "CHEF_MANIFEST_PATH")]
+    manifest_path: Option<PathBuf>,
+
+    /// When run from a member of a workspace, `prepare` computes the recipe for the whole
+    /// workspace, from its root. Use this flag to only include the member (and the local crates
+    /// it depends on).
+    #[clap(long, env = "CHEF_MEMBER_ONLY")]
+    member_only: bool,
+
+    /// Do not skip the manifests ignored by `.gitignore` files.
+    /// The ones ignored by `.chefignore` files (with the same syntax) are always skipped.
+    #[clap(long, env = "CHEF_NO_GITIGNORE")]
+    no_gitignore: bool,
+
+    /// Keep the `[package.metadata]` and `[workspace.metadata]` tables in the recipe.
+    /// They are left out by default: they do not affect how dependencies are compiled, unless a
+    /// build script reads them.
+    #[clap(long, env = "CHEF_KEEP_METADATA")]
+    keep_metadata: bool,
+
+    /// Keep the real build scripts of the local crates, instead of replacing them with dummy
+    /// ones, so that they run during `cook` (e.g. to generate code with `tonic-build`). Mark a
+    /// single crate with `keep-build-script = true` in `[package.metadata.chef]` instead.
+    #[clap(long, env = "CHEF_KEEP_BUILD_SCRIPTS")]
+    keep_build_scripts: bool,
+
+    /// Embed this target specification file (e.g. `my-target.json`, relative to the root of the
+    /// project) in the recipe, for `cook --target my-target.json` to find it wherever the
+    /// skeleton is unpacked. The flag can be passed multiple times.
+    #[clap(long, env = "CHEF_INCLUDE_TARGET_SPEC")]
+    include_target_spec: Vec<PathBuf>,
+
+    /// Write one recipe per member of the workspace to `--recipe-dir`, named after its package
+    /// (e.g. `api.json`), instead of a single recipe to `--recipe-path`.
+    /// Each recipe only covers what its member needs: changing the dependencies of a member
+    /// does not invalidate the recipes of the others.
+    #[clap(
+        long,
+        requires = "recipe-dir",
+        conflicts_with_all = &["bin", "package", "member-only"],
+        env = "CHEF_SPLIT_WORKSPACE"
+    )]
+    split_workspace: bool,
+
+    /// The directory to write the recipes computed with `--split-workspace` to.
+    #[clap(long, requires = "split-workspace", env = "CHEF_RECIPE_DIR")]
+    recipe_dir: Option<PathBuf>,
+
+    /// Also write the fingerprint of the recipe (as printed by `cargo chef hash`) to this file.
+    /// It is tiny and only changes with the recipe: Dockerfiles can copy it in an early layer,
+    /// e.g. to key a cache mount, and pass it to `cook --expect-cache-key`.
+    #[clap(long, conflicts_with_all = &["split-workspace", "check"], env = "CHEF_EMIT_CACHE_KEY")]
+    emit_cache_key: Option<PathBuf>,
+
+    /// Also write a summary of the recipe to this file, as JSON: the members of the workspace,
+    /// their remote dependencies and the fingerprint of the recipe. Unlike the recipe, its format
+    /// is stable: it is versioned by its `summary_version` field.
+    #[clap(long, conflicts_with_all = &["split-workspace", "check"], env = "CHEF_SUMMARY_PATH")]
+    summary_path: Option<PathBuf>,
+
+    /// Only write the recipe if its contents changed, leaving the existing file (and its
+    /// modification time) untouched otherwise. It exits with status code 2 if it wrote the
+    /// recipe, 0 if it was unchanged.
+    #[clap(long, conflicts_with = "check", env = "CHEF_IF_CHANGED")]
+    if_changed: bool,
+}
+
+fn parse_mask_version(version: &str) -> Result<String, String> {
+    semver::Version::parse(version)
+        .map(|_| version.to_owned())
+        .map_err(|e| format!("`{}` is not a valid semver version: {}", version, e))
 }
 
 #[derive(Parser)]
+#[clap(
+    after_help = "Every option can be set with the environment variable in brackets instead, \
+    e.g. `CHEF_PROFILE=release`: the command line takes precedence over it, and it takes \
+    precedence over the default cook arguments of the recipe. Lists are comma separated for the \
+    options that accept several values in one flag (e.g. `CHEF_FEATURES=a,b`) and flags accept \
+    `1`, `true` or `yes` (`0`, `false` or `no` to leave them unset). `CARGO_TARGET_DIR` and \
+    `RUSTC_WRAPPER`, which cargo reads as well, come last: after `CHEF_TARGET_DIR` and \
+    `CHEF_RUSTC_WRAPPER` and after the default cook arguments of the recipe. The extra cargo \
+    arguments after `--` have no variable: use the ones of cargo (e.g. `CARGO_NET_RETRY=5`)."
+)]
 pub struct Cook {
-    /// The filepath `cook` should be reading the recipe from.
+    /// The filepath `cook` should be reading the recipe from. Use `-` to read it from stdin.
+    /// For a directory written by `prepare --split-workspace`, the recipe of the member selected
+    /// with `--package` is cooked.
     ///
     /// It defaults to "recipe.json".
-    #[clap(long, default_value = "recipe.json")]
+    #[clap(long, default_value = "recipe.json", env = "CHEF_RECIPE_PATH")]
     recipe_path: PathBuf,
+    /// Fail, before doing anything, if the fingerprint of the recipe is not this one (e.g. the
+    /// key written by `prepare --emit-cache-key`). It catches stale recipes copied by mistake.
+    #[clap(long, env = "CHEF_EXPECT_CACHE_KEY")]
+    expect_cache_key: Option<String>,
     /// Build artifacts with the specified profile.
-    #[clap(long)]
+    #[clap(long, env = "CHEF_PROFILE")]
     profile: Option<String>,
     /// Build in release mode.
-    #[clap(long)]
+    #[clap(long, env = "CHEF_RELEASE")]
     release: bool,
     /// Run `cargo check` instead of `cargo build`. Primarily useful for speeding up your CI pipeline.
-    #[clap(long)]
+    #[clap(long, env = "CHEF_CHECK")]
     check: bool,
     /// Run `cargo clippy` instead of `cargo build`. Primarily useful for speeding up your CI pipeline. Requires clippy to be installed.
-    #[clap(long)]
+    #[clap(long, env = "CHEF_CLIPPY")]
     clippy: bool,
     /// Build for the target triple. The flag can be passed multiple times to cook for multiple targets.
-    #[clap(long)]
+    #[clap(long, env = "CHEF_TARGET")]
     target: Option<Vec<String>>,
     /// Directory for all generated artifacts.
-    #[clap(long, env = "CARGO_TARGET_DIR")]
+    /// It defaults, like for cargo, to `CARGO_TARGET_DIR`, then `build.target-dir` in the cargo
+    /// configuration and finally `target`.
+    #[clap(long, env = "CHEF_TARGET_DIR")]
     target_dir: Option<PathBuf>,
     /// Do not activate the `default` feature.
-    #[clap(long)]
+    #[clap(long, env = "CHEF_NO_DEFAULT_FEATURES")]
     no_default_features: bool,
     /// Space or comma separated list of features to activate.
-    #[clap(long, value_delimiter = ',')]
+    /// The flag can be passed multiple times.
+    #[clap(long, value_delimiter = ',', env = "CHEF_FEATURES")]
     features: Option<Vec<String>>,
+    /// Activate all available features of all selected packages.
+    #[clap(long, env = "CHEF_ALL_FEATURES")]
+    all_features: bool,
     /// Unstable feature to activate (only available on the nightly channel).
-    #[clap(short = 'Z')]
+    #[clap(short = 'Z', env = "CHEF_UNSTABLE_FEATURES")]
     unstable_features: Option<Vec<String>>,
     /// Build all benches
-    #[clap(long)]
+    #[clap(long, env = "CHEF_BENCHES")]
     benches: bool,
     /// Build all tests
-    #[clap(long)]
+    #[clap(long, env = "CHEF_TESTS")]
     tests: bool,
     /// Build all examples
-    #[clap(long)]
+    #[clap(long, env = "CHEF_EXAMPLES")]
     examples: bool,
     /// Build all targets.
     /// This is equivalent to specifying `--tests --benches --examples`.
-    #[clap(long)]
+    #[clap(long, env = "CHEF_ALL_TARGETS")]
     all_targets: bool,
-    /// Path to Cargo.toml
-    #[clap(long)]
+    /// Path to Cargo.toml.
+    /// If it is not one of the manifests of the recipe, the recipe is unpacked in its directory.
+    #[clap(long, env = "CHEF_MANIFEST_PATH")]
     manifest_path: Option<PathBuf>,
-    /// Package to build (see `cargo help pkgid`)
-    #[clap(long, short = 'p')]
-    package: Option<String>,
+    /// Unpack the skeleton in this directory instead of the current one and build it from there,
+    /// with `--manifest-path` pointing to it (`--manifest-path` is then one of the manifests of
+    /// the recipe, relative to it). The target directory stays the one of the current directory.
+    /// The files of the skeleton are removed once the dependencies are built, so that no dummy
+    /// source is left to clash with the real project.
+    #[clap(long, env = "CHEF_SKELETON_DIR")]
+    skeleton_dir: Option<PathBuf>,
+    /// Package to build (see `cargo help pkgid`). The flag can be passed multiple times.
+    #[clap(long, short = 'p', env = "CHEF_PACKAGE")]
+    package: Vec<String>,
     /// Build all members in the workspace.
-    #[clap(long)]
+    /// By default, `cook` builds the packages a plain `cargo build` would, i.e. the
+    /// `default-members` of the workspace if they are set.
+    #[clap(long, env = "CHEF_WORKSPACE")]
     workspace: bool,
     /// Build offline.
-    #[clap(long)]
+    #[clap(long, env = "CHEF_OFFLINE")]
     offline: bool,
-    /// Report build timings.
-    #[clap(long)]
-    timings: bool,
+    /// Require the lockfile of the recipe to be up to date: cargo fails instead of resolving
+    /// new versions.
+    #[clap(long, env = "CHEF_LOCKED")]
+    locked: bool,
+    /// Report build timings, in the given formats: `html` (the default) and `json`, printed as
+    /// messages on stdout by the versions of cargo that support it (with `-Z unstable-options`).
+    /// The HTML report is copied to `<target-dir>/cargo-timings/chef-cook-timings.html`.
+    #[clap(
+        long,
+        value_name = "FMTS",
+        min_values = 0,
+        require_equals = true,
+        value_delimiter = ',',
+        value_parser = ["html", "json"],
+        env = "CHEF_TIMINGS"
+    )]
+    timings: Option<Vec<String>>,
     /// Cook using `#[no_std]` configuration  (does not affect `proc-macro` crates)
-    #[clap(long)]
+    #[clap(long, env = "CHEF_NO_STD")]
     no_std: bool,
+    /// Build these crates of the standard library from source (e.g. `core,alloc`), with
+    /// `-Z build-std`, for targets without a prebuilt one. It requires a nightly toolchain,
+    /// checked before anything is built. Without a list, all of them are built.
+    #[clap(
+        long,
+        value_name = "CRATES",
+        value_delimiter = ',',
+        min_values = 0,
+        env = "CHEF_BUILD_STD"
+    )]
+    build_std: Option<Vec<String>>,
     /// When --bin is specified, `cargo-chef` will ignore all members of the workspace
     /// that are not necessary to successfully compile the specific binary.
-    #[clap(long)]
+    /// It must be one of the binaries of the recipe: only its dependencies are built, e.g. with
+    /// the `--features` its `required-features` list.
+    #[clap(long, env = "CHEF_BIN")]
     bin: Option<String>,
     /// Run `cargo zigbuild` instead of `cargo build`. You need to install
     /// the `cargo-zigbuild` crate and the Zig compiler toolchain separately
-    #[clap(long)]
+    #[clap(long, env = "CHEF_ZIGBUILD")]
     zigbuild: bool,
+    /// The cargo executable to invoke (e.g. `cross` or a wrapper script).
+    /// It defaults to the one that invoked `cargo-chef`.
+    #[clap(long, env = "CHEF_CARGO_PATH")]
+    cargo_path: Option<PathBuf>,
+    /// The wrapper to invoke rustc through (e.g. `sccache`), checked to exist before cooking.
+    /// When it is `sccache`, its cache statistics are printed once the dependencies are built.
+    /// It defaults, like for cargo, to `RUSTC_WRAPPER`.
+    #[clap(long, env = "CHEF_RUSTC_WRAPPER")]
+    rustc_wrapper: Option<PathBuf>,
+    /// Only write the skeleton (manifests, lockfile, dummy sources, configuration files) to the
+    /// current directory and print the paths of the files that were written, without building it.
+    #[clap(long, env = "CHEF_NO_BUILD")]
+    no_build: bool,
+    /// Keep the artifacts of the workspace crates, compiled from the dummy sources, in the target
+    /// directory. By default they are removed, forcing the real build to recompile those crates.
+    #[clap(long, env = "CHEF_NO_CLEAN")]
+    no_clean: bool,
+    /// Build from the sources vendored in this directory by `cargo vendor`, offline: crates.io is
+    /// replaced by it, like in the configuration printed by `cargo vendor`. Every registry
+    /// package of the lockfile is checked to be vendored before building.
+    #[clap(long, env = "CHEF_VENDORED")]
+    vendored: Option<PathBuf>,
+    /// A snapshot of the RustSec advisory database (e.g. a checkout of
+    /// https://github.com/rustsec/advisory-db) to check the crates.io packages of the lockfile
+    /// against before cooking. Requires `--deny-advisories` or `--warn-advisories`.
+    #[clap(long, requires = "advisories", env = "CHEF_ADVISORY_DB")]
+    advisory_db: Option<PathBuf>,
+    /// Refuse to cook if a package of the lockfile is affected by an advisory of `--advisory-db`,
+    /// after printing them.
+    #[clap(
+        long,
+        group = "advisories",
+        requires = "advisory-db",
+        env = "CHEF_DENY_ADVISORIES"
+    )]
+    deny_advisories: bool,
+    /// Like `--deny-advisories`, but only print the affected packages.
+    #[clap(
+        long,
+        group = "advisories",
+        requires = "advisory-db",
+        env = "CHEF_WARN_ADVISORIES"
+    )]
+    warn_advisories: bool,
+    /// Fail if the environment differs from the one the recipe was prepared in (e.g. another
+    /// minor version of rustc, another host or a missing Cargo.lock), instead of printing
+    /// warnings.
+    #[clap(long, env = "CHEF_STRICT_ENVIRONMENT")]
+    strict_environment: bool,
+    /// Invoke cargo once per member of the workspace (the ones `--workspace` or `--package`
+    /// select), the local crates they depend on first, instead of once for all of them. It keeps
+    /// the memory usage of large workspaces down, at the expense of some parallelism. It stops
+    /// at the first member that fails to build.
+    #[clap(long, conflicts_with = "bin", env = "CHEF_SEQUENTIAL_MEMBERS")]
+    sequential_members: bool,
+    /// The number of parallel jobs cargo runs.
+    #[clap(long, short = 'j', env = "CHEF_JOBS")]
+    jobs: Option<u32>,
+    /// Re-run cargo up to this many times if it fails on a transient network error (e.g. a
+    /// spurious network error or an early EOF while downloading a crate), waiting 1s, 2s, 4s, ...
+    /// in between. When it is set, cargo's stderr is relayed by `cargo-chef` to be inspected.
+    #[clap(long, default_value = "0", env = "CHEF_NETWORK_RETRIES")]
+    network_retries: u32,
+    /// Only download the dependencies of the recipe, with `cargo fetch --locked` (for each
+    /// `--target`), into `$CARGO_HOME`, without building them. It only depends on the lockfile:
+    /// run it in an earlier layer than the build.
+    #[clap(long, conflicts_with = "skip-fetch", env = "CHEF_ONLY_FETCH")]
+    only_fetch: bool,
+    /// Assume the dependencies were already downloaded with `--only-fetch`: build offline.
+    #[clap(long, env = "CHEF_SKIP_FETCH")]
+    skip_fetch: bool,
+    /// Do not build anything: unpack the skeleton and print how many crates would be built, the
+    /// ones with the most dependents and the features of the direct dependencies. It reads the
+    /// unit graph of cargo on nightly (`--unit-graph -Z unstable-options`), `cargo tree` on
+    /// stable. The target directory is left untouched.
+    #[clap(long, conflicts_with_all = &["only-fetch", "no-build"], env = "CHEF_DRY_RUN")]
+    dry_run: bool,
+    /// Do not set `SQLX_OFFLINE=true` for cargo when the recipe contains the offline query data
+    /// of `sqlx` (`.sqlx/` or `sqlx-data.json`). An `SQLX_OFFLINE` set in the environment is
+    /// always passed down as is.
+    #[clap(long, env = "CHEF_NO_SQLX_OFFLINE")]
+    no_sqlx_offline: bool,
+    /// Fail, once the dependencies are built, if more than this many crates had to be downloaded
+    /// into `$CARGO_HOME` (see the cache locations and the download counts `cook` prints), e.g.
+    /// because the cache mount is missing or shadowed.
+    #[clap(long, value_name = "N", env = "CHEF_REQUIRE_WARM_CACHE")]
+    require_warm_cache: Option<usize>,
+    /// Once the dependencies are built, remove the `.crate` archives and the unpacked sources of
+    /// the registries from `$CARGO_HOME` (keeping the index), printing how many bytes were
+    /// reclaimed, to keep them out of the image layer. Cargo downloads them again if a later
+    /// build needs them. It is skipped, with a warning, if `$CARGO_HOME` or its registry
+    /// directories are mount points, which may be shared with other builds.
+    #[clap(
+        long,
+        conflicts_with_all = &["only-fetch", "no-build", "dry-run"],
+        env = "CHEF_SWEEP_CACHE"
+    )]
+    sweep_cache: bool,
+    /// Sweep the registry cache even if it looks like a mount shared with other builds.
+    #[clap(long, requires = "sweep-cache", env = "CHEF_FORCE_SWEEP_CACHE")]
+    force_sweep_cache: bool,
+    /// The format of the progress messages: `human`, or `json` for one JSON object per line on
+    /// stderr for each step of `cook`. With `json`, cargo's messages are JSON as well, on stdout.
+    #[clap(long, default_value = "human", value_enum, env = "CHEF_MESSAGE_FORMAT")]
+    message_format: MessageFormatArg,
+    /// Extra arguments, appended verbatim to the cargo invocation (e.g.
+    /// `cargo chef cook -- --config net.retry=5`).
+    #[clap(last = true)]
+    cargo_args: Vec<String>,
+}
+
+/// The formats `cook --message-format` accepts.
+#[derive(Clone, Copy, ValueEnum)]
+enum MessageFormatArg {
+    Human,
+    Json,
+}
+
+impl From<MessageFormatArg> for MessageFormat {
+    fn from(format: MessageFormatArg) -> Self {
+        match format {
+            MessageFormatArg::Human => MessageFormat::Human,
+            MessageFormatArg::Json => MessageFormat::Json,
+        }
+    }
 }
 
 fn _main() -> Result<(), anyhow::Error> {
     let current_directory = std::env::current_dir().unwrap();
+    // e.g. the `ARG`s of a Dockerfile that were not passed to `docker build`: they are set, but
+    // empty. clap would take an empty value as `true` for a flag.
+    for (name, value) in std::env::vars_os() {
+        if name.to_string_lossy().starts_with("CHEF_") && value.is_empty() {
+            std::env::remove_var(name);
+        }
+    }
 
-    let cli = Cli::parse();
+    // `-V` only prints the version of `cargo-chef`.
+    let long_version = format!(
+        "{}\nrecipe format versions: {} to {} (prepare writes {})",
+        crate_version!(),
+        MIN_RECIPE_FORMAT_VERSION,
+        RECIPE_FORMAT_VERSION,
+        RECIPE_FORMAT_VERSION
+    );
+    let command = || {
+        Cli::command()
+            .long_version(long_version.as_str())
+            .propagate_version(true)
+    };
+    let mut matches = command().get_matches();
+    if unset_overridden_env_vars(&matches) {
+        matches = command().get_matches();
+    }
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
     // "Unwrapping" the actual command.
-    let command = match cli.command {
-        CargoInvocation::Chef { command } => command,
+    let (command, verbose) = match cli.command {
+        CargoInvocation::Chef { command, verbose } => (command, verbose),
     };
+    init_logger(verbose);
+
+    let cli_command = Cli::command();
+    let (name, subcommand_matches) = matches
+        .subcommand_matches("chef")
+        .and_then(ArgMatches::subcommand)
+        .expect("A subcommand is required");
+    let subcommand = cli_command
+        .find_subcommand("chef")
+        .and_then(|chef| chef.find_subcommand(name))
+        .expect("The subcommand is defined");
+    check_flag_env_vars(subcommand)?;
+    // `cook` logs it once the default arguments of the recipe are applied.
+    if name != "cook" {
+        log_configuration(subcommand, subcommand_matches, &[]);
+    }
 
     match command {
-        Command::Cook(Cook {
-            recipe_path,
-            profile,
-            release,
-            check,
-            clippy,
-            target,
-            no_default_features,
-            features,
-            unstable_features,
-            target_dir,
-            benches,
-            tests,
-            examples,
-            all_targets,
-            manifest_path,
-            package,
-            workspace,
-            offline,
-            timings,
-            no_std,
-            bin,
-            zigbuild,
-        }) => {
+        Command::Cook(cook) => {
+            let (cook, recipe) = with_default_cook_args(*cook, &matches)?;
+            let Cook {
+                recipe_path,
+                expect_cache_key,
+                profile,
+                release,
+                check,
+                clippy,
+                target,
+                no_default_features,
+                features,
+                all_features,
+                unstable_features,
+                target_dir,
+                benches,
+                tests,
+                examples,
+                all_targets,
+                manifest_path,
+                skeleton_dir,
+                package,
+                workspace,
+                offline,
+                locked,
+                timings,
+                no_std,
+                build_std,
+                bin,
+                zigbuild,
+                cargo_path,
+                cargo_args,
+                rustc_wrapper,
+                no_build,
+                no_clean,
+                vendored,
+                advisory_db,
+                // Implied by `advisory_db`, unless `warn_advisories` is set.
+                deny_advisories: _,
+                warn_advisories,
+                strict_environment,
+                sequential_members,
+                jobs,
+                network_retries,
+                only_fetch,
+                skip_fetch,
+                dry_run,
+                no_sqlx_offline,
+                require_warm_cache,
+                sweep_cache,
+                force_sweep_cache,
+                message_format,
+            } = cook;
+            if let Some(expected) = expect_cache_key {
+                let fingerprint = recipe.fingerprint()?;
+                if fingerprint != expected.trim() {
+                    return Err(anyhow!(
+                        "The recipe does not match the expected cache key: its fingerprint is {}, \
+                        {} was expected. Is it a stale copy of the recipe?",
+                        fingerprint,
+                        expected.trim()
+                    ));
+                }
+            }
             if atty::is(atty::Stream::Stdout) {
+                if is_stdio(&recipe_path) {
+                    return Err(anyhow!(
+                        "stdout appears to be a terminal: cargo-chef is not meant to be run in an \
+                        interactive environment and cannot ask for confirmation while reading the \
+                        recipe from stdin."
+                    ));
+                }
                 eprintln!("WARNING stdout appears to be a terminal.");
                 eprintln!(
                     "cargo-chef is not meant to be run in an interactive environment \
@@ -189,10 +725,16 @@ fn _main() -> Result<(), anyhow::Error> {
             }
 
             let features: Option<HashSet<String>> = features.and_then(|features| {
+                // Like cargo, we accept space separated lists (e.g. `--features "foo bar"`).
+                let features: HashSet<String> = features
+                    .iter()
+                    .flat_map(|features| features.split_whitespace())
+                    .map(|feature| feature.to_owned())
+                    .collect();
                 if features.is_empty() {
                     None
                 } else {
-                    Some(features.into_iter().collect())
+                    Some(features)
                 }
             });
 
@@ -209,6 +751,7 @@ fn _main() -> Result<(), anyhow::Error> {
                 (false, None) =>  OptimisationProfile::Debug,
                 (false, Some(profile)) if profile == "dev" => OptimisationProfile::Debug,
                 (true, None) => OptimisationProfile::Release,
+                (true, Some(profile)) if profile == "release" => OptimisationProfile::Release,
                 (false, Some(profile)) if profile == "release" => OptimisationProfile::Release,
                 (false, Some(custom_profile)) => OptimisationProfile::Other(custom_profile),
                 (true, Some(_)) => Err(anyhow!("You specified both --release and --profile arguments. Please remove one of them, or both"))?
@@ -221,54 +764,652 @@ fn _main() -> Result<(), anyhow::Error> {
                 _ => Err(anyhow!("Only one (or none) of the  `clippy`, `check` and `zigbuild` arguments are allowed. Please remove some of them, or all"))?,
             };
 
+            let message_format = MessageFormat::from(message_format);
+
             let default_features = if no_default_features {
                 DefaultFeatures::Disabled
             } else {
                 DefaultFeatures::Enabled
             };
 
-            let serialized = fs::read_to_string(recipe_path)
-                .context("Failed to read recipe from the specified path.")?;
-            let recipe: Recipe =
-                serde_json::from_str(&serialized).context("Failed to deserialize recipe.")?;
             let target_args = TargetArgs {
                 benches,
                 tests,
                 examples,
                 all_targets,
             };
-            recipe
-                .cook(CookArgs {
+            let report = chef::cook(
+                &recipe,
+                CookOptions {
+                    directory: None,
+                    skeleton_dir,
                     profile,
                     command,
                     default_features,
                     features,
+                    all_features,
                     unstable_features,
                     target,
-                    target_dir,
+                    target_dir: target_dir
+                        .or_else(|| cargo_variable("CARGO_TARGET_DIR", "--target-dir")),
                     target_args,
                     manifest_path,
                     package,
                     workspace,
                     offline,
+                    locked,
                     timings,
                     no_std,
+                    build_std: build_std.map(|crates| {
+                        crates
+                            .into_iter()
+                            .filter(|krate| !krate.is_empty())
+                            .collect()
+                    }),
                     bin,
-                })
-                .context("Failed to cook recipe.")?;
+                    cargo_path,
+                    cargo_args,
+                    // Like cargo, an empty `RUSTC_WRAPPER` disables the wrapper.
+                    rustc_wrapper: rustc_wrapper
+                        .or_else(|| cargo_variable("RUSTC_WRAPPER", "--rustc-wrapper"))
+                        .filter(|wrapper| !wrapper.as_os_str().is_empty()),
+                    no_build,
+                    no_clean,
+                    vendored,
+                    advisory_db,
+                    warn_advisories,
+                    strict_environment,
+                    sequential_members,
+                    jobs,
+                    network_retries,
+                    only_fetch,
+                    skip_fetch,
+                    dry_run,
+                    no_sqlx_offline,
+                    require_warm_cache,
+                    sweep_cache,
+                    force_sweep_cache,
+                    message_format,
+                },
+            )
+            .context("Failed to cook recipe.")?;
+            if !report.success() {
+                let code = report.exit_status.and_then(|status| status.code());
+                let member = report
+                    .member
+                    .map(|member| format!("cooking `{}` failed: ", member))
+                    .unwrap_or_default();
+                match code {
+                    Some(code) => {
+                        eprintln!("Error: {}cargo exited with status code {}.", member, code)
+                    }
+                    None => eprintln!("Error: {}cargo was terminated by a signal.", member),
+                }
+                std::process::exit(code.unwrap_or(101));
+            }
+        }
+        Command::Prepare(prepare) => {
+            let Prepare {
+                recipe_path,
+                bin,
+                package,
+                mask_version,
+                print_masked,
+                no_cargo_config,
+                cook_args,
+                check,
+                locked,
+                strict_lockfile,
+                allow_stale_lockfile,
+                no_dev_dependencies,
+                no_lockfile_pruning,
+                manifest_path,
+                member_only,
+                no_gitignore,
+                keep_metadata,
+                keep_build_scripts,
+                include_target_spec,
+                // Implied by `recipe_dir`.
+                split_workspace: _,
+                recipe_dir,
+                emit_cache_key,
+                summary_path,
+                if_changed,
+            } = *prepare;
+            if if_changed && is_stdio(&recipe_path) && recipe_dir.is_none() {
+                return Err(anyhow!(
+                    "`--if-changed` cannot be used when writing the recipe to stdout."
+                ));
+            }
+            let members: Vec<String> = bin.into_iter().chain(package).collect();
+            let mut args = PrepareArgs {
+                members,
+                mask_version,
+                print_masked,
+                no_cargo_config,
+                cook_args: cook_args
+                    .iter()
+                    .flat_map(|args| args.split_whitespace())
+                    .map(|arg| arg.to_owned())
+                    .collect(),
+                locked,
+                strict_lockfile,
+                allow_stale_lockfile,
+                no_dev_dependencies,
+                no_lockfile_pruning,
+                manifest_path,
+                member_only,
+                no_gitignore,
+                keep_metadata,
+                keep_build_scripts,
+                target_specs: include_target_spec,
+                include: vec![],
+                split_members: vec![],
+                config: ChefConfig::default(),
+            };
+            // Below the command line and the environment.
+            if let Some(config) =
+                ChefConfig::load(&current_directory, args.manifest_path.as_deref())?
+            {
+                config.apply(&mut args, |key| {
+                    subcommand.get_arguments().any(|arg| arg.get_id() == key)
+                        && matches!(
+                            subcommand_matches.value_source(key),
+                            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+                        )
+                });
+                log::info!(
+                    "Applied the chef configuration of the project: {:?}",
+                    args.config
+                );
+            }
+            let recipes = match recipe_dir {
+                Some(recipe_dir) => Recipe::prepare_split(current_directory, args)
+                    .context("Failed to compute the recipes")?
+                    .into_iter()
+                    .map(|(member, recipe)| (recipe_dir.join(format!("{}.json", member)), recipe))
+                    .collect(),
+                None => vec![(
+                    recipe_path,
+                    chef::prepare(WorkspaceOptions {
+                        path: current_directory,
+                        args,
+                    })
+                    .context("Failed to compute recipe")?,
+                )],
+            };
+            if check {
+                let mut is_up_to_date = true;
+                for (recipe_path, recipe) in &recipes {
+                    let existing = read_recipe(recipe_path.clone())?;
+                    let diff =
+                        RecipeDiff::new(&existing, recipe).context("Failed to compare recipes.")?;
+                    if diff.is_empty() {
+                        println!("{} is up to date.", recipe_path.display());
+                    } else {
+                        print!("{} is out of date:\n{}", recipe_path.display(), diff);
+                        is_up_to_date = false;
+                    }
+                }
+                if !is_up_to_date {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+            let mut has_written = false;
+            for (recipe_path, recipe) in recipes {
+                let serialized =
+                    serde_json::to_string(&recipe).context("Failed to serialize recipe.")?;
+                if if_changed
+                    && fs::read(&recipe_path).ok().as_deref() == Some(serialized.as_bytes())
+                {
+                    println!("{}: recipe unchanged", recipe_path.display());
+                } else if is_stdio(&recipe_path) {
+                    let mut stdout = std::io::stdout().lock();
+                    // Same bytes as the file, e.g. to hash it.
+                    stdout
+                        .write_all(serialized.as_bytes())
+                        .and_then(|_| stdout.flush())
+                        .context("Failed to write recipe to stdout")?;
+                } else {
+                    if let Some(directory) = recipe_path.parent() {
+                        fs::create_dir_all(directory).with_context(|| {
+                            format!("Failed to create the directory {:?}", directory)
+                        })?;
+                    }
+                    fs::write(&recipe_path, serialized)
+                        .with_context(|| format!("Failed to save recipe to {:?}", recipe_path))?;
+                    has_written = true;
+                }
+                if let Some(cache_key_path) = &emit_cache_key {
+                    let cache_key = format!("{}\n", recipe.fingerprint()?);
+                    if !(if_changed
+                        && fs::read(cache_key_path).ok().as_deref() == Some(cache_key.as_bytes()))
+                    {
+                        fs::write(cache_key_path, cache_key).with_context(|| {
+                            format!("Failed to save the cache key to {:?}", cache_key_path)
+                        })?;
+                        has_written = true;
+                    }
+                }
+                if let Some(summary_path) = &summary_path {
+                    let summary = RecipeSummary::new(&recipe)
+                        .and_then(|summary| summary.to_json())
+                        .context("Failed to summarise the recipe.")?;
+                    if !(if_changed
+                        && fs::read(summary_path).ok().as_deref() == Some(summary.as_bytes()))
+                    {
+                        fs::write(summary_path, summary).with_context(|| {
+                            format!("Failed to save the summary to {:?}", summary_path)
+                        })?;
+                        has_written = true;
+                    }
+                }
+            }
+            if if_changed && has_written {
+                std::process::exit(2);
+            }
+        }
+        Command::Diff(Diff {
+            old_recipe_path,
+            new_recipe_path,
+        }) => {
+            let old = read_recipe(old_recipe_path)?;
+            let new = read_recipe(new_recipe_path)?;
+            let diff = RecipeDiff::new(&old, &new).context("Failed to compare recipes.")?;
+            print!("{}", diff);
+            if !diff.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::Validate(Validate { recipe_path }) => {
+            let recipe = read_recipe(recipe_path)?;
+            recipe.validate()?;
+            println!("The recipe is valid.");
+        }
+        Command::Hash(Hash { recipe_path }) => {
+            let recipe = match recipe_path {
+                Some(recipe_path) => read_recipe(recipe_path)?,
+                None => Recipe::prepare(current_directory, PrepareArgs::default())
+                    .context("Failed to compute recipe")?,
+            };
+            println!("{}", recipe.fingerprint()?);
+        }
+        Command::Sbom(Sbom {
+            recipe_path,
+            format,
+        }) => {
+            let recipe = read_recipe(recipe_path)?;
+            let sbom = chef::Sbom::new(&recipe).context("Failed to generate the SBOM.")?;
+            let format = SbomFormat::from(format);
+            let created = match std::env::var("SOURCE_DATE_EPOCH") {
+                Ok(epoch) => {
+                    let epoch: u64 = epoch
+                        .trim()
+                        .parse()
+                        .context("`SOURCE_DATE_EPOCH` is not a number of seconds.")?;
+                    UNIX_EPOCH + Duration::from_secs(epoch)
+                }
+                Err(_) => SystemTime::now(),
+            };
+            let document = serde_json::to_string_pretty(&sbom.to_json(format, created))?;
+            println!("{}", document);
+        }
+        Command::Completions(Completions { shell }) => {
+            // `cargo` finds `cargo-chef` itself: only the `chef` subcommand is completed.
+            clap_complete::generate(
+                Shell::from(shell),
+                &mut Cli::command(),
+                "cargo",
+                &mut std::io::stdout(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// `-` stands for stdin (or stdout) instead of a file.
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+fn read_recipe(recipe_path: PathBuf) -> Result<Recipe, anyhow::Error> {
+    let serialized = if is_stdio(&recipe_path) {
+        // Buffer the whole recipe: we want to fail on truncated input before doing any work.
+        let mut serialized = String::new();
+        std::io::stdin()
+            .read_to_string(&mut serialized)
+            .context("Failed to read recipe from stdin.")?;
+        if serialized.trim().is_empty() {
+            return Err(anyhow!(
+                "Failed to read recipe from stdin: the input is empty."
+            ));
+        }
+        serialized
+    } else {
+        fs::read_to_string(recipe_path).context("Failed to read recipe from the specified path.")?
+    };
+    Recipe::from_json(&serialized)
+}
+
+/// The recipe of the package selected with `--package` in a directory of recipes written by
+/// `prepare --split-workspace`.
+fn recipe_in_directory(directory: &Path, packages: &[String]) -> Result<PathBuf, anyhow::Error> {
+    let available = || -> Result<String, anyhow::Error> {
+        let mut members = vec![];
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.extension() == Some("json".as_ref()) {
+                members.extend(
+                    path.file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned()),
+                );
+            }
+        }
+        members.sort();
+        Ok(members.join(", "))
+    };
+    match packages {
+        [package] => {
+            let recipe_path = directory.join(format!("{}.json", package));
+            if recipe_path.is_file() {
+                return Ok(recipe_path);
+            }
+            Err(anyhow!(
+                "There is no recipe for `{}` in {:?}. Available recipes: {}.",
+                package,
+                directory,
+                available()?
+            ))
+        }
+        _ => Err(anyhow!(
+            "{:?} is a directory of split recipes: select the one to cook with `--package \
+            <member>`. Available recipes: {}.",
+            directory,
+            available()?
+        )),
+    }
+}
+
+/// Groups of `cook` arguments that cannot be combined: setting one of them on the command line
+/// overrides the defaults stored in the recipe for all the others.
+const EXCLUSIVE_COOK_ARGS: &[&[&str]] =
+    &[&["release", "profile"], &["check", "clippy", "zigbuild"]];
+
+/// Read the recipe and apply its default cook arguments, unless they are overridden on the
+/// command line (or via environment variables).
+fn with_default_cook_args(
+    cook: Cook,
+    matches: &ArgMatches,
+) -> Result<(Cook, Recipe), anyhow::Error> {
+    let recipe_path = if cook.recipe_path.is_dir() {
+        recipe_in_directory(&cook.recipe_path, &cook.package)?
+    } else {
+        cook.recipe_path.clone()
+    };
+    let recipe = read_recipe(recipe_path)?;
+
+    let cli = Cli::command();
+    let cook_command = cli
+        .find_subcommand("chef")
+        .and_then(|chef| chef.find_subcommand("cook"))
+        .expect("The cook subcommand is always defined");
+    let cook_matches = matches
+        .subcommand_matches("chef")
+        .and_then(|chef| chef.subcommand_matches("cook"))
+        .expect("We are cooking");
+    if recipe.default_cook_args.is_empty() {
+        log_configuration(cook_command, cook_matches, &[]);
+        return Ok((cook, recipe));
+    }
+
+    let mut default_args = vec![];
+    let mut default_ids = vec![];
+    let mut tokens = recipe.default_cook_args.iter();
+    while let Some(token) = tokens.next() {
+        if token == "--" {
+            // Extra cargo arguments are replaced as a whole.
+            if cook.cargo_args.is_empty() {
+                default_args.push(token.to_owned());
+                default_args.extend(tokens.cloned());
+                default_ids.push("cargo-args");
+            }
+            break;
         }
-        Command::Prepare(Prepare { recipe_path, bin }) => {
-            let recipe =
-                Recipe::prepare(current_directory, bin).context("Failed to compute recipe")?;
-            let serialized =
-                serde_json::to_string(&recipe).context("Failed to serialize recipe.")?;
-            fs::write(recipe_path, serialized).context("Failed to save recipe to 'recipe.json'")?;
+        let (arg, has_inline_value) = if let Some(long) = token.strip_prefix("--") {
+            let (long, value) = match long.split_once('=') {
+                Some((long, value)) => (long, Some(value)),
+                None => (long, None),
+            };
+            let arg = cook_command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(long));
+            (arg, value.is_some())
+        } else {
+            let mut chars = token.chars();
+            let short = match (chars.next(), chars.next()) {
+                (Some('-'), Some(short)) => Some(short),
+                _ => None,
+            };
+            let arg = short.and_then(|short| {
+                cook_command
+                    .get_arguments()
+                    .find(|arg| arg.get_short() == Some(short))
+            });
+            (arg, chars.next().is_some())
+        };
+        let arg = arg
+            .filter(|arg| !["recipe-path", "expect-cache-key"].contains(&arg.get_id()))
+            .ok_or_else(|| {
+                anyhow!(
+                    "`{}` is not a valid default argument for `cargo chef cook`",
+                    token
+                )
+            })?;
+
+        let mut group = vec![token.to_owned()];
+        if arg.is_takes_value_set() && !has_inline_value {
+            group.extend(tokens.next().cloned());
+        }
+        // Arguments that are mutually exclusive override each other.
+        let id = arg.get_id();
+        let overriding_ids = EXCLUSIVE_COOK_ARGS
+            .iter()
+            .find(|ids| ids.contains(&id))
+            .copied()
+            .unwrap_or_else(|| std::slice::from_ref(&id));
+        let is_overridden = overriding_ids.iter().any(|id| {
+            matches!(
+                cook_matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        });
+        if !is_overridden {
+            default_args.extend(group);
+            default_ids.push(id);
+        }
+    }
+    if default_args.is_empty() {
+        log_configuration(cook_command, cook_matches, &[]);
+        return Ok((cook, recipe));
+    }
+    log::info!(
+        "Applying the default cook arguments of the recipe: {:?}",
+        default_args
+    );
+
+    // Defaults go right after `cook`, before the arguments passed on the command line,
+    // in particular before `--`.
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let cook_position = args
+        .iter()
+        .position(|arg| arg == "cook")
+        .expect("We are cooking");
+    args.splice(
+        cook_position + 1..cook_position + 1,
+        default_args.into_iter().map(OsString::from),
+    );
+    let matches = Cli::command()
+        .try_get_matches_from(args)
+        .unwrap_or_else(|e| e.exit());
+    let cook_matches = matches
+        .subcommand_matches("chef")
+        .and_then(|chef| chef.subcommand_matches("cook"))
+        .expect("We are cooking");
+    log_configuration(cook_command, cook_matches, &default_ids);
+    let cook = match Cli::from_arg_matches(&matches)
+        .unwrap_or_else(|e| e.exit())
+        .command
+    {
+        CargoInvocation::Chef {
+            command: Command::Cook(cook),
+            ..
+        } => *cook,
+        _ => unreachable!("We are cooking"),
+    };
+    Ok((cook, recipe))
+}
+
+/// An argument of `cook` passed on the command line overrides the environment variables of the
+/// arguments it is mutually exclusive with (e.g. `--profile` and `CHEF_RELEASE`), like it overrides
+/// the default cook arguments of the recipe: they are unset, for the command line to be parsed
+/// again. `true` if there were any.
+fn unset_overridden_env_vars(matches: &ArgMatches) -> bool {
+    let cook_matches = match matches
+        .subcommand_matches("chef")
+        .and_then(|chef| chef.subcommand_matches("cook"))
+    {
+        Some(cook_matches) => cook_matches,
+        None => return false,
+    };
+    let cli = Cli::command();
+    let cook_command = cli
+        .find_subcommand("chef")
+        .and_then(|chef| chef.find_subcommand("cook"))
+        .expect("The cook subcommand is always defined");
+    let mut unset = false;
+    for ids in EXCLUSIVE_COOK_ARGS {
+        let is_on_command_line = ids
+            .iter()
+            .any(|id| cook_matches.value_source(id) == Some(ValueSource::CommandLine));
+        if !is_on_command_line {
+            continue;
+        }
+        for id in ids
+            .iter()
+            .filter(|id| cook_matches.value_source(id) == Some(ValueSource::EnvVariable))
+        {
+            let name = cook_command
+                .get_arguments()
+                .find(|arg| arg.get_id() == *id)
+                .and_then(|arg| arg.get_env())
+                .expect("The exclusive arguments have an environment variable");
+            std::env::remove_var(name);
+            unset = true;
+        }
+    }
+    unset
+}
+
+/// clap takes any value of an environment variable but `0`, `false`, `no`, ... as `true` for a
+/// flag: typos are rejected instead.
+fn check_flag_env_vars(command: &clap::Command) -> Result<(), anyhow::Error> {
+    for arg in command.get_arguments() {
+        let name = match arg.get_env() {
+            Some(name) if !arg.is_takes_value_set() => name,
+            _ => continue,
+        };
+        if let Some(value) = std::env::var_os(name) {
+            let is_boolean = ["1", "true", "yes", "0", "false", "no"]
+                .iter()
+                .any(|boolean| value.eq_ignore_ascii_case(boolean));
+            if !is_boolean {
+                return Err(anyhow!(
+                    "`{}` must be `1`, `true` or `yes` (or `0`, `false` or `no`) to set `--{}`, \
+                    not {:?}.",
+                    name.to_string_lossy(),
+                    arg.get_id(),
+                    value
+                ));
+            }
         }
     }
     Ok(())
 }
 
+/// Log the options of `command` that are set, with their values and where they come from, to
+/// audit e.g. the `CHEF_*` environment variables of a Dockerfile. The ones in `from_recipe` were
+/// set by the default cook arguments of the recipe.
+fn log_configuration(command: &clap::Command, matches: &ArgMatches, from_recipe: &[&str]) {
+    if !log::log_enabled!(log::Level::Info) {
+        return;
+    }
+    let mut options = vec![];
+    for arg in command.get_arguments() {
+        let id = arg.get_id();
+        if ["help", "version"].contains(&id) {
+            continue;
+        }
+        let source = match matches.value_source(id) {
+            None => continue,
+            Some(ValueSource::DefaultValue) => "default".to_owned(),
+            Some(ValueSource::EnvVariable) => {
+                format!("`{}`", arg.get_env().unwrap_or_default().to_string_lossy())
+            }
+            Some(_) if from_recipe.contains(&id) => "recipe".to_owned(),
+            Some(_) => "command line".to_owned(),
+        };
+        let name = match (arg.get_long(), arg.get_short()) {
+            (Some(long), _) => format!("--{}", long),
+            (None, Some(short)) => format!("-{}", short),
+            // The extra cargo arguments.
+            (None, None) => "--".to_owned(),
+        };
+        let value = if arg.is_takes_value_set() {
+            let values: Vec<_> = matches
+                .get_raw(id)
+                .into_iter()
+                .flatten()
+                .map(|value| value.to_string_lossy())
+                .collect();
+            values.join(",")
+        } else {
+            "true".to_owned()
+        };
+        options.push(format!("{} {} ({})", name, value, source));
+    }
+    log::info!(
+        "Configuration of `cargo chef {}`:\n  {}",
+        command.get_name(),
+        options.join("\n  ")
+    );
+}
+
+/// The variable of cargo that `option` of `cook` falls back to, once the command line, the
+/// `CHEF_` variable and the recipe left it unset.
+fn cargo_variable(name: &str, option: &str) -> Option<PathBuf> {
+    let value = std::env::var_os(name)?;
+    log::info!("{} {} (`{}`)", option, value.to_string_lossy(), name);
+    Some(value.into())
+}
+
+/// Log to stderr, filtered by `RUST_LOG` unless `-v` is passed.
+fn init_logger(verbose: u8) {
+    let mut builder = env_logger::Builder::from_default_env();
+    let level = match verbose {
+        0 => None,
+        1 => Some(log::LevelFilter::Info),
+        2 => Some(log::LevelFilter::Debug),
+        _ => Some(log::LevelFilter::Trace),
+    };
+    if let Some(level) = level {
+        builder
+            .filter_module("chef", level)
+            .filter_module("cargo_chef", level);
+    }
+    builder.init();
+}
+
 fn main() -> Result<(), anyhow::Error> {
-    env_logger::init();
     _main()
 }
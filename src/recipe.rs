@@ -1,15 +1,140 @@
-use crate::Skeleton;
+use crate::advisories::VulnerabilityTable;
+use crate::cargo_home::{self, CacheLocations, CacheSnapshot};
+use crate::dry_run::BuildPlan;
+use crate::{
+    cook_args_from_metadata, find_workspace_root, AdvisoryDatabase, ChefConfig, Environment,
+    Skeleton, DEFAULT_MASK_VERSION,
+};
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::path::PathBuf;
-use std::process::Command;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+/// The version of the recipe format produced by this version of `cargo-chef`.
+///
+/// It must be bumped whenever the schema of the recipe changes:
+/// - 1: the original format, without a `format_version` field;
+/// - 2: adds `format_version`, `cargo_chef_version`, `default_cook_args`,
+///   `skeleton.rust_toolchain_file` and `skeleton.mask_version`;
+/// - 3: adds `environment`;
+/// - 4: adds `skeleton.included_files`;
+/// - 5: adds `config`.
+pub const RECIPE_FORMAT_VERSION: u32 = 5;
+
+/// The oldest recipe format this version of `cargo-chef` can still cook.
+pub const MIN_RECIPE_FORMAT_VERSION: u32 = 1;
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct Recipe {
+    /// Recipes produced before the format was versioned do not have this field.
+    #[serde(default = "legacy_format_version")]
+    pub format_version: u32,
+    /// The version of `cargo-chef` that produced the recipe, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cargo_chef_version: Option<String>,
     pub skeleton: Skeleton,
+    /// Arguments applied by `cook` unless they are overridden on its own command line.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub default_cook_args: Vec<String>,
+    /// The toolchain the recipe was prepared with, if it could be detected.
+    /// It is not part of the fingerprint of the recipe.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<Environment>,
+    /// The options taken from the chef configuration of the project (see [`ChefConfig`]), for
+    /// `cook` to warn if its own configuration disagrees. They are already reflected in the
+    /// skeleton, which is fingerprinted instead.
+    #[serde(default, skip_serializing_if = "ChefConfig::is_empty")]
+    pub config: ChefConfig,
+}
+
+fn legacy_format_version() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone)]
+pub struct PrepareArgs {
+    /// Workspace members the recipe should be scoped to. All members are included if empty.
+    pub members: Vec<String>,
+    /// Placeholder version used to mask the versions of local crates.
+    pub mask_version: String,
+    /// Print a report of the version masking decisions to stderr.
+    pub print_masked: bool,
+    /// Leave `.cargo/config.toml` out of the recipe.
+    pub no_cargo_config: bool,
+    /// Default arguments for `cook`.
+    /// If empty, they are read from `[package.metadata.chef]` (or `[workspace.metadata.chef]`)
+    /// in the root manifest, if available.
+    pub cook_args: Vec<String>,
+    /// Fail if the project does not have a `Cargo.lock` file.
+    pub locked: bool,
+    /// Fail if `Cargo.lock` is in a format more recent than the ones `cargo-chef` supports,
+    /// instead of copying it to the recipe as is.
+    pub strict_lockfile: bool,
+    /// Only warn, instead of failing, when `Cargo.lock` does not pin a package satisfying one of
+    /// the dependencies of the manifests (e.g. one added without updating the lockfile).
+    pub allow_stale_lockfile: bool,
+    /// Leave dev-dependencies out of the recipe.
+    pub no_dev_dependencies: bool,
+    /// Keep the whole lockfile, even if `members` is not empty.
+    pub no_lockfile_pruning: bool,
+    /// The manifest to prepare the recipe for, instead of the one in `base_path`.
+    /// The recipe is computed for the root of its workspace, scoped to the package if it is a
+    /// member.
+    pub manifest_path: Option<PathBuf>,
+    /// When `base_path` is a member of a workspace, only prepare the recipe for that member,
+    /// instead of the whole workspace.
+    pub member_only: bool,
+    /// Look at the manifests ignored by `.gitignore` files too.
+    /// The ones ignored by `.chefignore` files are always left out.
+    pub no_gitignore: bool,
+    /// Keep the `[package.metadata]` and `[workspace.metadata]` tables in the recipe.
+    pub keep_metadata: bool,
+    /// Keep the real build scripts of all the local crates, instead of only the ones marked with
+    /// `package.metadata.chef.keep-build-script`.
+    pub keep_build_scripts: bool,
+    /// Target specification files (e.g. `my-target.json`) of custom targets, relative to the
+    /// root of the project, embedded in the recipe for `cook --target my-target.json`.
+    pub target_specs: Vec<PathBuf>,
+    /// Glob patterns of files, relative to the root of the project, embedded in the recipe like
+    /// the ones of `[package.metadata.chef] include`.
+    pub include: Vec<String>,
+    /// The members [`Recipe::prepare_split`] computes recipes for. All of them if empty.
+    pub split_members: Vec<String>,
+    /// The options of the chef configuration of the project that were applied to these
+    /// arguments, recorded in the recipe (see [`ChefConfig::apply`]).
+    pub config: ChefConfig,
 }
 
+impl Default for PrepareArgs {
+    fn default() -> Self {
+        Self {
+            members: vec![],
+            mask_version: DEFAULT_MASK_VERSION.to_string(),
+            print_masked: false,
+            no_cargo_config: false,
+            cook_args: vec![],
+            locked: false,
+            strict_lockfile: false,
+            allow_stale_lockfile: false,
+            no_dev_dependencies: false,
+            no_lockfile_pruning: false,
+            manifest_path: None,
+            member_only: false,
+            no_gitignore: false,
+            keep_metadata: false,
+            keep_build_scripts: false,
+            target_specs: vec![],
+            include: vec![],
+            split_members: vec![],
+            config: ChefConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct TargetArgs {
     pub benches: bool,
     pub tests: bool,
@@ -17,6 +142,7 @@ pub struct TargetArgs {
     pub all_targets: bool,
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum CommandArg {
     Build,
     Check,
@@ -24,47 +150,1188 @@ pub enum CommandArg {
     Zigbuild,
 }
 
-pub struct CookArgs {
+/// How to build the skeleton of a recipe, mirroring the arguments of `cargo chef cook`.
+///
+/// Start from [`CookOptions::default`] (a plain `cargo build` in the current directory): fields
+/// may be added in minor releases.
+#[derive(Debug, Clone)]
+pub struct CookOptions {
+    /// Where to unpack the skeleton and run cargo from, instead of the current directory.
+    /// The other relative paths (e.g. `manifest_path`) are relative to it.
+    pub directory: Option<PathBuf>,
+    /// Unpack the skeleton in this directory (relative to `directory`) instead, and build it with
+    /// `--manifest-path` pointing there, in the target directory of `directory`. The files of the
+    /// skeleton are removed once the dependencies are built: none of the dummy sources is left
+    /// around when the real project is copied over.
+    pub skeleton_dir: Option<PathBuf>,
     pub profile: OptimisationProfile,
     pub command: CommandArg,
     pub default_features: DefaultFeatures,
     pub features: Option<HashSet<String>>,
+    pub all_features: bool,
     pub unstable_features: Option<HashSet<String>>,
     pub target: Option<Vec<String>>,
     pub target_dir: Option<PathBuf>,
     pub target_args: TargetArgs,
     pub manifest_path: Option<PathBuf>,
-    pub package: Option<String>,
+    pub package: Vec<String>,
     pub workspace: bool,
     pub offline: bool,
-    pub timings: bool,
+    /// Pass `--locked` to cargo: fail instead of updating the lockfile of the recipe.
+    pub locked: bool,
+    /// Pass `--timings` to cargo, with these report formats (e.g. `html`, `json`) if not empty.
+    /// The HTML report is copied to `cargo-timings/chef-cook-timings.html` in the target
+    /// directory.
+    pub timings: Option<Vec<String>>,
     pub no_std: bool,
+    /// The crates of the standard library to build from source, i.e. `-Z build-std=<crates>`
+    /// (all of them if empty). It requires a nightly toolchain.
+    pub build_std: Option<Vec<String>>,
     pub bin: Option<String>,
+    /// The cargo executable to invoke, instead of the one that invoked `cargo-chef`.
+    pub cargo_path: Option<PathBuf>,
+    /// Extra arguments, appended verbatim to the cargo invocation.
+    pub cargo_args: Vec<String>,
+    /// The wrapper to invoke rustc through, e.g. `sccache`.
+    pub rustc_wrapper: Option<PathBuf>,
+    /// Only write the skeleton to disk, without building it.
+    pub no_build: bool,
+    /// Keep the artifacts of the workspace crates, compiled from the dummy sources, around.
+    pub no_clean: bool,
+    /// A directory written by `cargo vendor` to build from, offline, instead of crates.io.
+    pub vendored: Option<PathBuf>,
+    /// A snapshot of the RustSec advisory database to check the lockfile against before cooking.
+    pub advisory_db: Option<PathBuf>,
+    /// Only report the packages affected by an advisory, instead of refusing to cook.
+    pub warn_advisories: bool,
+    /// Fail, instead of printing warnings, if the toolchain differs from the one the recipe was
+    /// prepared with or if the recipe has no lockfile.
+    pub strict_environment: bool,
+    /// Invoke cargo once per member of the workspace, in dependency order, instead of once for
+    /// all of them.
+    pub sequential_members: bool,
+    /// The number of parallel jobs cargo runs, i.e. `--jobs`.
+    pub jobs: Option<u32>,
+    /// How many times to re-run a cargo invocation that failed on a transient network error
+    /// (e.g. a spurious network error while downloading a crate), with an exponential backoff.
+    pub network_retries: u32,
+    /// Only download the dependencies of the skeleton with `cargo fetch`, without building it.
+    pub only_fetch: bool,
+    /// Assume the dependencies were already downloaded (e.g. with `only_fetch`): build offline.
+    pub skip_fetch: bool,
+    /// Print a summary of what would be built, from the unit graph of cargo, instead of building.
+    pub dry_run: bool,
+    /// Do not set `SQLX_OFFLINE=true` for cargo when the recipe contains the offline query data
+    /// of `sqlx`.
+    pub no_sqlx_offline: bool,
+    /// Fail once the dependencies are built if more than this many crates had to be downloaded
+    /// into `$CARGO_HOME`, e.g. because its cache mount is missing.
+    pub require_warm_cache: Option<usize>,
+    /// Once the dependencies are built, remove the `.crate` archives and the unpacked sources of
+    /// the registries from `$CARGO_HOME`. It is skipped, with a warning, if `$CARGO_HOME` looks
+    /// like a mount shared with other builds, unless `force_sweep_cache` is set.
+    pub sweep_cache: bool,
+    /// Sweep the cache even if it looks like a shared mount.
+    pub force_sweep_cache: bool,
+    pub message_format: MessageFormat,
+}
+
+/// The former name of [`CookOptions`].
+pub type CookArgs = CookOptions;
+
+impl Default for CookOptions {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            skeleton_dir: None,
+            profile: OptimisationProfile::Debug,
+            command: CommandArg::Build,
+            default_features: DefaultFeatures::Enabled,
+            features: None,
+            all_features: false,
+            unstable_features: None,
+            target: None,
+            target_dir: None,
+            target_args: TargetArgs::default(),
+            manifest_path: None,
+            package: vec![],
+            workspace: false,
+            offline: false,
+            locked: false,
+            timings: None,
+            no_std: false,
+            build_std: None,
+            bin: None,
+            cargo_path: None,
+            cargo_args: vec![],
+            rustc_wrapper: None,
+            no_build: false,
+            no_clean: false,
+            vendored: None,
+            advisory_db: None,
+            warn_advisories: false,
+            strict_environment: false,
+            sequential_members: false,
+            jobs: None,
+            network_retries: 0,
+            only_fetch: false,
+            skip_fetch: false,
+            dry_run: false,
+            no_sqlx_offline: false,
+            require_warm_cache: None,
+            sweep_cache: false,
+            force_sweep_cache: false,
+            message_format: MessageFormat::Human,
+        }
+    }
+}
+
+/// The directory to compute a recipe from, and how: the arguments of [`prepare`].
+#[derive(Debug, Clone, Default)]
+pub struct WorkspaceOptions {
+    /// The directory `cargo chef prepare` would run from: the root of a workspace, one of its
+    /// members or a standalone package.
+    pub path: PathBuf,
+    pub args: PrepareArgs,
+}
+
+/// Compute the recipe of a workspace, like `cargo chef prepare` (see [`Recipe::prepare`]).
+pub fn prepare(options: WorkspaceOptions) -> Result<Recipe, anyhow::Error> {
+    Recipe::prepare(options.path, options.args)
+}
+
+/// Unpack and build the skeleton of a recipe, like `cargo chef cook` (see [`Recipe::cook`]).
+pub fn cook(recipe: &Recipe, options: CookOptions) -> Result<CookReport, anyhow::Error> {
+    recipe.cook(options)
 }
 
 impl Recipe {
-    pub fn prepare(base_path: PathBuf, member: Option<String>) -> Result<Self, anyhow::Error> {
-        let skeleton = Skeleton::derive(base_path, member)?;
-        Ok(Recipe { skeleton })
+    pub fn prepare(base_path: PathBuf, args: PrepareArgs) -> Result<Self, anyhow::Error> {
+        let (base_path, args) = Self::workspace_root(base_path, args)?;
+        Self::prepare_workspace(base_path, &args)
+    }
+
+    /// Compute one recipe for each member of the workspace, keyed by the name of its package.
+    ///
+    /// Each recipe is scoped to its member (see [`PrepareArgs::members`]): it only depends on
+    /// the manifests of the local crates the member needs and on their dependencies.
+    pub fn prepare_split(
+        base_path: PathBuf,
+        args: PrepareArgs,
+    ) -> Result<BTreeMap<String, Self>, anyhow::Error> {
+        if !args.members.is_empty() || args.member_only {
+            anyhow::bail!("A split recipe cannot be scoped to some of the members.");
+        }
+        let (base_path, args) = Self::workspace_root(base_path, args)?;
+        let workspace = Skeleton::derive(&base_path, &args)?;
+        let mut members = workspace.member_names()?;
+        if !args.split_members.is_empty() {
+            if let Some(unknown) = args
+                .split_members
+                .iter()
+                .find(|member| !members.contains(member))
+            {
+                anyhow::bail!(
+                    "`{}`, one of the members to split the recipes for, is not a member of the \
+                    workspace.",
+                    unknown
+                );
+            }
+            members.retain(|member| args.split_members.contains(member));
+        }
+        members
+            .into_iter()
+            .map(|member| {
+                let args = PrepareArgs {
+                    members: vec![member.clone()],
+                    ..args.clone()
+                };
+                let recipe = Self::prepare_workspace(base_path.clone(), &args)
+                    .with_context(|| format!("Failed to compute the recipe of `{}`.", member))?;
+                Ok((member, recipe))
+            })
+            .collect()
+    }
+
+    /// Locate the root of the workspace to prepare the recipe for and adjust `args`
+    /// accordingly.
+    fn workspace_root(
+        base_path: PathBuf,
+        args: PrepareArgs,
+    ) -> Result<(PathBuf, PrepareArgs), anyhow::Error> {
+        // Like cargo, we look for the root of the workspace when invoked from a member.
+        let manifest_path = match &args.manifest_path {
+            Some(manifest_path) => Some(manifest_path.to_owned()),
+            None => Some(PathBuf::from("Cargo.toml")).filter(|path| base_path.join(path).is_file()),
+        };
+        let (base_path, args) = match manifest_path {
+            Some(manifest_path) => {
+                let (root, package) = find_workspace_root(&base_path.join(&manifest_path))
+                    .with_context(|| {
+                        format!("Failed to locate the workspace of {:?}.", manifest_path)
+                    })?;
+                let is_explicit = args.manifest_path.is_some();
+                if !is_explicit && package.is_some() {
+                    let root_manifest = root.join("Cargo.toml");
+                    let root_manifest = fs_err::canonicalize(&base_path)
+                        .ok()
+                        .and_then(|base_path| pathdiff::diff_paths(&root_manifest, base_path))
+                        .unwrap_or(root_manifest);
+                    eprintln!("Detected the workspace root at {}", root_manifest.display());
+                }
+                let mut members = args.members.clone();
+                if is_explicit || args.member_only {
+                    members.extend(package);
+                }
+                (
+                    root,
+                    PrepareArgs {
+                        members,
+                        manifest_path: None,
+                        ..args
+                    },
+                )
+            }
+            None => (base_path, args),
+        };
+        Ok((base_path, args))
+    }
+
+    fn prepare_workspace(base_path: PathBuf, args: &PrepareArgs) -> Result<Self, anyhow::Error> {
+        let default_cook_args = if args.cook_args.is_empty() {
+            cook_args_from_metadata(&base_path)?
+        } else {
+            args.cook_args.clone()
+        };
+        let environment = Environment::detect(&base_path);
+        let skeleton = Skeleton::derive(base_path, args)?;
+        Ok(Recipe {
+            format_version: RECIPE_FORMAT_VERSION,
+            cargo_chef_version: Some(env!("CARGO_PKG_VERSION").to_owned()),
+            skeleton,
+            default_cook_args,
+            environment,
+            config: args.config.clone(),
+        })
+    }
+
+    /// Deserialize a recipe, checking that its format is supported before looking at the rest
+    /// of its contents.
+    pub fn from_json(serialized: &str) -> Result<Self, anyhow::Error> {
+        #[derive(Deserialize)]
+        struct Header {
+            #[serde(default = "legacy_format_version")]
+            format_version: u32,
+            #[serde(default)]
+            cargo_chef_version: Option<String>,
+        }
+
+        let header: Header = serde_json::from_str(serialized).map_err(|e| {
+            let reason = if e.is_eof() {
+                "the recipe is truncated"
+            } else {
+                "it is not a valid recipe file"
+            };
+            anyhow::Error::from(e).context(format!("Failed to deserialize recipe: {}.", reason))
+        })?;
+        let supported = MIN_RECIPE_FORMAT_VERSION..=RECIPE_FORMAT_VERSION;
+        if !supported.contains(&header.format_version) {
+            let producer = match &header.cargo_chef_version {
+                Some(version) => format!("cargo-chef {}", version),
+                None => "an unknown version of cargo-chef".to_owned(),
+            };
+            let guidance = if header.format_version > RECIPE_FORMAT_VERSION {
+                "The recipe requires a newer cargo-chef: upgrade the one used for `cook` to the version used for `prepare`."
+            } else {
+                "The recipe is too old: re-run `cargo chef prepare` with this version of cargo-chef."
+            };
+            anyhow::bail!(
+                "The recipe was produced by {} (format {}), this binary (cargo-chef {}) supports formats {}..={}.\n{}",
+                producer,
+                header.format_version,
+                env!("CARGO_PKG_VERSION"),
+                MIN_RECIPE_FORMAT_VERSION,
+                RECIPE_FORMAT_VERSION,
+                guidance
+            );
+        }
+        serde_json::from_str(serialized).context("Failed to deserialize recipe.")
+    }
+
+    /// Fail, listing all of them, if the skeleton has violations (see [`Skeleton::violations`]).
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        let violations = self.skeleton.violations();
+        if violations.is_empty() {
+            return Ok(());
+        }
+        anyhow::bail!(
+            "The recipe is invalid, {} violation{} found:\n{}",
+            violations.len(),
+            if violations.len() == 1 {
+                " was"
+            } else {
+                "s were"
+            },
+            violations
+                .iter()
+                .map(|violation| format!("  - {}", violation))
+                .collect::<Vec<_>>()
+                .join("\n")
+        )
+    }
+
+    /// A stable sha256 fingerprint of the recipe, as a hex string, e.g. to key remote caches.
+    ///
+    /// It only depends on the canonical contents of the recipe (and its default cook
+    /// arguments), not on the version of `cargo-chef` that produced it.
+    pub fn fingerprint(&self) -> Result<String, anyhow::Error> {
+        #[derive(Serialize)]
+        struct Fingerprinted<'a> {
+            skeleton: &'a Skeleton,
+            default_cook_args: &'a [String],
+        }
+
+        let skeleton = self.skeleton.canonicalized()?;
+        let serialized = serde_json::to_vec(&Fingerprinted {
+            skeleton: &skeleton,
+            default_cook_args: &self.default_cook_args,
+        })?;
+        Ok(format!("{:x}", Sha256::digest(&serialized)))
+    }
+
+    /// Make the `sqlx` macros read the query data of the recipe instead of connecting to a
+    /// database, unless `SQLX_OFFLINE` is already set (it is then passed down as is): the final
+    /// build must use the same value, or the crates depending on `sqlx` are recompiled.
+    fn set_sqlx_offline(&self, command: &mut Command, args: &CookOptions) {
+        if args.no_sqlx_offline
+            || std::env::var_os("SQLX_OFFLINE").is_some()
+            || !self.skeleton.has_sqlx_offline_data()
+        {
+            return;
+        }
+        log::info!("Setting SQLX_OFFLINE=true: the recipe contains the query data of sqlx.");
+        command.env("SQLX_OFFLINE", "true");
+    }
+
+    /// `args`, selecting the packages to build with `--package` to leave out the ones marked with
+    /// `package.metadata.chef.skip-build`. `None` if all of them are.
+    fn without_skipped_packages(
+        &self,
+        args: CookOptions,
+    ) -> Result<Option<CookOptions>, anyhow::Error> {
+        let skipped = self.skeleton.skipped_packages()?;
+        if skipped.is_empty() || args.bin.is_some() {
+            return Ok(Some(args));
+        }
+        let selected = if args.package.is_empty() {
+            self.skeleton
+                .members_in_dependency_order(args.workspace)
+                .context("Failed to determine the members of the workspace.")?
+        } else {
+            args.package.clone()
+        };
+        let (skipped, package): (Vec<String>, Vec<String>) = selected
+            .into_iter()
+            .partition(|name| skipped.contains(name));
+        if skipped.is_empty() {
+            return Ok(Some(args));
+        }
+        if args.message_format == MessageFormat::Human {
+            for name in &skipped {
+                eprintln!(
+                    "Skipping `{}`: it is marked with `package.metadata.chef.skip-build`.",
+                    name
+                );
+            }
+        }
+        if package.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(CookOptions {
+            package,
+            workspace: false,
+            ..args
+        }))
     }
 
-    pub fn cook(&self, args: CookArgs) -> Result<(), anyhow::Error> {
+    /// Print what cargo would build from the unpacked skeleton, without building it: from the
+    /// unit graph of cargo on nightly, from `cargo tree` otherwise.
+    fn dry_run(
+        &self,
+        args: &CookOptions,
+        base_path: &Path,
+        written: Vec<PathBuf>,
+    ) -> Result<CookReport, anyhow::Error> {
+        let pinned_toolchain = self.skeleton.rust_toolchain_file.is_some();
+        let is_nightly = cargo_version(args, pinned_toolchain, base_path)
+            .is_some_and(|version| version.contains("-nightly") || version.contains("-dev"));
+        let mut command = if is_nightly {
+            let mut command = cargo_command(args, pinned_toolchain)?;
+            command
+                .arg("--unit-graph")
+                .arg("-Z")
+                .arg("unstable-options");
+            command
+        } else {
+            cargo_tree_command(args, pinned_toolchain)?
+        };
+        command.current_dir(base_path);
+        let command_line = command_line(&command);
+        log::info!("Running {:?}", command);
+        let output = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .output()
+            .with_context(|| failed_to_run(&command))?;
+        if output.status.success() {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let plan = if is_nightly {
+                BuildPlan::from_unit_graph(&stdout)?
+            } else {
+                BuildPlan::from_tree(&stdout)?
+            };
+            print!("{}", plan);
+        }
+        Ok(CookReport {
+            written,
+            command: Some(command_line),
+            exit_status: Some(output.status),
+            member: None,
+        })
+    }
+
+    /// Report what cargo downloaded into the caches of `$CARGO_HOME` since `cache` was
+    /// snapshotted, and fail if it is more crates than `args.require_warm_cache` allows.
+    fn check_downloads(
+        &self,
+        args: &CookOptions,
+        cache: Option<&(CacheLocations, CacheSnapshot)>,
+    ) -> Result<(), anyhow::Error> {
+        let (locations, before) = match cache {
+            Some(cache) => cache,
+            None => return Ok(()),
+        };
+        let registry_packages = self
+            .skeleton
+            .registry_packages()
+            .context("Failed to read the lockfile of the recipe.")?;
+        let downloads = locations
+            .snapshot()
+            .downloads_since(before, &registry_packages);
+        match args.message_format {
+            MessageFormat::Human => eprintln!(
+                "Downloaded {} crate{} ({} of the lockfile already cached) and cloned {} git \
+                repositor{}.",
+                downloads.crates,
+                if downloads.crates == 1 { "" } else { "s" },
+                downloads.cached_crates,
+                downloads.git_repositories,
+                if downloads.git_repositories == 1 {
+                    "y"
+                } else {
+                    "ies"
+                }
+            ),
+            MessageFormat::Json => emit(
+                args.message_format,
+                CookEvent::Downloads {
+                    crates: downloads.crates,
+                    cached_crates: downloads.cached_crates,
+                    git_repositories: downloads.git_repositories,
+                },
+            ),
+        }
+        match args.require_warm_cache {
+            Some(max_downloads) if downloads.crates > max_downloads => anyhow::bail!(
+                "`--require-warm-cache` allows {} downloaded crates at most, but cargo downloaded \
+                {}: is the cache of {:?} mounted in this stage?",
+                max_downloads,
+                downloads.crates,
+                locations.cargo_home
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// The differences between the environment `cook` runs in, from `directory` and with the chef
+    /// `config` found there, and the one the recipe was prepared in.
+    fn environment_mismatches(&self, directory: &Path, config: Option<&ChefConfig>) -> Vec<String> {
+        let mut mismatches = vec![];
+        if self.skeleton.lock_file.is_none() {
+            mismatches.push(
+                "the recipe has no Cargo.lock: the versions of the dependencies are resolved \
+                again, and may differ from the ones of the build."
+                    .to_owned(),
+            );
+        }
+        if let Some(version) = self
+            .cargo_chef_version
+            .as_deref()
+            .filter(|version| *version != env!("CARGO_PKG_VERSION"))
+        {
+            mismatches.push(format!(
+                "the recipe was prepared by cargo-chef {}, but it is cooked by cargo-chef {}.",
+                version,
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+        let current = Environment::detect(directory);
+        if let (Some(prepared), Some(current)) = (&self.environment, &current) {
+            mismatches.extend(prepared.mismatches(current));
+        }
+        // Without a configuration, e.g. because only the recipe was copied, there is nothing to
+        // compare.
+        for (key, prepared, current) in config
+            .map(|config| self.config.differences(config))
+            .unwrap_or_default()
+        {
+            mismatches.push(match (prepared, current) {
+                (Some(prepared), Some(current)) => format!(
+                    "the chef configuration sets `{}` to {}, but the recipe was prepared with {}.",
+                    key, current, prepared
+                ),
+                (None, Some(current)) => format!(
+                    "the chef configuration sets `{}` to {}, but the recipe was prepared without \
+                    it.",
+                    key, current
+                ),
+                (Some(prepared), None) => format!(
+                    "the recipe was prepared with `{}` set to {} by the chef configuration, which \
+                    does not set it anymore.",
+                    key, prepared
+                ),
+                (None, None) => unreachable!("Only the keys that are set differ"),
+            });
+        }
+        mismatches
+    }
+
+    /// Unpack the skeleton and build it with cargo.
+    ///
+    /// A failing cargo invocation is not an error: it is recorded in the returned report. The
+    /// artifacts of the workspace crates are only cleaned up if cargo succeeded.
+    pub fn cook(&self, args: CookOptions) -> Result<CookReport, anyhow::Error> {
         let current_directory = std::env::current_dir()?;
-        self.skeleton
-            .build_minimum_project(&current_directory, args.no_std)?;
-        build_dependencies(&args);
-        self.skeleton
-            .remove_compiled_dummies(
-                current_directory,
-                args.profile,
-                args.target,
-                args.target_dir,
+        let current_directory = match &args.directory {
+            Some(directory) => current_directory.join(directory),
+            None => current_directory,
+        };
+        if let Some(cargo_path) = &args.cargo_path {
+            if find_executable(cargo_path).is_none() {
+                anyhow::bail!(
+                    "The cargo executable {:?} could not be found. Check `--cargo-path`.",
+                    cargo_path
+                );
+            }
+        }
+        if matches!(args.command, CommandArg::Zigbuild) && !args.no_build {
+            let cargo_path = match &args.cargo_path {
+                Some(cargo_path) => cargo_path.clone(),
+                None => default_cargo_path()?,
+            };
+            ensure_zigbuild_is_installed(&cargo_path)?;
+        }
+        if let (Some(rustc_wrapper), false) = (&args.rustc_wrapper, args.no_build) {
+            if find_executable(rustc_wrapper).is_none() {
+                anyhow::bail!(
+                    "The rustc wrapper {:?} could not be found. Check `--rustc-wrapper` or the \
+                    `RUSTC_WRAPPER` environment variable.",
+                    rustc_wrapper
+                );
+            }
+        }
+        if self.skeleton.manifests.is_empty() {
+            anyhow::bail!(
+                "The recipe does not contain any manifest: was it prepared in the directory of \
+                the project?"
+            );
+        }
+        self.validate()?;
+        // Before the skeleton overwrites the root manifest.
+        let config = ChefConfig::load(&current_directory, None).unwrap_or_else(|e| {
+            eprintln!(
+                "warning: the chef configuration cannot be read, it is not compared with the one \
+                of the recipe: {:#}",
+                e
+            );
+            None
+        });
+        if let Some(bin) = &args.bin {
+            let binaries = self.skeleton.binary_names()?;
+            if binaries.is_empty() {
+                anyhow::bail!(
+                    "`--bin {}` was specified, but the recipe does not contain any binary.",
+                    bin
+                );
+            }
+            if !binaries.contains(bin) {
+                anyhow::bail!(
+                    "`--bin {}` does not match any binary of the recipe.\nAvailable binaries: {}.",
+                    bin,
+                    binaries.join(", ")
+                );
+            }
+        }
+        if args.only_fetch && args.skip_fetch {
+            anyhow::bail!("`--only-fetch` and `--skip-fetch` cannot be used together.");
+        }
+        if args.only_fetch && self.skeleton.lock_file.is_none() {
+            anyhow::bail!(
+                "`--only-fetch` was specified, but the recipe does not contain a Cargo.lock file: \
+                there is nothing to fetch the dependencies for."
+            );
+        }
+        if args.locked && self.skeleton.lock_file.is_none() {
+            anyhow::bail!(
+                "`--locked` was specified, but the recipe does not contain a Cargo.lock file."
+            );
+        }
+        if let Some(advisory_db) = &args.advisory_db {
+            let advisory_db = current_directory.join(advisory_db);
+            let vulnerabilities = AdvisoryDatabase::open(&advisory_db)
+                .with_context(|| {
+                    format!("Failed to read the advisory database at {:?}.", advisory_db)
+                })?
+                .check(self)?;
+            if !vulnerabilities.is_empty() {
+                eprintln!(
+                    "Found {} {} for the dependencies of the recipe:\n{}",
+                    vulnerabilities.len(),
+                    if vulnerabilities.len() == 1 {
+                        "advisory"
+                    } else {
+                        "advisories"
+                    },
+                    VulnerabilityTable(&vulnerabilities)
+                );
+                if !args.warn_advisories {
+                    anyhow::bail!(
+                        "Refusing to cook dependencies with security advisories. Upgrade them, \
+                        or pass `--warn-advisories` to only report them."
+                    );
+                }
+            }
+        }
+        let vendored = args
+            .vendored
+            .as_ref()
+            .map(|vendored| current_directory.join(vendored));
+        if let (Some(vendored), false) = (&vendored, args.no_build) {
+            if !vendored.is_dir() {
+                anyhow::bail!(
+                    "The directory of vendored sources {:?} does not exist.",
+                    vendored
+                );
+            }
+            let missing = self
+                .skeleton
+                .missing_vendored_packages(vendored)
+                .context("Failed to read the vendored sources.")?;
+            if !missing.is_empty() {
+                anyhow::bail!(
+                    "{} {} missing from the vendored sources in {:?}:\n{}\n\
+                    Re-run `cargo vendor` against the same Cargo.lock.",
+                    missing.len(),
+                    if missing.len() == 1 {
+                        "package of Cargo.lock is"
+                    } else {
+                        "packages of Cargo.lock are"
+                    },
+                    vendored,
+                    missing
+                        .iter()
+                        .map(|package| format!("  - {}", package))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                );
+            }
+        }
+        // `--manifest-path` either points to one of the manifests of the recipe, unpacked in the
+        // current directory, or to where the root manifest of the recipe should be unpacked.
+        let base_path = match (&args.skeleton_dir, &args.manifest_path) {
+            (Some(skeleton_dir), manifest_path) => {
+                if let Some(manifest_path) = manifest_path
+                    .as_ref()
+                    .filter(|manifest_path| !self.skeleton.contains_manifest(manifest_path))
+                {
+                    anyhow::bail!(
+                        "With `--skeleton-dir`, `--manifest-path` must be one of the manifests of \
+                        the recipe: {:?} is not.",
+                        manifest_path
+                    );
+                }
+                current_directory.join(skeleton_dir)
+            }
+            (None, Some(manifest_path)) if !self.skeleton.contains_manifest(manifest_path) => {
+                let manifest_path = current_directory.join(manifest_path);
+                manifest_path
+                    .parent()
+                    .context("The manifest path has no parent directory.")?
+                    .to_owned()
+            }
+            _ => current_directory.clone(),
+        };
+        let written = self
+            .skeleton
+            .build_minimum_project(&base_path, args.no_std)?;
+        if args.no_build && args.message_format == MessageFormat::Human {
+            for path in &written {
+                println!("{}", path.display());
+            }
+        }
+        emit(
+            args.message_format,
+            CookEvent::SkeletonUnpacked { files: &written },
+        );
+        if args.no_build {
+            return Ok(CookReport {
+                written,
+                command: None,
+                exit_status: None,
+                member: None,
+            });
+        }
+        if args.build_std.is_some() {
+            ensure_build_std_is_supported(
+                &args,
+                self.skeleton.rust_toolchain_file.is_some(),
+                &base_path,
+            )?;
+        }
+        // Where cargo, running from `base_path`, downloads the dependencies to, and what is
+        // already there.
+        let cache = match (CacheLocations::resolve(&base_path), args.dry_run) {
+            (_, true) => None,
+            (Some(locations), false) => {
+                report_cache_locations(&locations, args.message_format);
+                let snapshot = locations.snapshot();
+                Some((locations, snapshot))
+            }
+            (None, false) if args.require_warm_cache.is_some() => anyhow::bail!(
+                "`--require-warm-cache` was specified, but neither `CARGO_HOME` nor `HOME` is \
+                set to locate the cache."
+            ),
+            (None, false) => None,
+        };
+        // Cargo and the clean up of the dummy artifacts must agree on the target directory.
+        // Cargo runs where the skeleton was unpacked, to pick up its configuration, but a
+        // separate skeleton directory shares the target directory of the project.
+        let target_dir = args
+            .target_dir
+            .as_ref()
+            .map(|target_dir| current_directory.join(target_dir));
+        let target_dir_root = match &args.skeleton_dir {
+            Some(_) => &current_directory,
+            None => &base_path,
+        };
+        let target_dir = resolve_target_dir(target_dir_root, target_dir)
+            .context("Failed to determine the target directory.")?;
+        let manifest_path = match &args.skeleton_dir {
+            Some(_) => Some(
+                base_path.join(
+                    args.manifest_path
+                        .as_deref()
+                        .unwrap_or_else(|| Path::new("Cargo.toml")),
+                ),
+            ),
+            None => args
+                .manifest_path
+                .as_ref()
+                .map(|manifest_path| current_directory.join(manifest_path)),
+        };
+        // The target specifications of the recipe were unpacked with the skeleton.
+        let target = args.target.map(|targets| {
+            targets
+                .into_iter()
+                .map(|target| match self.skeleton.target_spec(&target) {
+                    Some(relative_path) => base_path.join(relative_path).display().to_string(),
+                    None => target,
+                })
+                .collect()
+        });
+        let args = CookOptions {
+            target,
+            target_dir: Some(target_dir),
+            manifest_path,
+            offline: args.offline || args.skip_fetch || vendored.is_some(),
+            vendored,
+            ..args
+        };
+        if args.only_fetch {
+            let mut command =
+                cargo_fetch_command(&args, self.skeleton.rust_toolchain_file.is_some())?;
+            command.current_dir(&base_path);
+            let command_line = command_line(&command);
+            emit(
+                args.message_format,
+                CookEvent::CargoStarted {
+                    command: &command_line,
+                },
+            );
+            let status = execute_command_with_retries(
+                &mut command,
+                args.network_retries,
+                args.message_format,
+            )?;
+            emit(
+                args.message_format,
+                CookEvent::CargoFinished {
+                    command: &command_line,
+                    success: status.success(),
+                    exit_code: status.code(),
+                },
+            );
+            if status.success() {
+                self.check_downloads(&args, cache.as_ref())?;
+            }
+            emit(
+                args.message_format,
+                CookEvent::CookFinished {
+                    command: &command_line,
+                    success: status.success(),
+                    exit_code: status.code(),
+                },
+            );
+            return Ok(CookReport {
+                written,
+                command: Some(command_line),
+                exit_status: Some(status),
+                member: None,
+            });
+        }
+        let args = match self.without_skipped_packages(args)? {
+            Some(args) => args,
+            None => {
+                eprintln!(
+                    "All the packages to build are marked with `package.metadata.chef.skip-build`: \
+                    there is nothing to cook."
+                );
+                return Ok(CookReport {
+                    written,
+                    command: None,
+                    exit_status: None,
+                    member: None,
+                });
+            }
+        };
+        if args.dry_run {
+            return self.dry_run(&args, &base_path, written);
+        }
+        let mismatches = self.environment_mismatches(&base_path, config.as_ref());
+        if !mismatches.is_empty() {
+            let mismatches = mismatches
+                .iter()
+                .map(|mismatch| format!("  - {}", mismatch))
+                .collect::<Vec<_>>()
+                .join("\n");
+            if args.strict_environment {
+                anyhow::bail!(
+                    "The environment differs from the one the recipe was prepared for:\n{}",
+                    mismatches
+                );
+            }
+            eprintln!(
+                "warning: the environment differs from the one the recipe was prepared for, \
+                the dependencies cooked now may not be reused by the build:\n{}",
+                mismatches
+            );
+        }
+        if args.timings.is_some() {
+            ensure_timings_are_supported(
+                &args,
+                self.skeleton.rust_toolchain_file.is_some(),
+                &base_path,
+            )?;
+        }
+        // With `--sequential-members`, cargo is invoked once per member, the crates they depend on
+        // first, to only hold the dependency graph of one member in memory at a time.
+        let invocations: Vec<(Option<String>, CookOptions)> = if args.sequential_members {
+            let members: Vec<_> = self
+                .skeleton
+                .members_in_dependency_order(args.workspace || !args.package.is_empty())
+                .context("Failed to determine the members of the workspace.")?
+                .into_iter()
+                .filter(|member| args.package.is_empty() || args.package.contains(member))
+                .collect();
+            if members.is_empty() {
+                anyhow::bail!(
+                    "`--sequential-members` was specified, but there is no member to cook."
+                );
+            }
+            members
+                .into_iter()
+                .map(|member| {
+                    let options = CookOptions {
+                        package: vec![member.clone()],
+                        workspace: false,
+                        ..args.clone()
+                    };
+                    (Some(member), options)
+                })
+                .collect()
+        } else {
+            vec![(None, args.clone())]
+        };
+        let mut last_command_line = vec![];
+        let mut last_exit_status = None;
+        for (member, options) in &invocations {
+            if let (Some(member), MessageFormat::Human) = (member, args.message_format) {
+                eprintln!("Cooking the dependencies of `{}`", member);
+            }
+            let mut command = cargo_command(options, self.skeleton.rust_toolchain_file.is_some())?;
+            command.current_dir(&base_path);
+            self.set_sqlx_offline(&mut command, options);
+            let command_line = command_line(&command);
+            emit(
+                args.message_format,
+                CookEvent::CargoStarted {
+                    command: &command_line,
+                },
+            );
+            let started = std::time::SystemTime::now();
+            let status = execute_command_with_retries(
+                &mut command,
+                args.network_retries,
+                args.message_format,
+            )?;
+            let html_timings = args.timings.as_ref().is_some_and(|formats| {
+                formats.is_empty() || formats.iter().any(|format| format == "html")
+            });
+            if let (true, Some(target_dir)) = (html_timings, &args.target_dir) {
+                copy_timings_report(target_dir, member.as_deref(), started)
+                    .context("Failed to copy the timings report.")?;
+            }
+            let finished = CookEvent::CargoFinished {
+                command: &command_line,
+                success: status.success(),
+                exit_code: status.code(),
+            };
+            emit(args.message_format, finished);
+            if !status.success() {
+                emit(
+                    args.message_format,
+                    CookEvent::CookFinished {
+                        command: &command_line,
+                        success: false,
+                        exit_code: status.code(),
+                    },
+                );
+                return Ok(CookReport {
+                    written,
+                    command: Some(command_line),
+                    exit_status: Some(status),
+                    member: member.clone(),
+                });
+            }
+            last_command_line = command_line;
+            last_exit_status = Some(status);
+        }
+        let (command_line, exit_status) = (
+            last_command_line,
+            last_exit_status.expect("cargo is invoked at least once"),
+        );
+        if let Some(rustc_wrapper) = &args.rustc_wrapper {
+            show_cache_statistics(rustc_wrapper, args.message_format);
+        }
+        let downloads = self.check_downloads(&args, cache.as_ref());
+        if !args.no_clean {
+            self.skeleton
+                .remove_compiled_dummies(&base_path, args.profile, args.target, args.target_dir)
+                .context("Failed to clean up dummy compilation artifacts.")?;
+            emit(args.message_format, CookEvent::CleanupFinished);
+        }
+        if args.skeleton_dir.is_some() {
+            remove_skeleton(&base_path, &written)
+                .context("Failed to remove the skeleton directory.")?;
+        }
+        // Only once the target directory is clean.
+        downloads?;
+        if args.sweep_cache {
+            sweep_cache(
+                cache.as_ref().map(|(locations, _)| locations),
+                args.force_sweep_cache,
+                args.message_format,
             )
-            .context("Failed to clean up dummy compilation artifacts.")?;
-        Ok(())
+            .context("Failed to sweep the registry cache.")?;
+        }
+        emit(
+            args.message_format,
+            CookEvent::CookFinished {
+                command: &command_line,
+                success: true,
+                exit_code: exit_status.code(),
+            },
+        );
+        Ok(CookReport {
+            written,
+            command: Some(command_line),
+            exit_status: Some(exit_status),
+            member: None,
+        })
     }
 }
 
+/// Remove the archives and the unpacked sources of the registries, unless the cache may be shared
+/// with other builds.
+fn sweep_cache(
+    locations: Option<&CacheLocations>,
+    force: bool,
+    message_format: MessageFormat,
+) -> Result<(), anyhow::Error> {
+    let locations = match locations {
+        Some(locations) => locations,
+        None => {
+            eprintln!(
+                "warning: not sweeping the registry cache: neither `CARGO_HOME` nor `HOME` is set \
+                to locate it."
+            );
+            return Ok(());
+        }
+    };
+    if let (Some(mount), false) = (locations.shared_mount(), force) {
+        eprintln!(
+            "warning: not sweeping the registry cache: {} looks like a mount point, which may be \
+            shared with other builds. Pass `--force-sweep-cache` to sweep it anyway.",
+            mount.display()
+        );
+        return Ok(());
+    }
+    let sweep = locations.sweep()?;
+    match message_format {
+        MessageFormat::Human => eprintln!(
+            "Swept the registry cache of {}: removed {} archives and {} unpacked sources, \
+            reclaiming {} bytes.",
+            locations.cargo_home.display(),
+            sweep.archives,
+            sweep.sources,
+            sweep.reclaimed_bytes
+        ),
+        MessageFormat::Json => emit(
+            message_format,
+            CookEvent::CacheSwept {
+                archives: sweep.archives,
+                sources: sweep.sources,
+                reclaimed_bytes: sweep.reclaimed_bytes,
+            },
+        ),
+    }
+    Ok(())
+}
+
+/// Print where cargo downloads the dependencies to, e.g. to check that a cache mount is used.
+fn report_cache_locations(locations: &CacheLocations, message_format: MessageFormat) {
+    match message_format {
+        MessageFormat::Human => eprintln!(
+            "CARGO_HOME is {}: registry cache in {}, git database in {}.",
+            locations.cargo_home.display(),
+            locations.registry_cache.display(),
+            locations.git_db.display()
+        ),
+        MessageFormat::Json => emit(
+            message_format,
+            CookEvent::CacheLocations {
+                cargo_home: &locations.cargo_home,
+                registry_cache: &locations.registry_cache,
+                git_db: &locations.git_db,
+            },
+        ),
+    }
+}
+
+/// What [`Recipe::cook`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CookReport {
+    /// The files of the skeleton written to disk, relative to where it was unpacked.
+    pub written: Vec<PathBuf>,
+    /// The (last) cargo invocation, program first. `None` if the skeleton was not built.
+    pub command: Option<Vec<String>>,
+    /// How cargo exited. `None` if the skeleton was not built.
+    pub exit_status: Option<ExitStatus>,
+    /// The member whose cargo invocation failed, with `sequential_members`.
+    pub member: Option<String>,
+}
+
+impl CookReport {
+    /// `true` unless cargo was invoked and failed.
+    pub fn success(&self) -> bool {
+        self.exit_status.is_none_or(|status| status.success())
+    }
+}
+
+/// How `cook` reports its progress.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MessageFormat {
+    /// Free-form messages, for humans.
+    Human,
+    /// One JSON object per line on stderr for each step of `cook`, while cargo is invoked with
+    /// `--message-format json-render-diagnostics`.
+    Json,
+}
+
+/// The steps of `cook`, reported with `--message-format json`.
+/// Like cargo's messages, they are tagged with a `reason`.
+#[derive(Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+enum CookEvent<'a> {
+    SkeletonUnpacked {
+        files: &'a [PathBuf],
+    },
+    CargoStarted {
+        command: &'a [String],
+    },
+    CargoFinished {
+        command: &'a [String],
+        success: bool,
+        exit_code: Option<i32>,
+    },
+    CacheStatistics {
+        rustc_wrapper: &'a Path,
+        output: String,
+    },
+    CargoRetrying {
+        command: &'a [String],
+        attempt: u32,
+        retries: u32,
+        delay_seconds: u64,
+    },
+    CacheLocations {
+        cargo_home: &'a Path,
+        registry_cache: &'a Path,
+        git_db: &'a Path,
+    },
+    Downloads {
+        crates: usize,
+        cached_crates: usize,
+        git_repositories: usize,
+    },
+    CleanupFinished,
+    CacheSwept {
+        archives: usize,
+        sources: usize,
+        reclaimed_bytes: u64,
+    },
+    CookFinished {
+        command: &'a [String],
+        success: bool,
+        exit_code: Option<i32>,
+    },
+}
+
+fn emit(message_format: MessageFormat, event: CookEvent) {
+    if message_format == MessageFormat::Json {
+        let event = serde_json::to_string(&event).expect("Events can always be serialized");
+        eprintln!("{}", event);
+    }
+}
+
+/// The program and the arguments of `command`.
+fn command_line(command: &Command) -> Vec<String> {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect()
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum OptimisationProfile {
     Release,
@@ -78,12 +1345,15 @@ pub enum DefaultFeatures {
     Disabled,
 }
 
-fn build_dependencies(args: &CookArgs) {
-    let CookArgs {
+fn cargo_command(args: &CookOptions, pinned_toolchain: bool) -> Result<Command, anyhow::Error> {
+    let CookOptions {
+        directory: _directory,
+        skeleton_dir: _skeleton_dir,
         profile,
         command: command_arg,
         default_features,
         features,
+        all_features,
         unstable_features,
         target,
         target_dir,
@@ -92,12 +1362,36 @@ fn build_dependencies(args: &CookArgs) {
         package,
         workspace,
         offline,
+        locked,
         timings,
         bin,
         no_std: _no_std,
+        build_std,
+        cargo_path,
+        cargo_args,
+        rustc_wrapper,
+        no_build: _no_build,
+        no_clean: _no_clean,
+        vendored,
+        advisory_db: _advisory_db,
+        warn_advisories: _warn_advisories,
+        strict_environment: _strict_environment,
+        sequential_members: _sequential_members,
+        jobs,
+        network_retries: _network_retries,
+        only_fetch: _only_fetch,
+        skip_fetch: _skip_fetch,
+        dry_run: _dry_run,
+        no_sqlx_offline: _no_sqlx_offline,
+        require_warm_cache: _require_warm_cache,
+        sweep_cache: _sweep_cache,
+        force_sweep_cache: _force_sweep_cache,
+        message_format,
     } = args;
-    let cargo_path = std::env::var("CARGO").expect("The `CARGO` environment variable was not set. This is unexpected: it should always be provided by `cargo` when invoking a custom sub-command, allowing `cargo-chef` to correctly detect which toolchain should be used. Please file a bug.");
-    let mut command = Command::new(cargo_path);
+    let mut command = cargo(cargo_path.as_deref(), pinned_toolchain)?;
+    if let Some(rustc_wrapper) = rustc_wrapper {
+        command.env("RUSTC_WRAPPER", rustc_wrapper);
+    }
     let command_with_args = match command_arg {
         CommandArg::Build => command.arg("build"),
         CommandArg::Check => command.arg("check"),
@@ -116,11 +1410,17 @@ fn build_dependencies(args: &CookArgs) {
         let feature_flag = features.iter().cloned().collect::<Vec<String>>().join(",");
         command_with_args.arg("--features").arg(feature_flag);
     }
+    if *all_features {
+        command_with_args.arg("--all-features");
+    }
     if let Some(unstable_features) = unstable_features {
         for unstable_feature in unstable_features.iter().cloned() {
             command_with_args.arg("-Z").arg(unstable_feature);
         }
     }
+    if let Some(build_std) = build_std {
+        command_with_args.arg("-Z").arg(build_std_flag(build_std));
+    }
     if let Some(target) = target {
         for target in target {
             command_with_args.arg("--target").arg(target);
@@ -144,7 +1444,7 @@ fn build_dependencies(args: &CookArgs) {
     if let Some(manifest_path) = manifest_path {
         command_with_args.arg("--manifest-path").arg(manifest_path);
     }
-    if let Some(package) = package {
+    for package in package {
         command_with_args.arg("--package").arg(package);
     }
     if let Some(binary_target) = bin {
@@ -153,27 +1453,488 @@ fn build_dependencies(args: &CookArgs) {
     if *workspace {
         command_with_args.arg("--workspace");
     }
+    if let Some(vendored) = vendored {
+        command_with_args.args(vendored_source_config(vendored));
+    }
     if *offline {
         command_with_args.arg("--offline");
     }
-    if *timings {
-        command_with_args.arg("--timings");
+    if *locked {
+        command_with_args.arg("--locked");
+    }
+    if let Some(formats) = timings {
+        // Recent versions of cargo only produce HTML reports and reject `--timings=html`.
+        if formats.iter().all(|format| format == "html") {
+            command_with_args.arg("--timings");
+        } else {
+            command_with_args.arg(format!("--timings={}", formats.join(",")));
+        }
+    }
+    if let Some(jobs) = jobs {
+        command_with_args.arg("--jobs").arg(jobs.to_string());
+    }
+    if message_format == &MessageFormat::Json {
+        command_with_args
+            .arg("--message-format")
+            .arg("json-render-diagnostics");
     }
-    execute_command(command_with_args);
+    command_with_args.args(cargo_args);
+    Ok(command)
 }
 
-fn execute_command(command: &mut Command) {
-    let mut child = command
-        .envs(std::env::vars())
-        .spawn()
-        .expect("Failed to execute process");
+/// The cargo executable to invoke, without arguments.
+fn cargo(cargo_path: Option<&Path>, pinned_toolchain: bool) -> Result<Command, anyhow::Error> {
+    // `rustup` resolved the toolchain before the toolchain file in the recipe was restored, and it
+    // passes it down via `RUSTUP_TOOLCHAIN`, which takes precedence over toolchain files.
+    // Unless a toolchain was explicitly requested (e.g. `cargo +nightly chef cook`), we go back
+    // through the `rustup` proxy to let it pick the pinned toolchain.
+    let use_pinned_toolchain = pinned_toolchain
+        && matches!(
+            std::env::var("RUSTUP_TOOLCHAIN_SOURCE").as_deref(),
+            Ok(source) if source != "cli" && source != "env"
+        );
+    Ok(match cargo_path {
+        // An explicitly chosen executable always wins.
+        Some(cargo_path) => Command::new(cargo_path),
+        None if use_pinned_toolchain => {
+            let mut command = Command::new("cargo");
+            command.env_remove("RUSTUP_TOOLCHAIN");
+            command
+        }
+        None => Command::new(default_cargo_path()?),
+    })
+}
+
+/// The output of `cargo -V` in `directory`, e.g. `cargo 1.79.0 (ffa9cf99a 2024-06-03)`.
+fn cargo_version(args: &CookOptions, pinned_toolchain: bool, directory: &Path) -> Option<String> {
+    let output = cargo(args.cargo_path.as_deref(), pinned_toolchain)
+        .ok()?
+        .arg("-V")
+        .current_dir(directory)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
 
-    let exit_status = child.wait().expect("Failed to run command");
+/// `build-std=<crates>`, or `build-std` to build all of them.
+fn build_std_flag(crates: &[String]) -> String {
+    if crates.is_empty() {
+        "build-std".to_owned()
+    } else {
+        format!("build-std={}", crates.join(","))
+    }
+}
+
+/// Fail early if the cargo of `directory` is not a nightly one, which `-Z build-std` requires. An
+/// unknown version is given the benefit of the doubt.
+fn ensure_build_std_is_supported(
+    args: &CookOptions,
+    pinned_toolchain: bool,
+    directory: &Path,
+) -> Result<(), anyhow::Error> {
+    let version = match cargo_version(args, pinned_toolchain, directory) {
+        Some(version) => version,
+        None => return Ok(()),
+    };
+    if !(version.contains("-nightly") || version.contains("-dev")) {
+        anyhow::bail!(
+            "`--build-std` requires a nightly toolchain, but the toolchain provides {}. Pin one \
+            in `rust-toolchain.toml` or run `cargo +nightly chef cook`.",
+            version
+        );
+    }
+    Ok(())
+}
+
+/// The oldest version of cargo with a stable `--timings`.
+const MIN_TIMINGS_CARGO_VERSION: (u64, u64) = (1, 60);
 
-    if !exit_status.success() {
-        match exit_status.code() {
-            Some(code) => panic!("Exited with status code: {}", code),
-            None => panic!("Process terminated by signal"),
+/// Fail early if the cargo of `directory` does not support `--timings`. An unknown version is
+/// given the benefit of the doubt.
+fn ensure_timings_are_supported(
+    args: &CookOptions,
+    pinned_toolchain: bool,
+    directory: &Path,
+) -> Result<(), anyhow::Error> {
+    let version = match cargo_version(args, pinned_toolchain, directory) {
+        Some(version) => version,
+        None => return Ok(()),
+    };
+    let minor = version
+        .split_whitespace()
+        .nth(1)
+        .and_then(|version| semver::Version::parse(version).ok())
+        .map(|version| (version.major, version.minor));
+    if minor.is_some_and(|minor| minor < MIN_TIMINGS_CARGO_VERSION) {
+        anyhow::bail!(
+            "`--timings` requires cargo {}.{} or newer, but the toolchain provides {}.",
+            MIN_TIMINGS_CARGO_VERSION.0,
+            MIN_TIMINGS_CARGO_VERSION.1,
+            version
+        );
+    }
+    Ok(())
+}
+
+/// Copy the HTML report of `--timings` (cargo names them after the time of the build) to a
+/// predictable path in the target directory, e.g. to extract it from an image. A report older
+/// than `since` was left by a previous build.
+fn copy_timings_report(
+    target_dir: &Path,
+    member: Option<&str>,
+    since: std::time::SystemTime,
+) -> Result<(), anyhow::Error> {
+    let directory = target_dir.join("cargo-timings");
+    let report = directory.join("cargo-timing.html");
+    let is_fresh = fs_err::metadata(&report)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified >= since);
+    if !is_fresh {
+        return Ok(());
+    }
+    let destination = directory.join(match member {
+        Some(member) => format!("chef-cook-timings-{}.html", member),
+        None => "chef-cook-timings.html".to_owned(),
+    });
+    fs_err::copy(&report, &destination)?;
+    eprintln!("Timings report: {}", destination.display());
+    Ok(())
+}
+
+/// `cargo fetch` for the skeleton: it only depends on the lockfile, the targets and where the
+/// crates are downloaded from.
+fn cargo_fetch_command(
+    args: &CookOptions,
+    pinned_toolchain: bool,
+) -> Result<Command, anyhow::Error> {
+    let mut command = cargo(args.cargo_path.as_deref(), pinned_toolchain)?;
+    command.arg("fetch").arg("--locked");
+    if let Some(unstable_features) = &args.unstable_features {
+        for unstable_feature in unstable_features {
+            command.arg("-Z").arg(unstable_feature);
+        }
+    }
+    // The dependencies of the standard library have to be downloaded too.
+    if let Some(build_std) = &args.build_std {
+        command.arg("-Z").arg(build_std_flag(build_std));
+    }
+    for target in args.target.iter().flatten() {
+        command.arg("--target").arg(target);
+    }
+    if let Some(manifest_path) = &args.manifest_path {
+        command.arg("--manifest-path").arg(manifest_path);
+    }
+    if let Some(vendored) = &args.vendored {
+        command.args(vendored_source_config(vendored));
+    }
+    if args.offline {
+        command.arg("--offline");
+    }
+    command.args(&args.cargo_args);
+    Ok(command)
+}
+
+/// `cargo tree` for the skeleton, listing the crates `cargo build` would compile with `args` and
+/// their features, one per line: `<depth><name> v<version>|<features>`.
+fn cargo_tree_command(
+    args: &CookOptions,
+    pinned_toolchain: bool,
+) -> Result<Command, anyhow::Error> {
+    let mut command = cargo(args.cargo_path.as_deref(), pinned_toolchain)?;
+    command
+        .arg("tree")
+        .arg("--edges")
+        .arg("normal,build")
+        .arg("--prefix")
+        .arg("depth")
+        .arg("--format")
+        .arg("{p}|{f}");
+    if args.default_features == DefaultFeatures::Disabled {
+        command.arg("--no-default-features");
+    }
+    if let Some(features) = &args.features {
+        let features: Vec<&str> = features.iter().map(|feature| feature.as_str()).collect();
+        command.arg("--features").arg(features.join(","));
+    }
+    if args.all_features {
+        command.arg("--all-features");
+    }
+    for unstable_feature in args.unstable_features.iter().flatten() {
+        command.arg("-Z").arg(unstable_feature);
+    }
+    for target in args.target.iter().flatten() {
+        command.arg("--target").arg(target);
+    }
+    if let Some(manifest_path) = &args.manifest_path {
+        command.arg("--manifest-path").arg(manifest_path);
+    }
+    for package in &args.package {
+        command.arg("--package").arg(package);
+    }
+    if args.workspace {
+        command.arg("--workspace");
+    }
+    if let Some(vendored) = &args.vendored {
+        command.args(vendored_source_config(vendored));
+    }
+    if args.offline {
+        command.arg("--offline");
+    }
+    if args.locked {
+        command.arg("--locked");
+    }
+    Ok(command)
+}
+
+/// The `--config` arguments replacing crates.io with the `vendored` directory, as printed by
+/// `cargo vendor`.
+fn vendored_source_config(vendored: &Path) -> [String; 4] {
+    let directory = toml::Value::String(vendored.to_string_lossy().into_owned());
+    [
+        "--config".to_owned(),
+        "source.crates-io.replace-with=\"vendored-sources\"".to_owned(),
+        "--config".to_owned(),
+        format!("source.vendored-sources.directory={}", directory),
+    ]
+}
+/// The cargo that invoked us, from the `CARGO` environment variable.
+fn default_cargo_path() -> Result<PathBuf, anyhow::Error> {
+    std::env::var_os("CARGO").map(PathBuf::from).context(
+        "The `CARGO` environment variable was not set: it is provided by `cargo` when invoking \
+        a custom sub-command (e.g. `cargo chef cook`), allowing `cargo-chef` to correctly detect \
+        which toolchain should be used. Run `cargo-chef` through cargo or set the cargo \
+        executable explicitly (`--cargo-path`, or `CookOptions::cargo_path` for the library).",
+    )
+}
+
+/// The target directory cargo builds into from `current_directory`, following the same rules:
+/// `--target-dir` or `CARGO_TARGET_DIR` (already merged by the CLI), `CARGO_BUILD_TARGET_DIR`,
+/// `build.target-dir` in the closest `.cargo/config.toml` (relative to the directory containing
+/// `.cargo`) or in `$CARGO_HOME/config.toml`, and finally `target`.
+/// Relative paths are made absolute.
+fn resolve_target_dir(
+    current_directory: &Path,
+    target_dir: Option<PathBuf>,
+) -> Result<PathBuf, anyhow::Error> {
+    if let Some(target_dir) =
+        target_dir.or_else(|| std::env::var_os("CARGO_BUILD_TARGET_DIR").map(PathBuf::from))
+    {
+        return Ok(current_directory.join(target_dir));
+    }
+    let cargo_home = cargo_home::cargo_home(current_directory);
+    let configuration_directories = current_directory
+        .ancestors()
+        .map(|directory| directory.join(".cargo"))
+        .chain(cargo_home);
+    for configuration_directory in configuration_directories {
+        for file_name in ["config.toml", "config"] {
+            let configuration_path = configuration_directory.join(file_name);
+            let contents = match fs_err::read_to_string(&configuration_path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let configuration: toml::Value = toml::from_str(&contents).with_context(|| {
+                format!(
+                    "Failed to parse the cargo configuration at {:?}.",
+                    configuration_path
+                )
+            })?;
+            let target_dir = configuration
+                .get("build")
+                .and_then(|build| build.get("target-dir"))
+                .and_then(|target_dir| target_dir.as_str());
+            if let Some(target_dir) = target_dir {
+                let root = configuration_directory
+                    .parent()
+                    .unwrap_or(current_directory);
+                return Ok(current_directory.join(root).join(target_dir));
+            }
+        }
+    }
+    Ok(current_directory.join("target"))
+}
+
+/// Remove the `written` files of the skeleton unpacked in `base_path`, the lockfile cargo
+/// generated if the recipe had none, and the directories (`base_path` included) they leave empty.
+fn remove_skeleton(base_path: &Path, written: &[PathBuf]) -> Result<(), anyhow::Error> {
+    let lock_file = PathBuf::from("Cargo.lock");
+    for relative_path in written.iter().chain(std::iter::once(&lock_file)) {
+        match fs_err::remove_file(base_path.join(relative_path)) {
+            Ok(()) => {}
+            // Written twice, e.g. a dummy source replaced by an included file, or not generated.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        }
+        for directory in relative_path.ancestors().skip(1) {
+            let directory = base_path.join(directory);
+            if fs_err::read_dir(&directory)?.next().is_some() {
+                break;
+            }
+            fs_err::remove_dir(&directory)?;
+        }
+    }
+    Ok(())
+}
+
+/// Where `executable` is: itself if it is a path, otherwise the first match in `PATH`.
+fn find_executable(executable: &Path) -> Option<PathBuf> {
+    let is_file = |path: &Path| path.is_file() || path.with_extension("exe").is_file();
+    if executable.components().count() > 1 {
+        return Some(executable.to_owned()).filter(|executable| is_file(executable));
+    }
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|directory| directory.join(executable))
+        .find(|candidate| is_file(candidate))
+}
+
+/// Print the statistics of the cache of `rustc_wrapper`, if it is `sccache`.
+/// This is best-effort: failing to get them does not fail the cook.
+fn show_cache_statistics(rustc_wrapper: &Path, message_format: MessageFormat) {
+    if rustc_wrapper.file_stem().and_then(|stem| stem.to_str()) != Some("sccache") {
+        return;
+    }
+    let mut command = Command::new(rustc_wrapper);
+    command.arg("--show-stats");
+    let status = match message_format {
+        MessageFormat::Human => command.status(),
+        // stdout belongs to cargo's messages.
+        MessageFormat::Json => command.stderr(Stdio::inherit()).output().map(|output| {
+            emit(
+                message_format,
+                CookEvent::CacheStatistics {
+                    rustc_wrapper,
+                    output: String::from_utf8_lossy(&output.stdout).into_owned(),
+                },
+            );
+            output.status
+        }),
+    };
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => log::warn!(
+            "`{} --show-stats` exited with {}.",
+            rustc_wrapper.display(),
+            status
+        ),
+        Err(e) => log::warn!(
+            "Failed to get the cache statistics of {:?}: {}",
+            rustc_wrapper,
+            e
+        ),
+    }
+}
+
+/// Fail early, before touching the filesystem, if `cargo zigbuild` is not available.
+fn ensure_zigbuild_is_installed(cargo_path: &Path) -> Result<(), anyhow::Error> {
+    let is_installed = Command::new(cargo_path)
+        .args(["zigbuild", "--version"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    if !is_installed {
+        anyhow::bail!(
+            "`cargo zigbuild` is not available. Install it with `cargo install --locked cargo-zigbuild` \
+            (it also requires the Zig compiler toolchain) or remove the `--zigbuild` flag."
+        );
+    }
+    Ok(())
+}
+
+fn execute_command(command: &mut Command) -> Result<ExitStatus, anyhow::Error> {
+    log::info!("Running {:?}", command);
+    let mut child = command.spawn().with_context(|| failed_to_run(command))?;
+
+    child.wait().with_context(|| failed_to_run(command))
+}
+
+/// The context of the error when `command` cannot be run, e.g. because its program does not
+/// exist or is not executable.
+fn failed_to_run(command: &Command) -> String {
+    format!(
+        "Failed to run {:?} (`{}`).",
+        command.get_program(),
+        command_line(command).join(" ")
+    )
+}
+
+/// What cargo prints when it fails to reach a registry or a git repository, in a way that may
+/// succeed on a second attempt.
+const TRANSIENT_NETWORK_ERRORS: [&str; 9] = [
+    "spurious network error",
+    "early EOF",
+    "Connection reset",
+    "Connection refused",
+    "Couldn't resolve host",
+    "timed out",
+    "failed to get successful HTTP response",
+    "HTTP2 framing layer",
+    "SSL connect error",
+];
+
+/// Run `command` and, if it failed on a transient network error, run it again up to `retries`
+/// times, waiting 1s, 2s, 4s, ... in between.
+fn execute_command_with_retries(
+    command: &mut Command,
+    retries: u32,
+    message_format: MessageFormat,
+) -> Result<ExitStatus, anyhow::Error> {
+    if retries == 0 {
+        return execute_command(command);
+    }
+    let mut attempt = 0;
+    loop {
+        let (status, stderr) = execute_command_teeing_stderr(command)?;
+        let is_transient = TRANSIENT_NETWORK_ERRORS
+            .iter()
+            .any(|pattern| stderr.contains(pattern));
+        if status.success() || !is_transient || attempt == retries {
+            return Ok(status);
+        }
+        attempt += 1;
+        let delay_seconds = 1 << (attempt - 1).min(6);
+        match message_format {
+            MessageFormat::Human => eprintln!(
+                "Retrying ({}/{}) after network error, in {}s.",
+                attempt, retries, delay_seconds
+            ),
+            MessageFormat::Json => emit(
+                message_format,
+                CookEvent::CargoRetrying {
+                    command: &command_line(command),
+                    attempt,
+                    retries,
+                    delay_seconds,
+                },
+            ),
+        }
+        std::thread::sleep(std::time::Duration::from_secs(delay_seconds));
+    }
+}
+
+/// Run `command`, forwarding its stderr as it is written while keeping a copy of it.
+fn execute_command_teeing_stderr(
+    command: &mut Command,
+) -> Result<(ExitStatus, String), anyhow::Error> {
+    log::info!("Running {:?}", command);
+    let mut child = command
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| failed_to_run(command))?;
+    let mut captured = vec![];
+    if let Some(stderr) = child.stderr.take() {
+        let mut reader = std::io::BufReader::new(stderr);
+        let mut line = vec![];
+        while reader.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+            // Like cargo's own output, forwarded on a best effort basis.
+            let _ = std::io::stderr().write_all(&line);
+            captured.append(&mut line);
         }
     }
+    let status = child.wait().with_context(|| failed_to_run(command))?;
+    Ok((status, String::from_utf8_lossy(&captured).into_owned()))
 }
@@ -0,0 +1,332 @@
+//! Logic to describe the dependencies pinned in the lockfile of a recipe as a Software Bill of
+//! Materials, before anything is built.
+use crate::Recipe;
+use anyhow::Context;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub(crate) const CRATES_IO_SOURCES: [&str; 2] = [
+    "registry+https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+
+/// The formats a [`Sbom`] can be serialized to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    /// CycloneDX 1.5, as JSON.
+    CycloneDx,
+    /// SPDX 2.3, as JSON.
+    SpdxJson,
+}
+
+/// The packages of the lockfile of a recipe.
+///
+/// The crates of the workspace (and their local `path` dependencies) are first-party
+/// components: their version is the one masked by `cargo chef prepare`, not the real one.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Sbom {
+    /// The fingerprint of the recipe, to give the same document the same SPDX namespace.
+    fingerprint: String,
+    components: Vec<Component>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Component {
+    name: String,
+    version: String,
+    source: Option<String>,
+    checksum: Option<String>,
+    is_first_party: bool,
+    is_version_masked: bool,
+}
+
+impl Sbom {
+    /// Fail if the recipe has no lockfile: without pinned versions, the SBOM would be misleading.
+    pub fn new(recipe: &Recipe) -> Result<Self, anyhow::Error> {
+        let lock_file: toml::Value = recipe
+            .skeleton
+            .lock_file
+            .as_deref()
+            .context(
+                "The recipe has no Cargo.lock: without the pinned versions of the dependencies, \
+                the SBOM would be misleading.",
+            )?
+            .parse()
+            .context("Failed to parse the Cargo.lock of the recipe.")?;
+        // Lockfiles before version 3 list the checksums in a `[metadata]` table, under
+        // `checksum <name> <version> (<source>)` keys.
+        let legacy_checksums: HashMap<&str, &str> = lock_file
+            .get("metadata")
+            .and_then(|metadata| metadata.as_table())
+            .into_iter()
+            .flatten()
+            .filter_map(|(key, value)| Some((key.strip_prefix("checksum ")?, value.as_str()?)))
+            .collect();
+
+        let mut components = vec![];
+        for package in lock_file
+            .get("package")
+            .and_then(|packages| packages.as_array())
+            .into_iter()
+            .flatten()
+        {
+            let get = |key: &str| package.get(key).and_then(|value| value.as_str());
+            let (name, version) = match (get("name"), get("version")) {
+                (Some(name), Some(version)) => (name.to_owned(), version.to_owned()),
+                _ => continue,
+            };
+            let source = get("source").map(|source| source.to_owned());
+            let is_first_party = source
+                .as_deref()
+                .is_none_or(|source| source.starts_with("path+"));
+            let checksum = get("checksum").or_else(|| {
+                let key = format!("{} {} ({})", name, version, source.as_deref()?);
+                legacy_checksums.get(key.as_str()).copied()
+            });
+            components.push(Component {
+                is_version_masked: is_first_party && version == recipe.skeleton.mask_version,
+                checksum: checksum.map(|checksum| checksum.to_owned()),
+                name,
+                version,
+                source,
+                is_first_party,
+            });
+        }
+        Ok(Sbom {
+            fingerprint: recipe.fingerprint()?,
+            components,
+        })
+    }
+
+    /// Serialize the SBOM. `created` is only recorded by SPDX documents, which require it.
+    pub fn to_json(&self, format: SbomFormat, created: SystemTime) -> Value {
+        match format {
+            SbomFormat::CycloneDx => self.cyclonedx(),
+            SbomFormat::SpdxJson => self.spdx(created),
+        }
+    }
+
+    fn cyclonedx(&self) -> Value {
+        let components: Vec<Value> = self
+            .components
+            .iter()
+            .map(|component| {
+                let mut value = json!({
+                    "type": "library",
+                    "bom-ref": component.bom_ref(),
+                    "name": component.name,
+                    "version": component.version,
+                });
+                if let Some(purl) = component.purl() {
+                    value["purl"] = json!(purl);
+                }
+                if let Some(checksum) = &component.checksum {
+                    value["hashes"] = json!([{ "alg": "SHA-256", "content": checksum }]);
+                }
+                if let Some(url) = component.download_location() {
+                    value["externalReferences"] = json!([{ "type": "distribution", "url": url }]);
+                }
+                let mut properties = vec![];
+                if let Some(source) = &component.source {
+                    properties.push(json!({ "name": "cargo-chef:source", "value": source }));
+                }
+                if component.is_first_party {
+                    properties.push(json!({ "name": "cargo-chef:first-party", "value": "true" }));
+                }
+                if component.is_version_masked {
+                    properties
+                        .push(json!({ "name": "cargo-chef:masked-version", "value": "true" }));
+                }
+                if !properties.is_empty() {
+                    value["properties"] = Value::Array(properties);
+                }
+                value
+            })
+            .collect();
+        json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "version": 1,
+            "metadata": {
+                "tools": {
+                    "components": [{
+                        "type": "application",
+                        "name": "cargo-chef",
+                        "version": env!("CARGO_PKG_VERSION"),
+                    }],
+                },
+            },
+            "components": components,
+        })
+    }
+
+    fn spdx(&self, created: SystemTime) -> Value {
+        let mut ids: Vec<String> = vec![];
+        for component in &self.components {
+            let base = format!(
+                "SPDXRef-Package-{}-{}",
+                spdx_id(&component.name),
+                spdx_id(&component.version)
+            );
+            let mut id = base.clone();
+            let mut suffix = 1;
+            while ids.contains(&id) {
+                suffix += 1;
+                id = format!("{}-{}", base, suffix);
+            }
+            ids.push(id);
+        }
+        let packages: Vec<Value> = self
+            .components
+            .iter()
+            .zip(&ids)
+            .map(|(component, id)| {
+                let mut value = json!({
+                    "SPDXID": id,
+                    "name": component.name,
+                    "versionInfo": component.version,
+                    "downloadLocation": component
+                        .download_location()
+                        .unwrap_or_else(|| "NOASSERTION".to_owned()),
+                    "filesAnalyzed": false,
+                    "primaryPackagePurpose": "LIBRARY",
+                });
+                if let Some(checksum) = &component.checksum {
+                    value["checksums"] =
+                        json!([{ "algorithm": "SHA256", "checksumValue": checksum }]);
+                }
+                if let Some(purl) = component.purl() {
+                    value["externalRefs"] = json!([{
+                        "referenceCategory": "PACKAGE-MANAGER",
+                        "referenceType": "purl",
+                        "referenceLocator": purl,
+                    }]);
+                }
+                if component.is_first_party {
+                    value["comment"] = json!(if component.is_version_masked {
+                        "First-party crate of the workspace. Its version is masked by cargo-chef."
+                    } else {
+                        "First-party crate of the workspace."
+                    });
+                }
+                value
+            })
+            .collect();
+        let relationships: Vec<Value> = self
+            .components
+            .iter()
+            .zip(&ids)
+            .filter(|(component, _)| component.is_first_party)
+            .map(|(_, id)| {
+                json!({
+                    "spdxElementId": "SPDXRef-DOCUMENT",
+                    "relationshipType": "DESCRIBES",
+                    "relatedSpdxElement": id,
+                })
+            })
+            .collect();
+        let namespace = format!("https://spdx.org/spdxdocs/cargo-chef-{}", self.fingerprint);
+        json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": "cargo-chef recipe",
+            "documentNamespace": namespace,
+            "creationInfo": {
+                "created": rfc3339(created),
+                "creators": [format!("Tool: cargo-chef-{}", env!("CARGO_PKG_VERSION"))],
+            },
+            "packages": packages,
+            "relationships": relationships,
+        })
+    }
+}
+
+impl Component {
+    fn bom_ref(&self) -> String {
+        match &self.source {
+            Some(source) if !self.is_first_party => {
+                format!("{}@{} ({})", self.name, self.version, source)
+            }
+            _ => format!("{}@{}", self.name, self.version),
+        }
+    }
+
+    /// The package URL of a crate downloaded from a registry or a git repository.
+    fn purl(&self) -> Option<String> {
+        let source = self.source.as_deref()?;
+        let purl = format!("pkg:cargo/{}@{}", self.name, self.version);
+        if CRATES_IO_SOURCES.contains(&source) {
+            Some(purl)
+        } else if let Some(index) = source
+            .strip_prefix("registry+")
+            .or_else(|| source.strip_prefix("sparse+"))
+        {
+            Some(format!("{}?repository_url={}", purl, index))
+        } else {
+            self.git_location()
+                .map(|location| format!("{}?vcs_url={}", purl, location))
+        }
+    }
+
+    fn download_location(&self) -> Option<String> {
+        let source = self.source.as_deref()?;
+        if CRATES_IO_SOURCES.contains(&source) {
+            Some(format!(
+                "https://crates.io/api/v1/crates/{}/{}/download",
+                self.name, self.version
+            ))
+        } else {
+            self.git_location()
+        }
+    }
+
+    /// `git+<url>@<commit>` for a `git+<url>?<reference>#<commit>` source.
+    fn git_location(&self) -> Option<String> {
+        let source = self.source.as_deref()?.strip_prefix("git+")?;
+        let (url, commit) = source.split_once('#')?;
+        let url = url.split('?').next().unwrap_or(url);
+        Some(format!("git+{}@{}", url, commit))
+    }
+}
+
+/// SPDX identifiers may only contain letters, numbers, `.` and `-`.
+fn spdx_id(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() || c == '.' || c == '-' => c,
+            _ => '-',
+        })
+        .collect()
+}
+
+/// Format a time as `YYYY-MM-DDThh:mm:ssZ`, in UTC.
+fn rfc3339(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let (days, seconds) = ((seconds / 86400) as i64, seconds % 86400);
+    // Howard Hinnant's `civil_from_days`.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
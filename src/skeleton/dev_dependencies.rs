@@ -0,0 +1,191 @@
+//! Logic to leave dev-dependencies out of a skeleton, for images that never build tests,
+//! examples or benchmarks.
+use super::version_masking::dependency_package_name;
+use super::{lockfile, ParsedManifest};
+use std::collections::{HashMap, HashSet};
+
+/// Remove all `[dev-dependencies]` tables (top-level and target-specific) from the manifests,
+/// together with the `[workspace.dependencies]` entries that are no longer inherited by any
+/// member, and prune the lockfile accordingly.
+///
+/// A crate that is both a normal (or build) dependency and a dev-dependency is kept.
+pub(super) fn remove_dev_dependencies(
+    manifests: &mut [ParsedManifest],
+    lock_file: &mut Option<toml::Value>,
+) {
+    for manifest in manifests.iter_mut() {
+        remove_dev_dependency_tables(&mut manifest.contents);
+        remove_dangling_feature_entries(&mut manifest.contents);
+    }
+
+    let workspace_dependencies = manifests.iter().find_map(|manifest| {
+        manifest
+            .contents
+            .get("workspace")?
+            .get("dependencies")?
+            .as_table()
+            .cloned()
+    });
+    // The version requirements of the remaining dependencies of each local crate.
+    let mut requirements: HashMap<String, HashMap<String, Vec<Option<String>>>> = HashMap::new();
+    let mut inherited = HashSet::new();
+    for manifest in manifests.iter() {
+        let package_name = manifest
+            .contents
+            .get("package")
+            .and_then(|package| package.get("name"))
+            .and_then(|name| name.as_str());
+        for (key, dependency) in dependencies(&manifest.contents) {
+            let is_inherited = dependency
+                .get("workspace")
+                .and_then(|workspace| workspace.as_bool())
+                .unwrap_or_default();
+            let dependency = if is_inherited {
+                inherited.insert(key.to_owned());
+                match workspace_dependencies
+                    .as_ref()
+                    .and_then(|dependencies| dependencies.get(key))
+                {
+                    Some(dependency) => dependency,
+                    None => continue,
+                }
+            } else {
+                dependency
+            };
+            if let Some(package_name) = package_name {
+                let version = match dependency {
+                    toml::Value::String(version) => Some(version.to_owned()),
+                    dependency => dependency
+                        .get("version")
+                        .and_then(|version| version.as_str())
+                        .map(|version| version.to_owned()),
+                };
+                requirements
+                    .entry(package_name.to_owned())
+                    .or_default()
+                    .entry(dependency_package_name(key, dependency).to_owned())
+                    .or_default()
+                    .push(version);
+            }
+        }
+    }
+
+    for manifest in manifests.iter_mut() {
+        if let Some(dependencies) = manifest
+            .contents
+            .get_mut("workspace")
+            .and_then(|workspace| workspace.get_mut("dependencies"))
+            .and_then(|dependencies| dependencies.as_table_mut())
+        {
+            *dependencies = std::mem::take(dependencies)
+                .into_iter()
+                .filter(|(key, _)| inherited.contains(key))
+                .collect();
+        }
+    }
+
+    if let Some(lock_file) = lock_file {
+        lockfile::retain_local_dependencies(lock_file, |package, name, version| {
+            // Local crates outside of the project (e.g. `path = "../../other"`) keep all their
+            // dependencies.
+            let requirements = match requirements.get(package) {
+                Some(requirements) => requirements,
+                None => return true,
+            };
+            match (requirements.get(name), version) {
+                (None, _) => false,
+                (Some(_), None) => true,
+                // Several versions of the same crate are locked: keep the ones that are still
+                // required.
+                (Some(requirements), Some(version)) => requirements
+                    .iter()
+                    .any(|requirement| matches_requirement(requirement.as_deref(), version)),
+            }
+        });
+        lockfile::remove_unreachable_packages(lock_file, |_| true);
+    }
+}
+
+/// Does the locked `version` satisfy the `requirement` of a dependency?
+/// Dependencies without a requirement (or with one we cannot parse) match any version.
+pub(super) fn matches_requirement(requirement: Option<&str>, version: &str) -> bool {
+    let requirement =
+        requirement.and_then(|requirement| semver::VersionReq::parse(requirement).ok());
+    let version = semver::Version::parse(version).ok();
+    match (requirement, version) {
+        (Some(requirement), Some(version)) => requirement.matches(&version),
+        _ => true,
+    }
+}
+
+/// Remove the top-level and the target-specific `[dev-dependencies]` tables.
+fn remove_dev_dependency_tables(manifest: &mut toml::Value) {
+    if let Some(manifest) = manifest.as_table_mut() {
+        manifest.remove("dev-dependencies");
+    }
+    if let Some(targets) = manifest
+        .get_mut("target")
+        .and_then(|targets| targets.as_table_mut())
+    {
+        for target in targets
+            .iter_mut()
+            .filter_map(|(_, target)| target.as_table_mut())
+        {
+            target.remove("dev-dependencies");
+        }
+    }
+}
+
+/// Remove the entries of `[features]` that enable a dependency gone with the dev-dependencies
+/// (e.g. `testing = ["dev-helper/extra"]`): cargo refuses to parse the manifest otherwise.
+/// The features themselves are kept, possibly empty, since other crates may enable them.
+fn remove_dangling_feature_entries(manifest: &mut toml::Value) {
+    let dependencies: HashSet<String> = dependencies(manifest)
+        .map(|(key, _)| key.to_owned())
+        .collect();
+    let features = match manifest
+        .get_mut("features")
+        .and_then(|features| features.as_table_mut())
+    {
+        Some(features) => features,
+        None => return,
+    };
+    for entries in features
+        .iter_mut()
+        .filter_map(|(_, entries)| entries.as_array_mut())
+    {
+        entries.retain(|entry| {
+            let entry = match entry.as_str() {
+                Some(entry) => entry,
+                None => return true,
+            };
+            // `dep:name`, `name/feature` and `name?/feature` point to a dependency, anything
+            // else to another feature.
+            let dependency = match entry.strip_prefix("dep:") {
+                Some(dependency) => dependency,
+                None => match entry.split_once('/') {
+                    Some((dependency, _)) => dependency.trim_end_matches('?'),
+                    None => return true,
+                },
+            };
+            dependencies.contains(dependency)
+        });
+    }
+}
+
+/// All the normal and build dependencies of a manifest, top-level and target-specific.
+fn dependencies(manifest: &toml::Value) -> impl Iterator<Item = (&String, &toml::Value)> {
+    let targets = manifest
+        .get("target")
+        .and_then(|targets| targets.as_table())
+        .into_iter()
+        .flat_map(|targets| targets.values());
+    std::iter::once(manifest)
+        .chain(targets)
+        .flat_map(|value| {
+            ["dependencies", "build-dependencies"]
+                .iter()
+                .filter_map(move |key| value.get(*key)?.as_table())
+        })
+        .flatten()
+}
@@ -0,0 +1,239 @@
+//! Logic to bring `path` dependencies that live outside of the project into the skeleton.
+use super::{read, workspace, ParsedManifest};
+use anyhow::Context;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Where the manifests of the `path` dependencies outside of the project are stored, relative to
+/// the root of the skeleton.
+pub(super) const EXTERNAL_DIRECTORY: &str = ".cargo-chef/external";
+
+/// Add the manifests of the `path` dependencies that live outside of `base_path` (e.g.
+/// `shared = { path = "../../shared" }`) to the skeleton, following them transitively.
+///
+/// They are stored under [`EXTERNAL_DIRECTORY`], keeping their layout relative to each other,
+/// and the `path` keys that cross the boundary of the project are rewritten to point to their
+/// new location. Absolute paths are rewritten as well: they do not exist where the recipe is
+/// cooked.
+///
+/// The `path` dependencies and the members listed explicitly in the root manifest that live in
+/// the project but were skipped while looking for manifests (e.g. because of a `.gitignore`)
+/// are added too, at their own location: cargo needs them.
+pub(super) fn include_external_path_dependencies(
+    base_path: &Path,
+    manifests: &mut Vec<ParsedManifest>,
+) -> Result<(), anyhow::Error> {
+    // The directory of each manifest relative to `base_path`, possibly starting with `..`.
+    let mut directories: Vec<PathBuf> = manifests
+        .iter()
+        .map(|manifest| directory(&manifest.relative_path))
+        .collect();
+
+    let mut to_visit: Vec<usize> = (0..manifests.len()).collect();
+    while let Some(i) = to_visit.pop() {
+        let mut dependencies = vec![];
+        for (key, path) in workspace::path_dependencies(&manifests[i].contents) {
+            let target = resolve(base_path, &directories[i], path);
+            let is_skipped = base_path.join(&target).join("Cargo.toml").is_file();
+            if (is_external(&target) || is_skipped) && !directories.contains(&target) {
+                dependencies.push((key.to_owned(), path.to_owned(), target));
+            }
+        }
+        if manifests[i].relative_path == Path::new("Cargo.toml") {
+            for member in literal_members(&manifests[i].contents) {
+                let target = resolve(base_path, Path::new(""), member);
+                let is_skipped = base_path.join(&target).join("Cargo.toml").is_file();
+                if is_skipped && !is_external(&target) && !directories.contains(&target) {
+                    dependencies.push((member.to_owned(), member.to_owned(), target));
+                }
+            }
+        }
+        for (key, path, target) in dependencies {
+            if directories.contains(&target) {
+                continue;
+            }
+            let manifest_path = base_path.join(&target).join("Cargo.toml");
+            let referenced_by = directories[i].join("Cargo.toml");
+            let contents = fs::read_to_string(&manifest_path).with_context(|| {
+                format!(
+                    "Failed to read the manifest of `{}`, a path dependency (path = {:?}) of {:?}, at {:?}.",
+                    key, path, referenced_by, manifest_path
+                )
+            })?;
+            log::info!(
+                "Found manifest {:?} of `{}`, needed by {:?}.",
+                manifest_path,
+                key,
+                referenced_by
+            );
+            let original: toml::Value = toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse the manifest at {:?}.", manifest_path))?;
+            manifests.push(ParsedManifest {
+                relative_path: target.join("Cargo.toml"),
+                contents: read::parse(&manifest_path, &contents, &original)?,
+            });
+            directories.push(target);
+            to_visit.push(manifests.len() - 1);
+        }
+    }
+    if !directories.iter().any(|directory| is_external(directory)) {
+        return Ok(());
+    }
+
+    // All the external crates are laid out below the directory `max_depth` levels above the
+    // project, so that the paths between them are left untouched.
+    let max_depth = directories.iter().map(|directory| depth(directory)).max();
+    let max_depth = max_depth.unwrap_or_default();
+    let mut ancestors: Vec<PathBuf> = fs::canonicalize(base_path)?
+        .ancestors()
+        .take(max_depth)
+        .filter_map(|ancestor| ancestor.file_name())
+        .map(PathBuf::from)
+        .collect();
+    ancestors.resize(max_depth, PathBuf::from("_"));
+    ancestors.reverse();
+    let relocate = |directory: &Path| -> PathBuf {
+        if !is_external(directory) {
+            return directory.to_owned();
+        }
+        let depth = depth(directory);
+        let mut relocated = PathBuf::from(EXTERNAL_DIRECTORY);
+        relocated.extend(&ancestors[..max_depth - depth]);
+        relocated.extend(directory.components().skip(depth));
+        relocated
+    };
+
+    for (i, manifest) in manifests.iter_mut().enumerate() {
+        let is_external_manifest = is_external(&directories[i]);
+        let relocated_directory = relocate(&directories[i]);
+        if is_external_manifest {
+            manifest.relative_path = relocated_directory.join("Cargo.toml");
+        }
+        for dependencies in dependency_tables_mut(&mut manifest.contents) {
+            for (_, dependency) in dependencies.iter_mut() {
+                if let Some(toml::Value::String(path)) = dependency.get_mut("path") {
+                    let target = resolve(base_path, &directories[i], path);
+                    if is_external_manifest || is_external(&target) || Path::new(path).is_absolute()
+                    {
+                        let relocated =
+                            pathdiff::diff_paths(relocate(&target), &relocated_directory)
+                                .unwrap_or_default();
+                        *path = workspace::to_slash(&relocated);
+                    }
+                }
+            }
+        }
+    }
+
+    // Path dependencies inside the workspace directory would automatically become members.
+    if let Some(workspace) = manifests
+        .iter_mut()
+        .find(|manifest| manifest.relative_path == Path::new("Cargo.toml"))
+        .and_then(|manifest| manifest.contents.get_mut("workspace"))
+        .and_then(|workspace| workspace.as_table_mut())
+    {
+        let external_root = EXTERNAL_DIRECTORY.split('/').next().unwrap_or_default();
+        let exclude = workspace
+            .entry("exclude")
+            .or_insert_with(|| toml::Value::Array(vec![]));
+        if let Some(exclude) = exclude.as_array_mut() {
+            exclude.push(toml::Value::String(external_root.to_owned()));
+        }
+    }
+    Ok(())
+}
+
+/// The entries of `[workspace] members` without wildcards.
+fn literal_members(manifest: &toml::Value) -> impl Iterator<Item = &str> {
+    manifest
+        .get("workspace")
+        .and_then(|workspace| workspace.get("members"))
+        .and_then(|members| members.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|member| member.as_str())
+        .filter(|member| !member.contains(['*', '?']))
+}
+
+fn directory(manifest_path: &Path) -> PathBuf {
+    manifest_path
+        .parent()
+        .map(|directory| directory.to_owned())
+        .unwrap_or_default()
+}
+
+/// Resolve the `path` of a dependency declared in the manifest in `directory`, relative to
+/// `base_path`, without touching the filesystem.
+fn resolve(base_path: &Path, directory: &Path, path: &str) -> PathBuf {
+    let path = Path::new(path);
+    let path = if path.is_absolute() {
+        pathdiff::diff_paths(path, base_path).unwrap_or_else(|| path.to_owned())
+    } else {
+        directory.join(path)
+    };
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if depth(&resolved) < resolved.components().count() => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+/// How many levels above the project a resolved directory is.
+fn depth(directory: &Path) -> usize {
+    directory
+        .components()
+        .take_while(|component| component == &Component::ParentDir)
+        .count()
+}
+
+fn is_external(directory: &Path) -> bool {
+    depth(directory) > 0
+}
+
+/// All the tables in a manifest whose entries may have a `path` key.
+pub(super) fn dependency_tables_mut(manifest: &mut toml::Value) -> Vec<&mut toml::value::Table> {
+    const DEPENDENCY_KEYS: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+    let mut tables = vec![];
+    let manifest = match manifest.as_table_mut() {
+        Some(manifest) => manifest,
+        None => return tables,
+    };
+    for (key, value) in manifest.iter_mut() {
+        match key.as_str() {
+            key if DEPENDENCY_KEYS.contains(&key) || key == "replace" => {
+                tables.extend(value.as_table_mut());
+            }
+            "target" => {
+                for (_, target) in value.as_table_mut().into_iter().flat_map(|t| t.iter_mut()) {
+                    for (key, dependencies) in
+                        target.as_table_mut().into_iter().flat_map(|t| t.iter_mut())
+                    {
+                        if DEPENDENCY_KEYS.contains(&key.as_str()) {
+                            tables.extend(dependencies.as_table_mut());
+                        }
+                    }
+                }
+            }
+            "workspace" => {
+                tables.extend(
+                    value
+                        .get_mut("dependencies")
+                        .and_then(|dependencies| dependencies.as_table_mut()),
+                );
+            }
+            "patch" => {
+                for (_, patches) in value.as_table_mut().into_iter().flat_map(|p| p.iter_mut()) {
+                    tables.extend(patches.as_table_mut());
+                }
+            }
+            _ => {}
+        }
+    }
+    tables
+}
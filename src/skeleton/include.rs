@@ -0,0 +1,349 @@
+//! Logic to embed the files listed in `[package.metadata.chef] include` (or in the `include`
+//! of the chef configuration) verbatim in the skeleton, for the build scripts that need them (e.g. protobuf schemas or SQL migrations), as
+//! well as the offline query data of `sqlx` and the specifications of custom targets.
+use super::{external, ParsedManifest};
+use anyhow::Context;
+use fs_err as fs;
+use globset::{GlobBuilder, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+/// A file of the project restored as is by `cook`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct IncludedFile {
+    /// Relative path with respect to the project root, with `/` separators.
+    #[serde(deserialize_with = "super::deserialize_recipe_path")]
+    pub relative_path: PathBuf,
+    pub contents: String,
+}
+
+/// Read the files matched by the `include` patterns of the manifests, relative to the
+/// directory of each crate. Patterns that match nothing are reported on stderr.
+pub(super) fn included_files(
+    base_path: &Path,
+    manifests: &[ParsedManifest],
+) -> Result<Vec<IncludedFile>, anyhow::Error> {
+    let mut included_files = vec![];
+    for manifest in manifests {
+        if manifest
+            .relative_path
+            .starts_with(external::EXTERNAL_DIRECTORY)
+        {
+            continue;
+        }
+        let patterns = include_patterns(&manifest.contents).with_context(|| {
+            format!(
+                "Failed to read `package.metadata.chef.include` in {:?}.",
+                manifest.relative_path
+            )
+        })?;
+        let crate_directory = manifest
+            .relative_path
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        let origin = format!(
+            "`package.metadata.chef.include` of {}",
+            manifest.relative_path.display()
+        );
+        included_files.extend(matching_files(
+            base_path,
+            crate_directory,
+            &patterns,
+            &origin,
+        )?);
+    }
+    Ok(included_files)
+}
+
+/// Read the files matched by the `include` patterns of the chef configuration, relative to the
+/// root of the project.
+pub(super) fn project_files(
+    base_path: &Path,
+    patterns: &[String],
+) -> Result<Vec<IncludedFile>, anyhow::Error> {
+    for pattern in patterns {
+        if !is_contained(Path::new(pattern)) {
+            anyhow::bail!(
+                "`{}` in the `include` of the chef configuration must be relative to the root of \
+                the project, without `..`.",
+                pattern
+            );
+        }
+    }
+    matching_files(
+        base_path,
+        Path::new(""),
+        patterns,
+        "the `include` of the chef configuration",
+    )
+}
+
+/// Read the files of `directory` (relative to `base_path`) matched by `patterns`, relative to
+/// it. Patterns that match nothing are reported on stderr, along with their `origin`.
+fn matching_files(
+    base_path: &Path,
+    directory: &Path,
+    patterns: &[String],
+    origin: &str,
+) -> Result<Vec<IncludedFile>, anyhow::Error> {
+    let mut files = vec![];
+    if patterns.is_empty() {
+        return Ok(files);
+    }
+    let mut matched = vec![false; patterns.len()];
+    let mut glob_set = GlobSetBuilder::new();
+    for pattern in patterns {
+        // As in `.gitignore`, `*` does not cross directories while `**` does.
+        glob_set.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+    }
+    let glob_set = glob_set.build()?;
+
+    let walker = ignore::WalkBuilder::new(base_path.join(directory))
+        .standard_filters(false)
+        .filter_entry(|entry| {
+            let name = entry.file_name();
+            !(entry.depth() > 0 && (name == "target" || name == ".git"))
+        })
+        .build();
+    for entry in walker {
+        let entry = entry?;
+        if !entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+        {
+            continue;
+        }
+        let relative_to_directory = entry
+            .path()
+            .strip_prefix(base_path.join(directory))
+            .context("The walk escaped the directory of the crate.")?;
+        let matches = glob_set.matches(relative_to_directory);
+        if matches.is_empty() {
+            continue;
+        }
+        for i in matches {
+            matched[i] = true;
+        }
+        let relative_path = directory.join(relative_to_directory);
+        let contents = fs::read_to_string(entry.path()).with_context(|| {
+            format!(
+                "Failed to include {:?}: only text files can be included in the recipe.",
+                relative_path
+            )
+        })?;
+        files.push(IncludedFile {
+            relative_path: PathBuf::from(super::normalize_path_separators(
+                &relative_path.to_string_lossy(),
+            )),
+            contents,
+        });
+    }
+    for (pattern, matched) in patterns.iter().zip(matched) {
+        if !matched {
+            eprintln!(
+                "warning: `{}` in {} does not match any file.",
+                pattern, origin
+            );
+        }
+    }
+    Ok(files)
+}
+
+/// The offline query data of `sqlx` (`cargo sqlx prepare`) at the root of the project and of each
+/// local crate: the `.sqlx/` directory or, for versions older than 0.7, `sqlx-data.json`.
+pub(super) fn sqlx_offline_data(
+    base_path: &Path,
+    manifests: &[ParsedManifest],
+) -> Result<Vec<IncludedFile>, anyhow::Error> {
+    let mut directories: Vec<&Path> = manifests
+        .iter()
+        .filter(|manifest| {
+            !manifest
+                .relative_path
+                .starts_with(external::EXTERNAL_DIRECTORY)
+        })
+        .map(|manifest| {
+            manifest
+                .relative_path
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+        })
+        .collect();
+    directories.push(Path::new(""));
+    directories.sort();
+    directories.dedup();
+
+    let mut files = vec![];
+    for directory in directories {
+        let sqlx_data = directory.join(SQLX_DATA_FILE);
+        if base_path.join(&sqlx_data).is_file() {
+            files.push(sqlx_data);
+        }
+        let query_directory = base_path.join(directory).join(SQLX_QUERY_DIRECTORY);
+        if query_directory.is_dir() {
+            for entry in fs::read_dir(&query_directory)? {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    files.push(directory.join(SQLX_QUERY_DIRECTORY).join(entry.file_name()));
+                }
+            }
+        }
+    }
+
+    files
+        .into_iter()
+        .map(|relative_path| {
+            let contents = fs::read_to_string(base_path.join(&relative_path))?;
+            Ok(IncludedFile {
+                relative_path: PathBuf::from(super::normalize_path_separators(
+                    &relative_path.to_string_lossy(),
+                )),
+                contents,
+            })
+        })
+        .collect()
+}
+
+/// Is `relative_path` part of the offline query data of `sqlx`?
+pub(super) fn is_sqlx_offline_data(relative_path: &Path) -> bool {
+    relative_path.file_name() == Some(SQLX_DATA_FILE.as_ref())
+        || relative_path
+            .parent()
+            .and_then(Path::file_name)
+            .is_some_and(|name| name == SQLX_QUERY_DIRECTORY)
+}
+
+/// The real build scripts of the local crates marked with
+/// `package.metadata.chef.keep-build-script = true`, or of all of them if `keep_all`, instead of
+/// the dummy ones. They run during `cook`, e.g. to generate code with `tonic-build`.
+pub(super) fn build_scripts(
+    base_path: &Path,
+    manifests: &[ParsedManifest],
+    keep_all: bool,
+) -> Result<Vec<IncludedFile>, anyhow::Error> {
+    let mut build_scripts = vec![];
+    for manifest in manifests {
+        if manifest
+            .relative_path
+            .starts_with(external::EXTERNAL_DIRECTORY)
+        {
+            continue;
+        }
+        let keep = super::chef_flag(&manifest.contents, KEEP_BUILD_SCRIPT)
+            .with_context(|| format!("Failed to read {:?}.", manifest.relative_path))?;
+        if !(keep || keep_all) {
+            continue;
+        }
+        // `build = true` points to the default location.
+        let build_path = match manifest
+            .contents
+            .get("package")
+            .and_then(|package| package.get("build"))
+        {
+            Some(toml::Value::String(build_path)) => build_path.as_str(),
+            Some(toml::Value::Boolean(true)) => "build.rs",
+            _ => continue,
+        };
+        if !is_contained(Path::new(build_path)) {
+            anyhow::bail!(
+                "The build script of {:?} is outside of the directory of the crate: `{}`.",
+                manifest.relative_path,
+                build_path
+            );
+        }
+        let relative_path = manifest
+            .relative_path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(super::platform_path(build_path));
+        let contents = fs::read_to_string(base_path.join(&relative_path))?;
+        build_scripts.push(IncludedFile {
+            relative_path: PathBuf::from(super::normalize_path_separators(
+                &relative_path.to_string_lossy(),
+            )),
+            contents,
+        });
+    }
+    Ok(build_scripts)
+}
+
+/// The target specification files of custom targets (e.g. `my-target.json`), relative to the
+/// root of the project, for `cook --target my-target.json`.
+pub(super) fn target_specs(
+    base_path: &Path,
+    relative_paths: &[PathBuf],
+) -> Result<Vec<IncludedFile>, anyhow::Error> {
+    let mut target_specs = vec![];
+    for relative_path in relative_paths {
+        if !is_contained(relative_path) {
+            anyhow::bail!(
+                "The target specification {:?} must be relative to the root of the project, \
+                without `..`.",
+                relative_path
+            );
+        }
+        let relative_path: PathBuf = relative_path
+            .components()
+            .filter(|component| component != &Component::CurDir)
+            .collect();
+        if relative_path.extension() != Some("json".as_ref()) {
+            anyhow::bail!(
+                "The target specification {:?} is not a `.json` file.",
+                relative_path
+            );
+        }
+        let contents = fs::read_to_string(base_path.join(&relative_path)).with_context(|| {
+            format!(
+                "Failed to read the target specification {:?}.",
+                relative_path
+            )
+        })?;
+        target_specs.push(IncludedFile {
+            relative_path: PathBuf::from(super::normalize_path_separators(
+                &relative_path.to_string_lossy(),
+            )),
+            contents,
+        });
+    }
+    Ok(target_specs)
+}
+
+const KEEP_BUILD_SCRIPT: &str = "keep-build-script";
+const SQLX_DATA_FILE: &str = "sqlx-data.json";
+const SQLX_QUERY_DIRECTORY: &str = ".sqlx";
+
+/// The glob patterns of `[package.metadata.chef] include`. They must stay within the directory
+/// of the crate.
+fn include_patterns(manifest: &toml::Value) -> Result<Vec<String>, anyhow::Error> {
+    let include = match manifest
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("chef"))
+        .and_then(|chef| chef.get("include"))
+    {
+        Some(include) => include,
+        None => return Ok(vec![]),
+    };
+    let patterns: Vec<String> = include
+        .clone()
+        .try_into()
+        .context("`package.metadata.chef.include` must be a list of glob patterns.")?;
+    for pattern in &patterns {
+        if !is_contained(Path::new(pattern)) {
+            anyhow::bail!(
+                "`{}` must be relative to the directory of the crate, without `..`.",
+                pattern
+            );
+        }
+    }
+    Ok(patterns)
+}
+
+/// Does `path` stay within the directory it is relative to, i.e. is it relative, without `..`?
+pub(super) fn is_contained(path: &Path) -> bool {
+    let path = super::normalize_path_separators(&path.to_string_lossy());
+    !path.starts_with('/')
+        && Path::new(&path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
@@ -0,0 +1,224 @@
+//! Logic to trim the lockfile of a skeleton down to the packages it actually needs.
+use std::collections::{HashMap, HashSet};
+
+/// A `[[package]]` entry of a lockfile.
+struct Package {
+    name: String,
+    version: String,
+    source: Option<String>,
+    dependencies: Vec<String>,
+}
+
+impl Package {
+    fn parse(package: &toml::Value) -> Option<Self> {
+        let get = |key: &str| package.get(key).and_then(|value| value.as_str());
+        Some(Package {
+            name: get("name")?.to_owned(),
+            version: get("version")?.to_owned(),
+            source: get("source").map(|source| source.to_owned()),
+            dependencies: package
+                .get("dependencies")
+                .and_then(|dependencies| dependencies.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|dependency| dependency.as_str())
+                .map(|dependency| dependency.to_owned())
+                .collect(),
+        })
+    }
+
+    /// Local crates have no source (or a `path+` one).
+    fn is_local(&self) -> bool {
+        !matches!(&self.source, Some(source) if !source.starts_with("path+"))
+    }
+
+    /// Does a `<name> [<version> [(<source>)]]` entry of a `dependencies` array point to us?
+    fn matches(&self, dependency: &str) -> bool {
+        let (name, version, source) = parse_dependency(dependency);
+        name == self.name
+            && version.is_none_or(|version| version == self.version)
+            && source.is_none_or(|source| Some(source) == self.source.as_deref())
+    }
+}
+
+/// Split a `<name> [<version> [(<source>)]]` entry of a `dependencies` array.
+fn parse_dependency(dependency: &str) -> (&str, Option<&str>, Option<&str>) {
+    let mut parts = dependency.splitn(3, ' ');
+    let name = parts.next().unwrap_or_default();
+    let version = parts.next();
+    let source = parts
+        .next()
+        .map(|source| source.trim_start_matches('(').trim_end_matches(')'));
+    (name, version, source)
+}
+
+fn packages_mut(lock_file: &mut toml::Value) -> Option<&mut Vec<toml::Value>> {
+    lock_file
+        .get_mut("package")
+        .and_then(|packages| packages.as_array_mut())
+}
+
+/// The name and the version of the packages of the lockfile downloaded from a registry.
+pub(super) fn registry_packages(lock_file: &toml::Value) -> Vec<(String, String)> {
+    lock_file
+        .get("package")
+        .and_then(|packages| packages.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(Package::parse)
+        .filter(|package| {
+            package.source.as_deref().is_some_and(|source| {
+                source.starts_with("registry+") || source.starts_with("sparse+")
+            })
+        })
+        .map(|package| (package.name, package.version))
+        .collect()
+}
+
+/// How many packages of the lockfile are not local: the ones a local package depends on
+/// directly, and the other ones, only depended on through them.
+pub(super) fn remote_package_counts(lock_file: &toml::Value) -> (usize, usize) {
+    let packages: Vec<Package> = lock_file
+        .get("package")
+        .and_then(|packages| packages.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(Package::parse)
+        .collect();
+    let (local, remote): (Vec<&Package>, Vec<&Package>) =
+        packages.iter().partition(|package| package.is_local());
+    let direct = remote
+        .iter()
+        .filter(|package| {
+            local.iter().any(|local| {
+                local
+                    .dependencies
+                    .iter()
+                    .any(|dependency| package.matches(dependency))
+            })
+        })
+        .count();
+    (direct, remote.len() - direct)
+}
+
+/// Drop entries from the `dependencies` of local packages.
+///
+/// `keep` is called with the name of the local package and the name and the version (if
+/// specified) of each of its dependencies.
+pub(super) fn retain_local_dependencies(
+    lock_file: &mut toml::Value,
+    keep: impl Fn(&str, &str, Option<&str>) -> bool,
+) {
+    for package in packages_mut(lock_file).into_iter().flatten() {
+        let parsed = match Package::parse(package) {
+            Some(parsed) if parsed.is_local() => parsed,
+            _ => continue,
+        };
+        if let Some(dependencies) = package
+            .get_mut("dependencies")
+            .and_then(|dependencies| dependencies.as_array_mut())
+        {
+            dependencies.retain(|dependency| match dependency.as_str() {
+                Some(dependency) => {
+                    let (name, version, _) = parse_dependency(dependency);
+                    let keep = keep(&parsed.name, name, version);
+                    if !keep {
+                        log::debug!(
+                            "Removing `{}` from the dependencies of `{}` in Cargo.lock",
+                            dependency,
+                            parsed.name
+                        );
+                    }
+                    keep
+                }
+                None => true,
+            });
+        }
+    }
+}
+
+/// Remove all the packages that cannot be reached, following their `dependencies`, from the
+/// local packages selected by `is_root`.
+///
+/// The checksums listed in the `[metadata]` table (used by older lockfile formats) of the
+/// removed packages are removed as well.
+pub(super) fn remove_unreachable_packages(
+    lock_file: &mut toml::Value,
+    is_root: impl Fn(&str) -> bool,
+) {
+    // Aligned with the `[[package]]` entries: entries we cannot make sense of are left alone.
+    let packages: Vec<Option<Package>> = match packages_mut(lock_file) {
+        Some(packages) => packages.iter().map(Package::parse).collect(),
+        None => return,
+    };
+    let mut packages_by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, package) in packages.iter().enumerate() {
+        if let Some(package) = package {
+            packages_by_name.entry(&package.name).or_default().push(i);
+        }
+    }
+
+    let mut reachable = HashSet::new();
+    let mut to_visit: Vec<usize> = packages
+        .iter()
+        .enumerate()
+        .filter_map(|(i, package)| Some((i, package.as_ref()?)))
+        .filter(|(_, package)| package.is_local() && is_root(&package.name))
+        .map(|(i, _)| i)
+        .collect();
+    while let Some(i) = to_visit.pop() {
+        if !reachable.insert(i) {
+            continue;
+        }
+        for dependency in packages[i].iter().flat_map(|package| &package.dependencies) {
+            let (name, _, _) = parse_dependency(dependency);
+            let candidates = packages_by_name.get(name).into_iter().flatten();
+            to_visit.extend(candidates.filter(|j| {
+                packages[**j]
+                    .as_ref()
+                    .is_some_and(|package| package.matches(dependency))
+            }));
+        }
+    }
+    let is_unreachable = |i: usize| packages[i].is_some() && !reachable.contains(&i);
+    for (i, package) in packages.iter().enumerate() {
+        match package {
+            Some(package) if !reachable.contains(&i) => log::debug!(
+                "Removing the unreachable package `{}` from Cargo.lock",
+                package.name
+            ),
+            _ => {}
+        }
+    }
+
+    if let Some(entries) = packages_mut(lock_file) {
+        let mut i = 0;
+        entries.retain(|_| {
+            i += 1;
+            !is_unreachable(i - 1)
+        });
+    }
+
+    let removed_checksums: HashSet<String> = packages
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| is_unreachable(*i))
+        .filter_map(|(_, package)| {
+            let package = package.as_ref()?;
+            let source = package.source.as_ref()?;
+            Some(format!(
+                "checksum {} {} ({})",
+                package.name, package.version, source
+            ))
+        })
+        .collect();
+    if let Some(metadata) = lock_file
+        .get_mut("metadata")
+        .and_then(|metadata| metadata.as_table_mut())
+    {
+        *metadata = std::mem::take(metadata)
+            .into_iter()
+            .filter(|(key, _)| !removed_checksums.contains(key))
+            .collect();
+    }
+}
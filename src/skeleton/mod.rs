@@ -1,27 +1,164 @@
+mod dev_dependencies;
+mod external;
+mod include;
+mod lockfile;
 mod read;
+mod stale_lockfile;
+mod validate;
 mod version_masking;
+mod workspace;
 
-use crate::OptimisationProfile;
+pub use include::IncludedFile;
+pub(crate) use read::cook_args_from_metadata;
+pub use validate::Violation;
+pub use version_masking::DEFAULT_MASK_VERSION;
+pub(crate) use workspace::find_workspace_root;
+
+use crate::{DirectDependency, OptimisationProfile, PrepareArgs};
 use anyhow::Context;
 use fs_err as fs;
-use globwalk::GlobWalkerBuilder;
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Component, Path, PathBuf};
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct Skeleton {
     pub manifests: Vec<Manifest>,
     pub config_file: Option<String>,
     pub lock_file: Option<String>,
+    /// The toolchain file pinning the Rust toolchain for the project, if any.
+    #[serde(default)]
+    pub rust_toolchain_file: Option<(RustToolchainFile, String)>,
+    /// The placeholder version used for all local crates.
+    #[serde(default = "default_mask_version")]
+    pub mask_version: String,
+    /// The files listed in `[package.metadata.chef] include`, restored verbatim.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub included_files: Vec<IncludedFile>,
+}
+
+fn default_mask_version() -> String {
+    DEFAULT_MASK_VERSION.to_string()
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RustToolchainFile {
+    /// The legacy `rust-toolchain` file.
+    Bare,
+    /// `rust-toolchain.toml`.
+    Toml,
+}
+
+impl RustToolchainFile {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            RustToolchainFile::Bare => "rust-toolchain",
+            RustToolchainFile::Toml => "rust-toolchain.toml",
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct Manifest {
-    /// Relative path with respect to the project root.
+    /// Relative path with respect to the project root, with `/` separators.
+    #[serde(deserialize_with = "deserialize_recipe_path")]
     pub relative_path: PathBuf,
     pub contents: String,
 }
 
+/// Recipes produced on Windows by older versions of `cargo-chef` store `\`-separated paths.
+fn deserialize_recipe_path<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<PathBuf, D::Error> {
+    let path = String::deserialize(deserializer)?;
+    Ok(PathBuf::from(normalize_path_separators(&path)))
+}
+
+/// Render a path stored in a recipe with `/` separators, whatever the platform it is produced
+/// on: `crates\api\Cargo.toml` becomes `crates/api/Cargo.toml`.
+pub fn normalize_path_separators(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+/// The platform-specific form of a path stored in a recipe, possibly produced on another
+/// platform.
+fn platform_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = normalize_path_separators(&path.as_ref().to_string_lossy());
+    Path::new(&path).components().collect()
+}
+
+/// Plan to write `contents` to `path`: the last contents planned for a path win.
+fn plan<P: AsRef<Path>, C: AsRef<str>>(files: &mut Vec<(PathBuf, String)>, path: P, contents: C) {
+    files.push((path.as_ref().to_path_buf(), contents.as_ref().to_owned()));
+}
+
+/// Write `files`, creating their parent directories, after checking that none of them would end
+/// up outside of `base_path`: the recipe may come from an untrusted source. It returns the paths
+/// of the files, relative to `base_path`.
+fn write_within(
+    base_path: &Path,
+    files: Vec<(PathBuf, String)>,
+) -> Result<Vec<PathBuf>, anyhow::Error> {
+    // Nothing below `base_path` can be a link if it does not exist yet.
+    let canonical_base_path = fs::canonicalize(base_path).ok();
+    let mut checked = vec![];
+    for (path, contents) in files {
+        let outside = || {
+            anyhow::anyhow!(
+                "Refusing to write {:?}: the recipe points outside of the project.",
+                path
+            )
+        };
+        let relative_path = path.strip_prefix(base_path).map_err(|_| outside())?;
+        // `..` is resolved lexically, e.g. for `path = "../shared/main.rs"` in a member.
+        let mut normalized = PathBuf::new();
+        for component in relative_path.components() {
+            match component {
+                Component::Normal(component) => normalized.push(component),
+                Component::CurDir => {}
+                Component::ParentDir if normalized.pop() => {}
+                _ => return Err(outside()),
+            }
+        }
+        if normalized.components().next().is_none() {
+            return Err(outside());
+        }
+        // The existing directories (or files) must not be links to the outside of the project.
+        if let Some(canonical_base_path) = &canonical_base_path {
+            let mut ancestor = base_path.to_path_buf();
+            for component in normalized.components() {
+                ancestor.push(component);
+                let is_link = fs::symlink_metadata(&ancestor)
+                    .is_ok_and(|metadata| metadata.file_type().is_symlink());
+                if is_link
+                    && !fs::canonicalize(&ancestor)
+                        .is_ok_and(|target| target.starts_with(canonical_base_path))
+                {
+                    anyhow::bail!(
+                        "Refusing to write {:?}: {:?} is a link to the outside of the project.",
+                        path,
+                        ancestor
+                    );
+                }
+            }
+        }
+        checked.push((normalized, contents));
+    }
+
+    for (relative_path, contents) in &checked {
+        let path = base_path.join(relative_path);
+        if let Some(parent_directory) = path.parent() {
+            fs::create_dir_all(parent_directory)?;
+        }
+        log::info!("Writing {:?}", path);
+        fs::write(&path, contents)?;
+    }
+    Ok(checked
+        .into_iter()
+        .map(|(relative_path, _)| relative_path)
+        .collect())
+}
+
 pub(in crate::skeleton) struct ParsedManifest {
     relative_path: PathBuf,
     contents: toml::Value,
@@ -29,22 +166,147 @@ pub(in crate::skeleton) struct ParsedManifest {
 
 impl Skeleton {
     /// Find all Cargo.toml files in `base_path` by traversing sub-directories recursively.
-    pub fn derive<P: AsRef<Path>>(
-        base_path: P,
-        member: Option<String>,
-    ) -> Result<Self, anyhow::Error> {
+    ///
+    /// If `args.members` is not empty, the skeleton is scoped to the specified workspace members.
+    pub fn derive<P: AsRef<Path>>(base_path: P, args: &PrepareArgs) -> Result<Self, anyhow::Error> {
+        // The skeleton must not depend on the order members were specified in.
+        let mut members = args.members.clone();
+        members.sort();
+        members.dedup();
+
         // Read relevant files from the filesystem
         let config_file = read::config(&base_path)?;
-        let mut manifests = read::manifests(&base_path, config_file.as_deref())?;
-        if let Some(member) = member.to_owned() {
-            ignore_all_members_except(&mut manifests, member);
+        let mut manifests =
+            read::manifests(&base_path, config_file.as_deref(), !args.no_gitignore)?;
+        external::include_external_path_dependencies(base_path.as_ref(), &mut manifests)?;
+        if !members.is_empty() {
+            ensure_members_exist(&manifests, &members)?;
+            ignore_all_members_except(&mut manifests, &members)?;
         }
+        expand_member_patterns(&mut manifests);
 
         let mut lock_file = read::lockfile(&base_path)?;
+        if args.locked && lock_file.is_none() {
+            anyhow::bail!("`--locked` was specified, but no Cargo.lock file was found.");
+        }
+        // Masking or pruning a lockfile in a format we do not know could produce a subtly wrong
+        // recipe: it is copied as is instead, unless `strict_lockfile` is set.
+        let mut unsupported_lock_file = None;
+        if let Some(version) = lock_file
+            .as_ref()
+            .and_then(read::unsupported_lockfile_version)
+        {
+            if args.strict_lockfile {
+                anyhow::bail!(
+                    "Cargo.lock has an unsupported version ({}): the latest version cargo-chef \
+                    knows about is {}. Are you using a newer cargo?",
+                    version,
+                    read::MAX_LOCKFILE_VERSION
+                );
+            }
+            eprintln!(
+                "warning: Cargo.lock has an unsupported version ({}), the latest version \
+                cargo-chef knows about is {}: it is copied to the recipe as is, without masking \
+                the versions of the local crates.",
+                version,
+                read::MAX_LOCKFILE_VERSION
+            );
+            unsupported_lock_file = lock_file.take();
+        }
+        if let Some(lock_file) = &lock_file {
+            let missing = stale_lockfile::missing_requirements(&manifests, lock_file);
+            if !missing.is_empty() {
+                let missing: String = missing
+                    .iter()
+                    .map(|requirement| format!("\n  - {}", requirement))
+                    .collect();
+                if !args.allow_stale_lockfile {
+                    anyhow::bail!(
+                        "Cargo.lock is out of sync with the manifests: `cook` would fail to \
+                        resolve the dependencies with `--locked`, or update the lockfile \
+                        otherwise. Run `cargo update --workspace` to update it (or pass \
+                        `--allow-stale-lockfile` to only warn). It pins no package for:{}",
+                        missing
+                    );
+                }
+                eprintln!(
+                    "warning: Cargo.lock is out of sync with the manifests: `cook` will update it, \
+                    or fail with `--locked`. It pins no package for:{}",
+                    missing
+                );
+            }
+        }
+        if lock_file.is_none() && unsupported_lock_file.is_none() {
+            let unpinned_patches = unpinned_git_patches(&manifests);
+            if !unpinned_patches.is_empty() {
+                eprintln!(
+                    "warning: there is no Cargo.lock to pin the commit of the git patches {}: \
+                    `cook` may build a different commit than the final build.",
+                    unpinned_patches.join(", ")
+                );
+            }
+        }
+        let rust_toolchain_file = read::rust_toolchain(&base_path)?;
+        // The config is still needed above, e.g. to locate vendored sources.
+        let config_file = if args.no_cargo_config {
+            None
+        } else {
+            config_file.map(|contents| contents.replace("\r\n", "\n"))
+        };
+
+        for manifest in &manifests {
+            skip_build(&manifest.contents)
+                .with_context(|| format!("Failed to read {:?}.", manifest.relative_path))?;
+        }
+        let mut included_files = include::included_files(base_path.as_ref(), &manifests)?;
+        included_files.extend(include::project_files(base_path.as_ref(), &args.include)?);
+        included_files.extend(include::sqlx_offline_data(base_path.as_ref(), &manifests)?);
+        included_files.extend(include::build_scripts(
+            base_path.as_ref(),
+            &manifests,
+            args.keep_build_scripts,
+        )?);
+        included_files.extend(include::target_specs(
+            base_path.as_ref(),
+            &args.target_specs,
+        )?);
+        included_files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+        included_files.dedup_by(|a, b| a.relative_path == b.relative_path);
+        if args.no_dev_dependencies {
+            dev_dependencies::remove_dev_dependencies(&mut manifests, &mut lock_file);
+        }
+        if !args.keep_metadata {
+            remove_metadata(&mut manifests);
+        }
+        remove_cosmetic_package_keys(&mut manifests);
+        // Dependencies only used by the members that were left out must not invalidate the
+        // recipe.
+        if let (false, false, Some(lock_file)) = (
+            members.is_empty(),
+            args.no_lockfile_pruning,
+            lock_file.as_mut(),
+        ) {
+            // Only the packages that are needed are left in the skeleton at this point.
+            let local_package_names = version_masking::parse_local_crate_names(&[], &manifests);
+            lockfile::remove_unreachable_packages(lock_file, |name| {
+                local_package_names.contains(name)
+            });
+        }
 
-        version_masking::mask_local_crate_versions(&member, &mut manifests, &mut lock_file);
+        let masking_report = version_masking::mask_local_crate_versions(
+            &members,
+            &args.mask_version,
+            &mut manifests,
+            &mut lock_file,
+        );
+        if args.print_masked {
+            eprint!("{}", masking_report);
+        }
 
-        let lock_file = lock_file.map(|l| toml::to_string(&l)).transpose()?;
+        let lock_file = lock_file
+            .or(unsupported_lock_file)
+            .map(|l| toml::to_string(&canonicalize_lockfile(l)))
+            .transpose()?;
 
         let mut serialised_manifests = serialize_manifests(manifests)?;
         // We don't want an ordering issue (e.g. related to how files are read from the filesystem)
@@ -55,32 +317,116 @@ impl Skeleton {
             manifests: serialised_manifests,
             config_file,
             lock_file,
+            rust_toolchain_file,
+            mask_version: args.mask_version.clone(),
+            included_files,
         })
     }
 
+    /// A copy of the skeleton with canonical contents (sorted keys, `\n` line endings), even if
+    /// it was produced by an older version of `cargo-chef` with different serialisation rules.
+    pub(crate) fn canonicalized(&self) -> Result<Self, anyhow::Error> {
+        let mut manifests = self
+            .manifests
+            .iter()
+            .map(|manifest| {
+                let contents: toml::Value = manifest.contents.parse()?;
+                Ok(Manifest {
+                    relative_path: manifest.relative_path.clone(),
+                    contents: toml::to_string(&canonicalize(contents))?,
+                })
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        manifests.sort_by_key(|m| m.relative_path.clone());
+        let lock_file = self
+            .lock_file
+            .as_deref()
+            .map(|lock_file| -> Result<_, anyhow::Error> {
+                Ok(toml::to_string(&canonicalize_lockfile(lock_file.parse()?))?)
+            })
+            .transpose()?;
+        let normalize = |contents: &str| contents.replace("\r\n", "\n");
+        Ok(Skeleton {
+            manifests,
+            config_file: self.config_file.as_deref().map(normalize),
+            lock_file,
+            rust_toolchain_file: self
+                .rust_toolchain_file
+                .as_ref()
+                .map(|(file_kind, content)| (*file_kind, normalize(content))),
+            mask_version: self.mask_version.clone(),
+            included_files: self
+                .included_files
+                .iter()
+                .map(|included_file| IncludedFile {
+                    relative_path: included_file.relative_path.clone(),
+                    contents: normalize(&included_file.contents),
+                })
+                .collect(),
+        })
+    }
+
+    /// Is `relative_path` (e.g. `./crates/api/Cargo.toml`) the path of one of the manifests?
+    pub fn contains_manifest(&self, relative_path: &Path) -> bool {
+        let relative_path: PathBuf = relative_path
+            .components()
+            .filter(|component| component != &Component::CurDir)
+            .collect();
+        self.manifests
+            .iter()
+            .any(|manifest| platform_path(&manifest.relative_path) == relative_path)
+    }
+
+    /// The path, relative to the project, of the target specification embedded in the recipe
+    /// that `target` (e.g. `--target ./specs/my-target.json`) refers to, if any.
+    pub(crate) fn target_spec(&self, target: &str) -> Option<PathBuf> {
+        if !target.ends_with(".json") {
+            return None;
+        }
+        let target: PathBuf = Path::new(target)
+            .components()
+            .filter(|component| component != &Component::CurDir)
+            .collect();
+        self.included_files
+            .iter()
+            .map(|file| platform_path(&file.relative_path))
+            .find(|relative_path| relative_path == &target)
+    }
+
     /// Given the manifests in the current skeleton, create the minimum set of files required to
     /// have a valid Rust project (i.e. write all manifests to disk and create dummy `lib.rs`,
     /// `main.rs` and `build.rs` files where needed).
     ///
     /// This function should be called on an empty canvas - i.e. an empty directory apart from
     /// the recipe file used to restore the skeleton.
+    ///
+    /// It returns the paths of the files it wrote, relative to `base_path`.
     pub fn build_minimum_project(
         &self,
         base_path: &Path,
         no_std: bool,
-    ) -> Result<(), anyhow::Error> {
+    ) -> Result<Vec<PathBuf>, anyhow::Error> {
+        // Nothing is written until all the paths were checked.
+        let mut files = vec![];
         // Save lockfile to disk, if available
         if let Some(lock_file) = &self.lock_file {
             let lock_file_path = base_path.join("Cargo.lock");
-            fs::write(lock_file_path, lock_file.as_str())?;
+            plan(&mut files, lock_file_path, lock_file.as_str());
         }
 
         // save config file to disk, if available
         if let Some(config_file) = &self.config_file {
-            let parent_dir = base_path.join(".cargo");
-            let config_file_path = parent_dir.join("config.toml");
-            fs::create_dir_all(parent_dir)?;
-            fs::write(config_file_path, config_file.as_str())?;
+            let config_file_path = base_path.join(".cargo").join("config.toml");
+            plan(&mut files, config_file_path, config_file.as_str());
+        }
+
+        // save rust toolchain file to disk, if available
+        if let Some((file_kind, content)) = &self.rust_toolchain_file {
+            plan(
+                &mut files,
+                base_path.join(file_kind.file_name()),
+                content.as_str(),
+            );
         }
 
         let no_std_entrypoint = "#![no_std]
@@ -95,16 +441,14 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
         // Save all manifests to disks
         for manifest in &self.manifests {
             // Persist manifest
-            let manifest_path = base_path.join(&manifest.relative_path);
-            let parent_directory = if let Some(parent_directory) = manifest_path.parent() {
-                fs::create_dir_all(parent_directory)?;
-                parent_directory.to_path_buf()
-            } else {
-                base_path.to_path_buf()
+            let manifest_path = base_path.join(platform_path(&manifest.relative_path));
+            let parent_directory = match manifest_path.parent() {
+                Some(parent_directory) => parent_directory.to_path_buf(),
+                None => base_path.to_path_buf(),
             };
-            fs::write(&manifest_path, &manifest.contents)?;
+            plan(&mut files, &manifest_path, &manifest.contents);
             let parsed_manifest =
-                cargo_manifest::Manifest::from_slice(manifest.contents.as_bytes())?;
+                read::typed_manifest(&manifest.contents, &manifest.contents.parse()?)?;
 
             let package_name = parsed_manifest.package.as_ref().map(|v| &v.name);
             // Create dummy entrypoint files for all binaries
@@ -116,29 +460,23 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
                     }
                     _ => "src/main.rs".to_owned(),
                 });
-                let binary_path = parent_directory.join(binary_relative_path);
-                if let Some(parent_directory) = binary_path.parent() {
-                    fs::create_dir_all(parent_directory)?;
-                }
+                let binary_path = parent_directory.join(platform_path(binary_relative_path));
                 if no_std {
-                    fs::write(binary_path, no_std_entrypoint)?;
+                    plan(&mut files, binary_path, no_std_entrypoint);
                 } else {
-                    fs::write(binary_path, "fn main() {}")?;
+                    plan(&mut files, binary_path, "fn main() {}");
                 }
             }
 
             // Create dummy entrypoint files for for all libraries
-            for lib in &parsed_manifest.lib {
+            if let Some(lib) = &parsed_manifest.lib {
                 // Relative to the manifest path
                 let lib_relative_path = lib.path.as_deref().unwrap_or("src/lib.rs");
-                let lib_path = parent_directory.join(lib_relative_path);
-                if let Some(parent_directory) = lib_path.parent() {
-                    fs::create_dir_all(parent_directory)?;
-                }
+                let lib_path = parent_directory.join(platform_path(lib_relative_path));
                 if no_std && !lib.proc_macro {
-                    fs::write(lib_path, "#![no_std]")?;
+                    plan(&mut files, lib_path, "#![no_std]");
                 } else {
-                    fs::write(lib_path, "")?;
+                    plan(&mut files, lib_path, "");
                 }
             }
 
@@ -150,11 +488,8 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
                     .path
                     .clone()
                     .unwrap_or_else(|| format!("benches/{}.rs", bench_name));
-                let bench_path = parent_directory.join(bench_relative_path);
-                if let Some(parent_directory) = bench_path.parent() {
-                    fs::create_dir_all(parent_directory)?;
-                }
-                fs::write(bench_path, "fn main() {}")?;
+                let bench_path = parent_directory.join(platform_path(bench_relative_path));
+                plan(&mut files, bench_path, "fn main() {}");
             }
 
             // Create dummy entrypoint files for for all tests
@@ -165,13 +500,11 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
                     .path
                     .clone()
                     .unwrap_or_else(|| format!("tests/{}.rs", test_name));
-                let test_path = parent_directory.join(test_relative_path);
-                if let Some(parent_directory) = test_path.parent() {
-                    fs::create_dir_all(parent_directory)?;
-                }
+                let test_path = parent_directory.join(platform_path(test_relative_path));
                 if no_std {
                     if test.harness {
-                        fs::write(
+                        plan(
+                            &mut files,
                             test_path,
                             r#"#![no_std]
 #![no_main]
@@ -188,14 +521,14 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
     loop {}
 }
 "#,
-                        )?;
+                        );
                     } else {
-                        fs::write(test_path, no_std_entrypoint)?;
+                        plan(&mut files, test_path, no_std_entrypoint);
                     }
                 } else if test.harness {
-                    fs::write(test_path, "")?;
+                    plan(&mut files, test_path, "");
                 } else {
-                    fs::write(test_path, "fn main() {}")?;
+                    plan(&mut files, test_path, "fn main() {}");
                 }
             }
 
@@ -207,37 +540,44 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
                     .path
                     .clone()
                     .unwrap_or_else(|| format!("examples/{}.rs", example_name));
-                let example_path = parent_directory.join(example_relative_path);
-                if let Some(parent_directory) = example_path.parent() {
-                    fs::create_dir_all(parent_directory)?;
-                }
+                let example_path = parent_directory.join(platform_path(example_relative_path));
                 if no_std {
-                    fs::write(example_path, no_std_entrypoint)?;
+                    plan(&mut files, example_path, no_std_entrypoint);
                 } else {
-                    fs::write(example_path, "fn main() {}")?;
+                    plan(&mut files, example_path, "fn main() {}");
                 }
             }
 
             // Create dummy build script file if specified
             if let Some(package) = parsed_manifest.package {
-                if let Some(cargo_manifest::Value::String(build_raw_path)) = package.build {
+                // `build = true` points to the default location.
+                let build_raw_path = match package.build {
+                    Some(cargo_manifest::Value::String(build_raw_path)) => Some(build_raw_path),
+                    Some(cargo_manifest::Value::Boolean(true)) => Some("build.rs".to_owned()),
+                    _ => None,
+                };
+                if let Some(build_raw_path) = build_raw_path {
                     // Relative to the manifest path
-                    let build_relative_path = PathBuf::from(build_raw_path);
-                    let build_path = parent_directory.join(build_relative_path);
-                    if let Some(parent_directory) = build_path.parent() {
-                        fs::create_dir_all(parent_directory)?;
-                    }
-                    fs::write(build_path, "fn main() {}")?;
+                    let build_path = parent_directory.join(platform_path(build_raw_path));
+                    plan(&mut files, build_path, "fn main() {}");
                 }
             }
         }
-        Ok(())
+
+        // Included files take precedence over the dummy sources, e.g. for a real `build.rs`.
+        for included_file in &self.included_files {
+            let path = base_path.join(platform_path(&included_file.relative_path));
+            plan(&mut files, path, &included_file.contents);
+        }
+        write_within(base_path, files)
     }
 
-    /// Scan the target directory and remove all compilation artifacts for libraries and build
-    /// scripts from the current workspace.
-    /// Given the usage of dummy `lib.rs` and `build.rs` files, keeping them around leads to funny
-    /// compilation errors.
+    /// Scan the target directory and remove all compilation artifacts of the crates in the
+    /// current workspace: fingerprints, build script outputs, incremental caches, libraries and
+    /// binaries compiled from the dummy sources.
+    /// Keeping them around leads to funny compilation errors or, worse, to cargo not rebuilding
+    /// the workspace crates at all when the sources are copied over with matching timestamps.
+    /// The artifacts of the dependencies are left untouched.
     pub fn remove_compiled_dummies<P: AsRef<Path>>(
         &self,
         base_path: P,
@@ -256,62 +596,348 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
             OptimisationProfile::Other(custom_profile) => custom_profile,
         };
 
-        let target_directories: Vec<PathBuf> = target
-            .map_or(vec![target_dir.clone()], |targets| {
-                targets
-                    .iter()
-                    .map(|target| target_dir.join(target_str(target)))
-                    .collect()
-            })
+        // Build scripts and proc-macros are compiled for the host, outside of the directories
+        // of the targets.
+        let mut target_directories = vec![target_dir.join(&profile)];
+        for target in target.iter().flatten() {
+            target_directories.push(target_dir.join(target_str(target)).join(&profile));
+        }
+
+        let local_crates = self.local_crate_names()?;
+        for target_directory in &target_directories {
+            // Uplifted artifacts, e.g. `target/debug/app` or `target/debug/libapp.rlib`.
+            remove_local_artifacts(target_directory, &local_crates, true)?;
+            for subdirectory in [".fingerprint", "build", "deps", "examples", "incremental"] {
+                remove_local_artifacts(&target_directory.join(subdirectory), &local_crates, false)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Does the skeleton contain the offline query data of `sqlx` (`.sqlx/` or `sqlx-data.json`)?
+    pub(crate) fn has_sqlx_offline_data(&self) -> bool {
+        self.included_files
+            .iter()
+            .any(|file| include::is_sqlx_offline_data(&file.relative_path))
+    }
+
+    /// The packages marked with `package.metadata.chef.skip-build = true`, that `cook` leaves
+    /// out of the build.
+    pub(crate) fn skipped_packages(&self) -> Result<Vec<String>, anyhow::Error> {
+        let mut skipped = vec![];
+        for manifest in &self.manifests {
+            let contents: toml::Value = manifest.contents.parse()?;
+            let name = contents
+                .get("package")
+                .and_then(|package| package.get("name"))
+                .and_then(|name| name.as_str());
+            if let (Some(name), true) = (name, skip_build(&contents)?) {
+                skipped.push(name.to_owned());
+            }
+        }
+        Ok(skipped)
+    }
+
+    /// The members of the workspace a plain `cargo build` builds (all of them with `all`), the
+    /// local crates they depend on first: the `default-members` of the workspace, if set.
+    pub(crate) fn members_in_dependency_order(
+        &self,
+        all: bool,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let mut manifests = vec![];
+        for manifest in &self.manifests {
+            if manifest
+                .relative_path
+                .starts_with(external::EXTERNAL_DIRECTORY)
+            {
+                continue;
+            }
+            let contents: toml::Value = manifest.contents.parse()?;
+            manifests.push((manifest.relative_path.clone(), contents));
+        }
+        let default_members: Vec<&str> = manifests
             .iter()
-            .map(|path| path.join(&profile))
+            .find(|(relative_path, _)| relative_path == Path::new("Cargo.toml"))
+            .and_then(|(_, root)| root.get("workspace")?.get("default-members")?.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|member| member.as_str())
+            .collect();
+        let members = workspace::packages_in_dependency_order(&manifests);
+        if all || default_members.is_empty() {
+            return Ok(members);
+        }
+        let is_default_member = |name: &str| {
+            manifests.iter().any(|(relative_path, manifest)| {
+                let directory = relative_path.parent().unwrap_or_else(|| Path::new(""));
+                let package_name = manifest
+                    .get("package")
+                    .and_then(|package| package.get("name"))
+                    .and_then(|name| name.as_str());
+                package_name == Some(name)
+                    && default_members.iter().any(|pattern| {
+                        workspace::matches_member_pattern(pattern, &workspace::to_slash(directory))
+                    })
+            })
+        };
+        Ok(members
+            .into_iter()
+            .filter(|member| is_default_member(member))
+            .collect())
+    }
+
+    /// The registry packages pinned by the lockfile, as `(name, version)`.
+    pub(crate) fn registry_packages(&self) -> Result<Vec<(String, String)>, anyhow::Error> {
+        match &self.lock_file {
+            Some(lock_file) => Ok(lockfile::registry_packages(&lock_file.parse()?)),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// The registry packages of the lockfile that are not in `vendor_directory`, a directory
+    /// written by `cargo vendor`, as `<name> v<version>`.
+    ///
+    /// `cargo vendor` names the directory of each crate after it, with a `-<version>` suffix when
+    /// several versions are vendored: we look at the manifests they contain instead.
+    pub(crate) fn missing_vendored_packages(
+        &self,
+        vendor_directory: &Path,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let lock_file: toml::Value = match &self.lock_file {
+            Some(lock_file) => lock_file.parse()?,
+            None => return Ok(vec![]),
+        };
+        let mut vendored = HashSet::new();
+        for entry in fs::read_dir(vendor_directory)? {
+            let manifest_path = entry?.path().join("Cargo.toml");
+            if !manifest_path.is_file() {
+                continue;
+            }
+            let manifest: toml::Value = fs::read_to_string(&manifest_path)?
+                .parse()
+                .with_context(|| format!("Failed to parse the manifest at {:?}.", manifest_path))?;
+            let get = |key: &str| {
+                manifest
+                    .get("package")
+                    .and_then(|package| package.get(key))
+                    .and_then(|value| value.as_str())
+                    .map(|value| value.to_owned())
+            };
+            if let (Some(name), Some(version)) = (get("name"), get("version")) {
+                vendored.insert((name, version));
+            }
+        }
+        let mut missing: Vec<String> = lockfile::registry_packages(&lock_file)
+            .into_iter()
+            .filter(|package| !vendored.contains(package))
+            .map(|(name, version)| format!("{} v{}", name, version))
             .collect();
+        missing.sort();
+        missing.dedup();
+        Ok(missing)
+    }
 
+    /// The names of the packages of the workspace: all the packages of the skeleton, except for
+    /// the `path` dependencies living outside of the project.
+    pub(crate) fn member_names(&self) -> Result<Vec<String>, anyhow::Error> {
+        let mut names = vec![];
         for manifest in &self.manifests {
-            let parsed_manifest =
-                cargo_manifest::Manifest::from_slice(manifest.contents.as_bytes())?;
-            if let Some(package) = parsed_manifest.package.as_ref() {
-                for target_directory in &target_directories {
-                    // Remove dummy libraries.
-                    for lib in &parsed_manifest.lib {
-                        let library_name =
-                            lib.name.as_ref().unwrap_or(&package.name).replace('-', "_");
-                        let walker = GlobWalkerBuilder::from_patterns(
-                            target_directory,
-                            &[
-                                format!("/**/lib{}.*", library_name),
-                                format!("/**/lib{}-*", library_name),
-                            ],
-                        )
-                        .build()?;
-                        for file in walker {
-                            let file = file?;
-                            if file.file_type().is_file() {
-                                fs::remove_file(file.path())?;
-                            } else if file.file_type().is_dir() {
-                                fs::remove_dir_all(file.path())?;
-                            }
-                        }
-                    }
+            if manifest
+                .relative_path
+                .starts_with(external::EXTERNAL_DIRECTORY)
+            {
+                continue;
+            }
+            let contents: toml::Value = manifest.contents.parse()?;
+            let name = contents
+                .get("package")
+                .and_then(|package| package.get("name"))
+                .and_then(|name| name.as_str());
+            names.extend(name.map(|name| name.to_owned()));
+        }
+        Ok(names)
+    }
 
-                    // Remove dummy build.rs script artifacts.
-                    if package.build.is_some() {
-                        let walker = GlobWalkerBuilder::new(
-                            target_directory,
-                            format!("/build/{}-*/build[-_]script[-_]build*", package.name),
-                        )
-                        .build()?;
-                        for file in walker {
-                            let file = file?;
-                            fs::remove_file(file.path())?;
+    /// The remote dependencies of the members of the workspace, sorted and deduplicated: the
+    /// entries of their dependency tables that are not `path` dependencies, with the ones
+    /// inherited with `workspace = true` resolved against `[workspace.dependencies]`.
+    pub(crate) fn direct_dependencies(&self) -> Result<Vec<DirectDependency>, anyhow::Error> {
+        let root_manifest: Option<toml::Value> = self
+            .manifests
+            .iter()
+            .find(|manifest| manifest.relative_path == Path::new("Cargo.toml"))
+            .map(|manifest| manifest.contents.parse())
+            .transpose()?;
+        let workspace_dependencies = root_manifest
+            .as_ref()
+            .and_then(|manifest| manifest.get("workspace"))
+            .and_then(|workspace| workspace.get("dependencies"))
+            .and_then(|dependencies| dependencies.as_table());
+
+        let mut dependencies = BTreeSet::new();
+        for manifest in &self.manifests {
+            if manifest
+                .relative_path
+                .starts_with(external::EXTERNAL_DIRECTORY)
+            {
+                continue;
+            }
+            let contents: toml::Value = manifest.contents.parse()?;
+            if contents.get("package").is_none() {
+                continue;
+            }
+            for table in version_masking::dependency_tables(&contents) {
+                for (key, dependency) in table.iter() {
+                    let inherited = dependency
+                        .get("workspace")
+                        .and_then(|workspace| workspace.as_bool())
+                        .unwrap_or(false);
+                    let dependency = match (inherited, workspace_dependencies) {
+                        (true, Some(workspace_dependencies)) => {
+                            match workspace_dependencies.get(key) {
+                                Some(dependency) => dependency,
+                                None => continue,
+                            }
                         }
+                        _ => dependency,
+                    };
+                    if dependency.get("path").is_some() {
+                        continue;
                     }
+                    let get = |key: &str| {
+                        dependency
+                            .get(key)
+                            .and_then(|value| value.as_str())
+                            .map(|value| value.to_owned())
+                    };
+                    dependencies.insert(DirectDependency {
+                        name: version_masking::dependency_package_name(key, dependency).to_owned(),
+                        requirement: dependency
+                            .as_str()
+                            .map(|requirement| requirement.to_owned())
+                            .or_else(|| get("version")),
+                        git: get("git"),
+                    });
                 }
             }
         }
+        Ok(dependencies.into_iter().collect())
+    }
 
-        Ok(())
+    /// How many remote packages the lockfile pins, as `(direct, transitive)`: the ones the local
+    /// crates depend on directly, and the other ones. `None` without a lockfile.
+    pub(crate) fn remote_package_counts(&self) -> Result<Option<(usize, usize)>, anyhow::Error> {
+        match &self.lock_file {
+            Some(lock_file) => Ok(Some(lockfile::remote_package_counts(&lock_file.parse()?))),
+            None => Ok(None),
+        }
+    }
+
+    /// The names of the binaries of the workspace, sorted: the `[[bin]]` targets of the local
+    /// crates, declared in their manifests or discovered by `prepare`.
+    pub(crate) fn binary_names(&self) -> Result<Vec<String>, anyhow::Error> {
+        let mut names = vec![];
+        for manifest in &self.manifests {
+            if manifest
+                .relative_path
+                .starts_with(external::EXTERNAL_DIRECTORY)
+            {
+                continue;
+            }
+            let contents: toml::Value = manifest.contents.parse()?;
+            let binaries = contents
+                .get("bin")
+                .and_then(|binaries| binaries.as_array())
+                .into_iter()
+                .flatten();
+            names.extend(
+                binaries
+                    .filter_map(|binary| binary.get("name")?.as_str())
+                    .map(|name| name.to_owned()),
+            );
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// The names of all the packages and targets in the skeleton, with `-` replaced by `_`.
+    fn local_crate_names(&self) -> Result<HashSet<String>, anyhow::Error> {
+        let mut names = HashSet::new();
+        for manifest in &self.manifests {
+            let parsed_manifest =
+                read::typed_manifest(&manifest.contents, &manifest.contents.parse()?)?;
+            if let Some(package) = &parsed_manifest.package {
+                names.insert(package.name.clone());
+            }
+            let targets = parsed_manifest
+                .lib
+                .iter()
+                .chain(parsed_manifest.bin.iter().flatten())
+                .chain(parsed_manifest.bench.iter().flatten())
+                .chain(parsed_manifest.test.iter().flatten())
+                .chain(parsed_manifest.example.iter().flatten());
+            names.extend(targets.filter_map(|target| target.name.clone()));
+        }
+        Ok(names.iter().map(|name| name.replace('-', "_")).collect())
+    }
+}
+
+/// Remove the entries of `directory` that belong to one of `local_crates`.
+/// Artifacts are named after their crate, followed by a hash (e.g. `libapp-4a3f2c1d0e9b8a7f.rlib`
+/// or `.fingerprint/app-4a3f2c1d0e9b8a7f`) unless they have been uplifted to the root of the
+/// profile directory.
+fn remove_local_artifacts(
+    directory: &Path,
+    local_crates: &HashSet<String>,
+    uplifted: bool,
+) -> Result<(), anyhow::Error> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if uplifted && !file_type.is_file() {
+            continue;
+        }
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        let stem = file_name.split('.').next().unwrap_or_default();
+        let name = if uplifted {
+            Some(stem)
+        } else {
+            strip_artifact_hash(stem)
+        };
+        let is_local = name.is_some_and(|name| {
+            let name = name.replace('-', "_");
+            let unprefixed = name.strip_prefix("lib").unwrap_or(&name);
+            local_crates.contains(&name) || local_crates.contains(unprefixed)
+        });
+        if !is_local {
+            continue;
+        }
+        if file_type.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+        }
     }
+    Ok(())
+}
+
+/// `app-4a3f2c1d0e9b8a7f` → `app`.
+/// Cargo uses 16 hexadecimal digits as hash, rustc's incremental cache 13 base-36 digits.
+fn strip_artifact_hash(stem: &str) -> Option<&str> {
+    let (name, hash) = stem.rsplit_once('-')?;
+    let is_hash = hash.len() >= 13
+        && hash
+            .chars()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_lowercase());
+    is_hash.then_some(name)
 }
 
 /// If a custom target spec file is used,
@@ -319,44 +945,470 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
 /// the `--target` flag refers to a `.json` file in the current directory.
 /// In this case, the actual name of the target is the value of `--target` without the `.json` suffix.
 fn target_str(target: &str) -> &str {
-    target.trim_end_matches(".json")
+    // Cargo names the directory of a custom target after its specification file.
+    match target.strip_suffix(".json") {
+        Some(spec) => Path::new(spec)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(spec),
+        None => target,
+    }
 }
 
 fn serialize_manifests(manifests: Vec<ParsedManifest>) -> Result<Vec<Manifest>, anyhow::Error> {
     let mut serialised_manifests = vec![];
-    for manifest in manifests {
+    for mut manifest in manifests {
+        // The same project must produce the same recipe on all platforms.
+        normalize_manifest_paths(&mut manifest.contents);
+        let relative_path = workspace::to_slash(&manifest.relative_path);
         // The serialised contents might be different from the original manifest!
-        let contents = toml::to_string(&manifest.contents)?;
+        let contents = toml::to_string(&canonicalize(manifest.contents))?;
         serialised_manifests.push(Manifest {
-            relative_path: manifest.relative_path,
+            relative_path: PathBuf::from(normalize_path_separators(&relative_path)),
             contents,
         });
     }
     Ok(serialised_manifests)
 }
 
-/// If the top-level `Cargo.toml` has a `members` field, replace it with
-/// a list consisting of just the specified member.
-fn ignore_all_members_except(manifests: &mut [ParsedManifest], member: String) {
-    let workspace_toml = manifests
-        .iter_mut()
-        .find(|manifest| manifest.relative_path == std::path::PathBuf::from("Cargo.toml"));
+/// Use `/` separators in all the paths of a manifest: the ones of its targets, of its build
+/// script, of its `path` dependencies and of its workspace members.
+fn normalize_manifest_paths(manifest: &mut toml::Value) {
+    let normalize = |value: &mut toml::Value| {
+        if let toml::Value::String(path) = value {
+            *path = normalize_path_separators(path);
+        }
+    };
+    if let Some(lib) = manifest.get_mut("lib").and_then(|lib| lib.get_mut("path")) {
+        normalize(lib);
+    }
+    for key in ["bin", "bench", "test", "example"] {
+        let targets = manifest
+            .get_mut(key)
+            .and_then(|targets| targets.as_array_mut());
+        for target in targets.into_iter().flatten() {
+            if let Some(path) = target.get_mut("path") {
+                normalize(path);
+            }
+        }
+    }
+    if let Some(build) = manifest
+        .get_mut("package")
+        .and_then(|package| package.get_mut("build"))
+    {
+        normalize(build);
+    }
+    if let Some(workspace) = manifest.get_mut("workspace") {
+        for key in ["members", "default-members", "exclude"] {
+            let paths = workspace
+                .get_mut(key)
+                .and_then(|paths| paths.as_array_mut());
+            paths.into_iter().flatten().for_each(normalize);
+        }
+    }
+    for dependencies in external::dependency_tables_mut(manifest) {
+        for (_, dependency) in dependencies.iter_mut() {
+            if let Some(path) = dependency.get_mut("path") {
+                normalize(path);
+            }
+        }
+    }
+}
+
+/// Render a TOML key path (e.g. `target."cfg(unix)".dependencies.foo.version`), quoting
+/// the keys that are not bare.
+///
+/// Array indexes are passed as `[i]` segments and rendered next to their key (e.g. `bin[0].path`).
+pub(crate) fn format_key_path(key_path: &[&str]) -> String {
+    let mut rendered = String::new();
+    for key in key_path {
+        let is_bare = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if key.starts_with('[') {
+            rendered.push_str(key);
+            continue;
+        }
+        if !rendered.is_empty() {
+            rendered.push('.');
+        }
+        if is_bare {
+            rendered.push_str(key);
+        } else {
+            rendered.push_str(&format!("{:?}", key));
+        }
+    }
+    rendered
+}
+
+/// Sort all tables by key, recursively.
+///
+/// The order of the keys in a table is not significant for cargo, but it leaks into the serialised
+/// skeleton: we do not want two manifests that only differ in how their keys are ordered (or in
+/// how the masking pass rewrote them) to produce different recipes and invalidate the cache.
+/// The order of array elements is preserved.
+fn canonicalize(value: toml::Value) -> toml::Value {
+    match value {
+        toml::Value::Table(table) => {
+            let mut entries: Vec<_> = table.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            toml::Value::Table(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, canonicalize(value)))
+                    .collect(),
+            )
+        }
+        toml::Value::Array(array) => {
+            toml::Value::Array(array.into_iter().map(canonicalize).collect())
+        }
+        value => value,
+    }
+}
+
+/// Canonicalize a lockfile.
+///
+/// Like `canonicalize`, but the keys of each `[[package]]` entry follow the order cargo uses
+/// when writing a lockfile (so that the recipe lockfile looks like one generated by cargo).
+fn canonicalize_lockfile(lock_file: toml::Value) -> toml::Value {
+    const PACKAGE_KEYS: [&str; 5] = ["name", "version", "source", "checksum", "dependencies"];
 
-    if let Some(members) = workspace_toml
-        .and_then(|toml| toml.contents.get_mut("workspace"))
-        .and_then(|workspace| workspace.get_mut("members"))
+    let mut lock_file = canonicalize(lock_file);
+    if let Some(packages) = lock_file
+        .get_mut("package")
+        .and_then(|packages| packages.as_array_mut())
     {
-        let mut search = member.clone();
-        search.insert_str(0, "/");
-        match members {
-            cargo_manifest::Value::Array(arr) => arr.retain(|i| {
-                if let cargo_manifest::Value::String(item) = i {
-                    item.contains(&search) || item.eq(&member)
-                } else {
-                    false
+        for package in packages.iter_mut() {
+            if let toml::Value::Table(table) = package {
+                let mut entries: Vec<_> = std::mem::take(table).into_iter().collect();
+                entries.sort_by_key(|(key, _)| {
+                    PACKAGE_KEYS
+                        .iter()
+                        .position(|k| k == key)
+                        .unwrap_or(PACKAGE_KEYS.len())
+                });
+                *table = entries.into_iter().collect();
+            }
+        }
+    }
+    lock_file
+}
+
+/// Remove the `[package.metadata]` and `[workspace.metadata]` tables: they are meant for
+/// other tools (e.g. `cargo-deb`) and editing them must not invalidate the recipe.
+/// `package.metadata.chef.skip-build`, which `cook` reads, is kept.
+fn remove_metadata(manifests: &mut [ParsedManifest]) {
+    for manifest in manifests {
+        let is_skipped = skip_build(&manifest.contents).unwrap_or(false);
+        for key in ["package", "workspace"] {
+            if let Some(table) = manifest
+                .contents
+                .get_mut(key)
+                .and_then(|table| table.as_table_mut())
+            {
+                table.remove("metadata");
+                if key == "package" && is_skipped {
+                    let mut chef = toml::value::Table::new();
+                    chef.insert(SKIP_BUILD.to_owned(), toml::Value::Boolean(true));
+                    let mut metadata = toml::value::Table::new();
+                    metadata.insert("chef".to_owned(), toml::Value::Table(chef));
+                    table.insert("metadata".to_owned(), toml::Value::Table(metadata));
                 }
-            }),
-            _ => panic!("Workspace manifest files has wrong format, members must be an array."),
-        };
+            }
+        }
+    }
+}
+
+/// The key of `[package.metadata.chef]` marking a crate that `cook` must not build, e.g.
+/// because its build script reads its real sources.
+const SKIP_BUILD: &str = "skip-build";
+
+/// Is the package of `manifest` marked with `package.metadata.chef.skip-build = true`?
+fn skip_build(manifest: &toml::Value) -> Result<bool, anyhow::Error> {
+    chef_flag(manifest, SKIP_BUILD)
+}
+
+/// The value of the boolean `package.metadata.chef.<key>`, `false` if it is not set.
+fn chef_flag(manifest: &toml::Value, key: &str) -> Result<bool, anyhow::Error> {
+    let value = manifest
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("chef"))
+        .and_then(|chef| chef.get(key));
+    match value {
+        None => Ok(false),
+        Some(toml::Value::Boolean(flag)) => Ok(*flag),
+        Some(_) => anyhow::bail!("`package.metadata.chef.{}` must be a boolean.", key),
+    }
+}
+
+/// The keys of `[package]` that have no effect on how the dependencies are compiled.
+///
+/// Everything else is kept, in particular `name`, `version` (masked), `edition`,
+/// `rust-version`, `links`, `build` and `publish`.
+const COSMETIC_PACKAGE_KEYS: [&str; 10] = [
+    "authors",
+    "categories",
+    "description",
+    "documentation",
+    "homepage",
+    "keywords",
+    "license",
+    "license-file",
+    "readme",
+    "repository",
+];
+
+/// Remove the [`COSMETIC_PACKAGE_KEYS`] from `[package]` and `[workspace.package]`, so that
+/// editing them does not invalidate the recipe.
+///
+/// Both are stripped together: a member inheriting one of them (`description.workspace = true`)
+/// must not outlive the value in the root manifest.
+fn remove_cosmetic_package_keys(manifests: &mut [ParsedManifest]) {
+    let strip = |package: Option<&mut toml::Value>| {
+        if let Some(package) = package.and_then(|package| package.as_table_mut()) {
+            for key in COSMETIC_PACKAGE_KEYS {
+                package.remove(key);
+            }
+        }
+    };
+    for manifest in manifests {
+        strip(manifest.contents.get_mut("package"));
+        strip(
+            manifest
+                .contents
+                .get_mut("workspace")
+                .and_then(|workspace| workspace.get_mut("package")),
+        );
+    }
+}
+
+/// Fail if any of the specified members does not match the name of a package in the workspace.
+fn ensure_members_exist(
+    manifests: &[ParsedManifest],
+    members: &[String],
+) -> Result<(), anyhow::Error> {
+    let package_names: Vec<&str> = manifests
+        .iter()
+        .filter_map(|manifest| manifest.contents.get("package")?.get("name")?.as_str())
+        .collect();
+    let missing: Vec<&str> = members
+        .iter()
+        .map(|member| member.as_str())
+        .filter(|member| !package_names.contains(member))
+        .collect();
+    if !missing.is_empty() {
+        let mut available = package_names;
+        available.sort_unstable();
+        return Err(anyhow::anyhow!(
+            "The following members could not be found in the workspace: {}.\nAvailable members: {}.",
+            missing.join(", "),
+            available.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// The `[patch]` entries pointing to a git branch rather than to a `rev`, as `` `<name>` (<source>) ``.
+fn unpinned_git_patches(manifests: &[ParsedManifest]) -> Vec<String> {
+    let mut patches = vec![];
+    for manifest in manifests {
+        for (source, entries) in version_masking::patch_tables(&manifest.contents) {
+            for (key, patch) in entries {
+                if patch.get("git").is_some() && patch.get("rev").is_none() {
+                    patches.push(format!("`{}` ({})", key, source));
+                }
+            }
+        }
+    }
+    patches
+}
+
+/// If the root `[workspace] members` contain wildcards (e.g. `crates/*`), replace them by the
+/// sorted list of the directories of the packages of the skeleton they match, minus the excluded
+/// ones.
+///
+/// At cook time, a pattern could match a directory of the real project without a stub in the
+/// skeleton (e.g. a non-Rust directory under `crates/`), and cargo would fail to load it.
+fn expand_member_patterns(manifests: &mut [ParsedManifest]) {
+    let root_manifest = Path::new("Cargo.toml");
+    let package_directories: Vec<String> = manifests
+        .iter()
+        .filter(|manifest| {
+            manifest.relative_path != root_manifest
+                && !manifest
+                    .relative_path
+                    .starts_with(external::EXTERNAL_DIRECTORY)
+                && manifest.contents.get("package").is_some()
+        })
+        .filter_map(|manifest| manifest.relative_path.parent())
+        .map(workspace::to_slash)
+        .collect();
+    let workspace = match manifests
+        .iter_mut()
+        .find(|manifest| manifest.relative_path == root_manifest)
+        .and_then(|manifest| manifest.contents.get_mut("workspace"))
+        .and_then(|workspace| workspace.as_table_mut())
+    {
+        Some(workspace) => workspace,
+        None => return,
+    };
+    let strings = |key: &str| -> Vec<String> {
+        workspace
+            .get(key)
+            .and_then(|values| values.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|value| value.as_str())
+            .map(|value| value.to_owned())
+            .collect()
+    };
+    let patterns = strings("members");
+    // Plain paths are left as the user wrote them.
+    if !patterns.iter().any(|pattern| pattern.contains(['*', '?'])) {
+        return;
+    }
+    let exclude = strings("exclude");
+    let mut members: Vec<&String> = package_directories
+        .iter()
+        .filter(|directory| {
+            patterns
+                .iter()
+                .any(|pattern| workspace::matches_member_pattern(pattern, directory))
+                && !Path::new(directory).ancestors().any(|ancestor| {
+                    let ancestor = workspace::to_slash(ancestor);
+                    exclude
+                        .iter()
+                        .any(|excluded| workspace::matches_member_pattern(excluded, &ancestor))
+                })
+        })
+        .collect();
+    members.sort();
+    members.dedup();
+    workspace.insert(
+        "members".to_owned(),
+        toml::Value::Array(
+            members
+                .into_iter()
+                .map(|member| toml::Value::String(member.to_owned()))
+                .collect(),
+        ),
+    );
+}
+
+/// Scope the workspace to the specified members and the local crates they (transitively) depend
+/// on: the manifests of all other packages are left out of the skeleton, and the `members`,
+/// `default-members` and `exclude` fields of the top-level `Cargo.toml` only list the packages
+/// that are kept.
+fn ignore_all_members_except(
+    manifests: &mut Vec<ParsedManifest>,
+    members: &[String],
+) -> Result<(), anyhow::Error> {
+    let root_manifest = Path::new("Cargo.toml");
+    let package_name = |manifest: &ParsedManifest| -> Option<String> {
+        Some(
+            manifest
+                .contents
+                .get("package")?
+                .get("name")?
+                .as_str()?
+                .to_owned(),
+        )
+    };
+
+    // A root package is always a workspace member: cargo needs it (and its local dependencies)
+    // even if it was not selected.
+    let mut roots = members.to_vec();
+    roots.extend(
+        manifests
+            .iter()
+            .filter(|manifest| manifest.relative_path == root_manifest)
+            .filter_map(package_name),
+    );
+    let local_package_names = version_masking::parse_local_crate_names(&roots, manifests);
+    manifests.retain(|manifest| {
+        manifest.relative_path == root_manifest
+            || package_name(manifest).is_none_or(|name| local_package_names.contains(&name))
+    });
+
+    let mut package_directories: Vec<String> = manifests
+        .iter()
+        .filter(|manifest| manifest.relative_path != root_manifest)
+        .filter(|manifest| package_name(manifest).is_some())
+        .filter_map(|manifest| manifest.relative_path.parent())
+        .map(workspace::to_slash)
+        .collect();
+    package_directories.sort();
+
+    let workspace = match manifests
+        .iter_mut()
+        .find(|manifest| manifest.relative_path == root_manifest)
+        .and_then(|manifest| manifest.contents.get_mut("workspace"))
+        .and_then(|workspace| workspace.as_table_mut())
+    {
+        Some(workspace) => workspace,
+        None => return Ok(()),
+    };
+    let patterns = |key: &str| -> Result<Vec<String>, anyhow::Error> {
+        match workspace.get(key) {
+            Some(toml::Value::Array(patterns)) => Ok(patterns
+                .iter()
+                .filter_map(|pattern| pattern.as_str())
+                .map(|pattern| pattern.to_owned())
+                .collect()),
+            Some(_) => anyhow::bail!(
+                "`workspace.{}` in the root manifest must be an array, e.g. `{} = [\"crates/*\"]`.",
+                key,
+                key
+            ),
+            None => Ok(vec![]),
+        }
+    };
+    let member_patterns = patterns("members")?;
+    let default_member_patterns = patterns("default-members")?;
+    let exclude: Vec<String> = patterns("exclude")?
+        .into_iter()
+        .filter(|excluded| {
+            package_directories
+                .iter()
+                .any(|directory| Path::new(directory).starts_with(excluded))
+        })
+        .collect();
+    // Only list the kept packages that were members to begin with: the other local crates
+    // are path dependencies, and cargo takes care of them.
+    let matching_directories = |patterns: &[String]| -> Vec<toml::Value> {
+        package_directories
+            .iter()
+            .filter(|directory| {
+                patterns
+                    .iter()
+                    .any(|pattern| workspace::matches_member_pattern(pattern, directory))
+                    && !exclude
+                        .iter()
+                        .any(|excluded| Path::new(directory).starts_with(excluded))
+            })
+            .map(|directory| toml::Value::String(directory.to_owned()))
+            .collect()
+    };
+    let members = matching_directories(&member_patterns);
+    let default_members = matching_directories(&default_member_patterns);
+
+    workspace.insert("members".to_owned(), toml::Value::Array(members));
+    if workspace.contains_key("default-members") {
+        // An empty list would select the root package only.
+        if default_members.is_empty() {
+            workspace.remove("default-members");
+        } else {
+            workspace.insert(
+                "default-members".to_owned(),
+                toml::Value::Array(default_members),
+            );
+        }
+    }
+    if workspace.contains_key("exclude") {
+        let exclude = exclude.into_iter().map(toml::Value::String).collect();
+        workspace.insert("exclude".to_owned(), toml::Value::Array(exclude));
     }
+    Ok(())
 }
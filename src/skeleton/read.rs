@@ -1,10 +1,12 @@
 //! Logic to read all the files required to build a caching layer for a project.
-use super::ParsedManifest;
+use super::{workspace, ParsedManifest, RustToolchainFile};
 use anyhow::Context;
-use globwalk::{GlobWalkerBuilder, WalkError};
+use ignore::{Error as WalkError, WalkBuilder, WalkState};
 use std::fs;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 
 pub(super) fn config<P: AsRef<Path>>(base_path: &P) -> Result<Option<String>, anyhow::Error> {
     // Given that we run primarily in Docker, assume to find config or config.toml at root level.
@@ -23,7 +25,9 @@ pub(super) fn config<P: AsRef<Path>>(base_path: &P) -> Result<Option<String>, an
     let config = file_contents("config").or_else(|_| file_contents("config.toml"));
 
     match config {
-        Ok(config) => Ok(Some(config)),
+        // Line endings depend on the platform (or the git settings) of whoever checked out the
+        // project: normalise them to keep the recipe stable.
+        Ok(config) => Ok(Some(config.replace("\r\n", "\n"))),
         Err(e) => {
             if std::io::ErrorKind::NotFound != e.kind() {
                 return Err(
@@ -46,85 +50,782 @@ fn vendored_directory(config_contents: Option<&str>) -> Option<String> {
     Some(vendored_sources.get("directory")?.as_str()?.to_owned())
 }
 
+/// A file with the syntax of `.gitignore`, listing the files `prepare` should never look at.
+pub(super) const CHEFIGNORE: &str = ".chefignore";
+
+/// Find and read all the manifests in `base_path`, except for the ones that are ignored:
+/// - the ones in the directories matched by a `.chefignore` or, if `respect_gitignore`, a
+///   `.gitignore` file;
+/// - the ones in the vendored sources and in cargo's target directories.
 pub(super) fn manifests<P: AsRef<Path>>(
     base_path: &P,
     config_contents: Option<&str>,
+    respect_gitignore: bool,
 ) -> Result<Vec<ParsedManifest>, anyhow::Error> {
-    let vendored_path = vendored_directory(config_contents);
-    let builder = if let Some(path) = vendored_path {
-        let exclude_vendored_sources = format!("!{}", path);
-        GlobWalkerBuilder::from_patterns(base_path, &["/**/Cargo.toml", &exclude_vendored_sources])
-    } else {
-        GlobWalkerBuilder::new(base_path, "/**/Cargo.toml")
+    let base_path = base_path.as_ref();
+    let vendored_path =
+        vendored_directory(config_contents).map(|path| lexical(&base_path.join(path)));
+    let default_target_directory = base_path.join("target");
+    let is_ignored_directory = move |directory: &Path| {
+        let is_target_directory = directory.file_name() == Some("target".as_ref())
+            && (directory == default_target_directory || directory.join("CACHEDIR.TAG").is_file());
+        is_target_directory || vendored_path.as_deref() == Some(lexical(directory).as_path())
     };
-    let walker = builder
-        .build()
-        .context("Failed to scan the files in the current directory.")?;
+    let is_walked_directory_ignored = is_ignored_directory.clone();
+    let walker = WalkBuilder::new(base_path)
+        // Symlinked members (e.g. `crates/proto -> ../generated/proto`) are read as if they
+        // lived at the location of the link: the skeleton never contains links.
+        .follow_links(true)
+        .hidden(false)
+        .ignore(false)
+        // Docker build contexts usually do not contain `.git`.
+        .require_git(false)
+        .git_global(false)
+        .git_ignore(respect_gitignore)
+        .git_exclude(respect_gitignore)
+        .add_custom_ignore_filename(CHEFIGNORE)
+        .filter_entry(move |entry| {
+            !(entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_dir())
+                && is_walked_directory_ignored(entry.path()))
+        })
+        .build_parallel();
+
+    // Directories are walked, and the manifests read and parsed as TOML, on all the available
+    // cores: on large monorepos (or slow filesystems) this is where `prepare` spends its time.
+    // The manifests are found in no particular order, we sort them by path afterwards.
+    let found = Mutex::new(vec![]);
+    let fatal_error = Mutex::new(None);
+    let scanned = AtomicUsize::new(0);
+    walker.run(|| {
+        Box::new(|manifest| {
+            let manifest = match manifest {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    return match handle_walk_error(e) {
+                        ErrorStrategy::Ignore => WalkState::Continue,
+                        ErrorStrategy::Crash(e) => {
+                            fatal_error.lock().unwrap().get_or_insert(e.into());
+                            WalkState::Quit
+                        }
+                    };
+                }
+            };
+            let is_file = manifest
+                .file_type()
+                .is_some_and(|file_type| file_type.is_file());
+            if is_file {
+                scanned.fetch_add(1, Ordering::Relaxed);
+            }
+            let is_manifest = manifest.file_name() == "Cargo.toml" && is_file;
+            if !is_manifest {
+                return WalkState::Continue;
+            }
+            match found_manifest(base_path, manifest.path()) {
+                Ok(manifest) => {
+                    found.lock().unwrap().push(manifest);
+                    WalkState::Continue
+                }
+                Err(e) => {
+                    fatal_error.lock().unwrap().get_or_insert(e);
+                    WalkState::Quit
+                }
+            }
+        })
+    });
+    if let Some(e) = fatal_error.into_inner().unwrap() {
+        return Err(e);
+    }
+    let mut found = found.into_inner().unwrap();
+    if found.is_empty() {
+        return Err(no_manifest_error(
+            base_path,
+            scanned.into_inner(),
+            respect_gitignore,
+            is_ignored_directory,
+        ));
+    }
+    found.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
 
+    // Aligned: the absolute path and contents of each manifest, and its relative path and raw
+    // TOML.
+    let mut files = vec![];
+    let mut raw_manifests = vec![];
+    // We keep going after an invalid manifest, to report all of them at once.
+    let mut errors = vec![];
+    for manifest in found {
+        match manifest.original {
+            Ok(original) => {
+                files.push((manifest.absolute_path, manifest.contents));
+                raw_manifests.push((manifest.relative_path, original));
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    // Manifests that do not belong to the workspace (e.g. nested workspaces) are never parsed:
+    // cargo does not look at them either.
+    let owned = workspace::owned_manifests(&raw_manifests);
+    let mut to_parse = vec![];
+    for (((absolute_path, contents), (relative_path, original)), owned) in
+        files.into_iter().zip(raw_manifests).zip(owned)
+    {
+        if !owned {
+            log::info!(
+                "Skipping {:?}: it does not belong to the workspace.",
+                relative_path
+            );
+            continue;
+        }
+        log::info!("Found manifest {:?}", relative_path);
+        to_parse.push((absolute_path, contents, relative_path, original));
+    }
+    let parsed = parallel_map(&to_parse, |(absolute_path, contents, _, original)| {
+        parse(absolute_path, contents, original)
+    });
     let mut manifests = vec![];
-    for manifest in walker {
-        match manifest {
-            Ok(manifest) => {
-                let absolute_path = manifest.path().to_path_buf();
-                let contents = fs::read_to_string(&absolute_path)?;
-
-                let mut parsed = cargo_manifest::Manifest::from_str(&contents)?;
-                // Required to detect bin/libs when the related section is omitted from the manifest
-                parsed.complete_from_path(&absolute_path)?;
-
-                let mut intermediate = toml::Value::try_from(parsed)?;
-
-                // Specifically, toml gives no guarantees to the ordering of the auto binaries
-                // in its results. We will manually sort these to ensure that the output
-                // manifest will match.
-                let bins = intermediate
-                    .get_mut("bin")
-                    .and_then(|bins| bins.as_array_mut());
-                if let Some(bins) = bins {
-                    bins.sort_by(|bin_a, bin_b| {
-                        let bin_a_path = bin_a
-                            .as_table()
-                            .and_then(|table| table.get("path").or_else(|| table.get("name")))
-                            .and_then(|path| path.as_str())
-                            .unwrap();
-                        let bin_b_path = bin_b
-                            .as_table()
-                            .and_then(|table| table.get("path").or_else(|| table.get("name")))
-                            .and_then(|path| path.as_str())
-                            .unwrap();
-                        bin_a_path.cmp(bin_b_path)
-                    });
+    for ((_, contents, relative_path, _), parsed) in to_parse.into_iter().zip(parsed) {
+        match parsed {
+            Ok(parsed) => manifests.push(ParsedManifest {
+                relative_path,
+                contents: parsed,
+            }),
+            Err(e) => errors.push(match toml_error(&e) {
+                Some(e) => describe_toml_error(&relative_path, &contents, e),
+                None => format!("{}: {:#}", relative_path.display(), e),
+            }),
+        }
+    }
+    if !errors.is_empty() {
+        anyhow::bail!(
+            "Failed to parse {} manifest{}:\n\n{}",
+            errors.len(),
+            if errors.len() == 1 { "" } else { "s" },
+            errors.join("\n\n")
+        );
+    }
+    Ok(manifests)
+}
+
+/// Explain why no manifest was found in `base_path`: the manifests may all be ignored, or
+/// `prepare` may have been run in the wrong directory, e.g. below the root of the workspace.
+fn no_manifest_error(
+    base_path: &Path,
+    scanned: usize,
+    respect_gitignore: bool,
+    is_ignored_directory: impl Fn(&Path) -> bool + Send + Sync + 'static,
+) -> anyhow::Error {
+    let mut message = format!(
+        "No Cargo.toml was found in {} ({} file{} scanned).",
+        base_path.display(),
+        scanned,
+        if scanned == 1 { "" } else { "s" }
+    );
+
+    // The same walk, without the ignore files.
+    let ignored = WalkBuilder::new(base_path)
+        .follow_links(true)
+        .standard_filters(false)
+        .filter_entry(move |entry| {
+            !(entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_dir())
+                && is_ignored_directory(entry.path()))
+        })
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry.file_name() == "Cargo.toml"
+                && entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_file())
+        })
+        .count();
+    if ignored > 0 {
+        message.push_str(&format!(
+            "\n{} Cargo.toml file{} ignored by `.gitignore` or `{}` files.",
+            ignored,
+            if ignored == 1 { " is" } else { "s are" },
+            CHEFIGNORE
+        ));
+        if respect_gitignore {
+            message.push_str(" Pass `--no-gitignore` to look at the ones `.gitignore` ignores.");
+        }
+    }
+
+    let parent_manifest = base_path
+        .ancestors()
+        .skip(1)
+        .map(|ancestor| ancestor.join("Cargo.toml"))
+        .find(|manifest| manifest.is_file());
+    if let Some(parent_manifest) = parent_manifest {
+        let is_workspace_root = fs::read_to_string(&parent_manifest)
+            .ok()
+            .and_then(|contents| contents.parse::<toml::Value>().ok())
+            .is_some_and(|manifest| manifest.get("workspace").is_some());
+        message.push_str(&format!(
+            "\nA parent directory contains {}: {}. Run `cargo chef prepare` from there, or pass \
+            `--manifest-path {}`.",
+            if is_workspace_root {
+                "the root of a workspace"
+            } else {
+                "a manifest"
+            },
+            parent_manifest.display(),
+            parent_manifest.display()
+        ));
+    }
+    anyhow::anyhow!(message)
+}
+
+/// A `Cargo.toml` found while walking the project.
+struct FoundManifest {
+    absolute_path: PathBuf,
+    relative_path: PathBuf,
+    contents: String,
+    /// The raw TOML, or the description of why it is invalid.
+    original: Result<toml::Value, String>,
+}
+
+fn found_manifest(base_path: &Path, absolute_path: &Path) -> Result<FoundManifest, anyhow::Error> {
+    let contents = fs::read_to_string(absolute_path)?;
+    let relative_path = pathdiff::diff_paths(absolute_path, base_path).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Failed to compute relative path of manifest {:?}",
+            absolute_path
+        )
+    })?;
+    let original =
+        toml::from_str(&contents).map_err(|e| describe_toml_error(&relative_path, &contents, &e));
+    Ok(FoundManifest {
+        absolute_path: absolute_path.to_owned(),
+        relative_path,
+        contents,
+        original,
+    })
+}
+
+/// Apply `f` to all the `items` on all the available cores, keeping their order.
+fn parallel_map<T: Sync, U: Send>(items: &[T], f: impl Fn(&T) -> U + Sync) -> Vec<U> {
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = items.len().div_ceil(threads).max(1);
+    let f = &f;
+    std::thread::scope(|scope| {
+        let chunks: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<_>>()))
+            .collect();
+        chunks
+            .into_iter()
+            .flat_map(|chunk| {
+                chunk
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
+
+/// The TOML error behind a failure of [`parse`], if any.
+fn toml_error(e: &anyhow::Error) -> Option<&toml::de::Error> {
+    e.chain().find_map(|cause| {
+        cause.downcast_ref::<toml::de::Error>().or_else(|| {
+            match cause.downcast_ref::<cargo_manifest::Error>() {
+                Some(cargo_manifest::Error::Parse(e)) => Some(e),
+                _ => None,
+            }
+        })
+    })
+}
+
+/// Describe an invalid TOML file as `<path>:<line>:<column>: <message>`, followed by the
+/// offending line with a caret below the position of the error, e.g.
+///
+/// ```text
+/// crates/api/Cargo.toml:3:8: expected a value, found a newline at line 3 column 8
+///   |
+/// 3 | name =
+///   |        ^
+/// ```
+fn describe_toml_error(relative_path: &Path, contents: &str, e: &toml::de::Error) -> String {
+    let (line, column) = match e.line_col() {
+        Some(position) => position,
+        None => return format!("{}: {}", relative_path.display(), e),
+    };
+    let snippet = contents.lines().nth(line).unwrap_or_default();
+    let number = (line + 1).to_string();
+    let margin = " ".repeat(number.len());
+    // The column counts characters, not bytes.
+    let padding: String = snippet
+        .chars()
+        .take(column)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    format!(
+        "{}:{}:{}: {}\n{} |\n{} | {}\n{} | {}^",
+        relative_path.display(),
+        line + 1,
+        column + 1,
+        e,
+        margin,
+        number,
+        snippet,
+        margin,
+        padding
+    )
+}
+
+/// The kinds of targets cargo discovers on disk: their section, the directory they are
+/// discovered in and the key of `[package]` that turns the discovery off.
+const DISCOVERED_TARGETS: [(&str, &str, &str); 4] = [
+    ("bin", "src/bin", "autobins"),
+    ("example", "examples", "autoexamples"),
+    ("test", "tests", "autotests"),
+    ("bench", "benches", "autobenches"),
+];
+
+fn discovered_targets_mut<'a>(
+    parsed: &'a mut cargo_manifest::Manifest,
+    section: &str,
+) -> &'a mut Option<Vec<cargo_manifest::Product>> {
+    match section {
+        "bin" => &mut parsed.bin,
+        "example" => &mut parsed.example,
+        "test" => &mut parsed.test,
+        _ => &mut parsed.bench,
+    }
+}
+
+/// Add the targets cargo discovers (e.g. `src/main.rs`, `src/bin/*.rs`, `tests/*.rs` or
+/// `examples/*/main.rs`) next to the declared ones, and fill in the paths the declared ones
+/// leave out, like cargo does. Only the presence of the files matters, not their contents.
+///
+/// `complete_from_path` only discovers the targets of a kind when none of them is declared.
+fn complete_targets(
+    parsed: &mut cargo_manifest::Manifest,
+    absolute_path: &Path,
+    original: &toml::Value,
+) -> Result<(), anyhow::Error> {
+    let package_name = match &parsed.package {
+        Some(package) => package.name.clone(),
+        None => return Ok(()),
+    };
+    let original_package = original.get("package");
+    // Up to the 2015 edition, declaring a target turns the discovery off, unless it is
+    // requested explicitly.
+    let is_2015 = match original_package.and_then(|package| package.get("edition")) {
+        None => true,
+        Some(toml::Value::String(edition)) => edition == "2015",
+        // Inherited from the workspace.
+        Some(_) => false,
+    };
+    let crate_directory = absolute_path.parent().unwrap_or_else(|| Path::new(""));
+
+    for (section, directory, auto_key) in DISCOVERED_TARGETS {
+        if discovered_targets_mut(parsed, section)
+            .as_ref()
+            .is_none_or(|targets| targets.is_empty())
+        {
+            continue;
+        }
+        let mut discovered = parsed.clone();
+        *discovered_targets_mut(&mut discovered, section) = None;
+        discovered.complete_from_path(absolute_path)?;
+        let discovered = discovered_targets_mut(&mut discovered, section)
+            .take()
+            .unwrap_or_default();
+
+        let targets = discovered_targets_mut(parsed, section).get_or_insert_with(Vec::new);
+        for target in targets.iter_mut().filter(|target| target.path.is_none()) {
+            let name = match &target.name {
+                Some(name) => name,
+                None => continue,
+            };
+            let main = if section == "bin" && name == &package_name {
+                Some("src/main.rs".to_owned())
+            } else {
+                None
+            };
+            target.path = main
+                .into_iter()
+                .chain(vec![
+                    format!("{}/{}.rs", directory, name),
+                    format!("{}/{}/main.rs", directory, name),
+                ])
+                .find(|candidate| crate_directory.join(candidate).exists());
+        }
+
+        let auto = original_package
+            .and_then(|package| package.get(auto_key))
+            .and_then(|auto| auto.as_bool());
+        if !auto.unwrap_or(!is_2015) {
+            continue;
+        }
+        for target in discovered {
+            let is_declared = targets
+                .iter()
+                .any(|declared| declared.name == target.name || declared.path == target.path);
+            if !is_declared {
+                targets.push(target);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parse a manifest, making all its targets explicit.
+///
+/// `original` is the raw TOML of `contents`, used for the sections `cargo_manifest` does not
+/// model.
+pub(super) fn parse(
+    absolute_path: &Path,
+    contents: &str,
+    original: &toml::Value,
+) -> Result<toml::Value, anyhow::Error> {
+    let mut parsed = typed_manifest(contents, original)?;
+    // Required to detect bin/libs when the related section is omitted from the manifest
+    parsed.complete_from_path(absolute_path)?;
+    complete_targets(&mut parsed, absolute_path, original)?;
+    // `complete_from_path` predates `autolib` (cargo 1.83): without a `[lib]` section, turning
+    // the discovery off leaves the package without a library.
+    let autolib = original
+        .get("package")
+        .and_then(|package| package.get("autolib"))
+        .and_then(|autolib| autolib.as_bool());
+    if autolib == Some(false) && original.get("lib").is_none() {
+        parsed.lib = None;
+    }
+    // `complete_from_path` leaves an explicit `[lib]` section untouched, even if it
+    // only sets a few keys (e.g. `proc-macro = true`): fill in the defaults, so that
+    // the library is as explicit as the inferred ones.
+    if let (Some(lib), Some(package)) = (parsed.lib.as_mut(), parsed.package.as_ref()) {
+        lib.name
+            .get_or_insert_with(|| package.name.replace('-', "_"));
+        lib.path.get_or_insert_with(|| "src/lib.rs".to_owned());
+    }
+
+    let mut intermediate = toml::Value::try_from(parsed)?;
+
+    if let (Some(original), Some(intermediate)) = (original.as_table(), intermediate.as_table_mut())
+    {
+        carry_over_unknown_keys(original, intermediate);
+    }
+    restore_package_keys(original, &mut intermediate);
+    restore_dependency_keys(original, &mut intermediate);
+    restore_features(original, &mut intermediate);
+    restore_patches(original, &mut intermediate);
+    // The original editions of the targets were lost if `typed_manifest` had to replace them.
+    if with_known_values(original).is_some() {
+        restore_target_editions(original, &mut intermediate);
+    }
+
+    // Specifically, toml gives no guarantees to the ordering of the auto binaries
+    // in its results. We will manually sort these to ensure that the output
+    // manifest will match.
+    let bins = intermediate
+        .get_mut("bin")
+        .and_then(|bins| bins.as_array_mut());
+    if let Some(bins) = bins {
+        bins.sort_by(|bin_a, bin_b| {
+            let bin_a_path = bin_a
+                .as_table()
+                .and_then(|table| table.get("path").or_else(|| table.get("name")))
+                .and_then(|path| path.as_str())
+                .unwrap();
+            let bin_b_path = bin_b
+                .as_table()
+                .and_then(|table| table.get("path").or_else(|| table.get("name")))
+                .and_then(|path| path.as_str())
+                .unwrap();
+            bin_a_path.cmp(bin_b_path)
+        });
+    }
+    Ok(intermediate)
+}
+
+/// The latest version of the `Cargo.lock` format we know about. Versions 1 and 2 predate the
+/// `version` key, version 4 only changed how the sources of git dependencies are encoded.
+pub(super) const MAX_LOCKFILE_VERSION: i64 = 4;
+
+/// `cargo_manifest` only models a subset of the manifest format, while cargo adds new tables
+/// regularly (e.g. `[lints]` and `[workspace.lints]`): copy over verbatim from the original
+/// manifest the top-level tables, and the keys of `[package]` and `[workspace]`, it dropped.
+///
+/// `[replace]` and `[profile]` are always copied: `cargo_manifest` skips the legacy
+/// `[replace]` section and only knows a subset of the profile settings (e.g. it drops `strip`
+/// and `split-debuginfo`, which affect how dependencies are compiled).
+fn carry_over_unknown_keys(original: &toml::value::Table, intermediate: &mut toml::value::Table) {
+    for (key, value) in original {
+        match (key.as_str(), intermediate.get_mut(key)) {
+            ("replace" | "profile", _) | (_, None) => {
+                intermediate.insert(key.to_owned(), value.clone());
+            }
+            ("package" | "workspace", Some(toml::Value::Table(table))) => {
+                for (key, value) in value.as_table().into_iter().flatten() {
+                    if !table.contains_key(key) {
+                        table.insert(key.to_owned(), value.clone());
+                    }
                 }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The editions known to `cargo_manifest`, oldest first.
+const KNOWN_EDITIONS: [&str; 3] = ["2015", "2018", "2021"];
+
+/// The versions of the dependency resolver known to `cargo_manifest`, oldest first.
+const KNOWN_RESOLVERS: [&str; 2] = ["1", "2"];
+
+/// The keys of `[package]` (and `[workspace.package]`) that change how dependencies are
+/// resolved or compiled: they must reach cargo exactly as they were written (e.g.
+/// `rust-version.workspace = true`).
+const RESOLUTION_KEYS: [&str; 4] = ["edition", "rust-version", "links", "resolver"];
 
-                let relative_path =
-                    pathdiff::diff_paths(&absolute_path, base_path).ok_or_else(|| {
-                        anyhow::anyhow!(
-                            "Failed to compute relative path of manifest {:?}",
-                            &absolute_path
-                        )
-                    })?;
-                manifests.push(ParsedManifest {
-                    relative_path,
-                    contents: intermediate,
-                });
-            }
-            Err(e) => match handle_walk_error(e) {
-                ErrorStrategy::Ignore => {}
-                ErrorStrategy::Crash(e) => {
-                    return Err(e.into());
+/// The kinds of targets, as the top-level keys of a manifest.
+const TARGET_KINDS: [&str; 5] = ["lib", "bin", "test", "bench", "example"];
+
+/// Parse a manifest with `cargo_manifest`, given its raw TOML.
+///
+/// `cargo_manifest` rejects the editions and resolvers that came out after it (e.g. edition
+/// `2024` or `resolver = "3"`): it parses a copy of the manifest using ones it knows about
+/// instead.
+pub(super) fn typed_manifest(
+    contents: &str,
+    original: &toml::Value,
+) -> Result<cargo_manifest::Manifest, anyhow::Error> {
+    match with_known_values(original) {
+        Some(manifest) => Ok(cargo_manifest::Manifest::from_str(&toml::to_string(
+            &manifest,
+        )?)?),
+        None => Ok(cargo_manifest::Manifest::from_str(contents)?),
+    }
+}
+
+/// A copy of `manifest` where the editions and resolvers unknown to `cargo_manifest` are
+/// replaced by the latest ones it knows, or `None` if there are none.
+fn with_known_values(manifest: &toml::Value) -> Option<toml::Value> {
+    let mut manifest = manifest.clone();
+    let mut replaced = false;
+    let mut replace = |value: &mut toml::Value, known: &[&str]| {
+        if value.as_str().is_some_and(|value| !known.contains(&value)) {
+            *value = toml::Value::String(known[known.len() - 1].to_owned());
+            replaced = true;
+        }
+    };
+    for edition in editions_mut(&mut manifest) {
+        replace(edition, &KNOWN_EDITIONS);
+    }
+    for key in ["package", "workspace"] {
+        if let Some(resolver) = manifest.get_mut(key).and_then(|t| t.get_mut("resolver")) {
+            replace(resolver, &KNOWN_RESOLVERS);
+        }
+    }
+    replaced.then_some(manifest)
+}
+
+/// The `edition` keys of the package, of the workspace and of each target.
+fn editions_mut(manifest: &mut toml::Value) -> Vec<&mut toml::Value> {
+    let mut editions = vec![];
+    let manifest = match manifest.as_table_mut() {
+        Some(manifest) => manifest,
+        None => return editions,
+    };
+    for (key, value) in manifest.iter_mut() {
+        match key.as_str() {
+            "package" => editions.extend(value.get_mut("edition")),
+            "workspace" => editions.extend(
+                value
+                    .get_mut("package")
+                    .and_then(|package| package.get_mut("edition")),
+            ),
+            key if TARGET_KINDS.contains(&key) => {
+                for target in targets_mut(value) {
+                    editions.extend(target.get_mut("edition"));
                 }
-            },
+            }
+            _ => {}
         }
     }
-    Ok(manifests)
+    editions
+}
+
+/// The targets of one kind: `[lib]` is a table, the others are arrays of tables.
+fn targets_mut(targets: &mut toml::Value) -> Vec<&mut toml::Value> {
+    match targets {
+        toml::Value::Array(targets) => targets.iter_mut().collect(),
+        target => vec![target],
+    }
+}
+
+fn targets<'a>(manifest: &'a toml::Value, kind: &str) -> Vec<&'a toml::Value> {
+    match manifest.get(kind) {
+        Some(toml::Value::Array(targets)) => targets.iter().collect(),
+        Some(target) => vec![target],
+        None => vec![],
+    }
+}
+
+/// Copy the [`RESOLUTION_KEYS`] of `[package]`, `[workspace]` and `[workspace.package]`
+/// verbatim from the original manifest.
+fn restore_package_keys(original: &toml::Value, intermediate: &mut toml::Value) {
+    let restore = |original: Option<&toml::Value>, package: Option<&mut toml::Value>| {
+        if let (Some(original), Some(toml::Value::Table(package))) = (original, package) {
+            for key in RESOLUTION_KEYS {
+                if let Some(value) = original.get(key) {
+                    package.insert(key.to_owned(), value.clone());
+                }
+            }
+        }
+    };
+    restore(original.get("package"), intermediate.get_mut("package"));
+    restore(original.get("workspace"), intermediate.get_mut("workspace"));
+    restore(
+        original
+            .get("workspace")
+            .and_then(|workspace| workspace.get("package")),
+        intermediate
+            .get_mut("workspace")
+            .and_then(|workspace| workspace.get_mut("package")),
+    );
+}
+
+/// Copy the keys of each dependency verbatim from the original manifest, in particular the ones
+/// that `cargo_manifest` does not model (e.g. `artifact`, `lib` and `target` for the artifact
+/// dependencies of `-Z bindeps`).
+fn restore_dependency_keys(original: &toml::Value, intermediate: &mut toml::Value) {
+    for path in dependency_table_paths(original) {
+        let original_dependencies = path
+            .iter()
+            .try_fold(original, |value, key| value.get(key))
+            .and_then(|dependencies| dependencies.as_table());
+        let dependencies = path
+            .iter()
+            .try_fold(&mut *intermediate, |value, key| value.get_mut(key))
+            .and_then(|dependencies| dependencies.as_table_mut());
+        let (original_dependencies, dependencies) = match (original_dependencies, dependencies) {
+            (Some(original_dependencies), Some(dependencies)) => {
+                (original_dependencies, dependencies)
+            }
+            _ => continue,
+        };
+        for (name, original_dependency) in original_dependencies {
+            if let (Some(original_dependency), Some(toml::Value::Table(dependency))) =
+                (original_dependency.as_table(), dependencies.get_mut(name))
+            {
+                for (key, value) in original_dependency {
+                    // `cargo_manifest` normalises some keys, e.g. `default_features`: the
+                    // original spelling is kept, so that no sibling of `version` is rewritten.
+                    let normalized = key.replace('_', "-");
+                    if normalized != *key {
+                        dependency.remove(&normalized);
+                    }
+                    dependency.insert(key.to_owned(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Copy `[features]` verbatim from the original manifest: which optional dependencies are
+/// compiled, and with which features, depends on it (e.g. `full = ["dep:helper", "helper/extra"]`).
+fn restore_features(original: &toml::Value, intermediate: &mut toml::Value) {
+    if let (Some(features), Some(intermediate)) =
+        (original.get("features"), intermediate.as_table_mut())
+    {
+        intermediate.insert("features".to_owned(), features.clone());
+    }
+}
+
+/// Copy `[patch]` verbatim from the original manifest. The `branch` of a git patch is not
+/// resolved: the commit is the one pinned by the `source` of the package in Cargo.lock, which is
+/// how `cook` and the final build agree on it.
+fn restore_patches(original: &toml::Value, intermediate: &mut toml::Value) {
+    if let (Some(patch), Some(intermediate)) = (original.get("patch"), intermediate.as_table_mut())
+    {
+        intermediate.insert("patch".to_owned(), patch.clone());
+    }
+}
+
+/// The paths to all the tables of dependencies in a manifest.
+fn dependency_table_paths(manifest: &toml::Value) -> Vec<Vec<&str>> {
+    const DEPENDENCY_KEYS: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+    let mut paths: Vec<Vec<&str>> = DEPENDENCY_KEYS.iter().map(|key| vec![*key]).collect();
+    paths.push(vec!["workspace", "dependencies"]);
+    let keys = |key: &str| -> Vec<&str> {
+        manifest
+            .get(key)
+            .and_then(|table| table.as_table())
+            .into_iter()
+            .flat_map(|table| table.keys())
+            .map(|key| key.as_str())
+            .collect()
+    };
+    for target in keys("target") {
+        for key in DEPENDENCY_KEYS {
+            paths.push(vec!["target", target, key]);
+        }
+    }
+    paths
+}
+
+/// Give back their original edition to the targets of a manifest parsed through
+/// [`with_known_values`]: the one they set explicitly or, for the others, the one of the
+/// package.
+fn restore_target_editions(original: &toml::Value, intermediate: &mut toml::Value) {
+    let package_edition = original
+        .get("package")
+        .and_then(|package| package.get("edition"))
+        .filter(|edition| edition.is_str());
+    for kind in TARGET_KINDS {
+        let original_targets = targets(original, kind);
+        let intermediate_targets = intermediate.get_mut(kind).map(targets_mut);
+        for target in intermediate_targets.into_iter().flatten() {
+            let name = target.get("name").cloned();
+            let explicit = original_targets
+                .iter()
+                .find(|original| kind == "lib" || original.get("name") == name.as_ref());
+            let edition = explicit
+                .and_then(|original| original.get("edition"))
+                .or(package_edition);
+            if let (Some(edition), Some(target)) = (edition, target.as_table_mut()) {
+                target.insert("edition".to_owned(), edition.clone());
+            }
+        }
+    }
+}
+
+/// The `version` of a lockfile, if it is more recent than [`MAX_LOCKFILE_VERSION`].
+pub(super) fn unsupported_lockfile_version(lock_file: &toml::Value) -> Option<i64> {
+    lock_file
+        .get("version")
+        .and_then(|version| version.as_integer())
+        .filter(|version| *version > MAX_LOCKFILE_VERSION)
 }
 
 pub(super) fn lockfile<P: AsRef<Path>>(
     base_path: &P,
 ) -> Result<Option<toml::Value>, anyhow::Error> {
     match fs::read_to_string(base_path.as_ref().join("Cargo.lock")) {
-        Ok(lock) => {
-            let lock: toml::Value = toml::from_str(&lock)?;
+        Ok(contents) => {
+            let lock: toml::Value = toml::from_str(&contents).map_err(|e| {
+                anyhow::anyhow!(
+                    "Cargo.lock is corrupt: it is not valid TOML.\n\n{}",
+                    describe_toml_error(Path::new("Cargo.lock"), &contents, &e)
+                )
+            })?;
+            // Lockfiles before version 3 do not have a `version` key. More recent versions than
+            // the ones we know about are dealt with by the caller.
+            match lock.get("version") {
+                None => {}
+                Some(toml::Value::Integer(version)) if *version >= 1 => {}
+                Some(version) => anyhow::bail!(
+                    "Cargo.lock is corrupt: {} is not a valid lockfile version.",
+                    version
+                ),
+            }
             Ok(Some(lock))
         }
         Err(e) => {
@@ -136,6 +837,63 @@ pub(super) fn lockfile<P: AsRef<Path>>(
     }
 }
 
+/// Read the default `cook` arguments from the `cook-args` key of `[package.metadata.chef]` (or
+/// `[workspace.metadata.chef]`) in the root manifest, e.g.
+///
+/// ```toml
+/// [workspace.metadata.chef]
+/// cook-args = ["--release", "--target", "x86_64-unknown-linux-musl"]
+/// ```
+pub(crate) fn cook_args_from_metadata<P: AsRef<Path>>(
+    base_path: &P,
+) -> Result<Vec<String>, anyhow::Error> {
+    let contents = match fs::read_to_string(base_path.as_ref().join("Cargo.toml")) {
+        Ok(contents) => contents,
+        Err(e) => {
+            if std::io::ErrorKind::NotFound != e.kind() {
+                return Err(anyhow::Error::from(e).context("Failed to read the root manifest."));
+            }
+            return Ok(vec![]);
+        }
+    };
+    let manifest: toml::Value = toml::from_str(&contents)?;
+    let cook_args = ["package", "workspace"].iter().find_map(|key| {
+        manifest
+            .get(key)?
+            .get("metadata")?
+            .get("chef")?
+            .get("cook-args")
+    });
+    match cook_args {
+        None => Ok(vec![]),
+        Some(cook_args) => cook_args
+            .clone()
+            .try_into()
+            .context("`metadata.chef.cook-args` in the root manifest must be a list of strings."),
+    }
+}
+
+/// Read the file pinning the Rust toolchain at the root of the project, if any.
+///
+/// Like `rustup`, we give priority to the legacy `rust-toolchain` file if both are present.
+pub(super) fn rust_toolchain<P: AsRef<Path>>(
+    base_path: &P,
+) -> Result<Option<(RustToolchainFile, String)>, anyhow::Error> {
+    for file_kind in [RustToolchainFile::Bare, RustToolchainFile::Toml] {
+        let file_name = file_kind.file_name();
+        match fs::read_to_string(base_path.as_ref().join(file_name)) {
+            Ok(content) => return Ok(Some((file_kind, content.replace("\r\n", "\n")))),
+            Err(e) => {
+                if std::io::ErrorKind::NotFound != e.kind() {
+                    return Err(anyhow::Error::from(e)
+                        .context(format!("Failed to read {} file.", file_name)));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
 /// What should we should when we encounter an issue while walking the current directory?
 ///
 /// If `ErrorStrategy::Ignore`, just skip the file/directory and keep going.
@@ -145,14 +903,64 @@ enum ErrorStrategy {
     Crash(WalkError),
 }
 
-/// Ignore directory/files for which we don't have enough permissions to perform our scan.
+/// Ignore directory/files for which we don't have enough permissions to perform our scan,
+/// broken symlinks, symlinks pointing to one of their ancestors and invalid lines in ignore
+/// files.
 #[must_use]
 fn handle_walk_error(e: WalkError) -> ErrorStrategy {
+    if let Some((ancestor, child)) = loop_paths(&e) {
+        log::warn!(
+            "{:?} is a symlink to its ancestor {:?}.\nSkipping.",
+            child,
+            ancestor
+        );
+        return ErrorStrategy::Ignore;
+    }
+    if e.is_partial() {
+        log::warn!("Failed to read some ignore rules: {}\nSkipping them.", e);
+        return ErrorStrategy::Ignore;
+    }
     if let Some(inner) = e.io_error() {
         if std::io::ErrorKind::PermissionDenied == inner.kind() {
             log::warn!("Missing permission to read entry: {}\nSkipping.", inner);
             return ErrorStrategy::Ignore;
         }
+        let is_broken_symlink = std::io::ErrorKind::NotFound == inner.kind()
+            && error_path(&e).is_some_and(|path| path.is_symlink());
+        if is_broken_symlink {
+            log::warn!(
+                "{:?} is a broken symlink: {}\nSkipping.",
+                error_path(&e).unwrap_or_else(|| Path::new("")),
+                inner
+            );
+            return ErrorStrategy::Ignore;
+        }
     }
     ErrorStrategy::Crash(e)
 }
+
+fn error_path(e: &WalkError) -> Option<&Path> {
+    match e {
+        WalkError::WithPath { path, .. } => Some(path),
+        WalkError::WithDepth { err, .. } | WalkError::WithLineNumber { err, .. } => error_path(err),
+        _ => None,
+    }
+}
+
+/// The ancestor and the child of a symlink loop.
+fn loop_paths(e: &WalkError) -> Option<(&Path, &Path)> {
+    match e {
+        WalkError::Loop { ancestor, child } => Some((ancestor, child)),
+        WalkError::WithPath { err, .. }
+        | WalkError::WithDepth { err, .. }
+        | WalkError::WithLineNumber { err, .. } => loop_paths(err),
+        _ => None,
+    }
+}
+
+/// `path` without `.` components, to compare paths written differently.
+fn lexical(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| component != &Component::CurDir)
+        .collect()
+}
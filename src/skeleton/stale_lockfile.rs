@@ -0,0 +1,151 @@
+//! Logic to notice that the lockfile is out of sync with the manifests (e.g. a dependency was
+//! added without updating it) before `cook` has to resolve the dependencies again.
+use super::dev_dependencies::matches_requirement;
+use super::version_masking::{dependency_package_name, dependency_tables};
+use super::ParsedManifest;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+/// A dependency of a manifest that no package of the lockfile satisfies.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) struct MissingRequirement {
+    manifest_path: PathBuf,
+    name: String,
+    /// `None` for git dependencies.
+    requirement: Option<String>,
+}
+
+impl fmt::Display for MissingRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.requirement {
+            Some(requirement) => write!(f, "`{} {}`", self.name, requirement)?,
+            None => write!(f, "`{}` from git", self.name)?,
+        }
+        write!(f, ", required by {}", self.manifest_path.display())
+    }
+}
+
+/// The dependencies of the manifests, other than `path` ones, without a satisfying package in
+/// the lockfile, sorted:
+/// - registry dependencies need a package with the same name and a version matching their
+///   requirement (any version if they have none or it cannot be parsed);
+/// - git dependencies need a package with the same name.
+///
+/// Inherited dependencies are resolved against `[workspace.dependencies]`, and optional
+/// dependencies are only checked if the `default` feature of their manifest enables them.
+pub(super) fn missing_requirements(
+    manifests: &[ParsedManifest],
+    lock_file: &toml::Value,
+) -> Vec<MissingRequirement> {
+    let locked_packages: Vec<(&str, &str)> = lock_file
+        .get("package")
+        .and_then(|packages| packages.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|package| {
+            Some((
+                package.get("name")?.as_str()?,
+                package.get("version")?.as_str()?,
+            ))
+        })
+        .collect();
+    let workspace_dependencies = manifests.iter().find_map(|manifest| {
+        manifest
+            .contents
+            .get("workspace")?
+            .get("dependencies")?
+            .as_table()
+    });
+
+    let mut missing = BTreeSet::new();
+    for manifest in manifests {
+        let enabled = default_optional_dependencies(&manifest.contents);
+        for table in dependency_tables(&manifest.contents) {
+            for (key, dependency) in table.iter() {
+                let is_optional = dependency
+                    .get("optional")
+                    .and_then(|optional| optional.as_bool())
+                    == Some(true);
+                if is_optional && !enabled.contains(key.as_str()) {
+                    continue;
+                }
+                let inherits = dependency
+                    .get("workspace")
+                    .and_then(|workspace| workspace.as_bool())
+                    == Some(true);
+                let dependency = if inherits {
+                    // `cook` reports inheriting a dependency the workspace does not declare.
+                    match workspace_dependencies.and_then(|dependencies| dependencies.get(key)) {
+                        Some(inherited) => inherited,
+                        None => continue,
+                    }
+                } else {
+                    dependency
+                };
+                if dependency.get("path").is_some() {
+                    continue;
+                }
+                let name = dependency_package_name(key, dependency);
+                let requirement = match dependency {
+                    toml::Value::String(requirement) => Some(requirement.as_str()),
+                    _ => dependency
+                        .get("version")
+                        .and_then(|version| version.as_str()),
+                };
+                let is_git = dependency.get("git").is_some();
+                let is_locked = locked_packages.iter().any(|(locked_name, version)| {
+                    *locked_name == name && (is_git || matches_requirement(requirement, version))
+                });
+                if !is_locked {
+                    missing.insert(MissingRequirement {
+                        manifest_path: manifest.relative_path.clone(),
+                        name: name.to_owned(),
+                        requirement: match (is_git, requirement) {
+                            (true, _) => None,
+                            (false, requirement) => Some(requirement.unwrap_or("*").to_owned()),
+                        },
+                    });
+                }
+            }
+        }
+    }
+    missing.into_iter().collect()
+}
+
+/// The keys of the optional dependencies of a manifest that its `default` feature enables,
+/// transitively: through `dep:name`, `name/feature` or the implicit feature of the dependency.
+fn default_optional_dependencies(manifest: &toml::Value) -> HashSet<&str> {
+    let features = manifest
+        .get("features")
+        .and_then(|features| features.as_table());
+    let mut enabled = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut to_visit = vec!["default"];
+    while let Some(feature) = to_visit.pop() {
+        if !visited.insert(feature) {
+            continue;
+        }
+        let values = features
+            .and_then(|features| features.get(feature))
+            .and_then(|values| values.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|value| value.as_str());
+        for value in values {
+            if let Some(dependency) = value.strip_prefix("dep:") {
+                enabled.insert(dependency);
+            } else if let Some((dependency, _)) = value.split_once('/') {
+                // `name?/feature` does not enable `name`.
+                if !dependency.ends_with('?') {
+                    enabled.insert(dependency);
+                }
+            } else if features.is_some_and(|features| features.contains_key(value)) {
+                to_visit.push(value);
+            } else {
+                enabled.insert(value);
+            }
+        }
+    }
+    enabled
+}
@@ -0,0 +1,196 @@
+//! Logic to check that a skeleton is consistent before it is cooked: truncated or hand-edited
+//! recipes would otherwise fail in the middle of `cook`, or silently build half a workspace.
+use super::version_masking::{dependency_package_name, dependency_tables, patch_tables};
+use super::{include, Skeleton};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// A problem found in a recipe, for the file it was found in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// The path of the file in the recipe, e.g. `crates/api/Cargo.toml` or `Cargo.lock`.
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl Skeleton {
+    /// Check that the manifests and the lockfile parse, that every `path` dependency points to
+    /// a manifest of the skeleton, that the lockfile pins every other dependency and that all
+    /// the paths are normalized and stay within the project. An empty list if it is valid.
+    pub fn violations(&self) -> Vec<Violation> {
+        let mut violations = vec![];
+        let mut violation = |path: &Path, message: String| {
+            violations.push(Violation {
+                path: path.to_owned(),
+                message,
+            })
+        };
+
+        let relative_paths = self
+            .manifests
+            .iter()
+            .map(|manifest| &manifest.relative_path)
+            .chain(self.included_files.iter().map(|file| &file.relative_path));
+        for relative_path in relative_paths {
+            if !is_normalized(relative_path) {
+                violation(
+                    relative_path,
+                    "the path must be relative to the project, without `.` or `..`.".into(),
+                );
+            }
+        }
+
+        let mut manifests = vec![];
+        for manifest in &self.manifests {
+            if manifest.relative_path.file_name() != Some("Cargo.toml".as_ref()) {
+                violation(&manifest.relative_path, "not a `Cargo.toml` file.".into());
+            }
+            match manifest.contents.parse::<toml::Value>() {
+                Ok(contents) => manifests.push((&manifest.relative_path, contents)),
+                Err(e) => violation(&manifest.relative_path, format!("invalid TOML: {}", e)),
+            }
+        }
+        let manifest_paths: BTreeSet<&Path> = manifests
+            .iter()
+            .map(|(relative_path, _)| relative_path.as_path())
+            .collect();
+        let workspace_dependencies = manifests
+            .iter()
+            .find_map(|(_, contents)| contents.get("workspace")?.get("dependencies")?.as_table());
+
+        // `None` if there is no (valid) lockfile to check the dependencies against.
+        let locked_packages = match self.lock_file.as_deref().map(str::parse::<toml::Value>) {
+            Some(Ok(lock_file)) => Some(
+                lock_file
+                    .get("package")
+                    .and_then(|packages| packages.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|package| package.get("name")?.as_str())
+                    .map(|name| name.to_owned())
+                    .collect::<BTreeSet<String>>(),
+            ),
+            Some(Err(e)) => {
+                violation(Path::new("Cargo.lock"), format!("invalid TOML: {}", e));
+                None
+            }
+            None => None,
+        };
+
+        for (relative_path, contents) in &manifests {
+            let directory = relative_path.parent().unwrap_or_else(|| Path::new(""));
+            // `[workspace.dependencies]` are only checked when a member inherits them, and
+            // patches are not necessarily used: only their `path` is checked.
+            let dependencies = dependency_tables(contents)
+                .into_iter()
+                .flat_map(|table| table.iter().map(|entry| (entry, false)))
+                .chain(
+                    patch_tables(contents)
+                        .flat_map(|(_, patches)| patches.iter().map(|entry| (entry, true))),
+                );
+            for ((key, dependency), is_patch) in dependencies {
+                let inherits = dependency
+                    .get("workspace")
+                    .and_then(|workspace| workspace.as_bool())
+                    == Some(true);
+                let (dependency, directory) = if inherits {
+                    match workspace_dependencies.and_then(|dependencies| dependencies.get(key)) {
+                        // Relative to the root of the workspace.
+                        Some(inherited) => (inherited, Path::new("")),
+                        None => {
+                            violation(
+                                relative_path,
+                                format!(
+                                    "`{}` is inherited from `[workspace.dependencies]`, which \
+                                    does not declare it.",
+                                    key
+                                ),
+                            );
+                            continue;
+                        }
+                    }
+                } else {
+                    (dependency, directory)
+                };
+                match dependency.get("path").and_then(|path| path.as_str()) {
+                    Some(path) => match resolve(directory, path) {
+                        Some(resolved) => {
+                            let manifest_path = resolved.join("Cargo.toml");
+                            if !manifest_paths.contains(manifest_path.as_path()) {
+                                violation(
+                                    relative_path,
+                                    format!(
+                                        "the `path` of `{}` points to {}, which is not in the \
+                                        recipe.",
+                                        key,
+                                        manifest_path.display()
+                                    ),
+                                );
+                            }
+                        }
+                        None => violation(
+                            relative_path,
+                            format!(
+                                "the `path` of `{}` points outside of the project: `{}`.",
+                                key, path
+                            ),
+                        ),
+                    },
+                    None if is_patch => {}
+                    None => {
+                        let name = dependency_package_name(key, dependency);
+                        let is_locked = locked_packages
+                            .as_ref()
+                            .is_none_or(|locked_packages| locked_packages.contains(name));
+                        if !is_locked {
+                            violation(
+                                relative_path,
+                                format!("`{}` is not pinned in Cargo.lock.", name),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// Is `path` relative, without `.`, `..` or empty components?
+fn is_normalized(path: &Path) -> bool {
+    include::is_contained(path)
+        && path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// The directory `path` points to from `directory`, resolved lexically. `None` if it is
+/// absolute or escapes the project.
+fn resolve(directory: &Path, path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    if path.is_absolute() {
+        return None;
+    }
+    let mut resolved = PathBuf::new();
+    for component in directory.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return None;
+                }
+            }
+            Component::Normal(component) => resolved.push(component),
+            _ => return None,
+        }
+    }
+    Some(resolved)
+}
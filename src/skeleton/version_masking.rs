@@ -1,6 +1,7 @@
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
 
-use super::ParsedManifest;
+use super::{format_key_path, ParsedManifest};
 
 /// All local dependencies are emptied out when running `prepare`.
 /// We do not want the recipe file to change if the only difference with
@@ -10,32 +11,107 @@ use super::ParsedManifest;
 /// We replace versions of local crates in `Cargo.lock` and in all `Cargo.toml`s, including
 /// when specified as dependency of another crate in the workspace.
 pub(super) fn mask_local_crate_versions(
-    member: &Option<String>,
+    members: &[String],
+    mask_version: &str,
     manifests: &mut [ParsedManifest],
     lock_file: &mut Option<toml::Value>,
-) {
-    let local_package_names = parse_local_crate_names(member, manifests);
-    mask_local_versions_in_manifests(manifests, &local_package_names);
+) -> MaskingReport {
+    let replaced_package_versions = parse_replaced_crate_versions(manifests);
+    let mut local_package_names = parse_local_crate_names(members, manifests);
+    // Local crates used in the `[replace]` section must have exactly the version specified in
+    // the replacement key (e.g. `"serde:1.0.188" = { path = "forks/serde" }`), otherwise cargo
+    // ignores the replacement. That version identifies the remote package being replaced and
+    // it only changes if the remote dependency tree changes: we must not mask it.
+    local_package_names.retain(|name| !replaced_package_versions.contains_key(name));
+    let patched_crate_names = parse_patched_crate_names(manifests, &local_package_names);
+    let mut report = MaskingReport {
+        local_package_names: local_package_names.iter().cloned().collect(),
+        ..Default::default()
+    };
+    mask_local_versions_in_manifests(
+        manifests,
+        &local_package_names,
+        &patched_crate_names,
+        &replaced_package_versions,
+        mask_version,
+        &mut report,
+    );
     if let Some(l) = lock_file {
-        mask_local_versions_in_lockfile(l, &local_package_names);
+        mask_local_versions_in_lockfile(l, &local_package_names, mask_version, &mut report);
+    }
+    report
+}
+
+/// A summary of the decisions taken while masking the versions of local crates,
+/// to help troubleshoot unexpected recipe changes.
+///
+/// All entries are sorted, so that reports from different runs can be diffed.
+#[derive(Debug, Default)]
+pub(super) struct MaskingReport {
+    local_package_names: BTreeSet<String>,
+    /// The locations whose version was rewritten, e.g. `app/Cargo.toml: dependencies.common.version`.
+    masked: BTreeSet<String>,
+    /// Local-looking entries (`path` dependencies, sourceless lockfile packages) that were not
+    /// masked because they do not refer to a local crate.
+    unmasked: BTreeSet<String>,
+}
+
+impl MaskingReport {
+    fn masked(&mut self, file: &str, key_path: &[&str]) {
+        let location = format!("{}: {}", file, format_key_path(key_path));
+        log::debug!("Masking the version at {}", location);
+        self.masked.insert(location);
+    }
+
+    fn unmasked(&mut self, file: &str, key_path: &[&str]) {
+        let location = format!("{}: {}", file, format_key_path(key_path));
+        log::debug!(
+            "Not masking the version at {}: it does not refer to a local crate",
+            location
+        );
+        self.unmasked.insert(location);
     }
 }
 
-/// Dummy version used for all local crates.
-const CONST_VERSION: &str = "0.0.1";
+impl fmt::Display for MaskingReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = [
+            ("Local crates", &self.local_package_names),
+            ("Masked versions", &self.masked),
+            ("Local-looking entries that were not masked", &self.unmasked),
+        ];
+        for (title, entries) in sections {
+            writeln!(f, "{} ({}):", title, entries.len())?;
+            for entry in entries {
+                writeln!(f, "  {}", entry)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+const LOCKFILE: &str = "Cargo.lock";
+
+/// Dummy version used for all local crates, unless a different one is specified.
+pub const DEFAULT_MASK_VERSION: &str = "0.0.1";
 
 fn mask_local_versions_in_lockfile(
     lock_file: &mut toml::Value,
     local_package_names: &HashSet<String>,
+    mask_version: &str,
+    report: &mut MaskingReport,
 ) {
+    // The original `<name> <version>` pairs of all local crates.
+    let mut local_versions = HashSet::new();
     if let Some(packages) = lock_file
         .get_mut("package")
         .and_then(|packages| packages.as_array_mut())
     {
         packages
             .iter_mut()
+            .enumerate()
             // Find all local crates
-            .filter(|package| {
+            .filter(|(_, package)| {
                 package
                     .get("name")
                     .map(|name| {
@@ -47,43 +123,213 @@ fn mask_local_versions_in_lockfile(
                     })
                     .unwrap_or_default()
             })
+            // Local crates have no source (or a `path+` one): a package that shares its name
+            // with a local crate but comes from a registry or a git repository is left alone.
+            .filter(|(_, package)| {
+                !matches!(
+                    package.get("source").and_then(|source| source.as_str()),
+                    Some(source) if !source.starts_with("path+")
+                )
+            })
             // Mask the version
-            .for_each(|package| {
+            .for_each(|(i, package)| {
+                let index = format!("[{}]", i);
+                if let (Some(name), Some(version)) = (
+                    package.get("name").and_then(|name| name.as_str()),
+                    package.get("version").and_then(|version| version.as_str()),
+                ) {
+                    local_versions.insert(format!("{} {}", name, version));
+                }
                 if let Some(version) = package.get_mut("version") {
-                    *version = toml::Value::String(CONST_VERSION.to_string())
+                    *version = toml::Value::String(mask_version.to_string());
+                    report.masked(LOCKFILE, &["package", &index, "version"]);
+                }
+                // Local crates are not checksummed by cargo: drop stale entries, if any.
+                if let Some(package) = package.as_table_mut() {
+                    if package.remove("checksum").is_some() {
+                        report.masked(LOCKFILE, &["package", &index, "checksum"]);
+                    }
                 }
             });
+
+        // Other packages refer to local crates in their `dependencies` array.
+        // The version is embedded in the entry when required to disambiguate between two
+        // packages with the same name (e.g. `"my-crate 0.3.1"`) and, for older lockfile
+        // formats, always (e.g. `"my-crate 0.3.1 (path+file:///...)"`).
+        for (i, dependencies) in packages.iter_mut().enumerate().filter_map(|(i, package)| {
+            package
+                .get_mut("dependencies")
+                .and_then(|dependencies| dependencies.as_array_mut())
+                .map(|dependencies| (i, dependencies))
+        }) {
+            for (j, dependency) in dependencies.iter_mut().enumerate() {
+                if let Some(masked) = dependency.as_str().and_then(|dependency| {
+                    mask_lockfile_entry(dependency, &local_versions, mask_version)
+                }) {
+                    *dependency = toml::Value::String(masked);
+                    report.masked(
+                        LOCKFILE,
+                        &[
+                            "package",
+                            &format!("[{}]", i),
+                            "dependencies",
+                            &format!("[{}]", j),
+                        ],
+                    );
+                }
+            }
+        }
+    }
+
+    // Older lockfile formats store checksums in the `[metadata]` table, with keys like
+    // `"checksum my-crate 0.3.1 (<source>)"`.
+    if let Some(metadata) = lock_file
+        .get_mut("metadata")
+        .and_then(|metadata| metadata.as_table_mut())
+    {
+        let local_checksums: Vec<String> = metadata
+            .keys()
+            .filter(|key| {
+                key.strip_prefix("checksum ")
+                    .and_then(|entry| mask_lockfile_entry(entry, &local_versions, mask_version))
+                    .is_some()
+            })
+            .cloned()
+            .collect();
+        for key in local_checksums {
+            metadata.remove(&key);
+            report.masked(LOCKFILE, &["metadata", &key]);
+        }
+    }
+}
+
+/// Mask the version in a `<name> <version> [(<source>)]` lockfile entry, if it refers to
+/// a local crate.
+/// Returns `None` if the entry does not need to be masked.
+fn mask_lockfile_entry(
+    entry: &str,
+    local_versions: &HashSet<String>,
+    mask_version: &str,
+) -> Option<String> {
+    let mut parts = entry.splitn(3, ' ');
+    let name = parts.next()?;
+    let version = parts.next()?;
+    let source = parts.next();
+    // Local crates either have no source or a `path+` one.
+    if matches!(source, Some(source) if !source.starts_with("(path+")) {
+        return None;
+    }
+    if !local_versions.contains(&format!("{} {}", name, version)) {
+        return None;
     }
+    Some(match source {
+        Some(source) => format!("{} {} {}", name, mask_version, source),
+        None => format!("{} {}", name, mask_version),
+    })
 }
 
 fn mask_local_versions_in_manifests(
     manifests: &mut [ParsedManifest],
     local_package_names: &HashSet<String>,
+    patched_crate_names: &HashMap<String, HashSet<String>>,
+    replaced_package_versions: &HashMap<String, String>,
+    mask_version: &str,
+    report: &mut MaskingReport,
 ) {
     for manifest in manifests.iter_mut() {
+        let file = manifest.relative_path.display().to_string();
+        // Members can inherit their version from the workspace root (`version.workspace = true`).
+        // We leave the inheritance marker untouched and mask the version in
+        // `[workspace.package]` instead - it is the only place where the actual value lives.
         if let Some(package) = manifest.contents.get_mut("package") {
+            let replaced_version = package
+                .get("name")
+                .and_then(|name| name.as_str())
+                .and_then(|name| replaced_package_versions.get(name));
+            let masked_version = replaced_version.map_or(mask_version, |v| v.as_str());
             if let Some(version) = package.get_mut("version") {
                 if version.as_str().is_some() {
-                    *version = toml::Value::String(CONST_VERSION.to_string());
+                    *version = toml::Value::String(masked_version.to_string());
+                    if replaced_version.is_some() {
+                        report.unmasked(&file, &["package", "version"]);
+                    } else {
+                        report.masked(&file, &["package", "version"]);
+                    }
                 }
             }
         }
-        mask_local_dependency_versions(local_package_names, manifest);
+        if let Some(package) = manifest
+            .contents
+            .get_mut("workspace")
+            .and_then(|workspace| workspace.get_mut("package"))
+        {
+            if let Some(version) = package.get_mut("version") {
+                *version = toml::Value::String(mask_version.to_string());
+                report.masked(&file, &["workspace", "package", "version"]);
+            }
+        }
+        mask_local_dependency_versions(
+            local_package_names,
+            patched_crate_names,
+            manifest,
+            mask_version,
+            report,
+        );
     }
 }
 
 fn mask_local_dependency_versions(
     local_package_names: &HashSet<String>,
+    patched_crate_names: &HashMap<String, HashSet<String>>,
     manifest: &mut ParsedManifest,
+    mask_version: &str,
+    report: &mut MaskingReport,
 ) {
-    fn _mask(local_package_names: &HashSet<String>, toml_value: &mut toml::Value) {
+    fn _mask(
+        (local_package_names, patched_crate_names): (
+            &HashSet<String>,
+            &HashMap<String, HashSet<String>>,
+        ),
+        toml_value: &mut toml::Value,
+        mask_version: &str,
+        (file, prefix): (&str, &[&str]),
+        report: &mut MaskingReport,
+    ) {
         for dependency_key in ["dependencies", "dev-dependencies", "build-dependencies"] {
-            if let Some(dependencies) = toml_value.get_mut(dependency_key) {
-                for local_package in local_package_names.iter() {
-                    if let Some(local_dependency) = dependencies.get_mut(local_package) {
-                        if let Some(version) = local_dependency.get_mut("version") {
-                            *version = toml::Value::String(CONST_VERSION.to_string());
+            if let Some(dependencies) = toml_value
+                .get_mut(dependency_key)
+                .and_then(|dependencies| dependencies.as_table_mut())
+            {
+                for (key, dependency) in dependencies.iter_mut() {
+                    let key_path = [prefix, &[dependency_key, key]].concat();
+                    // The table key is not necessarily the name of the package: the dependency
+                    // might have been renamed (e.g. `foo-renamed = { package = "foo", ... }`).
+                    let package_name = dependency_package_name(key, dependency);
+                    if !local_package_names.contains(package_name) {
+                        if dependency.get("path").is_some() {
+                            report.unmasked(file, &key_path);
+                        }
+                        continue;
+                    }
+                    // A local crate is either a `path` dependency or it is patched in for the
+                    // source of the dependency. Otherwise it is a crate from a registry (or a git
+                    // repository) that happens to share its name with a local crate, and its
+                    // version requirement must be left alone.
+                    let is_local = dependency.get("path").is_some()
+                        || patched_crate_names
+                            .get(dependency_source(dependency))
+                            .is_some_and(|names| names.contains(package_name));
+                    if !is_local {
+                        if dependency.get("version").is_some() {
+                            report.unmasked(file, &key_path);
                         }
+                        continue;
+                    }
+                    // Only `version` is rewritten: `optional`, `default-features` and
+                    // `features` decide what gets compiled (e.g. `helper = ["dep:local-helper"]`).
+                    if let Some(version) = dependency.get_mut("version") {
+                        *version = toml::Value::String(mask_version.to_string());
+                        report.masked(file, &[&key_path[..], &["version"]].concat());
                     }
                 }
             }
@@ -103,13 +349,29 @@ fn mask_local_dependency_versions(
     // ```
     // The inner structure for target-specific dependencies mirrors the structure expected
     // for top-level dependencies.
+    // Dotted keys (`target.'cfg(unix)'.build-dependencies.codegen.version = "0.4.0"`), inline
+    // tables and full `[dependencies.codegen]` tables are parsed into the same nested tables, so
+    // there is no need to handle them separately.
     // Check out cargo's documentation (https://doc.rust-lang.org/cargo/reference/specifying-dependencies.html)
     // for more details.
-    _mask(local_package_names, &mut manifest.contents);
+    let file = manifest.relative_path.display().to_string();
+    _mask(
+        (local_package_names, patched_crate_names),
+        &mut manifest.contents,
+        mask_version,
+        (&file, &[]),
+        report,
+    );
     if let Some(targets) = manifest.contents.get_mut("target") {
         if let Some(target_table) = targets.as_table_mut() {
-            for (_, target_config) in target_table.iter_mut() {
-                _mask(local_package_names, target_config)
+            for (target, target_config) in target_table.iter_mut() {
+                _mask(
+                    (local_package_names, patched_crate_names),
+                    target_config,
+                    mask_version,
+                    (&file, &["target", target]),
+                    report,
+                )
             }
         }
     }
@@ -123,52 +385,247 @@ fn mask_local_dependency_versions(
     // Check out cargo's documentation (https://doc.rust-lang.org/cargo/reference/workspaces.html#the-workspacedependencies-table)
     // for more details.
     if let Some(workspace) = manifest.contents.get_mut("workspace") {
-        // Mask the workspace package version
-        if let Some(package) = workspace.get_mut("package") {
-            if let Some(version) = package.get_mut("version") {
-                *version = toml::Value::String(CONST_VERSION.to_string());
+        // Mask the local crates in the workspace dependencies
+        _mask(
+            (local_package_names, patched_crate_names),
+            workspace,
+            mask_version,
+            (&file, &["workspace"]),
+            report,
+        );
+    }
+
+    // Local crates can also override a dependency via the `[patch]` section, one table per
+    // patched registry (or git repository).
+    // ```toml
+    // [patch.crates-io]
+    // uuid = { path = "../uuid", version = "1.0.0" }
+    //
+    // [patch."https://my-registry"]
+    // my-fork = { package = "foo", path = "../foo", version = "0.3.0" }
+    // ```
+    // Check out cargo's documentation (https://doc.rust-lang.org/cargo/reference/overriding-dependencies.html#the-patch-section)
+    // for more details.
+    for (source, patches) in patch_tables_mut(&mut manifest.contents) {
+        for (key, patch) in patches.iter_mut() {
+            if patch.get("path").is_none() {
+                continue;
+            }
+            if !local_package_names.contains(dependency_package_name(key, patch)) {
+                report.unmasked(&file, &["patch", source, key]);
+                continue;
+            }
+            if let Some(version) = patch.get_mut("version") {
+                *version = toml::Value::String(mask_version.to_string());
+                report.masked(&file, &["patch", source, key, "version"]);
+            }
+        }
+    }
+}
+
+/// Collect the name and the version of all the packages replaced by a local crate in the
+/// `[replace]` section.
+/// ```toml
+/// [replace]
+/// "serde:1.0.188" = { path = "forks/serde" }
+/// ```
+fn parse_replaced_crate_versions(manifests: &[ParsedManifest]) -> HashMap<String, String> {
+    let mut replaced_versions = HashMap::new();
+    for manifest in manifests {
+        let replacements = match manifest
+            .contents
+            .get("replace")
+            .and_then(|replace| replace.as_table())
+        {
+            Some(replacements) => replacements,
+            None => continue,
+        };
+        for (key, replacement) in replacements {
+            if replacement.get("path").is_none() {
+                continue;
+            }
+            // Both `name:version` and `name@version` are valid package id specifications.
+            if let Some((name, version)) = key.split_once(':').or_else(|| key.split_once('@')) {
+                replaced_versions.insert(name.to_owned(), version.to_owned());
             }
         }
-        // Mask the local crates in the workspace dependencies
-        _mask(local_package_names, workspace);
     }
+    replaced_versions
 }
 
-fn parse_local_crate_names(
-    member: &Option<String>,
+/// All the dependency tables of a manifest, top-level and target-specific
+/// (e.g. `[target.'cfg(windows)'.build-dependencies]`).
+pub(super) fn dependency_tables(manifest: &toml::Value) -> Vec<&toml::value::Table> {
+    let targets = manifest
+        .get("target")
+        .and_then(|targets| targets.as_table())
+        .into_iter()
+        .flat_map(|targets| targets.values());
+    std::iter::once(manifest)
+        .chain(targets)
+        .flat_map(|value| {
+            ["dependencies", "dev-dependencies", "build-dependencies"]
+                .iter()
+                .filter_map(move |dependency_key| value.get(*dependency_key)?.as_table())
+        })
+        .collect()
+}
+
+/// All the tables nested under `[patch]`, one for each patched source.
+pub(super) fn patch_tables(
+    toml_value: &toml::Value,
+) -> impl Iterator<Item = (&String, &toml::value::Table)> {
+    toml_value
+        .get("patch")
+        .and_then(|patch| patch.as_table())
+        .into_iter()
+        .flat_map(|patch| patch.iter())
+        .filter_map(|(source, patches)| Some((source, patches.as_table()?)))
+}
+
+fn patch_tables_mut(
+    toml_value: &mut toml::Value,
+) -> impl Iterator<Item = (&String, &mut toml::value::Table)> {
+    toml_value
+        .get_mut("patch")
+        .and_then(|patch| patch.as_table_mut())
+        .into_iter()
+        .flat_map(|patch| patch.iter_mut())
+        .filter_map(|(source, patches)| Some((source, patches.as_table_mut()?)))
+}
+
+/// Collect, for each patched source, the names of the local crates patched in with a `path`.
+/// ```toml
+/// [patch.crates-io]
+/// uuid = { path = "../uuid" }
+/// ```
+fn parse_patched_crate_names(
+    manifests: &[ParsedManifest],
+    local_package_names: &HashSet<String>,
+) -> HashMap<String, HashSet<String>> {
+    let mut patched_crate_names: HashMap<String, HashSet<String>> = HashMap::new();
+    for manifest in manifests {
+        for (source, patches) in patch_tables(&manifest.contents) {
+            for (key, patch) in patches {
+                let name = dependency_package_name(key, patch);
+                if patch.get("path").is_some() && local_package_names.contains(name) {
+                    patched_crate_names
+                        .entry(normalize_source(source).to_owned())
+                        .or_default()
+                        .insert(name.to_owned());
+                }
+            }
+        }
+    }
+    patched_crate_names
+}
+
+/// The source a dependency is fetched from, using the same keys as the `[patch]` section:
+/// the URL of a git repository, the name (or the index URL) of a registry, or `crates-io`.
+fn dependency_source(dependency: &toml::Value) -> &str {
+    let get = |key: &str| dependency.get(key).and_then(|value| value.as_str());
+    get("git")
+        .or_else(|| get("registry"))
+        .or_else(|| get("registry-index"))
+        .map_or("crates-io", normalize_source)
+}
+
+/// crates.io can be referred to by name or by the URL of its index.
+fn normalize_source(source: &str) -> &str {
+    match source.trim_end_matches('/') {
+        "https://github.com/rust-lang/crates.io-index" | "sparse+https://index.crates.io" => {
+            "crates-io"
+        }
+        _ => source,
+    }
+}
+
+/// The name of the package a dependency entry points to.
+/// It differs from the table key when the dependency is renamed using `package = "..."`.
+pub(super) fn dependency_package_name<'a>(key: &'a str, dependency: &'a toml::Value) -> &'a str {
+    dependency
+        .get("package")
+        .and_then(|package| package.as_str())
+        .unwrap_or(key)
+}
+
+/// Collect the names of all local crates.
+///
+/// If no member was selected, all packages in the workspace are local.
+/// Otherwise we only consider the selected members and the local crates they (transitively)
+/// depend on via `path` dependencies.
+pub(super) fn parse_local_crate_names(
+    members: &[String],
     manifests: &[ParsedManifest],
 ) -> HashSet<String> {
     let mut local_package_names = HashSet::new();
+    let mut packages = HashMap::new();
     for manifest in manifests.iter() {
-        if let Some(package) = manifest.contents.get("package") {
-            if let Some(name) = package.get("name") {
-                if let toml::Value::String(name) = name {
-                    if let Some(member) = member {
-                        if member != name {
-                            // just evaluate the selected package for local dependencies if user specifed --bin option
-                            continue;
-                        }
-                        // evaluate the dependencies sections and extract local path dependencies
-                        for dependency_key in
-                            ["dependencies", "dev-dependencies", "build-dependencies"]
-                        {
-                            if let Some(dependencies) = manifest.contents.get(dependency_key) {
-                                if let toml::Value::Table(dependencies) = dependencies {
-                                    for (key, value) in dependencies.iter() {
-                                        // local dependencies have a path
-                                        if let Some(_) = value.get("path") {
-                                            local_package_names.insert(key.to_owned());
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    } else {
-                        local_package_names.insert(name.to_owned());
+        // Crates patched in with a local path are local, no matter which member was selected.
+        for (_, patches) in patch_tables(&manifest.contents) {
+            for (key, patch) in patches.iter() {
+                if patch.get("path").is_some() {
+                    local_package_names.insert(dependency_package_name(key, patch).to_owned());
+                }
+            }
+        }
+
+        if let Some(toml::Value::String(name)) = manifest
+            .contents
+            .get("package")
+            .and_then(|package| package.get("name"))
+        {
+            packages.insert(name.as_str(), &manifest.contents);
+        }
+    }
+
+    if members.is_empty() {
+        local_package_names.extend(packages.keys().map(|name| name.to_string()));
+        return local_package_names;
+    }
+
+    // Members can inherit a dependency from the workspace root (`common = { workspace = true }`).
+    // The `path` key, if any, lives in the root's `[workspace.dependencies]` table.
+    let workspace_dependencies = manifests.iter().find_map(|manifest| {
+        manifest
+            .contents
+            .get("workspace")?
+            .get("dependencies")?
+            .as_table()
+    });
+
+    // Walk the graph of local path dependencies, starting from the selected members.
+    let mut visited = HashSet::new();
+    let mut to_visit: Vec<&str> = members.iter().map(|member| member.as_str()).collect();
+    while let Some(name) = to_visit.pop() {
+        if !visited.insert(name) {
+            continue;
+        }
+        let manifest = match packages.get(name) {
+            Some(manifest) => manifest,
+            None => continue,
+        };
+        for dependencies in dependency_tables(manifest) {
+            for (key, value) in dependencies.iter() {
+                let is_inherited = value
+                    .get("workspace")
+                    .and_then(|workspace| workspace.as_bool())
+                    .unwrap_or_default();
+                let value = if is_inherited {
+                    match workspace_dependencies.and_then(|dependencies| dependencies.get(key)) {
+                        Some(value) => value,
+                        None => continue,
                     }
+                } else {
+                    value
+                };
+                // local dependencies have a path
+                if value.get("path").is_some() {
+                    to_visit.push(dependency_package_name(key, value));
                 }
             }
         }
     }
+    local_package_names.extend(visited.into_iter().map(|name| name.to_owned()));
     local_package_names
 }
@@ -0,0 +1,304 @@
+//! Logic to tell which of the manifests found in a project belong to its root workspace.
+use super::version_masking::{dependency_tables, patch_tables};
+use anyhow::Context;
+use fs_err as fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Find the root of the workspace a manifest belongs to, the way cargo does: the directory of
+/// the manifest itself if it has a `[workspace]` section, the one `package.workspace` points
+/// to, or else the closest parent directory with a `[workspace]` root listing it as a member
+/// (or with a root package, whose `path` dependencies are members as well) unless it excludes
+/// it.
+///
+/// The name of the package is returned as well if the manifest is a member, and not the root,
+/// of the workspace.
+pub(crate) fn find_workspace_root(
+    manifest_path: &Path,
+) -> Result<(PathBuf, Option<String>), anyhow::Error> {
+    let read = |manifest_path: &Path| -> Result<toml::Value, anyhow::Error> {
+        let contents = fs::read_to_string(manifest_path)?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse the manifest at {:?}.", manifest_path))
+    };
+    let manifest_path = fs::canonicalize(manifest_path)?;
+    let manifest = read(&manifest_path)?;
+    let directory = manifest_path
+        .parent()
+        .context("The manifest path has no parent directory.")?
+        .to_owned();
+    if manifest.get("workspace").is_some() {
+        return Ok((directory, None));
+    }
+    let package_name = manifest
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(|name| name.as_str())
+        .map(|name| name.to_owned());
+
+    let explicit_root = manifest
+        .get("package")
+        .and_then(|package| package.get("workspace"))
+        .and_then(|workspace| workspace.as_str());
+    if let Some(explicit_root) = explicit_root {
+        return Ok((
+            fs::canonicalize(directory.join(explicit_root))?,
+            package_name,
+        ));
+    }
+    for ancestor in directory.ancestors().skip(1) {
+        let candidate = ancestor.join("Cargo.toml");
+        if !candidate.is_file() {
+            continue;
+        }
+        let candidate = read(&candidate)?;
+        let workspace = match candidate.get("workspace") {
+            Some(workspace) => workspace,
+            None => continue,
+        };
+        let relative_directory = to_slash(directory.strip_prefix(ancestor).unwrap_or(&directory));
+        let is_excluded = workspace
+            .get("exclude")
+            .and_then(|exclude| exclude.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|pattern| pattern.as_str())
+            .any(|pattern| {
+                Path::new(&relative_directory)
+                    .ancestors()
+                    .any(|excluded| matches_member_pattern(pattern, &to_slash(excluded)))
+            });
+        let is_member = candidate.get("package").is_some()
+            || workspace
+                .get("members")
+                .and_then(|members| members.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|pattern| pattern.as_str())
+                .any(|pattern| matches_member_pattern(pattern, &relative_directory));
+        if is_excluded || !is_member {
+            break;
+        }
+        return Ok((ancestor.to_owned(), package_name));
+    }
+    Ok((directory, None))
+}
+
+/// Select, among all the manifests found in the project, the ones owned by its root workspace.
+///
+/// `manifests` holds the relative path and the raw contents of each `Cargo.toml`.
+/// We leave out the manifests that cargo never looks at when building the root workspace:
+/// - the ones in the directories listed in the root `[workspace] exclude`;
+/// - nested workspaces, i.e. directories with their own `[workspace]` root, and everything
+///   below them.
+///
+/// Both are kept, though, if they are (transitively) needed as `path` dependencies.
+/// The returned vector says, for each manifest, whether it should be kept.
+pub(super) fn owned_manifests(manifests: &[(PathBuf, toml::Value)]) -> Vec<bool> {
+    let root = match manifests
+        .iter()
+        .find(|(relative_path, _)| relative_path == Path::new("Cargo.toml"))
+    {
+        Some((_, root)) => root,
+        // Without a root manifest, there is no workspace to scope the project to.
+        None => return vec![true; manifests.len()],
+    };
+    let exclude: Vec<&str> = root
+        .get("workspace")
+        .and_then(|workspace| workspace.get("exclude"))
+        .and_then(|exclude| exclude.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|excluded| excluded.as_str())
+        .collect();
+    let nested_workspaces: Vec<&Path> = manifests
+        .iter()
+        .filter(|(relative_path, manifest)| {
+            relative_path != Path::new("Cargo.toml") && manifest.get("workspace").is_some()
+        })
+        .filter_map(|(relative_path, _)| relative_path.parent())
+        .collect();
+
+    let is_owned = |relative_path: &Path| {
+        let directory = relative_path.parent().unwrap_or_else(|| Path::new(""));
+        let is_excluded = directory.ancestors().any(|ancestor| {
+            let ancestor = to_slash(ancestor);
+            exclude
+                .iter()
+                .any(|excluded| matches_member_pattern(excluded, &ancestor))
+        });
+        let is_nested = nested_workspaces
+            .iter()
+            .any(|nested_workspace| directory.starts_with(nested_workspace));
+        !is_excluded && !is_nested
+    };
+    let mut keep: Vec<bool> = manifests
+        .iter()
+        .map(|(relative_path, _)| is_owned(relative_path))
+        .collect();
+
+    // Follow the `path` dependencies of the manifests we keep.
+    let mut to_visit: Vec<usize> = (0..manifests.len()).filter(|i| keep[*i]).collect();
+    while let Some(i) = to_visit.pop() {
+        let (relative_path, manifest) = &manifests[i];
+        let directory = relative_path.parent().unwrap_or_else(|| Path::new(""));
+        for (_, path) in path_dependencies(manifest) {
+            let dependency_manifest = match normalize(&directory.join(path).join("Cargo.toml")) {
+                Some(dependency_manifest) => dependency_manifest,
+                // Outside of the project.
+                None => continue,
+            };
+            if let Some(j) = manifests
+                .iter()
+                .position(|(relative_path, _)| relative_path == &dependency_manifest)
+            {
+                if !keep[j] {
+                    keep[j] = true;
+                    to_visit.push(j);
+                }
+            }
+        }
+    }
+    keep
+}
+
+/// The key and the `path` of all the dependencies declared in a manifest, including the
+/// workspace dependencies, the patches and the replacements.
+pub(super) fn path_dependencies(manifest: &toml::Value) -> impl Iterator<Item = (&String, &str)> {
+    let workspace_dependencies = manifest
+        .get("workspace")
+        .and_then(|workspace| workspace.get("dependencies"))
+        .and_then(|dependencies| dependencies.as_table());
+    let replace = manifest
+        .get("replace")
+        .and_then(|replace| replace.as_table());
+    dependency_tables(manifest)
+        .into_iter()
+        .chain(workspace_dependencies)
+        .chain(patch_tables(manifest).map(|(_, patches)| patches))
+        .chain(replace)
+        .flatten()
+        .filter_map(|(key, dependency)| Some((key, dependency.get("path")?.as_str()?)))
+}
+
+/// The name of the packages among `manifests` (relative path and contents), the ones they
+/// depend on with a `path` first.
+///
+/// Dependencies inherited with `workspace = true` are resolved through the
+/// `[workspace.dependencies]` of the root manifest. Cycles (e.g. through dev-dependencies) are
+/// broken following the order of `manifests`.
+pub(super) fn packages_in_dependency_order(manifests: &[(PathBuf, toml::Value)]) -> Vec<String> {
+    let directory =
+        |relative_path: &Path| relative_path.parent().unwrap_or(Path::new("")).to_owned();
+    let workspace_dependencies = manifests
+        .iter()
+        .find(|(relative_path, _)| relative_path == Path::new("Cargo.toml"))
+        .and_then(|(_, root)| root.get("workspace")?.get("dependencies")?.as_table());
+    let packages: Vec<(PathBuf, &str, &toml::Value)> = manifests
+        .iter()
+        .filter_map(|(relative_path, manifest)| {
+            let name = manifest.get("package")?.get("name")?.as_str()?;
+            Some((directory(relative_path), name, manifest))
+        })
+        .collect();
+    let position = |dependency_directory: Option<PathBuf>| {
+        let dependency_directory = dependency_directory?;
+        packages
+            .iter()
+            .position(|(directory, _, _)| directory == &dependency_directory)
+    };
+    let dependencies: Vec<Vec<usize>> = packages
+        .iter()
+        .map(|(directory, _, manifest)| {
+            dependency_tables(manifest)
+                .into_iter()
+                .flatten()
+                .filter_map(|(key, dependency)| {
+                    if let Some(path) = dependency.get("path").and_then(|path| path.as_str()) {
+                        return position(normalize(&directory.join(path)));
+                    }
+                    let is_inherited = dependency.get("workspace").and_then(|w| w.as_bool());
+                    if is_inherited != Some(true) {
+                        return None;
+                    }
+                    let path = workspace_dependencies?.get(key)?.get("path")?.as_str()?;
+                    position(normalize(Path::new(path)))
+                })
+                .collect()
+        })
+        .collect();
+
+    fn visit(i: usize, dependencies: &[Vec<usize>], visited: &mut [bool], order: &mut Vec<usize>) {
+        if visited[i] {
+            return;
+        }
+        visited[i] = true;
+        for j in &dependencies[i] {
+            visit(*j, dependencies, visited, order);
+        }
+        order.push(i);
+    }
+    let mut visited = vec![false; packages.len()];
+    let mut order = vec![];
+    for i in 0..packages.len() {
+        visit(i, &dependencies, &mut visited, &mut order);
+    }
+    order
+        .into_iter()
+        .map(|i| packages[i].1.to_owned())
+        .collect()
+}
+
+/// Resolve the `.` and `..` components of a relative path, without touching the filesystem.
+/// Returns `None` if the path points outside of the project.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::Normal(component) => normalized.push(component),
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+/// Render a relative path with `/` separators, as they are written in manifests.
+pub(super) fn to_slash(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Does an entry of `[workspace] members` or `exclude` (a path, possibly containing `*` and `?`
+/// wildcards) match a directory?
+pub(super) fn matches_member_pattern(pattern: &str, directory: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match (pattern.first(), name.first()) {
+            (None, None) => true,
+            (Some('*'), _) => {
+                matches(&pattern[1..], name) || (!name.is_empty() && matches(pattern, &name[1..]))
+            }
+            (Some('?'), Some(_)) => matches(&pattern[1..], &name[1..]),
+            (Some(p), Some(n)) if p == n => matches(&pattern[1..], &name[1..]),
+            _ => false,
+        }
+    }
+    let components = |path: &str| -> Vec<Vec<char>> {
+        path.split('/')
+            .filter(|component| !component.is_empty() && *component != ".")
+            .map(|component| component.chars().collect())
+            .collect()
+    };
+    let (pattern, directory) = (components(pattern), components(directory));
+    pattern.len() == directory.len()
+        && pattern
+            .iter()
+            .zip(directory.iter())
+            .all(|(pattern, name)| matches(pattern, name))
+}
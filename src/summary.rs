@@ -0,0 +1,72 @@
+//! Logic to summarise what a recipe covers, for tools that treat the recipe format as opaque
+//! (e.g. a build dashboard).
+use crate::Recipe;
+use serde::{Deserialize, Serialize};
+
+/// The version of the format of [`RecipeSummary`], bumped whenever a field changes or goes
+/// away. It is independent from [`crate::RECIPE_FORMAT_VERSION`].
+pub const SUMMARY_FORMAT_VERSION: u32 = 1;
+
+/// What a recipe covers: its members and their remote dependencies.
+///
+/// The same recipe always gives the same summary, byte for byte: the members and the
+/// dependencies are sorted.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecipeSummary {
+    /// [`SUMMARY_FORMAT_VERSION`] when it was written.
+    pub summary_version: u32,
+    /// The fingerprint of the recipe, as printed by `cargo chef hash`.
+    pub fingerprint: String,
+    /// The names of the packages of the workspace.
+    pub members: Vec<String>,
+    /// The remote packages pinned by the lockfile, `None` if the recipe has no lockfile.
+    pub remote_packages: Option<RemotePackageCounts>,
+    /// The remote dependencies declared by the members, in their `[dependencies]`,
+    /// `[dev-dependencies]` and `[build-dependencies]` tables.
+    pub direct_dependencies: Vec<DirectDependency>,
+}
+
+/// How many remote packages the lockfile of a recipe pins.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemotePackageCounts {
+    /// The ones the local crates depend on directly.
+    pub direct: usize,
+    /// The other ones, only depended on through other remote packages.
+    pub transitive: usize,
+}
+
+/// A remote dependency of a member of the workspace. Members depending on the same package
+/// with different requirements give one entry each.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DirectDependency {
+    /// The name of the package, not the one it is renamed to.
+    pub name: String,
+    /// The version requirement, e.g. `^1.2`. `None` for git dependencies without one.
+    pub requirement: Option<String>,
+    /// The repository of git dependencies.
+    pub git: Option<String>,
+}
+
+impl RecipeSummary {
+    pub fn new(recipe: &Recipe) -> Result<Self, anyhow::Error> {
+        let mut members = recipe.skeleton.member_names()?;
+        members.sort();
+        members.dedup();
+        let remote_packages = recipe
+            .skeleton
+            .remote_package_counts()?
+            .map(|(direct, transitive)| RemotePackageCounts { direct, transitive });
+        Ok(Self {
+            summary_version: SUMMARY_FORMAT_VERSION,
+            fingerprint: recipe.fingerprint()?,
+            members,
+            remote_packages,
+            direct_dependencies: recipe.skeleton.direct_dependencies()?,
+        })
+    }
+
+    /// The summary as pretty-printed JSON, with a trailing newline.
+    pub fn to_json(&self) -> Result<String, anyhow::Error> {
+        Ok(format!("{}\n", serde_json::to_string_pretty(self)?))
+    }
+}
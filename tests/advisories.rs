@@ -0,0 +1,244 @@
+use assert_fs::prelude::{FileWriteStr, PathChild};
+use assert_fs::TempDir;
+use chef::{AdvisoryDatabase, PrepareArgs, Recipe};
+
+const MANIFEST: &str = r#"
+[package]
+name = "app"
+version = "1.2.3"
+edition = "2018"
+
+[dependencies]
+smallvec = "1.6"
+time = "0.1"
+"#;
+
+const LOCKFILE: &str = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.0.1"
+dependencies = [
+ "smallvec",
+ "time",
+]
+
+[[package]]
+name = "smallvec"
+version = "1.6.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "time"
+version = "0.1.43"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+fn recipe() -> Recipe {
+    let directory = TempDir::new().unwrap();
+    directory.child("Cargo.toml").write_str(MANIFEST).unwrap();
+    directory.child("Cargo.lock").write_str(LOCKFILE).unwrap();
+    directory.child("src/main.rs").write_str("").unwrap();
+    Recipe::prepare(directory.path().into(), PrepareArgs::default()).unwrap()
+}
+
+/// An advisory in the Markdown format of the RustSec advisory database.
+fn advisory(id: &str, package: &str, extra: &str, versions: &str) -> String {
+    format!(
+        r#"```toml
+[advisory]
+id = "{}"
+package = "{}"
+date = "2021-01-08"
+{}
+
+[versions]
+{}
+```
+
+# A memory safety issue in `{}`
+
+Details.
+"#,
+        id, package, extra, versions, package
+    )
+}
+
+#[test]
+fn packages_in_the_affected_range_are_reported() {
+    // Arrange
+    let db = TempDir::new().unwrap();
+    db.child("crates/smallvec/RUSTSEC-2021-0003.md")
+        .write_str(&advisory(
+            "RUSTSEC-2021-0003",
+            "smallvec",
+            "",
+            r#"patched = [">= 1.6.1"]
+unaffected = ["< 1.3.0"]"#,
+        ))
+        .unwrap();
+    db.child("crates/time/RUSTSEC-2020-0071.md")
+        .write_str(&advisory(
+            "RUSTSEC-2020-0071",
+            "time",
+            "",
+            r#"patched = [">= 0.2.23"]
+unaffected = ["= 0.2.0", "= 0.2.1"]"#,
+        ))
+        .unwrap();
+    db.child("crates/time/RUSTSEC-2019-0001.md")
+        .write_str(&advisory(
+            "RUSTSEC-2019-0001",
+            "time",
+            "",
+            r#"patched = [">= 0.1.40"]"#,
+        ))
+        .unwrap();
+
+    // Act
+    let vulnerabilities = AdvisoryDatabase::open(db.path())
+        .unwrap()
+        .check(&recipe())
+        .unwrap();
+
+    // Assert
+    let ids: Vec<_> = vulnerabilities
+        .iter()
+        .map(|vulnerability| {
+            (
+                vulnerability.package.as_str(),
+                vulnerability.version.as_str(),
+                vulnerability.advisory_id.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            ("smallvec", "1.6.0", "RUSTSEC-2021-0003"),
+            ("time", "0.1.43", "RUSTSEC-2020-0071")
+        ],
+        ids
+    );
+    assert_eq!(vec![">=1.6.1".to_owned()], vulnerabilities[0].patched);
+    assert_eq!(
+        Some("A memory safety issue in `smallvec`"),
+        vulnerabilities[0].title.as_deref()
+    );
+}
+
+#[test]
+fn local_crates_are_skipped() {
+    // Arrange
+    let db = TempDir::new().unwrap();
+    db.child("crates/app/RUSTSEC-2021-0001.md")
+        .write_str(&advisory("RUSTSEC-2021-0001", "app", "", "patched = []"))
+        .unwrap();
+
+    // Act
+    let vulnerabilities = AdvisoryDatabase::open(db.path())
+        .unwrap()
+        .check(&recipe())
+        .unwrap();
+
+    // Assert
+    assert!(vulnerabilities.is_empty(), "{:?}", vulnerabilities);
+}
+
+#[test]
+fn withdrawn_and_informational_advisories_are_ignored() {
+    // Arrange
+    let db = TempDir::new().unwrap();
+    db.child("crates/time/RUSTSEC-2020-0071.md")
+        .write_str(&advisory(
+            "RUSTSEC-2020-0071",
+            "time",
+            r#"withdrawn = "2021-01-01""#,
+            "patched = []",
+        ))
+        .unwrap();
+    db.child("crates/smallvec/RUSTSEC-2021-0003.md")
+        .write_str(&advisory(
+            "RUSTSEC-2021-0003",
+            "smallvec",
+            r#"informational = "unmaintained""#,
+            "patched = []",
+        ))
+        .unwrap();
+
+    // Act
+    let vulnerabilities = AdvisoryDatabase::open(db.path())
+        .unwrap()
+        .check(&recipe())
+        .unwrap();
+
+    // Assert
+    assert!(vulnerabilities.is_empty(), "{:?}", vulnerabilities);
+}
+
+#[test]
+fn legacy_toml_advisories_are_supported() {
+    // Arrange
+    let db = TempDir::new().unwrap();
+    db.child("crates/time/RUSTSEC-2020-0071.toml")
+        .write_str(
+            r#"
+[advisory]
+id = "RUSTSEC-2020-0071"
+package = "time"
+title = "Potential segfault in the time crate"
+
+[versions]
+patched = [">= 0.2.23"]
+"#,
+        )
+        .unwrap();
+
+    // Act
+    let vulnerabilities = AdvisoryDatabase::open(db.path())
+        .unwrap()
+        .check(&recipe())
+        .unwrap();
+
+    // Assert
+    assert_eq!(1, vulnerabilities.len());
+    assert_eq!(
+        Some("Potential segfault in the time crate"),
+        vulnerabilities[0].title.as_deref()
+    );
+}
+
+#[test]
+fn a_directory_that_is_not_an_advisory_database_is_an_error() {
+    // Arrange
+    let db = TempDir::new().unwrap();
+
+    // Act
+    let error = AdvisoryDatabase::open(&db.path().join("missing")).unwrap_err();
+
+    // Assert
+    assert!(
+        error.to_string().contains("is not an advisory database"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn invalid_advisories_are_errors() {
+    // Arrange
+    let db = TempDir::new().unwrap();
+    db.child("crates/time/RUSTSEC-2020-0071.md")
+        .write_str("# Not an advisory\n")
+        .unwrap();
+
+    // Act
+    let error = AdvisoryDatabase::open(db.path()).unwrap_err();
+
+    // Assert
+    assert!(
+        format!("{:?}", error).contains("RUSTSEC-2020-0071.md"),
+        "{:?}",
+        error
+    );
+}
@@ -0,0 +1,67 @@
+//! The command line of `cargo chef`: typos must fail instead of being ignored, and the version
+//! tells which recipes can be cooked.
+use assert_cmd::Command;
+use chef::{MIN_RECIPE_FORMAT_VERSION, RECIPE_FORMAT_VERSION};
+
+fn chef(args: &[&str]) -> assert_cmd::assert::Assert {
+    Command::cargo_bin("cargo-chef")
+        .unwrap()
+        .arg("chef")
+        .args(args)
+        .assert()
+}
+
+#[test]
+fn the_version_lists_the_supported_recipe_formats() {
+    // Act
+    let assert = chef(&["--version"]);
+
+    // Assert
+    assert.success().stdout(format!(
+        "cargo-chef {}\nrecipe format versions: {} to {} (prepare writes {})\n",
+        env!("CARGO_PKG_VERSION"),
+        MIN_RECIPE_FORMAT_VERSION,
+        RECIPE_FORMAT_VERSION,
+        RECIPE_FORMAT_VERSION
+    ));
+}
+
+#[test]
+fn unknown_flags_are_rejected_with_a_suggestion() {
+    // Act
+    let assert = chef(&["cook", "--hepl"]);
+
+    // Assert
+    assert
+        .failure()
+        .stderr(predicates::str::contains(
+            "Found argument '--hepl' which wasn't expected",
+        ))
+        .stderr(predicates::str::contains("Did you mean '--help'?"));
+}
+
+#[test]
+fn completions_cover_the_subcommands_and_their_flags() {
+    for shell in &["bash", "zsh", "fish"] {
+        // Act
+        let assert = chef(&["completions", shell]);
+
+        // Assert
+        assert
+            .success()
+            .stdout(predicates::str::contains("cook"))
+            .stdout(predicates::str::contains("recipe-path"))
+            .stdout(predicates::str::contains("sweep-cache"));
+    }
+}
+
+#[test]
+fn completions_are_only_generated_for_the_supported_shells() {
+    // Act
+    let assert = chef(&["completions", "powershell"]);
+
+    // Assert
+    assert.failure().stderr(predicates::str::contains(
+        "possible values: bash, zsh, fish",
+    ));
+}
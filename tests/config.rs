@@ -0,0 +1,165 @@
+//! `chef.toml` (or `[workspace.metadata.chef]`) sets the defaults of `prepare` for a project,
+//! below the command line and the environment.
+use assert_cmd::Command;
+use assert_fs::prelude::{FileTouch, FileWriteStr, PathChild};
+use assert_fs::TempDir;
+use chef::{ChefConfig, Recipe};
+
+/// A workspace with an `api` and a `worker` member, `api` having a dev-dependency.
+fn workspace(directory: &TempDir) {
+    directory
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["api", "worker"]
+
+[workspace.metadata.chef]
+mask-version = "9.9.9"
+keep-metadata = true
+"#,
+        )
+        .unwrap();
+    directory
+        .child("api/Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "api"
+version = "0.1.0"
+edition = "2021"
+
+[dev-dependencies]
+insta = "1"
+"#,
+        )
+        .unwrap();
+    directory.child("api/src/main.rs").touch().unwrap();
+    directory
+        .child("worker/Cargo.toml")
+        .write_str("[package]\nname = \"worker\"\nversion = \"0.1.0\"\nedition = \"2021\"\n")
+        .unwrap();
+    directory.child("worker/src/main.rs").touch().unwrap();
+    directory
+        .child("proto/service.proto")
+        .write_str("syntax = \"proto3\";\n")
+        .unwrap();
+}
+
+fn prepare(directory: &TempDir) -> Command {
+    let mut command = Command::cargo_bin("cargo-chef").unwrap();
+    command
+        .current_dir(directory.path())
+        .arg("chef")
+        .arg("prepare");
+    command
+}
+
+fn read_recipe(directory: &TempDir, path: &str) -> Recipe {
+    Recipe::from_json(&std::fs::read_to_string(directory.child(path).path()).unwrap()).unwrap()
+}
+
+#[test]
+fn prepare_applies_the_configuration_below_the_command_line() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace(&directory);
+    directory
+        .child("chef.toml")
+        .write_str(
+            r#"
+mask-version = "1.2.3"
+no-dev-dependencies = true
+include = ["proto/*.proto"]
+cook-args = ["--release"]
+split-workspace = true
+"#,
+        )
+        .unwrap();
+
+    // Act
+    let assert = prepare(&directory)
+        .env("CHEF_COOK_ARGS", "--profile ci")
+        .args(["--recipe-path", "recipe.json"])
+        .assert();
+
+    // Assert
+    assert.success().stderr(predicates::str::contains(
+        "warning: unknown key `split-workspace` in chef.toml, it is ignored.",
+    ));
+    let recipe = read_recipe(&directory, "recipe.json");
+    // `chef.toml` takes precedence over `[workspace.metadata.chef]`, the environment over both.
+    assert_eq!("1.2.3", recipe.skeleton.mask_version);
+    assert_eq!(vec!["--profile", "ci"], recipe.default_cook_args);
+    assert!(recipe
+        .skeleton
+        .manifests
+        .iter()
+        .all(|manifest| !manifest.contents.contains("insta")));
+    assert_eq!(
+        vec!["proto/service.proto"],
+        recipe
+            .skeleton
+            .included_files
+            .iter()
+            .map(|file| file.relative_path.to_str().unwrap())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        ChefConfig {
+            mask_version: Some("1.2.3".into()),
+            no_dev_dependencies: Some(true),
+            keep_metadata: Some(true),
+            include: Some(vec!["proto/*.proto".into()]),
+            ..ChefConfig::default()
+        },
+        recipe.config
+    );
+}
+
+#[test]
+fn prepare_splits_the_recipes_of_the_configured_members() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace(&directory);
+    directory
+        .child("chef.toml")
+        .write_str("split-members = [\"worker\"]\n")
+        .unwrap();
+
+    // Act
+    let assert = prepare(&directory)
+        .args(["--split-workspace", "--recipe-dir", "recipes"])
+        .assert();
+
+    // Assert
+    assert.success();
+    assert!(directory.child("recipes/worker.json").path().is_file());
+    assert!(!directory.child("recipes/api.json").path().exists());
+    assert_eq!(
+        None,
+        read_recipe(&directory, "recipes/worker.json")
+            .config
+            .split_members
+    );
+}
+
+#[test]
+fn prepare_rejects_invalid_configurations() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace(&directory);
+    directory
+        .child("chef.toml")
+        .write_str("no-dev-dependencies = \"yes\"\n")
+        .unwrap();
+
+    // Act
+    let assert = prepare(&directory).assert();
+
+    // Assert
+    assert.failure().stderr(predicates::str::contains(
+        "Failed to read the chef configuration of the project.",
+    ));
+    assert!(!directory.child("recipe.json").path().exists());
+}
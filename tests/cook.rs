@@ -0,0 +1,3513 @@
+//! End-to-end tests: prepare a recipe and cook it with the `cargo-chef` binary.
+//!
+//! The dependencies are vendored in a directory source outside of the project: they are compiled
+//! from their actual sources, without requiring network access.
+use assert_cmd::Command;
+use assert_fs::prelude::{FileTouch, FileWriteStr, PathAssert, PathChild, PathCopy, PathCreateDir};
+use assert_fs::TempDir;
+use chef::{ChefConfig, CookOptions, PrepareArgs, Recipe, WorkspaceOptions};
+use std::path::Path;
+
+/// Vendor a crate, with no dependencies, under `directory/vendor` and point the cargo
+/// configuration of `directory/project` to it.
+fn vendor(directory: &TempDir, name: &str) {
+    vendor_with_features(directory, name, &[]);
+}
+
+/// Like [`vendor`], for a crate with the given (empty) `features`.
+fn vendor_with_features(directory: &TempDir, name: &str, features: &[&str]) {
+    let vendored = directory.child("vendor").child(name);
+    let features: String = features
+        .iter()
+        .map(|feature| format!("{} = []\n", feature))
+        .collect();
+    vendored
+        .child("Cargo.toml")
+        .write_str(&format!(
+            r#"
+[package]
+name = "{}"
+version = "0.1.0"
+edition = "2018"
+
+[features]
+{}"#,
+            name, features
+        ))
+        .unwrap();
+    vendored.child("src").child("lib.rs").touch().unwrap();
+    vendored
+        .child(".cargo-checksum.json")
+        .write_str(r#"{"files":{},"package":null}"#)
+        .unwrap();
+    directory
+        .child("project")
+        .child(".cargo")
+        .child("config.toml")
+        .write_str(&format!(
+            r#"
+[source.crates-io]
+replace-with = "vendored"
+
+[source.vendored]
+directory = {:?}
+"#,
+            directory.child("vendor").path()
+        ))
+        .unwrap();
+}
+
+/// A workspace with two members, `api` and `worker`, each with its own dependency.
+/// Only `api` is built by a plain `cargo build`.
+fn workspace_with_default_members(directory: &TempDir) -> Recipe {
+    directory
+        .child("project")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["crates/api", "crates/worker"]
+default-members = ["crates/api"]
+"#,
+        )
+        .unwrap();
+    for name in ["api", "worker"] {
+        let member = directory.child("project").child("crates").child(name);
+        member
+            .child("Cargo.toml")
+            .write_str(&format!(
+                r#"
+[package]
+name = "{0}"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+{0}-dependency = "0.1"
+"#,
+                name
+            ))
+            .unwrap();
+        member.child("src").child("lib.rs").touch().unwrap();
+        vendor(directory, &format!("{}-dependency", name));
+    }
+    Recipe::prepare(
+        directory.child("project").path().into(),
+        PrepareArgs::default(),
+    )
+    .unwrap()
+}
+
+/// `cargo chef cook`, ready to run in `directory/cook` next to the serialized `recipe`.
+fn cook_command(directory: &TempDir, recipe: &Recipe) -> Command {
+    let cook_directory = directory.child("cook");
+    cook_directory
+        .child("recipe.json")
+        .write_str(&serde_json::to_string(recipe).unwrap())
+        .unwrap();
+    let mut command = Command::cargo_bin("cargo-chef").unwrap();
+    command
+        .current_dir(cook_directory.path())
+        .env("CARGO", env!("CARGO"))
+        .args(["chef", "cook", "--recipe-path", "recipe.json"]);
+    command
+}
+
+fn cook(directory: &TempDir, recipe: &Recipe, args: &[&str]) {
+    cook_command(directory, recipe)
+        .args(args)
+        .assert()
+        .success();
+}
+
+/// Was a library compiled in the `debug` profile of `target_directory`?
+fn is_compiled(target_directory: &Path, library: &str) -> bool {
+    let prefix = format!("lib{}-", library);
+    let deps = target_directory.join("debug").join("deps");
+    if !deps.exists() {
+        return false;
+    }
+    std::fs::read_dir(deps).unwrap().any(|entry| {
+        entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(&prefix)
+    })
+}
+
+#[test]
+fn cook_fails_early_when_cargo_zigbuild_is_not_installed() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    // cargo looks for subcommands in `PATH` and in `$CARGO_HOME/bin`.
+    let empty = directory.child("empty");
+    std::fs::create_dir(empty.path()).unwrap();
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .env("PATH", empty.path())
+        .env("CARGO_HOME", empty.path())
+        .arg("--zigbuild")
+        .assert();
+
+    // Assert
+    let assert = assert.failure().stderr(predicates::str::contains(
+        "`cargo zigbuild` is not available. Install it with `cargo install --locked cargo-zigbuild`",
+    ));
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    directory
+        .child("cook")
+        .child("Cargo.toml")
+        .assert(predicates::path::missing());
+}
+
+#[test]
+fn cook_fails_early_when_the_cargo_path_does_not_exist() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .args(["--cargo-path", "/does/not/exist/cargo"])
+        .assert();
+
+    // Assert
+    let assert = assert.failure().stderr(predicates::str::contains(
+        "The cargo executable \"/does/not/exist/cargo\" could not be found. Check `--cargo-path`.",
+    ));
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    directory
+        .child("cook")
+        .child("Cargo.toml")
+        .assert(predicates::path::missing());
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_invokes_the_cargo_path_with_the_trailing_cargo_args() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let cargo = recording_cargo(&directory, "cargo 1.79.0 (ffa9cf99a 2024-06-03)");
+    // The dummy artifacts are cleaned up from the target directory the fake cargo does not create.
+    directory
+        .child("cook")
+        .child("target")
+        .child("release")
+        .create_dir_all()
+        .unwrap();
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .arg("--cargo-path")
+        .arg(&cargo)
+        .args(["--release", "--", "--config", "net.offline = true", "-vv"])
+        .assert();
+
+    // Assert
+    assert.success();
+    // Only the fake cargo records its arguments.
+    let arguments = std::fs::read_to_string(directory.child("arguments").path()).unwrap();
+    let arguments: Vec<&str> = arguments.lines().collect();
+    assert_eq!(Some(&"build"), arguments.first(), "{:?}", arguments);
+    assert!(arguments.contains(&"--release"), "{:?}", arguments);
+    assert!(
+        arguments.ends_with(&["--config", "net.offline = true", "-vv"]),
+        "{:?}",
+        arguments
+    );
+}
+
+#[test]
+fn cook_respects_default_members() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    cook(&directory, &recipe, &[]);
+
+    // Assert
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "api_dependency"));
+    assert!(!is_compiled(&target_directory, "worker_dependency"));
+}
+
+#[test]
+fn cook_the_whole_workspace() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    cook(&directory, &recipe, &["--workspace"]);
+
+    // Assert
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "api_dependency"));
+    assert!(is_compiled(&target_directory, "worker_dependency"));
+}
+
+#[test]
+fn cook_a_workspace_with_inherited_lints() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["app"]
+
+[workspace.lints.rust]
+unsafe_code = "forbid"
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[lints]
+workspace = true
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("src")
+        .child("main.rs")
+        .write_str("fn main() {}")
+        .unwrap();
+    let recipe = Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap();
+
+    // Act & Assert
+    cook(&directory, &recipe, &[]);
+}
+
+#[test]
+fn cook_a_package_on_the_2024_edition_with_links() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "sys"
+version = "0.1.0"
+edition = "2024"
+rust-version = "1.85"
+links = "foo"
+build = "build.rs"
+
+[dependencies]
+dependency = "0.1"
+"#,
+        )
+        .unwrap();
+    project.child("build.rs").write_str("fn main() {}").unwrap();
+    project.child("src").child("lib.rs").touch().unwrap();
+    vendor(&directory, "dependency");
+    let recipe = Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap();
+
+    // Act
+    cook(&directory, &recipe, &[]);
+
+    // Assert
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "dependency"));
+}
+
+/// The compilation units of a `cargo build --message-format json <args>` in `directory` that
+/// came from dependencies and were not fresh.
+fn rebuilt_dependencies(directory: &Path, args: &[&str]) -> Vec<String> {
+    let output = std::process::Command::new(env!("CARGO"))
+        .current_dir(directory)
+        .args(["build", "--message-format", "json"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|message| message["reason"] == "compiler-artifact")
+        .filter(|message| message["fresh"] == false)
+        .map(|message| message["package_id"].as_str().unwrap().to_owned())
+        .filter(|package_id| !package_id.starts_with("path+"))
+        .collect()
+}
+
+#[test]
+fn the_real_build_reuses_the_dependencies_cooked_with_resolver_3() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["api", "worker"]
+resolver = "3"
+
+[workspace.package]
+edition = "2024"
+rust-version = "1.85"
+"#,
+        )
+        .unwrap();
+    for (name, rust_version) in [
+        ("api", "rust-version.workspace = true"),
+        ("worker", "rust-version = \"1.88\""),
+    ] {
+        let member = project.child(name);
+        member
+            .child("Cargo.toml")
+            .write_str(&format!(
+                r#"
+[package]
+name = "{}"
+version = "0.1.0"
+edition.workspace = true
+{}
+
+[dependencies]
+dependency = "0.1"
+"#,
+                name, rust_version
+            ))
+            .unwrap();
+        member
+            .child("src")
+            .child("lib.rs")
+            .write_str("pub fn f() {}")
+            .unwrap();
+    }
+    vendor(&directory, "dependency");
+    let recipe = Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap();
+    cook(&directory, &recipe, &[]);
+
+    // Act
+    let cook_directory = directory.child("cook");
+    cook_directory.copy_from(project.path(), &["**"]).unwrap();
+    let rebuilt = rebuilt_dependencies(cook_directory.path(), &[]);
+
+    // Assert
+    assert!(rebuilt.is_empty(), "{:?}", rebuilt);
+}
+
+/// Is the cargo running the tests a nightly one?
+fn is_nightly() -> bool {
+    let output = std::process::Command::new(env!("CARGO"))
+        .arg("-V")
+        .output()
+        .unwrap();
+    String::from_utf8_lossy(&output.stdout).contains("nightly")
+}
+
+/// Requires a nightly toolchain (`cargo +nightly test --test cook nightly`): skipped otherwise.
+#[test]
+fn cook_artifact_dependencies_on_nightly() {
+    if !is_nightly() {
+        eprintln!("Skipped: artifact dependencies require a nightly toolchain.");
+        return;
+    }
+
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str("[workspace]\nmembers = [\"app\", \"tool\"]\n")
+        .unwrap();
+    project
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+build = "build.rs"
+
+[build-dependencies]
+tool = { path = "../tool", artifact = "bin", target = "target" }
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("build.rs")
+        .write_str(r#"fn main() { std::env::var("CARGO_BIN_FILE_TOOL").unwrap(); }"#)
+        .unwrap();
+    project
+        .child("app")
+        .child("src")
+        .child("main.rs")
+        .write_str("fn main() {}")
+        .unwrap();
+    project
+        .child("tool")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "tool"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+dependency = "0.1"
+"#,
+        )
+        .unwrap();
+    project
+        .child("tool")
+        .child("src")
+        .child("main.rs")
+        .write_str("fn main() {}")
+        .unwrap();
+    vendor(&directory, "dependency");
+    let recipe = Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap();
+
+    // Act
+    cook(&directory, &recipe, &["-Z", "bindeps"]);
+
+    // Assert
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "dependency"));
+}
+
+/// A workspace with a `workspace-hack` crate, laid out like the ones generated by
+/// `cargo hakari`: it enables the `extra` feature of `dependency` for all the members.
+fn workspace_with_workspace_hack(directory: &TempDir) -> Recipe {
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["api", "worker", "workspace-hack"]
+
+[workspace.dependencies]
+workspace-hack = { version = "0.1", path = "workspace-hack" }
+"#,
+        )
+        .unwrap();
+    project
+        .child("workspace-hack")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+# This file is generated by `cargo hakari`.
+[package]
+name = "workspace-hack"
+version = "0.1.0"
+edition = "2021"
+publish = false
+
+### BEGIN HAKARI SECTION
+[dependencies]
+dependency = { version = "0.1", default-features = false, features = ["extra"] }
+
+[build-dependencies]
+dependency = { version = "0.1", default-features = false, features = ["extra"] }
+
+### END HAKARI SECTION
+"#,
+        )
+        .unwrap();
+    project
+        .child("workspace-hack")
+        .child("src")
+        .child("lib.rs")
+        .write_str("// This is a stub lib.rs.\n")
+        .unwrap();
+    for name in ["api", "worker"] {
+        let member = project.child(name);
+        member
+            .child("Cargo.toml")
+            .write_str(&format!(
+                r#"
+[package]
+name = "{}"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+dependency = "0.1"
+workspace-hack = {{ workspace = true }}
+"#,
+                name
+            ))
+            .unwrap();
+        member
+            .child("src")
+            .child("lib.rs")
+            .write_str("pub fn f() {}")
+            .unwrap();
+    }
+    vendor_with_features(directory, "dependency", &["extra"]);
+    Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap()
+}
+
+#[test]
+fn the_real_build_of_each_member_reuses_the_dependencies_unified_by_the_workspace_hack() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_workspace_hack(&directory);
+    cook(&directory, &recipe, &[]);
+    let cook_directory = directory.child("cook");
+    cook_directory
+        .copy_from(directory.child("project").path(), &["**"])
+        .unwrap();
+
+    for member in ["api", "worker"] {
+        // Act
+        let rebuilt = rebuilt_dependencies(cook_directory.path(), &["--package", member]);
+
+        // Assert
+        assert!(rebuilt.is_empty(), "{:?}", rebuilt);
+    }
+}
+
+#[test]
+fn cook_a_workspace_with_an_excluded_nested_workspace() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["app"]
+exclude = ["tools"]
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("src")
+        .child("main.rs")
+        .write_str("fn main() {}")
+        .unwrap();
+    let tools = project.child("tools");
+    tools
+        .child("Cargo.toml")
+        .write_str("[workspace]\nmembers = [\"gen\"]\n")
+        .unwrap();
+    tools
+        .child("gen")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "gen"
+version = "0.1.0"
+edition = "2024"
+"#,
+        )
+        .unwrap();
+    tools
+        .child("gen")
+        .child("src")
+        .child("lib.rs")
+        .touch()
+        .unwrap();
+
+    // Act
+    let recipe = Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap();
+    cook(&directory, &recipe, &[]);
+
+    // Assert
+    assert_eq!(2, recipe.skeleton.manifests.len());
+    assert!(!directory.child("cook").child("tools").path().exists());
+}
+
+#[test]
+fn cook_ignores_directories_matched_by_member_globs_without_a_stub() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str("[workspace]\nmembers = [\"crates/*\"]\n")
+        .unwrap();
+    project
+        .child("crates")
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .unwrap();
+    project
+        .child("crates")
+        .child("app")
+        .child("src")
+        .child("main.rs")
+        .write_str("fn main() {}")
+        .unwrap();
+    let recipe = Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap();
+    // A directory of the real project, e.g. copied before cooking, that is not in the recipe.
+    directory
+        .child("cook")
+        .child("crates")
+        .child("scripts")
+        .child("deploy.sh")
+        .touch()
+        .unwrap();
+
+    // Act & Assert
+    cook(&directory, &recipe, &[]);
+}
+
+#[test]
+fn cook_a_crate_with_a_custom_layout() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "legacy"
+version = "0.1.0"
+edition = "2018"
+autobins = false
+
+[lib]
+path = "lib/mod.rs"
+
+[[bin]]
+name = "app"
+path = "app/main.rs"
+"#,
+        )
+        .unwrap();
+    project.child("lib").child("mod.rs").touch().unwrap();
+    project
+        .child("app")
+        .child("main.rs")
+        .write_str("fn main() {}")
+        .unwrap();
+    // Not a target, since `autobins = false`.
+    project
+        .child("src")
+        .child("main.rs")
+        .write_str("compile_error!(\"not a target\");")
+        .unwrap();
+
+    // Act
+    let recipe = Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap();
+    cook(&directory, &recipe, &[]);
+
+    // Assert
+    let cook_directory = directory.child("cook");
+    cook_directory
+        .child("lib")
+        .child("mod.rs")
+        .assert(predicates::path::exists());
+    cook_directory
+        .child("src")
+        .assert(predicates::path::missing());
+}
+
+#[test]
+fn cook_path_dependencies_outside_of_the_project() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+shared = { path = "../shared" }
+"#,
+        )
+        .unwrap();
+    project
+        .child("src")
+        .child("main.rs")
+        .write_str("fn main() {}")
+        .unwrap();
+    directory
+        .child("shared")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "shared"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .unwrap();
+    directory
+        .child("shared")
+        .child("src")
+        .child("lib.rs")
+        .touch()
+        .unwrap();
+
+    // Act
+    let recipe = Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap();
+    // `../shared` does not exist next to the cook directory.
+    let cook_directory = TempDir::new().unwrap();
+    cook(&cook_directory, &recipe, &[]);
+
+    // Assert
+    cook_directory
+        .child("cook")
+        .child(".cargo-chef/external/shared/Cargo.toml")
+        .assert(predicates::path::exists());
+}
+
+#[test]
+fn cook_without_building() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let cook_directory = directory.child("cook");
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .arg("--no-build")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    // Assert
+    let mut written: Vec<String> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| line.replace('\\', "/"))
+        .collect();
+    written.sort();
+    assert_eq!(
+        written,
+        vec![
+            ".cargo/config.toml",
+            "Cargo.toml",
+            "crates/api/Cargo.toml",
+            "crates/api/src/lib.rs",
+            "crates/worker/Cargo.toml",
+            "crates/worker/src/lib.rs",
+        ]
+    );
+    for path in &written {
+        cook_directory
+            .child(path)
+            .assert(predicates::path::exists());
+    }
+    cook_directory
+        .child("target")
+        .assert(predicates::path::missing());
+
+    // A plain `cargo build` picks up from there.
+    std::process::Command::new(env!("CARGO"))
+        .arg("build")
+        .current_dir(cook_directory.path())
+        .status()
+        .map(|status| assert!(status.success()))
+        .unwrap();
+    let target_directory = cook_directory.child("target");
+    assert!(is_compiled(&target_directory, "api_dependency"));
+    assert!(!is_compiled(&target_directory, "worker_dependency"));
+}
+
+/// The packages with a fingerprint in the `debug` profile of `target_directory`.
+fn fingerprinted_packages(target_directory: &Path) -> Vec<String> {
+    let mut packages: Vec<String> =
+        std::fs::read_dir(target_directory.join("debug").join(".fingerprint"))
+            .unwrap()
+            .map(|entry| {
+                let file_name = entry.unwrap().file_name().to_string_lossy().into_owned();
+                let (package, _hash) = file_name.rsplit_once('-').unwrap();
+                package.to_owned()
+            })
+            .collect();
+    packages.sort();
+    packages
+}
+
+#[test]
+fn cook_removes_the_artifacts_of_the_workspace_crates() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    cook(&directory, &recipe, &[]);
+
+    // Assert
+    let target_directory = directory.child("cook").child("target");
+    assert_eq!(
+        vec!["api-dependency"],
+        fingerprinted_packages(&target_directory)
+    );
+    assert!(!is_compiled(&target_directory, "api"));
+    assert!(is_compiled(&target_directory, "api_dependency"));
+}
+
+#[test]
+fn cook_without_cleaning() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    cook(&directory, &recipe, &["--no-clean"]);
+
+    // Assert
+    let target_directory = directory.child("cook").child("target");
+    assert_eq!(
+        vec!["api", "api-dependency"],
+        fingerprinted_packages(&target_directory)
+    );
+    assert!(is_compiled(&target_directory, "api"));
+}
+
+/// The triple of the host, e.g. `x86_64-unknown-linux-gnu`.
+fn host_triple() -> String {
+    let output = std::process::Command::new("rustc")
+        .arg("-vV")
+        .output()
+        .unwrap();
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .unwrap()
+        .to_owned()
+}
+
+#[test]
+fn cook_into_the_target_directory_of_the_cargo_configuration() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let mut recipe = workspace_with_default_members(&directory);
+    let config_file = recipe.skeleton.config_file.take().unwrap();
+    recipe.skeleton.config_file = Some(format!(
+        "{}\n[build]\ntarget-dir = \"cache/target\"\n",
+        config_file
+    ));
+
+    // Act
+    cook(&directory, &recipe, &[]);
+
+    // Assert
+    let cook_directory = directory.child("cook");
+    cook_directory
+        .child("target")
+        .assert(predicates::path::missing());
+    let target_directory = cook_directory.child("cache").child("target");
+    assert_eq!(
+        vec!["api-dependency"],
+        fingerprinted_packages(&target_directory)
+    );
+    assert!(!is_compiled(&target_directory, "api"));
+}
+
+#[test]
+fn cook_into_an_absolute_target_directory_from_the_environment() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let target_directory = directory.child("target-cache");
+
+    // Act
+    cook_command(&directory, &recipe)
+        .env("CARGO_TARGET_DIR", target_directory.path())
+        .assert()
+        .success();
+
+    // Assert
+    directory
+        .child("cook")
+        .child("target")
+        .assert(predicates::path::missing());
+    assert_eq!(
+        vec!["api-dependency"],
+        fingerprinted_packages(&target_directory)
+    );
+    assert!(!is_compiled(&target_directory, "api"));
+}
+
+#[test]
+fn cook_into_a_relative_target_directory_for_a_target() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let triple = host_triple();
+
+    // Act
+    cook(
+        &directory,
+        &recipe,
+        &["--target-dir", "../target-cache", "--target", &triple],
+    );
+
+    // Assert
+    let target_directory = directory.child("target-cache").child(&triple);
+    assert_eq!(
+        vec!["api-dependency"],
+        fingerprinted_packages(&target_directory)
+    );
+    assert!(is_compiled(&target_directory, "api_dependency"));
+    assert!(!is_compiled(&target_directory, "api"));
+}
+
+#[test]
+fn cook_fails_early_when_the_rustc_wrapper_does_not_exist() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .args(["--rustc-wrapper", "scache"])
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicates::str::contains(
+        "rustc wrapper \"scache\" could not be found",
+    ));
+    directory
+        .child("cook")
+        .child("Cargo.toml")
+        .assert(predicates::path::missing());
+}
+
+/// A fake `sccache` in `directory/bin`, forwarding to rustc and recording its invocations in
+/// `directory/invocations`. `--show-stats` prints `stats` and exits with `stats_status`.
+#[cfg(unix)]
+fn fake_sccache(directory: &TempDir, stats: &str, stats_status: u8) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let sccache = directory.child("bin").child("sccache");
+    sccache
+        .write_str(&format!(
+            r#"#!/bin/sh
+if [ "$1" = "--show-stats" ]; then
+    echo "{}"
+    exit {}
+fi
+echo "$@" >> {:?}
+exec "$@"
+"#,
+            stats,
+            stats_status,
+            directory.child("invocations").path()
+        ))
+        .unwrap();
+    std::fs::set_permissions(sccache.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+    sccache.path().to_owned()
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_through_the_rustc_wrapper_and_show_its_statistics() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let sccache = fake_sccache(&directory, "Cache hits 42", 0);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .env("RUSTC_WRAPPER", &sccache)
+        .assert();
+
+    // Assert
+    assert
+        .success()
+        .stdout(predicates::str::contains("Cache hits 42"));
+    directory
+        .child("invocations")
+        .assert(predicates::str::contains("api_dependency"));
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_succeeds_when_the_statistics_are_not_available() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let sccache = fake_sccache(&directory, "Server not running", 2);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .arg("--rustc-wrapper")
+        .arg(&sccache)
+        .assert();
+
+    // Assert
+    assert.success();
+    assert!(is_compiled(
+        &directory.child("cook").child("target"),
+        "api_dependency"
+    ));
+}
+
+/// The events of `cook --message-format json`, interleaved with cargo's output on stderr.
+fn events(stderr: &[u8]) -> Vec<serde_json::Value> {
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|event| event.get("reason").is_some())
+        .collect()
+}
+
+fn reasons(events: &[serde_json::Value]) -> Vec<&str> {
+    events
+        .iter()
+        .map(|event| event["reason"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn cook_with_json_messages() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .args(["--message-format", "json"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Assert
+    let events = events(&output.stderr);
+    assert_eq!(
+        vec![
+            "skeleton-unpacked",
+            "cache-locations",
+            "cargo-started",
+            "cargo-finished",
+            "downloads",
+            "cleanup-finished",
+            "cook-finished"
+        ],
+        reasons(&events)
+    );
+    let finished = events.last().unwrap();
+    assert_eq!(true, finished["success"]);
+    assert_eq!(0, finished["exit_code"]);
+    let command: Vec<&str> = finished["command"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|arg| arg.as_str().unwrap())
+        .collect();
+    assert_eq!("build", command[1]);
+    assert!(command.contains(&"--target-dir"));
+    assert_eq!(
+        ["--message-format", "json-render-diagnostics"],
+        command[command.len() - 2..]
+    );
+    // Compiler messages are on stdout.
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.lines().count() > 0);
+    for line in stdout.lines() {
+        let message: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert!(message.get("reason").is_some());
+    }
+}
+
+#[test]
+fn cook_with_json_messages_reports_cargo_failures() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .args(["--message-format", "json", "--features", "missing"])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+
+    // Assert
+    let events = events(&output.stderr);
+    assert_eq!(
+        vec![
+            "skeleton-unpacked",
+            "cache-locations",
+            "cargo-started",
+            "cargo-finished",
+            "cook-finished"
+        ],
+        reasons(&events)
+    );
+    let finished = events.last().unwrap();
+    assert_eq!(false, finished["success"]);
+    assert_eq!(101, finished["exit_code"]);
+}
+
+#[test]
+fn cook_with_human_messages_emits_no_events() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Assert
+    assert!(events(&output.stderr).is_empty());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn prepare_logs_on_stderr_when_verbose() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_with_default_members(&directory);
+
+    // Act
+    let output = Command::cargo_bin("cargo-chef")
+        .unwrap()
+        .current_dir(directory.child("project").path())
+        .env_remove("RUST_LOG")
+        .args(["chef", "prepare", "-vv", "--recipe-path", "-"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Assert
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(r#"Found manifest "crates/api/Cargo.toml""#));
+    assert!(stderr.contains("Masking the version at crates/api/Cargo.toml: package.version"));
+    // The recipe on stdout is left alone.
+    let recipe: Recipe = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(3, recipe.skeleton.manifests.len());
+}
+
+#[test]
+fn cook_logs_the_files_written_and_the_cargo_invocation_when_verbose() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .env_remove("RUST_LOG")
+        .arg("-v")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Assert
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Writing") && stderr.contains("lib.rs"));
+    assert!(stderr.contains(r#"Running "#) && stderr.contains(r#""build""#));
+    assert!(!stderr.contains("Masking"));
+}
+
+#[test]
+fn cook_does_not_log_by_default() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .env_remove("RUST_LOG")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Assert
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("Writing"));
+    assert!(!stderr.contains("Running"));
+}
+
+#[test]
+fn cook_unpacks_the_recipe_next_to_the_manifest_path() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    cook(
+        &directory,
+        &recipe,
+        &["--manifest-path", "unpacked/Cargo.toml"],
+    );
+
+    // Assert
+    let cook_directory = directory.child("cook");
+    cook_directory
+        .child("Cargo.toml")
+        .assert(predicates::path::missing());
+    let unpacked = cook_directory.child("unpacked");
+    unpacked
+        .child("crates/api/Cargo.toml")
+        .assert(predicates::path::exists());
+    // Cargo found the configuration of the recipe, pointing to the vendored dependencies.
+    assert!(is_compiled(&unpacked.child("target"), "api_dependency"));
+}
+
+#[test]
+fn cook_a_member_of_the_recipe_with_manifest_path() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    cook(
+        &directory,
+        &recipe,
+        &["--manifest-path", "crates/worker/Cargo.toml"],
+    );
+
+    // Assert
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "worker_dependency"));
+    assert!(!is_compiled(&target_directory, "api_dependency"));
+}
+
+#[test]
+fn prepare_reports_the_detected_workspace_root() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_with_default_members(&directory);
+
+    // Act
+    let output = Command::cargo_bin("cargo-chef")
+        .unwrap()
+        .current_dir(directory.child("project/crates/api").path())
+        .args(["chef", "prepare", "--recipe-path", "-"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Assert
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Detected the workspace root at ../../Cargo.toml"),
+        "{}",
+        stderr
+    );
+    let recipe: Recipe = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(3, recipe.skeleton.manifests.len());
+}
+
+/// `cargo chef prepare --split-workspace`, writing the recipes to `directory/cook/recipes`.
+fn prepare_split_recipes(directory: &TempDir) {
+    Command::cargo_bin("cargo-chef")
+        .unwrap()
+        .current_dir(directory.child("project").path())
+        .args(["chef", "prepare", "--split-workspace", "--recipe-dir"])
+        .arg(directory.child("cook").child("recipes").path())
+        .assert()
+        .success();
+}
+
+#[test]
+fn cook_a_member_from_a_directory_of_split_recipes() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_with_default_members(&directory);
+    prepare_split_recipes(&directory);
+    directory
+        .child("cook/recipes/api.json")
+        .assert(predicates::path::is_file());
+
+    // Act
+    Command::cargo_bin("cargo-chef")
+        .unwrap()
+        .current_dir(directory.child("cook").path())
+        .env("CARGO", env!("CARGO"))
+        .args([
+            "chef",
+            "cook",
+            "--recipe-path",
+            "recipes",
+            "--package",
+            "worker",
+        ])
+        .assert()
+        .success();
+
+    // Assert
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "worker_dependency"));
+    assert!(!is_compiled(&target_directory, "api_dependency"));
+    directory
+        .child("cook/crates/api/Cargo.toml")
+        .assert(predicates::path::missing());
+}
+
+#[test]
+fn cooking_a_directory_of_split_recipes_requires_a_package() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_with_default_members(&directory);
+    prepare_split_recipes(&directory);
+
+    // Act
+    let output = Command::cargo_bin("cargo-chef")
+        .unwrap()
+        .current_dir(directory.child("cook").path())
+        .env("CARGO", env!("CARGO"))
+        .args(["chef", "cook", "--recipe-path", "recipes"])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+
+    // Assert
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("api, worker"), "{}", stderr);
+}
+
+#[test]
+fn prepare_emits_the_fingerprint_of_the_recipe_as_cache_key() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    Command::cargo_bin("cargo-chef")
+        .unwrap()
+        .current_dir(directory.child("project").path())
+        .args([
+            "chef",
+            "prepare",
+            "--recipe-path",
+            "recipe.json",
+            "--emit-cache-key",
+            "cache-key",
+        ])
+        .assert()
+        .success();
+
+    // Assert
+    let cache_key = std::fs::read_to_string(directory.child("project/cache-key").path()).unwrap();
+    assert_eq!(format!("{}\n", recipe.fingerprint().unwrap()), cache_key);
+}
+
+#[test]
+fn cook_checks_the_expected_cache_key() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let fingerprint = recipe.fingerprint().unwrap();
+
+    // Act
+    cook(&directory, &recipe, &["--expect-cache-key", &fingerprint]);
+
+    // Assert
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "api_dependency"));
+}
+
+#[test]
+fn cook_fails_fast_on_a_stale_recipe() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let stale = "0".repeat(64);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .args(["--expect-cache-key", &stale])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+
+    // Assert
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("does not match the expected cache key"),
+        "{}",
+        stderr
+    );
+    directory
+        .child("cook/Cargo.toml")
+        .assert(predicates::path::missing());
+}
+
+/// The recipe of [`workspace_with_default_members`], with its lockfile but without the cargo
+/// configuration pointing to the vendored crates: `cook` must be told where they are.
+fn workspace_with_vendored_dependencies(directory: &TempDir) -> Recipe {
+    workspace_with_default_members(directory);
+    prepare_with_lockfile(directory)
+}
+
+/// Generate the lockfile of `directory/project` and prepare its recipe, without its cargo
+/// configuration.
+fn prepare_with_lockfile(directory: &TempDir) -> Recipe {
+    std::process::Command::new(env!("CARGO"))
+        .current_dir(directory.child("project").path())
+        .args(["generate-lockfile", "--offline"])
+        .status()
+        .unwrap();
+    Recipe::prepare(
+        directory.child("project").path().into(),
+        PrepareArgs {
+            no_cargo_config: true,
+            ..PrepareArgs::default()
+        },
+    )
+    .unwrap()
+}
+
+#[test]
+fn cook_against_vendored_sources() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_vendored_dependencies(&directory);
+    assert!(recipe.skeleton.lock_file.is_some());
+    assert!(recipe.skeleton.config_file.is_none());
+
+    // Act
+    cook(
+        &directory,
+        &recipe,
+        &["--workspace", "--vendored", "../vendor"],
+    );
+
+    // Assert
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "api_dependency"));
+    assert!(is_compiled(&target_directory, "worker_dependency"));
+}
+
+#[test]
+fn cook_lists_the_packages_missing_from_the_vendored_sources() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_vendored_dependencies(&directory);
+    std::fs::remove_dir_all(directory.child("vendor/worker-dependency").path()).unwrap();
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .args(["--vendored", "../vendor"])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+
+    // Assert
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("1 package of Cargo.lock is missing from the vendored sources"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("  - worker-dependency v0.1.0"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("api-dependency"), "{}", stderr);
+    directory
+        .child("cook/Cargo.toml")
+        .assert(predicates::path::missing());
+}
+
+/// An advisory database with an advisory for all the versions of `api-dependency`.
+fn advisory_db(directory: &TempDir) -> std::path::PathBuf {
+    let db = directory.child("advisory-db");
+    db.child("crates/api-dependency/RUSTSEC-2024-0001.md")
+        .write_str(
+            r#"```toml
+[advisory]
+id = "RUSTSEC-2024-0001"
+package = "api-dependency"
+date = "2024-01-01"
+
+[versions]
+patched = []
+```
+
+# Everything is broken
+"#,
+        )
+        .unwrap();
+    db.path().to_owned()
+}
+
+#[test]
+fn cook_refuses_to_build_dependencies_with_advisories() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_vendored_dependencies(&directory);
+    let db = advisory_db(&directory);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .arg("--deny-advisories")
+        .arg("--advisory-db")
+        .arg(&db)
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+
+    // Assert
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Found 1 advisory for the dependencies of the recipe"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("api-dependency  0.1.0    RUSTSEC-2024-0001  none"),
+        "{}",
+        stderr
+    );
+    directory
+        .child("cook/Cargo.toml")
+        .assert(predicates::path::missing());
+}
+
+#[test]
+fn cook_only_reports_advisories_on_request() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_vendored_dependencies(&directory);
+    let db = advisory_db(&directory);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .args([
+            "--vendored",
+            "../vendor",
+            "--warn-advisories",
+            "--advisory-db",
+        ])
+        .arg(&db)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Assert
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("RUSTSEC-2024-0001"), "{}", stderr);
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "api_dependency"));
+}
+
+#[test]
+fn cook_fails_when_the_advisory_db_cannot_be_read() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_vendored_dependencies(&directory);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .args(["--deny-advisories", "--advisory-db", "missing"])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+
+    // Assert
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Failed to read the advisory database"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn prepare_and_cook_through_the_library() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_with_default_members(&directory);
+
+    // Act
+    let recipe = chef::prepare(WorkspaceOptions {
+        path: directory.child("project").path().into(),
+        ..WorkspaceOptions::default()
+    })
+    .unwrap();
+    let report = chef::cook(
+        &recipe,
+        CookOptions {
+            directory: Some(directory.child("cook").path().into()),
+            cargo_path: Some(env!("CARGO").into()),
+            package: vec!["worker".to_owned()],
+            ..CookOptions::default()
+        },
+    )
+    .unwrap();
+
+    // Assert
+    assert!(report.success());
+    assert!(report.written.contains(&"Cargo.toml".into()));
+    let command = report.command.unwrap();
+    assert_eq!(env!("CARGO"), command[0]);
+    assert_eq!(["build", "--target-dir"], command[1..3]);
+    assert_eq!(["--package", "worker"], command[4..6]);
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "worker_dependency"));
+}
+
+#[test]
+fn cargo_failures_are_reported_by_the_library() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let features = vec!["missing".to_owned()].into_iter().collect();
+
+    // Act
+    let report = chef::cook(
+        &recipe,
+        CookOptions {
+            directory: Some(directory.child("cook").path().into()),
+            cargo_path: Some(env!("CARGO").into()),
+            features: Some(features),
+            ..CookOptions::default()
+        },
+    )
+    .unwrap();
+
+    // Assert
+    assert!(!report.success());
+    assert_eq!(Some(101), report.exit_status.unwrap().code());
+}
+
+#[test]
+fn the_library_reports_a_missing_cargo_instead_of_panicking() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    // Not running under cargo, e.g. a build frontend embedding the library. The other tests set
+    // the cargo path explicitly.
+    std::env::remove_var("CARGO");
+
+    // Act
+    let result = chef::cook(
+        &recipe,
+        CookOptions {
+            directory: Some(directory.child("cook").path().into()),
+            ..CookOptions::default()
+        },
+    );
+
+    // Assert
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(
+        error.contains("The `CARGO` environment variable was not set"),
+        "{}",
+        error
+    );
+}
+
+fn prepare_if_changed(directory: &TempDir) -> assert_cmd::assert::Assert {
+    Command::cargo_bin("cargo-chef")
+        .unwrap()
+        .current_dir(directory.child("project").path())
+        .args([
+            "chef",
+            "prepare",
+            "--recipe-path",
+            "recipe.json",
+            "--if-changed",
+        ])
+        .assert()
+}
+
+#[test]
+fn prepare_if_changed_leaves_an_unchanged_recipe_untouched() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_with_default_members(&directory);
+    let recipe_path = directory.child("project/recipe.json");
+    prepare_if_changed(&directory).code(2);
+    let modified = std::fs::metadata(recipe_path.path())
+        .unwrap()
+        .modified()
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(50));
+
+    // Act
+    let output = prepare_if_changed(&directory).code(0).get_output().clone();
+
+    // Assert
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("recipe.json: recipe unchanged"),
+        "{}",
+        stdout
+    );
+    let unchanged = std::fs::metadata(recipe_path.path())
+        .unwrap()
+        .modified()
+        .unwrap();
+    assert_eq!(modified, unchanged);
+}
+
+#[test]
+fn prepare_if_changed_writes_a_changed_recipe() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_with_default_members(&directory);
+    prepare_if_changed(&directory).code(2);
+    directory
+        .child("project/crates/api/Cargo.toml")
+        .write_str("[package]\nname = \"api\"\nversion = \"0.1.0\"\nedition = \"2018\"\n")
+        .unwrap();
+
+    // Act
+    prepare_if_changed(&directory).code(2);
+
+    // Assert
+    let recipe: Recipe = serde_json::from_str(
+        &std::fs::read_to_string(directory.child("project/recipe.json").path()).unwrap(),
+    )
+    .unwrap();
+    let api = recipe
+        .skeleton
+        .manifests
+        .iter()
+        .find(|manifest| manifest.relative_path == Path::new("crates/api/Cargo.toml"))
+        .unwrap();
+    assert!(!api.contents.contains("api-dependency"), "{}", api.contents);
+}
+
+/// The `--package` of each `cargo-started` event.
+fn started_packages(events: &[serde_json::Value]) -> Vec<&str> {
+    events
+        .iter()
+        .filter(|event| event["reason"] == "cargo-started")
+        .map(|event| {
+            let command = event["command"].as_array().unwrap();
+            let package = command.iter().position(|arg| arg == "--package").unwrap();
+            command[package + 1].as_str().unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn cook_members_sequentially_in_dependency_order() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_with_default_members(&directory);
+    directory
+        .child("project/crates/api/Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "api"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+api-dependency = "0.1"
+worker = { path = "../worker" }
+"#,
+        )
+        .unwrap();
+    let recipe = Recipe::prepare(
+        directory.child("project").path().into(),
+        PrepareArgs::default(),
+    )
+    .unwrap();
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .args(["--sequential-members", "--workspace", "--jobs", "1"])
+        .args(["--message-format", "json"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Assert
+    let events = events(&output.stderr);
+    assert_eq!(vec!["worker", "api"], started_packages(&events));
+    let command = events.last().unwrap()["command"].as_array().unwrap();
+    assert!(!command.iter().any(|arg| arg == "--workspace"));
+    assert!(command.windows(2).any(|args| args == ["--jobs", "1"]));
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "api_dependency"));
+    assert!(is_compiled(&target_directory, "worker_dependency"));
+}
+
+#[test]
+fn cook_members_sequentially_only_cooks_the_default_members() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .args(["--sequential-members", "--message-format", "json"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Assert
+    assert_eq!(vec!["api"], started_packages(&events(&output.stderr)));
+    let target_directory = directory.child("cook").child("target");
+    assert!(!is_compiled(&target_directory, "worker_dependency"));
+}
+
+#[test]
+fn cook_members_sequentially_reports_the_failing_member() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .args([
+            "--sequential-members",
+            "--workspace",
+            "--features",
+            "missing",
+        ])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+
+    // Assert
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Error: cooking `api` failed: cargo exited with status code 101."),
+        "{}",
+        stderr
+    );
+    assert!(
+        !stderr.contains("Cooking the dependencies of `worker`"),
+        "{}",
+        stderr
+    );
+}
+
+/// A fake cargo in `directory/bin`, failing with `error` on stderr for its first `failures`
+/// invocations and forwarding to the real cargo afterwards.
+#[cfg(unix)]
+fn flaky_cargo(directory: &TempDir, error: &str, failures: u32) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let cargo = directory.child("bin").child("cargo");
+    cargo
+        .write_str(&format!(
+            r#"#!/bin/sh
+echo invoked >> {attempts:?}
+if [ "$(wc -l < {attempts:?})" -le {failures} ]; then
+    echo "error: {error}" >&2
+    exit 101
+fi
+exec {cargo:?} "$@"
+"#,
+            attempts = directory.child("attempts").path(),
+            failures = failures,
+            error = error,
+            cargo = env!("CARGO"),
+        ))
+        .unwrap();
+    std::fs::set_permissions(cargo.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+    cargo.path().to_owned()
+}
+
+fn attempts(directory: &TempDir) -> usize {
+    std::fs::read_to_string(directory.child("attempts").path())
+        .unwrap()
+        .lines()
+        .count()
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_retries_after_a_transient_network_error() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let cargo = flaky_cargo(&directory, "spurious network error: early EOF", 1);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .arg("--cargo-path")
+        .arg(&cargo)
+        .args(["--network-retries", "2"])
+        .assert();
+
+    // Assert
+    assert.success().stderr(predicates::str::contains(
+        "Retrying (1/2) after network error, in 1s.",
+    ));
+    assert_eq!(2, attempts(&directory));
+    assert!(is_compiled(
+        &directory.child("cook").child("target"),
+        "api_dependency"
+    ));
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_gives_up_after_the_network_retries() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let cargo = flaky_cargo(&directory, "spurious network error", 3);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .arg("--cargo-path")
+        .arg(&cargo)
+        .args(["--network-retries", "1"])
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicates::str::contains(
+        "cargo exited with status code 101.",
+    ));
+    assert_eq!(2, attempts(&directory));
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_does_not_retry_other_failures() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let cargo = flaky_cargo(&directory, "could not compile `api`", 1);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .arg("--cargo-path")
+        .arg(&cargo)
+        .args(["--network-retries", "3"])
+        .assert();
+
+    // Assert
+    assert.failure();
+    assert_eq!(1, attempts(&directory));
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_does_not_retry_by_default() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let cargo = flaky_cargo(&directory, "spurious network error", 1);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .arg("--cargo-path")
+        .arg(&cargo)
+        .assert();
+
+    // Assert
+    assert.failure();
+    assert_eq!(1, attempts(&directory));
+}
+
+/// The command of the last event of `cook --message-format json`.
+fn last_command(stderr: &[u8]) -> Vec<String> {
+    events(stderr).last().unwrap()["command"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|arg| arg.as_str().unwrap().to_owned())
+        .collect()
+}
+
+#[test]
+fn cook_only_fetches_the_dependencies() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_vendored_dependencies(&directory);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .args(["--only-fetch", "--vendored", "../vendor"])
+        .args([
+            "--target",
+            "wasm32-unknown-unknown",
+            "--message-format",
+            "json",
+        ])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Assert
+    assert_eq!(
+        vec![
+            "skeleton-unpacked",
+            "cache-locations",
+            "cargo-started",
+            "cargo-finished",
+            "downloads",
+            "cook-finished"
+        ],
+        reasons(&events(&output.stderr))
+    );
+    let command = last_command(&output.stderr);
+    assert_eq!(["fetch", "--locked"], command[1..3]);
+    assert!(command
+        .windows(2)
+        .any(|args| args == ["--target", "wasm32-unknown-unknown"]));
+    assert!(!command.contains(&"--release".to_owned()));
+    directory
+        .child("cook")
+        .child("target")
+        .assert(predicates::path::missing());
+}
+
+#[test]
+fn cook_skips_the_fetch_by_building_offline() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_with_default_members(&directory);
+    std::process::Command::new(env!("CARGO"))
+        .current_dir(directory.child("project").path())
+        .args(["generate-lockfile", "--offline"])
+        .status()
+        .unwrap();
+    let recipe = Recipe::prepare(
+        directory.child("project").path().into(),
+        PrepareArgs::default(),
+    )
+    .unwrap();
+    cook(&directory, &recipe, &["--only-fetch"]);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .args(["--skip-fetch", "--message-format", "json"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Assert
+    let command = last_command(&output.stderr);
+    assert_eq!("build", command[1]);
+    assert!(command.contains(&"--offline".to_owned()));
+    assert!(is_compiled(
+        &directory.child("cook").child("target"),
+        "api_dependency"
+    ));
+}
+
+#[test]
+fn cook_cannot_both_only_and_skip_the_fetch() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_vendored_dependencies(&directory);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .args(["--only-fetch", "--skip-fetch"])
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicates::str::contains(
+        "'--only-fetch' cannot be used with '--skip-fetch'",
+    ));
+}
+
+#[test]
+fn cook_only_fetch_requires_a_lockfile() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .arg("--only-fetch")
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicates::str::contains(
+        "the recipe does not contain a Cargo.lock file",
+    ));
+}
+
+/// `recipe`, as if it had been prepared with `rustc_version` on the current host.
+fn prepared_with(recipe: &Recipe, rustc_version: &str) -> Recipe {
+    let mut environment = recipe.environment.clone().unwrap();
+    environment.rustc_version = rustc_version.to_owned();
+    Recipe {
+        environment: Some(environment),
+        ..recipe.clone()
+    }
+}
+
+#[test]
+fn prepare_records_the_environment() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+
+    // Act
+    let recipe = workspace_with_vendored_dependencies(&directory);
+
+    // Assert
+    let environment = recipe.environment.clone().unwrap();
+    assert!(
+        environment.rustc_version.starts_with("rustc 1."),
+        "{:?}",
+        environment
+    );
+    assert!(!environment.host.is_empty());
+
+    let output = cook_command(&directory, &recipe)
+        .args(["--vendored", "../vendor", "--strict-environment"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("the environment differs"), "{}", stderr);
+}
+
+#[test]
+fn cook_warns_about_another_rustc_version() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_vendored_dependencies(&directory);
+    let recipe = prepared_with(&recipe, "rustc 1.0.0 (a59de37e9 2015-05-13)");
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .args(["--vendored", "../vendor"])
+        .assert();
+
+    // Assert
+    assert
+        .success()
+        .stderr(predicates::str::contains(
+            "warning: the environment differs from the one the recipe was prepared for",
+        ))
+        .stderr(predicates::str::contains(
+            "the recipe was prepared with rustc 1.0.0 (a59de37e9 2015-05-13), but it is cooked with",
+        ));
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_warns_when_its_chef_configuration_disagrees_with_the_recipe() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let mut recipe = workspace_with_default_members(&directory);
+    recipe.config = ChefConfig {
+        mask_version: Some("0.0.1".into()),
+        no_dev_dependencies: Some(true),
+        ..ChefConfig::default()
+    };
+    directory
+        .child("cook/chef.toml")
+        .write_str("mask-version = \"1.2.3\"\ninclude = [\"proto/*.proto\"]\n")
+        .unwrap();
+    let cargo = recording_cargo(&directory, "cargo 1.79.0 (ffa9cf99a 2024-06-03)");
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .arg("--cargo-path")
+        .arg(&cargo)
+        .assert();
+
+    // Assert
+    assert
+        .success()
+        .stderr(predicates::str::contains(
+            "the chef configuration sets `mask-version` to \"1.2.3\", but the recipe was prepared with \"0.0.1\".",
+        ))
+        .stderr(predicates::str::contains(
+            "the chef configuration sets `include` to [\"proto/*.proto\"], but the recipe was prepared without it.",
+        ))
+        .stderr(predicates::str::contains(
+            "the recipe was prepared with `no-dev-dependencies` set to true by the chef configuration, which does not set it anymore.",
+        ));
+}
+
+#[test]
+fn cook_ignores_another_patch_version_of_rustc() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_vendored_dependencies(&directory);
+    let version = recipe.environment.as_ref().unwrap().rustc_version.clone();
+    let mut words: Vec<&str> = version.split_whitespace().collect();
+    let other_patch = {
+        let mut parts: Vec<&str> = words[1].splitn(3, '.').collect();
+        parts[2] = "99";
+        parts.join(".")
+    };
+    words[1] = &other_patch;
+    let recipe = prepared_with(&recipe, &words.join(" "));
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .args(["--vendored", "../vendor", "--strict-environment"])
+        .assert();
+
+    // Assert
+    assert.success();
+}
+
+#[test]
+fn cook_fails_on_environment_mismatches_when_strict() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .arg("--strict-environment")
+        .assert();
+
+    // Assert
+    assert
+        .failure()
+        .stderr(predicates::str::contains(
+            "The environment differs from the one the recipe was prepared for:",
+        ))
+        .stderr(predicates::str::contains("- the recipe has no Cargo.lock"));
+    assert!(!is_compiled(
+        &directory.child("cook").child("target"),
+        "api_dependency"
+    ));
+}
+
+#[test]
+fn cook_dry_run_reports_what_would_be_built() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_vendored_dependencies(&directory);
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .args(["--dry-run", "--workspace", "--vendored", "../vendor"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Assert
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("4 crates would be built"), "{}", stdout);
+    assert!(
+        stdout.contains(
+            "Crates with the most dependents:\n  api-dependency v0.1.0     1 dependent\n"
+        ),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains(
+            "Features of the direct dependencies:\n  api-dependency v0.1.0: (none)\n  worker-dependency v0.1.0: (none)\n"
+        ),
+        "{}",
+        stdout
+    );
+    directory
+        .child("cook")
+        .child("target")
+        .assert(predicates::path::missing());
+}
+
+#[test]
+fn cook_dry_run_reports_the_features_of_the_selected_members() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_with_default_members(&directory);
+    vendor_with_features(&directory, "api-dependency", &["fast"]);
+    directory
+        .child("project/crates/api/Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "api"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+api-dependency = { version = "0.1", features = ["fast"] }
+"#,
+        )
+        .unwrap();
+    let recipe = Recipe::prepare(
+        directory.child("project").path().into(),
+        PrepareArgs::default(),
+    )
+    .unwrap();
+
+    // Act
+    let output = cook_command(&directory, &recipe)
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+
+    // Assert
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("2 crates would be built"), "{}", stdout);
+    assert!(
+        stdout.contains("  api-dependency v0.1.0: fast\n"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("worker-dependency"), "{}", stdout);
+}
+
+#[test]
+fn cook_copies_the_timings_report_to_a_predictable_path() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .arg("--timings=html")
+        .assert();
+
+    // Assert
+    let timings = directory.child("cook/target/cargo-timings");
+    let report = timings.child("chef-cook-timings.html");
+    assert.success().stderr(predicates::str::contains(format!(
+        "Timings report: {}",
+        report.path().display()
+    )));
+    report.assert(predicates::str::contains("api-dependency"));
+    timings
+        .child("cargo-timing.html")
+        .assert(predicates::path::exists());
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_timings_require_a_recent_cargo() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let cargo = directory.child("bin").child("cargo");
+    cargo
+        .write_str("#!/bin/sh\necho 'cargo 1.59.0 (49d8809dc 2022-02-10)'\n")
+        .unwrap();
+    std::fs::set_permissions(cargo.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .arg("--cargo-path")
+        .arg(cargo.path())
+        .arg("--timings")
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicates::str::contains(
+        "`--timings` requires cargo 1.60 or newer, but the toolchain provides cargo 1.59.0 (49d8809dc 2022-02-10).",
+    ));
+}
+
+/// A cargo reporting `version` for `-V` and recording its arguments, one per line, in
+/// `directory/arguments` otherwise.
+#[cfg(unix)]
+fn recording_cargo(directory: &TempDir, version: &str) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let cargo = directory.child("bin").child("cargo");
+    cargo
+        .write_str(&format!(
+            r#"#!/bin/sh
+if [ "$1" = "-V" ]; then
+    echo '{}'
+    exit 0
+fi
+printf '%s\n' "$@" > {:?}
+"#,
+            version,
+            directory.child("arguments").path()
+        ))
+        .unwrap();
+    std::fs::set_permissions(cargo.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+    cargo.path().to_owned()
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_build_std_requires_a_nightly_toolchain() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let cargo = recording_cargo(&directory, "cargo 1.79.0 (ffa9cf99a 2024-06-03)");
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .arg("--cargo-path")
+        .arg(&cargo)
+        .args(["--build-std", "core,alloc"])
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicates::str::contains(
+        "`--build-std` requires a nightly toolchain, but the toolchain provides cargo 1.79.0 (ffa9cf99a 2024-06-03).",
+    ));
+    directory
+        .child("arguments")
+        .assert(predicates::path::missing());
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_builds_the_standard_library_for_a_custom_target() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str("[package]\nname = \"firmware\"\nversion = \"0.1.0\"\nedition = \"2021\"\n")
+        .unwrap();
+    project.child("src").child("lib.rs").touch().unwrap();
+    project
+        .child("targets")
+        .child("board.json")
+        .write_str(r#"{"llvm-target": "thumbv7em-none-eabihf"}"#)
+        .unwrap();
+    let args = PrepareArgs {
+        target_specs: vec!["targets/board.json".into()],
+        ..Default::default()
+    };
+    let recipe = Recipe::prepare(project.path().into(), args).unwrap();
+    let cargo = recording_cargo(&directory, "cargo 1.81.0-nightly (bc89bffa5 2024-06-22)");
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .arg("--cargo-path")
+        .arg(&cargo)
+        .args(["--skeleton-dir", "skeleton", "--no-std"])
+        .args([
+            "--build-std",
+            "core,alloc",
+            "--target",
+            "targets/board.json",
+        ])
+        .assert();
+
+    // Assert
+    assert.success();
+    let arguments = std::fs::read_to_string(directory.child("arguments").path()).unwrap();
+    let arguments: Vec<&str> = arguments.lines().collect();
+    let target_spec = directory.child("cook/skeleton/targets/board.json");
+    let expected = [
+        vec!["-Z", "build-std=core,alloc"],
+        vec!["--target", target_spec.path().to_str().unwrap()],
+    ];
+    for expected in &expected {
+        assert!(
+            arguments
+                .windows(2)
+                .any(|window| window == expected.as_slice()),
+            "{:?} is not in {:?}",
+            expected,
+            arguments
+        );
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_options_can_be_set_with_environment_variables() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let mut recipe = workspace_with_default_members(&directory);
+    recipe.default_cook_args = vec!["--jobs".into(), "2".into(), "--offline".into()];
+    let cargo = recording_cargo(&directory, "cargo 1.79.0 (ffa9cf99a 2024-06-03)");
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .env("CHEF_CARGO_PATH", &cargo)
+        .env("CHEF_RELEASE", "yes")
+        .env("CHEF_FEATURES", "a,b")
+        .env("CHEF_LOCKED", "0")
+        .env("CHEF_TESTS", "")
+        .env("CHEF_JOBS", "3")
+        .env("CHEF_PROFILE", "ci")
+        .args(["--profile", "bench", "-v"])
+        .assert();
+
+    // Assert
+    assert
+        .success()
+        .stderr(predicates::str::contains(
+            "Configuration of `cargo chef cook`:",
+        ))
+        .stderr(predicates::str::contains("--jobs 3 (`CHEF_JOBS`)"))
+        .stderr(predicates::str::contains("--offline true (recipe)"))
+        .stderr(predicates::str::contains("--profile bench (command line)"));
+    let arguments = std::fs::read_to_string(directory.child("arguments").path()).unwrap();
+    let arguments: Vec<&str> = arguments.lines().collect();
+    // The command line takes precedence over the environment, which takes precedence over the
+    // default arguments of the recipe. Empty variables are ignored.
+    let expected = [vec!["--profile", "bench"], vec!["--jobs", "3"]];
+    for expected in &expected {
+        assert!(
+            arguments
+                .windows(2)
+                .any(|window| window == expected.as_slice()),
+            "{:?} is not in {:?}",
+            expected,
+            arguments
+        );
+    }
+    let features = arguments
+        .iter()
+        .position(|argument| *argument == "--features")
+        .map(|position| arguments[position + 1]);
+    assert!(
+        features == Some("a,b") || features == Some("b,a"),
+        "{:?}",
+        arguments
+    );
+    assert!(arguments.contains(&"--offline"), "{:?}", arguments);
+    for unexpected in &["--release", "--locked", "--tests", "ci"] {
+        assert!(!arguments.contains(unexpected), "{:?}", arguments);
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_falls_back_to_the_variables_of_cargo_last() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let mut recipe = workspace_with_default_members(&directory);
+    recipe.default_cook_args = vec!["--target-dir".into(), "recipe-target".into()];
+    let cargo = recording_cargo(&directory, "cargo 1.79.0 (ffa9cf99a 2024-06-03)");
+    let sccache = fake_sccache(&directory, "Cache hits 42", 0);
+    let target_dir_argument = || {
+        let arguments = std::fs::read_to_string(directory.child("arguments").path()).unwrap();
+        let arguments: Vec<String> = arguments.lines().map(str::to_owned).collect();
+        let position = arguments
+            .iter()
+            .position(|argument| argument == "--target-dir")
+            .unwrap();
+        arguments[position + 1].clone()
+    };
+
+    // Act
+    let with_chef_variables = cook_command(&directory, &recipe)
+        .env("CHEF_CARGO_PATH", &cargo)
+        .env("CHEF_TARGET_DIR", "chef-target")
+        .env("CARGO_TARGET_DIR", "cargo-target")
+        .env("CHEF_RUSTC_WRAPPER", &sccache)
+        .env("RUSTC_WRAPPER", "missing-wrapper")
+        .assert();
+    let chef_target_dir = target_dir_argument();
+    let with_the_recipe = cook_command(&directory, &recipe)
+        .env("CHEF_CARGO_PATH", &cargo)
+        .env("CARGO_TARGET_DIR", "cargo-target")
+        .assert();
+    let recipe_target_dir = target_dir_argument();
+    recipe.default_cook_args.clear();
+    let with_cargo_variables = cook_command(&directory, &recipe)
+        .env("CHEF_CARGO_PATH", &cargo)
+        .env("CARGO_TARGET_DIR", "cargo-target")
+        .env("RUSTC_WRAPPER", &sccache)
+        .arg("-v")
+        .assert();
+    let cargo_target_dir = target_dir_argument();
+
+    // Assert
+    // `CHEF_TARGET_DIR` beats the recipe, which beats `CARGO_TARGET_DIR`. The missing
+    // `RUSTC_WRAPPER` would fail the build if it were checked.
+    with_chef_variables
+        .success()
+        .stdout(predicates::str::contains("Cache hits 42"));
+    assert!(
+        chef_target_dir.ends_with("chef-target"),
+        "{}",
+        chef_target_dir
+    );
+    with_the_recipe.success();
+    assert!(
+        recipe_target_dir.ends_with("recipe-target"),
+        "{}",
+        recipe_target_dir
+    );
+    with_cargo_variables
+        .success()
+        .stdout(predicates::str::contains("Cache hits 42"))
+        .stderr(predicates::str::contains(
+            "--target-dir cargo-target (`CARGO_TARGET_DIR`)",
+        ));
+    assert!(
+        cargo_target_dir.ends_with("cargo-target"),
+        "{}",
+        cargo_target_dir
+    );
+}
+
+#[test]
+fn cook_rejects_invalid_environment_variables() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let flag = cook_command(&directory, &recipe)
+        .env("CHEF_RELEASE", "ture")
+        .assert();
+    let option = cook_command(&directory, &recipe)
+        .env("CHEF_MESSAGE_FORMAT", "xml")
+        .assert();
+
+    // Assert
+    flag.failure().stderr(predicates::str::contains(
+        "`CHEF_RELEASE` must be `1`, `true` or `yes` (or `0`, `false` or `no`) to set `--release`, not \"ture\".",
+    ));
+    option.failure().stderr(predicates::str::contains(
+        "\"xml\" isn't a valid value for '--message-format",
+    ));
+    directory
+        .child("cook/Cargo.toml")
+        .assert(predicates::path::missing());
+}
+
+/// A package whose lockfile pins `itoa` and `ryu` from crates.io, with a `CARGO_HOME` caching
+/// `itoa` only and a cargo that "downloads" `ryu` and clones a git repository into it.
+#[cfg(unix)]
+fn package_with_a_partially_warm_cache(directory: &TempDir) -> (Recipe, std::path::PathBuf) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+itoa = "1"
+ryu = "1"
+"#,
+        )
+        .unwrap();
+    project.child("src").child("main.rs").touch().unwrap();
+    project
+        .child("Cargo.lock")
+        .write_str(
+            r#"version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["itoa", "ryu"]
+
+[[package]]
+name = "itoa"
+version = "1.0.9"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "ryu"
+version = "1.0.15"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#,
+        )
+        .unwrap();
+    let registry_cache =
+        directory.child("cargo-home/registry/cache/index.crates.io-6f17d22bba15001f");
+    registry_cache.child("itoa-1.0.9.crate").touch().unwrap();
+    let cargo = directory.child("bin").child("cargo");
+    cargo
+        .write_str(&format!(
+            "#!/bin/sh\ntouch {:?}\nmkdir -p \"$CARGO_HOME/git/db/helper-0123abc\"\n",
+            registry_cache.child("ryu-1.0.15.crate").path()
+        ))
+        .unwrap();
+    std::fs::set_permissions(cargo.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+    let recipe = Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap();
+    (recipe, cargo.path().to_owned())
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_reports_the_cache_locations_and_the_downloads() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let (recipe, cargo) = package_with_a_partially_warm_cache(&directory);
+    let cargo_home = directory.child("cargo-home");
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .env("CARGO_HOME", cargo_home.path())
+        .arg("--cargo-path")
+        .arg(&cargo)
+        .assert();
+
+    // Assert
+    let cargo_home = cargo_home.path().display();
+    assert
+        .success()
+        .stderr(predicates::str::contains(format!(
+            "CARGO_HOME is {0}: registry cache in {0}/registry/cache, git database in {0}/git/db.",
+            cargo_home
+        )))
+        .stderr(predicates::str::contains(
+            "Downloaded 1 crate (1 of the lockfile already cached) and cloned 1 git repository.",
+        ));
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_requires_a_warm_cache() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let (recipe, cargo) = package_with_a_partially_warm_cache(&directory);
+    let cook_with_a_warm_cache = |max_downloads: &str| {
+        cook_command(&directory, &recipe)
+            .env("CARGO_HOME", directory.child("cargo-home").path())
+            .arg("--cargo-path")
+            .arg(&cargo)
+            .args(["--require-warm-cache", max_downloads])
+            .assert()
+    };
+
+    // Act
+    let cold = cook_with_a_warm_cache("0");
+    std::fs::remove_file(
+        directory
+            .child("cargo-home/registry/cache/index.crates.io-6f17d22bba15001f/ryu-1.0.15.crate")
+            .path(),
+    )
+    .unwrap();
+    let warm_enough = cook_with_a_warm_cache("1");
+
+    // Assert
+    cold.failure().stderr(predicates::str::contains(
+        "`--require-warm-cache` allows 0 downloaded crates at most, but cargo downloaded 1:",
+    ));
+    warm_enough.success();
+}
+
+#[test]
+#[cfg(unix)]
+fn cook_sweeps_the_registry_cache_but_keeps_the_index() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let (recipe, cargo) = package_with_a_partially_warm_cache(&directory);
+    let cargo_home = directory.child("cargo-home");
+    let registry = "index.crates.io-6f17d22bba15001f";
+    let archive = cargo_home.child(format!("registry/cache/{}/itoa-1.0.9.crate", registry));
+    archive.write_str("12345").unwrap();
+    let source = cargo_home.child(format!("registry/src/{}/itoa-1.0.9", registry));
+    source.child("src/lib.rs").write_str("0123456789").unwrap();
+    let index = cargo_home.child(format!("registry/index/{}/config.json", registry));
+    index.write_str("{}").unwrap();
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .env("CARGO_HOME", cargo_home.path())
+        .arg("--cargo-path")
+        .arg(&cargo)
+        .arg("--sweep-cache")
+        .assert();
+
+    // Assert
+    assert.success().stderr(predicates::str::contains(format!(
+        "Swept the registry cache of {}: removed 2 archives and 1 unpacked sources, reclaiming \
+        15 bytes.",
+        cargo_home.path().display()
+    )));
+    archive.assert(predicates::path::missing());
+    source.assert(predicates::path::missing());
+    index.assert(predicates::path::exists());
+    cargo_home
+        .child("git/db/helper-0123abc")
+        .assert(predicates::path::exists());
+}
+
+/// Like [`workspace_with_vendored_dependencies`], with `worker` marked with `skip-build`.
+fn workspace_with_a_skipped_member(directory: &TempDir) -> Recipe {
+    workspace_with_default_members(directory);
+    directory
+        .child("project/crates/worker/Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "worker"
+version = "0.1.0"
+edition = "2018"
+
+[package.metadata.chef]
+skip-build = true
+
+[dependencies]
+worker-dependency = "0.1"
+"#,
+        )
+        .unwrap();
+    prepare_with_lockfile(directory)
+}
+
+#[test]
+fn cook_skips_the_members_marked_with_skip_build() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_a_skipped_member(&directory);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .args(["--workspace", "--vendored", "../vendor", "--no-clean"])
+        .assert();
+
+    // Assert
+    assert.success().stderr(predicates::str::contains(
+        "Skipping `worker`: it is marked with `package.metadata.chef.skip-build`.",
+    ));
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "api"));
+    assert!(!is_compiled(&target_directory, "worker"));
+    assert!(!is_compiled(&target_directory, "worker_dependency"));
+}
+
+#[test]
+fn cook_has_nothing_to_do_when_the_selected_members_are_skipped() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let mut recipe = workspace_with_a_skipped_member(&directory);
+    recipe.skeleton.lock_file = None;
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .args(["--package", "worker", "--vendored", "../vendor"])
+        .assert();
+
+    // Assert
+    assert
+        .success()
+        .stderr(predicates::str::contains("there is nothing to cook."));
+    directory
+        .child("cook")
+        .child("target")
+        .assert(predicates::path::missing());
+}
+
+/// Like [`workspace_with_vendored_dependencies`], with a build script in `worker` that reads a
+/// schema, both listed in `package.metadata.chef.include`.
+fn workspace_with_included_files(directory: &TempDir, include: &str) {
+    workspace_with_default_members(directory);
+    let worker = directory.child("project/crates/worker");
+    worker
+        .child("Cargo.toml")
+        .write_str(&format!(
+            r#"
+[package]
+name = "worker"
+version = "0.1.0"
+edition = "2018"
+
+[package.metadata.chef]
+include = {}
+
+[dependencies]
+worker-dependency = "0.1"
+"#,
+            include
+        ))
+        .unwrap();
+    worker
+        .child("build.rs")
+        .write_str(r#"const SCHEMA: &str = include_str!("schema/jobs.sql"); fn main() { assert!(SCHEMA.starts_with("CREATE")); }"#)
+        .unwrap();
+    worker
+        .child("schema")
+        .child("jobs.sql")
+        .write_str("CREATE TABLE jobs (id INTEGER);\n")
+        .unwrap();
+}
+
+#[test]
+fn cook_restores_the_included_files() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_with_included_files(&directory, r#"["build.rs", "schema/*.sql"]"#);
+    let recipe = prepare_with_lockfile(&directory);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .args(["--workspace", "--vendored", "../vendor", "--no-clean"])
+        .assert();
+
+    // Assert
+    assert.success();
+    let worker = directory.child("cook/crates/worker");
+    worker
+        .child("schema/jobs.sql")
+        .assert("CREATE TABLE jobs (id INTEGER);\n");
+    worker
+        .child("build.rs")
+        .assert(predicates::str::contains("include_str!"));
+    assert!(is_compiled(
+        &directory.child("cook").child("target"),
+        "worker"
+    ));
+}
+
+#[test]
+fn prepare_warns_about_include_patterns_that_match_nothing() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_with_included_files(&directory, r#"["schema/*.sql", "migrations/**"]"#);
+
+    // Act
+    let assert = Command::cargo_bin("cargo-chef")
+        .unwrap()
+        .current_dir(directory.child("project").path())
+        .args(["chef", "prepare", "--recipe-path", "-"])
+        .assert();
+
+    // Assert
+    assert.success().stderr(predicates::str::contains(
+        "warning: `migrations/**` in `package.metadata.chef.include` of crates/worker/Cargo.toml does not match any file.",
+    ));
+}
+
+#[test]
+fn prepare_warns_about_git_patches_without_a_lockfile() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+uuid = "1"
+log = "0.4"
+
+[patch.crates-io]
+uuid = { git = "https://github.com/example/uuid", branch = "fix-v4" }
+log = { git = "https://github.com/example/log", rev = "0123abc" }
+"#,
+        )
+        .unwrap();
+    project.child("src").child("main.rs").touch().unwrap();
+    let prepare = || {
+        Command::cargo_bin("cargo-chef")
+            .unwrap()
+            .current_dir(project.path())
+            .args(["chef", "prepare", "--recipe-path", "-"])
+            .assert()
+            .success()
+    };
+
+    // Act
+    let unlocked = prepare();
+    project
+        .child("Cargo.lock")
+        .write_str(
+            r#"version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["log", "uuid"]
+
+[[package]]
+name = "log"
+version = "0.4.20"
+source = "git+https://github.com/example/log?rev=0123abc#0123abc0123abc0123abc0123abc0123abc0123"
+
+[[package]]
+name = "uuid"
+version = "1.4.1"
+source = "git+https://github.com/example/uuid?branch=fix-v4#4d3c2b1a4d3c2b1a4d3c2b1a4d3c2b1a4d3c2b1a"
+"#,
+        )
+        .unwrap();
+    let locked = prepare();
+
+    // Assert
+    // Only the branch is unpinned: a `rev` is a commit already.
+    unlocked.stderr(predicates::str::contains(
+        "warning: there is no Cargo.lock to pin the commit of the git patches `uuid` (crates-io):",
+    ));
+    locked.stderr(predicates::str::contains("warning").count(0));
+}
+
+/// Like [`workspace_with_included_files`], with the offline query data of `sqlx` in `worker` and
+/// a build script that fails unless `SQLX_OFFLINE=true`.
+fn workspace_with_sqlx_offline_data(directory: &TempDir) -> Recipe {
+    workspace_with_included_files(directory, r#"["build.rs"]"#);
+    let worker = directory.child("project/crates/worker");
+    worker
+        .child("build.rs")
+        .write_str(
+            r#"fn main() { if std::env::var("SQLX_OFFLINE").as_deref() != Ok("true") { panic!("SQLX_OFFLINE is not set"); } }"#,
+        )
+        .unwrap();
+    worker
+        .child(".sqlx")
+        .child("query-0123abcd.json")
+        .write_str(r#"{"db_name": "PostgreSQL", "query": "SELECT 1"}"#)
+        .unwrap();
+    prepare_with_lockfile(directory)
+}
+
+#[test]
+fn cook_sets_sqlx_offline_when_the_recipe_contains_query_data() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_sqlx_offline_data(&directory);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .env_remove("SQLX_OFFLINE")
+        .args(["--workspace", "--vendored", "../vendor"])
+        .assert();
+
+    // Assert
+    assert.success();
+    directory
+        .child("cook/crates/worker/.sqlx/query-0123abcd.json")
+        .assert(predicates::str::contains("SELECT 1"));
+}
+
+#[test]
+fn cook_does_not_set_sqlx_offline_when_opted_out() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_sqlx_offline_data(&directory);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .env_remove("SQLX_OFFLINE")
+        .args([
+            "--workspace",
+            "--vendored",
+            "../vendor",
+            "--no-sqlx-offline",
+        ])
+        .assert();
+
+    // Assert
+    assert
+        .failure()
+        .stderr(predicates::str::contains("SQLX_OFFLINE is not set"));
+}
+
+#[test]
+fn cook_runs_the_build_scripts_that_are_kept() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_with_default_members(&directory);
+    vendor(&directory, "codegen");
+    let worker = directory.child("project/crates/worker");
+    worker
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "worker"
+version = "0.1.0"
+edition = "2018"
+
+[package.metadata.chef]
+keep-build-script = true
+
+[dependencies]
+worker-dependency = "0.1"
+
+[build-dependencies]
+codegen = "0.1"
+"#,
+        )
+        .unwrap();
+    worker
+        .child("build.rs")
+        .write_str(
+            r#"extern crate codegen;
+fn main() {
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    std::fs::write(format!("{}/generated.rs", out_dir), "pub fn generated() {}").unwrap();
+}
+"#,
+        )
+        .unwrap();
+    let recipe = prepare_with_lockfile(&directory);
+
+    // Act
+    cook(
+        &directory,
+        &recipe,
+        &[
+            "--package",
+            "worker",
+            "--vendored",
+            "../vendor",
+            "--no-clean",
+        ],
+    );
+
+    // Assert
+    let build_directory = directory.child("cook/target/debug/build");
+    let generated = std::fs::read_dir(build_directory.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path().join("out").join("generated.rs"))
+        .any(|path| path.exists());
+    assert!(generated);
+}
+
+#[test]
+fn cook_refuses_a_recipe_without_manifests() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let mut recipe = workspace_with_default_members(&directory);
+    recipe.skeleton.manifests.clear();
+
+    // Act
+    let assert = cook_command(&directory, &recipe).assert();
+
+    // Assert
+    assert.failure().stderr(predicates::str::contains(
+        "The recipe does not contain any manifest",
+    ));
+    directory
+        .child("cook")
+        .child("target")
+        .assert(predicates::path::missing());
+}
+
+#[test]
+fn cook_refuses_an_invalid_recipe() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let mut recipe = workspace_with_default_members(&directory);
+    recipe.skeleton.manifests[1]
+        .contents
+        .push_str("\n[dependencies\n");
+
+    // Act
+    let assert = cook_command(&directory, &recipe).assert();
+
+    // Assert
+    assert
+        .failure()
+        .stderr(predicates::str::contains(
+            "The recipe is invalid, 1 violation was found:",
+        ))
+        .stderr(predicates::str::contains(
+            "crates/api/Cargo.toml: invalid TOML:",
+        ));
+    directory
+        .child("cook")
+        .child("target")
+        .assert(predicates::path::missing());
+}
+
+#[test]
+fn cook_refuses_a_recipe_that_writes_outside_of_the_project() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let mut recipe = workspace_with_default_members(&directory);
+    let mut contents: toml::Value = recipe.skeleton.manifests[1].contents.parse().unwrap();
+    contents["lib"] = toml::from_str("path = \"../../../outside.rs\"").unwrap();
+    recipe.skeleton.manifests[1].contents = toml::to_string(&contents).unwrap();
+
+    // Act
+    let assert = cook_command(&directory, &recipe).assert();
+
+    // Assert
+    assert.failure().stderr(predicates::str::contains(
+        "the recipe points outside of the project.",
+    ));
+    directory
+        .child("outside.rs")
+        .assert(predicates::path::missing());
+    let unpacked: Vec<_> = std::fs::read_dir(directory.child("cook").path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(vec![std::ffi::OsString::from("recipe.json")], unpacked);
+}
+
+#[test]
+fn cook_in_a_separate_skeleton_directory() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+    let cook_directory = directory.child("cook");
+    cook_directory
+        .child("crates")
+        .child("api")
+        .child("src")
+        .child("main.rs")
+        .write_str("fn main() { println!(\"real\"); }")
+        .unwrap();
+
+    // Act
+    cook(&directory, &recipe, &["--skeleton-dir", "../skeleton"]);
+
+    // Assert
+    let target_directory = cook_directory.child("target");
+    assert!(is_compiled(&target_directory, "api_dependency"));
+    directory
+        .child("skeleton")
+        .assert(predicates::path::missing());
+    cook_directory
+        .child("Cargo.toml")
+        .assert(predicates::path::missing());
+    cook_directory
+        .child("crates")
+        .child("api")
+        .child("src")
+        .child("main.rs")
+        .assert("fn main() { println!(\"real\"); }");
+}
+
+#[test]
+fn cook_in_a_skeleton_directory_requires_a_manifest_of_the_recipe() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = workspace_with_default_members(&directory);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .args([
+            "--skeleton-dir",
+            "../skeleton",
+            "--manifest-path",
+            "elsewhere/Cargo.toml",
+        ])
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicates::str::contains(
+        "With `--skeleton-dir`, `--manifest-path` must be one of the manifests of the recipe",
+    ));
+    directory
+        .child("skeleton")
+        .assert(predicates::path::missing());
+}
+
+#[test]
+fn cook_an_optional_local_dependency_enabled_by_a_feature() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str("[workspace]\nmembers = [\"crates/*\"]\n")
+        .unwrap();
+    project
+        .child("crates")
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[features]
+full = ["dep:helper", "helper/extra"]
+
+[dependencies]
+helper = { path = "../helper", version = "0.2.0", optional = true }
+"#,
+        )
+        .unwrap();
+    project
+        .child("crates")
+        .child("helper")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "helper"
+version = "0.2.0"
+edition = "2021"
+
+[features]
+extra = ["dep:helper-dependency"]
+
+[dependencies]
+helper-dependency = { version = "0.1", optional = true }
+"#,
+        )
+        .unwrap();
+    for member in ["app", "helper"] {
+        project
+            .child("crates")
+            .child(member)
+            .child("src")
+            .child("lib.rs")
+            .touch()
+            .unwrap();
+    }
+    vendor(&directory, "helper-dependency");
+    let recipe = Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap();
+
+    // Act
+    cook(&directory, &recipe, &["--features", "app/full"]);
+
+    // Assert
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "helper_dependency"));
+}
+
+/// A package with two binaries: `api-server`, requiring the `grpc` feature, and `worker`,
+/// requiring the `jobs` feature, each enabling its own dependency.
+fn package_with_binaries_requiring_features(directory: &TempDir) -> Recipe {
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[features]
+grpc = ["dep:grpc-dependency"]
+jobs = ["dep:jobs-dependency"]
+
+[dependencies]
+grpc-dependency = { version = "0.1", optional = true }
+jobs-dependency = { version = "0.1", optional = true }
+
+[[bin]]
+name = "api-server"
+required-features = ["grpc"]
+
+[[bin]]
+name = "worker"
+required-features = ["jobs"]
+"#,
+        )
+        .unwrap();
+    for binary in ["api-server", "worker"] {
+        project
+            .child("src")
+            .child("bin")
+            .child(format!("{}.rs", binary))
+            .write_str("fn main() {}")
+            .unwrap();
+    }
+    vendor(directory, "grpc-dependency");
+    vendor(directory, "jobs-dependency");
+    Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap()
+}
+
+#[test]
+fn cook_the_dependencies_of_a_single_binary() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = package_with_binaries_requiring_features(&directory);
+
+    // Act
+    cook(
+        &directory,
+        &recipe,
+        &["--bin", "api-server", "--features", "grpc"],
+    );
+
+    // Assert
+    let target_directory = directory.child("cook").child("target");
+    assert!(is_compiled(&target_directory, "grpc_dependency"));
+    assert!(!is_compiled(&target_directory, "jobs_dependency"));
+}
+
+#[test]
+fn cook_rejects_a_binary_that_is_not_in_the_recipe() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let recipe = package_with_binaries_requiring_features(&directory);
+
+    // Act
+    let assert = cook_command(&directory, &recipe)
+        .args(["--bin", "api"])
+        .assert();
+
+    // Assert
+    assert
+        .failure()
+        .stderr(predicates::str::contains(
+            "`--bin api` does not match any binary of the recipe.",
+        ))
+        .stderr(predicates::str::contains(
+            "Available binaries: api-server, worker.",
+        ));
+    directory
+        .child("cook")
+        .child("Cargo.toml")
+        .assert(predicates::path::missing());
+}
@@ -0,0 +1,228 @@
+use assert_fs::prelude::{FileWriteStr, PathChild};
+use assert_fs::TempDir;
+use chef::{PrepareArgs, Recipe, RecipeDiff};
+use expect_test::expect;
+
+fn recipe_from_files(files: &[(&str, &str)], mask_version: &str) -> Recipe {
+    let recipe_directory = TempDir::new().unwrap();
+    for (path, content) in files {
+        recipe_directory.child(path).write_str(content).unwrap();
+    }
+    let args = PrepareArgs {
+        mask_version: mask_version.to_string(),
+        ..Default::default()
+    };
+    Recipe::prepare(recipe_directory.path().into(), args).unwrap()
+}
+
+const WORKSPACE: &str = r#"
+[workspace]
+members = ["app", "common"]
+"#;
+
+fn app(version: &str, uuid: &str) -> String {
+    format!(
+        r#"
+[package]
+name = "app"
+version = "{}"
+edition = "2018"
+
+[dependencies]
+common = {{ path = "../common", version = "{}" }}
+uuid = "{}"
+"#,
+        version, version, uuid
+    )
+}
+
+fn common(version: &str) -> String {
+    format!(
+        r#"
+[package]
+name = "common"
+version = "{}"
+edition = "2018"
+"#,
+        version
+    )
+}
+
+fn lockfile(version: &str, uuid: &str) -> String {
+    format!(
+        r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "{0}"
+dependencies = ["common", "uuid"]
+
+[[package]]
+name = "common"
+version = "{0}"
+
+[[package]]
+name = "uuid"
+version = "{1}"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#,
+        version, uuid
+    )
+}
+
+#[test]
+fn masked_local_versions_are_not_reported() {
+    // Arrange
+    let old = recipe_from_files(
+        &[
+            ("Cargo.toml", WORKSPACE),
+            ("Cargo.lock", &lockfile("0.1.0", "0.8.0")),
+            ("app/Cargo.toml", &app("0.1.0", "0.8")),
+            ("app/src/main.rs", ""),
+            ("common/Cargo.toml", &common("0.1.0")),
+            ("common/src/lib.rs", ""),
+        ],
+        "0.0.1",
+    );
+    let new = recipe_from_files(
+        &[
+            ("Cargo.toml", WORKSPACE),
+            ("Cargo.lock", &lockfile("0.2.0", "0.8.0")),
+            ("app/Cargo.toml", &app("0.2.0", "0.8")),
+            ("app/src/main.rs", ""),
+            ("common/Cargo.toml", &common("0.2.0")),
+            ("common/src/lib.rs", ""),
+        ],
+        "0.0.0-masked",
+    );
+
+    // Act
+    let diff = RecipeDiff::new(&old, &new).unwrap();
+
+    // Assert
+    assert!(diff.is_empty(), "{}", diff);
+    assert_eq!("The recipes are equivalent.\n", diff.to_string());
+}
+
+#[test]
+fn report_manifest_and_lockfile_changes() {
+    // Arrange
+    let old = recipe_from_files(
+        &[
+            ("Cargo.toml", WORKSPACE),
+            ("Cargo.lock", &lockfile("0.1.0", "0.8.0")),
+            ("app/Cargo.toml", &app("0.1.0", "0.8")),
+            ("app/src/main.rs", ""),
+            ("common/Cargo.toml", &common("0.1.0")),
+            ("common/src/lib.rs", ""),
+        ],
+        "0.0.1",
+    );
+    let workspace = r#"
+[workspace]
+members = ["app", "common", "xtask"]
+"#;
+    let xtask = r#"
+[package]
+name = "xtask"
+version = "0.1.0"
+edition = "2018"
+"#;
+    let new = recipe_from_files(
+        &[
+            ("Cargo.toml", workspace),
+            ("Cargo.lock", &lockfile("0.1.0", "1.1.0")),
+            ("app/Cargo.toml", &app("0.1.0", "1.1")),
+            ("app/src/main.rs", ""),
+            ("common/Cargo.toml", &common("0.1.0")),
+            ("common/src/lib.rs", ""),
+            ("xtask/Cargo.toml", xtask),
+            ("xtask/src/main.rs", ""),
+        ],
+        "0.0.1",
+    );
+
+    // Act
+    let diff = RecipeDiff::new(&old, &new).unwrap();
+
+    // Assert
+    assert!(!diff.is_empty());
+    expect![[r#"
+        Manifests:
+          + xtask/Cargo.toml
+          ~ Cargo.toml
+              ~ workspace.members: ["app", "common"] -> ["app", "common", "xtask"]
+          ~ app/Cargo.toml
+              ~ dependencies.uuid: "0.8" -> "1.1"
+        Cargo.lock:
+          ~ uuid (registry+https://github.com/rust-lang/crates.io-index): 0.8.0 -> 1.1.0
+    "#]]
+    .assert_eq(&diff.to_string());
+}
+
+#[test]
+fn report_default_cook_args_changes() {
+    // Arrange
+    let files = [
+        ("Cargo.toml", common("0.1.0")),
+        ("src/lib.rs", String::new()),
+    ];
+    let files: Vec<(&str, &str)> = files.iter().map(|(p, c)| (*p, c.as_str())).collect();
+    let old = recipe_from_files(&files, "0.0.1");
+    let mut new = old.clone();
+    new.default_cook_args = vec!["--release".into()];
+
+    // Act
+    let diff = RecipeDiff::new(&old, &new).unwrap();
+
+    // Assert
+    assert_eq!(
+        "Default cook arguments: [] -> [\"--release\"]\n",
+        diff.to_string()
+    );
+}
+
+#[test]
+fn report_included_files_changes() {
+    // Arrange
+    let manifest = r#"
+[package]
+name = "grpc"
+version = "0.1.0"
+edition = "2018"
+
+[package.metadata.chef]
+include = ["proto/*.proto"]
+"#;
+    let old = recipe_from_files(
+        &[
+            ("Cargo.toml", manifest),
+            ("src/lib.rs", ""),
+            ("proto/api.proto", "syntax = \"proto3\";"),
+            ("proto/legacy.proto", "syntax = \"proto2\";"),
+        ],
+        "0.0.1",
+    );
+    let new = recipe_from_files(
+        &[
+            ("Cargo.toml", manifest),
+            ("src/lib.rs", ""),
+            ("proto/api.proto", "syntax = \"proto3\"; package api;"),
+            ("proto/user.proto", "syntax = \"proto3\";"),
+        ],
+        "0.0.1",
+    );
+
+    // Act
+    let diff = RecipeDiff::new(&old, &new).unwrap();
+
+    // Assert
+    expect![[r#"
+        Included files:
+          ~ proto/api.proto
+          - proto/legacy.proto
+          + proto/user.proto
+    "#]]
+    .assert_eq(&diff.to_string());
+}
@@ -0,0 +1,216 @@
+//! The fingerprint of a recipe is used as a cache key: these tests document which changes
+//! it ignores and which ones it must reflect.
+use assert_fs::prelude::{FileWriteStr, PathChild};
+use assert_fs::TempDir;
+use chef::{Environment, PrepareArgs, Recipe};
+
+fn fingerprint(files: &[(&str, &str)]) -> String {
+    let recipe_directory = TempDir::new().unwrap();
+    for (path, content) in files {
+        recipe_directory.child(path).write_str(content).unwrap();
+    }
+    // Some projects change a requirement without updating the lockfile, on purpose.
+    let args = PrepareArgs {
+        allow_stale_lockfile: true,
+        ..PrepareArgs::default()
+    };
+    Recipe::prepare(recipe_directory.path().into(), args)
+        .unwrap()
+        .fingerprint()
+        .unwrap()
+}
+
+const WORKSPACE: &str = r#"
+[workspace]
+members = ["app"]
+"#;
+
+fn app(version: &str, dependencies: &str) -> String {
+    format!(
+        r#"
+[package]
+name = "app"
+version = "{}"
+edition = "2018"
+
+[dependencies]
+{}
+"#,
+        version, dependencies
+    )
+}
+
+fn lockfile(version: &str, uuid: &str) -> String {
+    format!(
+        r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "{}"
+dependencies = ["uuid"]
+
+[[package]]
+name = "uuid"
+version = "{}"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#,
+        version, uuid
+    )
+}
+
+const CONFIG: &str = r#"
+[build]
+rustflags = ["-C", "target-cpu=native"]
+"#;
+
+fn project(app_manifest: &str, lockfile: &str, config: &str) -> String {
+    fingerprint(&[
+        ("Cargo.toml", WORKSPACE),
+        ("Cargo.lock", lockfile),
+        (".cargo/config.toml", config),
+        ("app/Cargo.toml", app_manifest),
+        ("app/src/main.rs", ""),
+    ])
+}
+
+fn baseline() -> String {
+    project(
+        &app("0.1.0", r#"uuid = { version = "0.8", features = ["v4"] }"#),
+        &lockfile("0.1.0", "0.8.2"),
+        CONFIG,
+    )
+}
+
+#[test]
+fn fingerprint_is_a_sha256() {
+    let fingerprint = baseline();
+    assert_eq!(64, fingerprint.len());
+    assert!(fingerprint.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(fingerprint, baseline());
+}
+
+#[test]
+fn fingerprint_ignores_local_version_bumps() {
+    let bumped = project(
+        &app("0.2.0", r#"uuid = { version = "0.8", features = ["v4"] }"#),
+        &lockfile("0.2.0", "0.8.2"),
+        CONFIG,
+    );
+    assert_eq!(baseline(), bumped);
+}
+
+#[test]
+fn fingerprint_ignores_key_order() {
+    let reordered_app = r#"
+[dependencies]
+uuid = { features = ["v4"], version = "0.8" }
+
+[package]
+edition = "2018"
+version = "0.1.0"
+name = "app"
+"#;
+    let reordered_lockfile = r#"
+version = 3
+
+[[package]]
+dependencies = ["uuid"]
+version = "0.1.0"
+name = "app"
+
+[[package]]
+source = "registry+https://github.com/rust-lang/crates.io-index"
+name = "uuid"
+version = "0.8.2"
+"#;
+    let reordered = project(reordered_app, reordered_lockfile, CONFIG);
+    assert_eq!(baseline(), reordered);
+}
+
+#[test]
+fn fingerprint_ignores_line_endings() {
+    let crlf = |content: &str| content.replace('\n', "\r\n");
+    let with_crlf = project(
+        &crlf(&app(
+            "0.1.0",
+            r#"uuid = { version = "0.8", features = ["v4"] }"#,
+        )),
+        &crlf(&lockfile("0.1.0", "0.8.2")),
+        &crlf(CONFIG),
+    );
+    assert_eq!(baseline(), with_crlf);
+}
+
+#[test]
+fn fingerprint_changes_with_the_dependencies() {
+    let base = baseline();
+    let requirement = project(
+        &app("0.1.0", r#"uuid = { version = "1.0", features = ["v4"] }"#),
+        &lockfile("0.1.0", "0.8.2"),
+        CONFIG,
+    );
+    let features = project(
+        &app("0.1.0", r#"uuid = { version = "0.8", features = ["v5"] }"#),
+        &lockfile("0.1.0", "0.8.2"),
+        CONFIG,
+    );
+    let pin = project(
+        &app("0.1.0", r#"uuid = { version = "0.8", features = ["v4"] }"#),
+        &lockfile("0.1.0", "0.8.1"),
+        CONFIG,
+    );
+    let new_dependency = project(
+        &app(
+            "0.1.0",
+            "uuid = { version = \"0.8\", features = [\"v4\"] }\nitoa = \"1\"",
+        ),
+        &lockfile("0.1.0", "0.8.2"),
+        CONFIG,
+    );
+    for changed in [&requirement, &features, &pin, &new_dependency] {
+        assert_ne!(&base, changed);
+    }
+}
+
+#[test]
+fn fingerprint_does_not_depend_on_the_producer() {
+    // A recipe produced by cargo-chef 0.1.51 before recipes were versioned, with a different
+    // key order in its manifests...
+    let format_1 = Recipe::from_json(include_str!("fixtures/recipes/format-1.json")).unwrap();
+    // ...and the same project prepared with a versioned recipe format.
+    let mut format_2 = Recipe::from_json(include_str!("fixtures/recipes/format-2.json")).unwrap();
+    assert_ne!(
+        format_2.fingerprint().unwrap(),
+        format_1.fingerprint().unwrap()
+    );
+    format_2.skeleton.rust_toolchain_file = None;
+    format_2.default_cook_args.clear();
+    format_2.cargo_chef_version = Some("1.0.0".into());
+
+    assert_eq!(
+        format_1.fingerprint().unwrap(),
+        format_2.fingerprint().unwrap()
+    );
+}
+
+#[test]
+fn fingerprint_is_stable_across_releases() {
+    // Changing this value invalidates every cache keyed off a fingerprint: it must only happen
+    // if the contents of the recipe it is computed from change.
+    let recipe = Recipe::from_json(include_str!("fixtures/recipes/format-2.json")).unwrap();
+    expect_test::expect!["d0d671cb5dcbd9b247adfd34b8690e9f3d3da62aa474c49f0682e5bed0998196"]
+        .assert_eq(&recipe.fingerprint().unwrap());
+}
+
+#[test]
+fn fingerprint_ignores_the_environment() {
+    let mut recipe = Recipe::from_json(include_str!("fixtures/recipes/format-2.json")).unwrap();
+    let fingerprint = recipe.fingerprint().unwrap();
+    recipe.environment = Some(Environment {
+        rustc_version: "rustc 1.79.0 (129f3b996 2024-06-10)".into(),
+        host: "x86_64-unknown-linux-gnu".into(),
+    });
+
+    assert_eq!(fingerprint, recipe.fingerprint().unwrap());
+}
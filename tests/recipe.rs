@@ -1,6 +1,7 @@
-use assert_fs::prelude::{FileTouch, FileWriteStr, PathChild, PathCreateDir};
+use assert_fs::prelude::{FileTouch, FileWriteStr, PathAssert, PathChild, PathCreateDir};
 use assert_fs::TempDir;
-use chef::Recipe;
+use chef::{normalize_path_separators, PrepareArgs, Recipe, RecipeDiff, RECIPE_FORMAT_VERSION};
+use std::path::Path;
 
 fn quick_recipe(content: &str) -> Recipe {
     let recipe_directory = TempDir::new().unwrap();
@@ -14,7 +15,7 @@ fn quick_recipe(content: &str) -> Recipe {
         bin_dir.child(filename).touch().unwrap();
         test_dir.child(filename).touch().unwrap();
     }
-    Recipe::prepare(recipe_directory.path().into(), None).unwrap()
+    Recipe::prepare(recipe_directory.path().into(), PrepareArgs::default()).unwrap()
 }
 
 #[test]
@@ -58,3 +59,808 @@ path = "some-path.rs"
         );
     }
 }
+
+fn recipe_from_files(files: &[(&str, &str)]) -> Recipe {
+    let recipe_directory = TempDir::new().unwrap();
+    for (path, content) in files {
+        recipe_directory.child(path).write_str(content).unwrap();
+    }
+    Recipe::prepare(recipe_directory.path().into(), PrepareArgs::default()).unwrap()
+}
+
+#[test]
+fn test_recipe_does_not_depend_on_key_order() {
+    let workspace = r#"
+[workspace]
+members = ["app", "common"]
+
+[profile.release]
+lto = true
+opt-level = 3
+
+[replace]
+"serde:1.0.188" = { path = "forks/serde" }
+"uuid:0.8.0" = { path = "forks/uuid" }
+"#;
+    let reordered_workspace = r#"
+[replace]
+"uuid:0.8.0" = { path = "forks/uuid" }
+"serde:1.0.188" = { path = "forks/serde" }
+
+[profile.release]
+opt-level = 3
+lto = true
+
+[workspace]
+members = ["app", "common"]
+"#;
+    let app = r#"
+[package]
+name = "app"
+version = "1.2.3"
+edition = "2018"
+
+[dependencies]
+common = { path = "../common", version = "0.1.0" }
+uuid = { version = "0.8", features = ["v4", "serde"], default-features = false }
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+"#;
+    let reordered_app = r#"
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+
+[dependencies]
+uuid = { default-features = false, features = ["v4", "serde"], version = "0.8" }
+common = { version = "0.1.0", path = "../common" }
+
+[package]
+edition = "2018"
+version = "1.2.3"
+name = "app"
+"#;
+    let common = r#"
+[package]
+name = "common"
+version = "0.1.0"
+edition = "2018"
+"#;
+    let lockfile = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "1.2.3"
+dependencies = ["common", "libc", "uuid"]
+
+[[package]]
+name = "common"
+version = "0.1.0"
+
+[[package]]
+name = "libc"
+version = "0.2.139"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "uuid"
+version = "0.8.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "bc5cf98d8186244414c848017f0e2676b3fcb46807f6668a97dfe67359a3c4b7"
+"#;
+    let reordered_lockfile = r#"
+version = 3
+
+[[package]]
+dependencies = ["common", "libc", "uuid"]
+version = "1.2.3"
+name = "app"
+
+[[package]]
+version = "0.1.0"
+name = "common"
+
+[[package]]
+source = "registry+https://github.com/rust-lang/crates.io-index"
+version = "0.2.139"
+name = "libc"
+
+[[package]]
+checksum = "bc5cf98d8186244414c848017f0e2676b3fcb46807f6668a97dfe67359a3c4b7"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+name = "uuid"
+version = "0.8.0"
+"#;
+    let config = "[build]\nrustflags = [\"-C\", \"target-cpu=native\"]\n";
+    let files = |workspace, app, lockfile, config| {
+        vec![
+            ("Cargo.toml", workspace),
+            ("Cargo.lock", lockfile),
+            (".cargo/config.toml", config),
+            ("app/Cargo.toml", app),
+            ("app/src/main.rs", ""),
+            ("common/Cargo.toml", common),
+            ("common/src/lib.rs", ""),
+        ]
+    };
+
+    let recipe_json =
+        serde_json::to_string(&recipe_from_files(&files(workspace, app, lockfile, config)))
+            .unwrap();
+    let crlf_config = config.replace('\n', "\r\n");
+    let reordered_files = files(
+        reordered_workspace,
+        reordered_app,
+        reordered_lockfile,
+        &crlf_config,
+    );
+    for _ in 0..5 {
+        let recipe = recipe_from_files(&reordered_files);
+        assert_eq!(
+            recipe_json,
+            serde_json::to_string(&recipe).unwrap(),
+            "recipes that only differ in key order are not equal"
+        );
+    }
+
+    // Serialised contents are already canonical: a round-trip leaves them untouched.
+    let recipe: Recipe = serde_json::from_str(&recipe_json).unwrap();
+    let lock_file = recipe.skeleton.lock_file.unwrap();
+    let contents = recipe
+        .skeleton
+        .manifests
+        .into_iter()
+        .map(|manifest| manifest.contents)
+        .chain(std::iter::once(lock_file));
+    for content in contents {
+        let value: toml::Value = content.parse().unwrap();
+        assert_eq!(content, toml::to_string(&value).unwrap());
+    }
+}
+
+#[test]
+fn test_default_cook_args() {
+    let content = r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+
+[package.metadata.chef]
+cook-args = ["--release", "--target", "x86_64-unknown-linux-musl"]
+"#;
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(content)
+        .unwrap();
+    recipe_directory
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+
+    // Read from the metadata of the root manifest by default...
+    let recipe = Recipe::prepare(recipe_directory.path().into(), PrepareArgs::default()).unwrap();
+    assert_eq!(
+        vec!["--release", "--target", "x86_64-unknown-linux-musl"],
+        recipe.default_cook_args
+    );
+
+    // ...unless they are passed explicitly.
+    let args = PrepareArgs {
+        cook_args: vec!["--profile".into(), "ci".into()],
+        ..Default::default()
+    };
+    let recipe = Recipe::prepare(recipe_directory.path().into(), args).unwrap();
+    assert_eq!(vec!["--profile", "ci"], recipe.default_cook_args);
+
+    // Recipes without default arguments are serialised as before.
+    let mut recipe: serde_json::Value = serde_json::to_value(&recipe).unwrap();
+    recipe.as_object_mut().unwrap().remove("default_cook_args");
+    let recipe: Recipe = serde_json::from_value(recipe).unwrap();
+    assert!(recipe.default_cook_args.is_empty());
+    assert!(!serde_json::to_string(&recipe)
+        .unwrap()
+        .contains("default_cook_args"));
+}
+
+#[test]
+fn test_invalid_default_cook_args() {
+    let content = r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+
+[package.metadata.chef]
+cook-args = "--release"
+"#;
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(content)
+        .unwrap();
+    recipe_directory
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+
+    assert!(Recipe::prepare(recipe_directory.path().into(), PrepareArgs::default()).is_err());
+}
+
+#[test]
+fn test_load_format_1_recipe() {
+    // Produced by cargo-chef 0.1.51, before recipes were versioned.
+    let recipe = Recipe::from_json(include_str!("fixtures/recipes/format-1.json")).unwrap();
+
+    assert_eq!(1, recipe.format_version);
+    assert_eq!(None, recipe.cargo_chef_version);
+    assert_eq!(None, recipe.skeleton.rust_toolchain_file);
+    assert_eq!("0.0.1", recipe.skeleton.mask_version);
+    assert!(recipe.default_cook_args.is_empty());
+    assert_eq!(1, recipe.skeleton.manifests.len());
+    assert!(recipe.skeleton.lock_file.is_some());
+}
+
+#[test]
+fn test_load_format_2_recipe() {
+    let recipe = Recipe::from_json(include_str!("fixtures/recipes/format-2.json")).unwrap();
+
+    assert_eq!(2, recipe.format_version);
+    assert_eq!(Some("0.1.51"), recipe.cargo_chef_version.as_deref());
+    assert_eq!(vec!["--release"], recipe.default_cook_args);
+    assert!(recipe.skeleton.rust_toolchain_file.is_some());
+
+    // Both fixtures describe the same project: only the toolchain file and the default cook
+    // arguments were added.
+    let format_1 = Recipe::from_json(include_str!("fixtures/recipes/format-1.json")).unwrap();
+    let diff = RecipeDiff::new(&format_1, &recipe).unwrap();
+    assert_eq!(
+        "Rust toolchain file: added\nDefault cook arguments: [] -> [\"--release\"]\n",
+        diff.to_string()
+    );
+}
+
+#[test]
+fn test_prepared_recipes_use_the_current_format() {
+    let recipe = quick_recipe(
+        r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+"#,
+    );
+    assert_eq!(RECIPE_FORMAT_VERSION, recipe.format_version);
+
+    let serialized = serde_json::to_string(&recipe).unwrap();
+    assert_eq!(recipe, Recipe::from_json(&serialized).unwrap());
+}
+
+#[test]
+fn test_unsupported_format_is_reported_explicitly() {
+    let serialized = r#"{"format_version": 1000, "cargo_chef_version": "9.9.9", "skeleton": {"something": "new"}}"#;
+
+    let error = Recipe::from_json(serialized).unwrap_err().to_string();
+
+    assert!(
+        error.starts_with(&format!(
+            "The recipe was produced by cargo-chef 9.9.9 (format 1000), this binary (cargo-chef {}) supports formats 1..={}.",
+            env!("CARGO_PKG_VERSION"),
+            RECIPE_FORMAT_VERSION
+        )),
+        "{}",
+        error
+    );
+    assert!(error.contains("requires a newer cargo-chef"), "{}", error);
+}
+
+#[test]
+fn test_truncated_recipe_is_reported_explicitly() {
+    let serialized = include_str!("fixtures/recipes/format-2.json");
+
+    let error = Recipe::from_json(&serialized[..serialized.len() / 2])
+        .unwrap_err()
+        .to_string();
+
+    assert_eq!(
+        "Failed to deserialize recipe: the recipe is truncated.",
+        error
+    );
+}
+
+#[test]
+fn test_normalize_path_separators() {
+    assert_eq!(
+        "crates/api/Cargo.toml",
+        normalize_path_separators(r"crates\api\Cargo.toml")
+    );
+    assert_eq!("../common", normalize_path_separators(r"..\common"));
+    assert_eq!(
+        "crates/api/Cargo.toml",
+        normalize_path_separators("crates/api/Cargo.toml")
+    );
+}
+
+/// The path and the contents of each file of a project.
+type Checkout = Vec<(String, String)>;
+
+/// The same project checked out on Windows (CRLF line endings, `\`-separated paths) and on Linux.
+fn windows_and_linux_checkouts() -> (Checkout, Checkout) {
+    let linux = vec![
+        (
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"app\", \"common\"]\n",
+        ),
+        (
+            "app/Cargo.toml",
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[[bin]]
+name = "tool"
+path = "src/bin/tool.rs"
+
+[dependencies]
+common = { path = "../common" }
+"#,
+        ),
+        (
+            "common/Cargo.toml",
+            "[package]\nname = \"common\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+        ),
+        ("common/src/lib.rs", ""),
+        (
+            ".cargo/config.toml",
+            "[build]\nrustflags = [\"-C\", \"target-cpu=native\"]\n",
+        ),
+        ("rust-toolchain", "1.70.0\n"),
+    ];
+    let windows = linux
+        .iter()
+        .map(|(path, contents)| {
+            let contents = contents
+                .replace("src/bin/tool.rs", r"src\\bin\\tool.rs")
+                .replace("../common", r"..\\common")
+                .replace('\n', "\r\n");
+            (path.to_string(), contents)
+        })
+        .collect();
+    let linux = linux
+        .into_iter()
+        .map(|(path, contents)| (path.to_owned(), contents.to_owned()))
+        .collect();
+    (windows, linux)
+}
+
+fn as_refs(files: &[(String, String)]) -> Vec<(&str, &str)> {
+    files
+        .iter()
+        .map(|(path, contents)| (path.as_str(), contents.as_str()))
+        .collect()
+}
+
+#[test]
+fn test_recipe_does_not_depend_on_the_platform() {
+    let (windows, linux) = windows_and_linux_checkouts();
+    let windows = recipe_from_files(&as_refs(&windows));
+    let linux = recipe_from_files(&as_refs(&linux));
+
+    assert_eq!(
+        serde_json::to_string(&linux).unwrap(),
+        serde_json::to_string(&windows).unwrap()
+    );
+    assert!(!serde_json::to_string(&windows).unwrap().contains("\\r\\n"));
+}
+
+#[test]
+fn test_cook_recipe_with_windows_paths() {
+    // A recipe produced on Windows by an older version of cargo-chef.
+    let (_, linux) = windows_and_linux_checkouts();
+    let recipe = recipe_from_files(&as_refs(&linux));
+    // Escaped once for JSON and, in the manifest contents, once more for TOML.
+    let serialized = serde_json::to_string(&recipe)
+        .unwrap()
+        .replace("app/Cargo.toml", r"app\\Cargo.toml")
+        .replace("src/bin/tool.rs", r"src\\\\bin\\\\tool.rs");
+
+    let windows = Recipe::from_json(&serialized).unwrap();
+    assert_eq!(
+        Path::new("app/Cargo.toml"),
+        windows.skeleton.manifests[1].relative_path
+    );
+    let cook_directory = TempDir::new().unwrap();
+    windows
+        .skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    cook_directory
+        .child("app")
+        .child("Cargo.toml")
+        .assert(predicates::path::exists());
+    cook_directory
+        .child("app/src/bin/tool.rs")
+        .assert("fn main() {}");
+}
+
+/// A workspace in `directory/project`, with a `tools/*` member that is excluded.
+fn workspace_for_manifest_path(directory: &TempDir) {
+    let files = [
+        (
+            "project/Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\"]\nexclude = [\"crates/tools\"]\n",
+        ),
+        (
+            "project/crates/api/Cargo.toml",
+            "[package]\nname = \"api\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n[dependencies]\ncore = { path = \"../core\" }\n",
+        ),
+        ("project/crates/api/src/lib.rs", ""),
+        (
+            "project/crates/core/Cargo.toml",
+            "[package]\nname = \"core\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+        ),
+        ("project/crates/core/src/lib.rs", ""),
+        (
+            "project/crates/cli/Cargo.toml",
+            "[package]\nname = \"cli\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+        ),
+        ("project/crates/cli/src/main.rs", ""),
+        (
+            "project/crates/tools/Cargo.toml",
+            "[package]\nname = \"tools\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+        ),
+        ("project/crates/tools/src/main.rs", ""),
+    ];
+    for (path, content) in files {
+        directory.child(path).write_str(content).unwrap();
+    }
+}
+
+fn prepare_manifest_path(directory: &TempDir, manifest_path: &str) -> Recipe {
+    Recipe::prepare(
+        directory.path().into(),
+        PrepareArgs {
+            manifest_path: Some(manifest_path.into()),
+            ..PrepareArgs::default()
+        },
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_manifest_path_of_a_member_is_like_selecting_the_package() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_for_manifest_path(&directory);
+
+    // Act
+    let recipe = prepare_manifest_path(&directory, "project/crates/api/Cargo.toml");
+
+    // Assert
+    let expected = Recipe::prepare(
+        directory.child("project").path().into(),
+        PrepareArgs {
+            members: vec!["api".to_owned()],
+            ..PrepareArgs::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(expected, recipe);
+    let paths: Vec<_> = recipe
+        .skeleton
+        .manifests
+        .iter()
+        .map(|manifest| manifest.relative_path.to_str().unwrap())
+        .collect();
+    assert_eq!(
+        vec![
+            "Cargo.toml",
+            "crates/api/Cargo.toml",
+            "crates/core/Cargo.toml"
+        ],
+        paths
+    );
+}
+
+#[test]
+fn test_manifest_path_of_the_root() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_for_manifest_path(&directory);
+
+    // Act
+    let recipe = prepare_manifest_path(&directory, "project/Cargo.toml");
+
+    // Assert
+    let expected = Recipe::prepare(
+        directory.child("project").path().into(),
+        PrepareArgs::default(),
+    )
+    .unwrap();
+    assert_eq!(expected, recipe);
+    assert_eq!(4, recipe.skeleton.manifests.len());
+}
+
+#[test]
+fn test_manifest_path_of_an_excluded_package() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_for_manifest_path(&directory);
+
+    // Act
+    let recipe = prepare_manifest_path(&directory, "project/crates/tools/Cargo.toml");
+
+    // Assert
+    assert_eq!(1, recipe.skeleton.manifests.len());
+    assert_eq!(
+        Path::new("Cargo.toml"),
+        recipe.skeleton.manifests[0].relative_path
+    );
+    assert!(recipe.skeleton.manifests[0]
+        .contents
+        .contains("name = \"tools\""));
+}
+
+#[test]
+fn test_missing_manifest_path() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+
+    // Act
+    let result = Recipe::prepare(
+        directory.path().into(),
+        PrepareArgs {
+            manifest_path: Some("missing/Cargo.toml".into()),
+            ..PrepareArgs::default()
+        },
+    );
+
+    // Assert
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(
+        error.contains("Failed to locate the workspace of \"missing/Cargo.toml\""),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_prepare_from_a_member_covers_the_whole_workspace() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_for_manifest_path(&directory);
+    let project = directory.child("project");
+
+    // Act
+    let recipe = Recipe::prepare(
+        project.child("crates").child("api").path().into(),
+        PrepareArgs::default(),
+    )
+    .unwrap();
+
+    // Assert
+    let expected = Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap();
+    assert_eq!(expected, recipe);
+}
+
+#[test]
+fn test_prepare_from_a_member_with_member_only() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_for_manifest_path(&directory);
+    let project = directory.child("project");
+
+    // Act
+    let recipe = Recipe::prepare(
+        project.child("crates").child("api").path().into(),
+        PrepareArgs {
+            member_only: true,
+            ..PrepareArgs::default()
+        },
+    )
+    .unwrap();
+
+    // Assert
+    let expected = Recipe::prepare(
+        project.path().into(),
+        PrepareArgs {
+            members: vec!["api".to_owned()],
+            ..PrepareArgs::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(expected, recipe);
+}
+
+#[test]
+fn test_prepare_from_a_package_that_is_not_a_member() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_for_manifest_path(&directory);
+    let scratch = directory.child("project").child("scratch");
+    scratch
+        .child("Cargo.toml")
+        .write_str("[package]\nname = \"scratch\"\nversion = \"0.1.0\"\nedition = \"2018\"\n")
+        .unwrap();
+    scratch.child("src").child("main.rs").touch().unwrap();
+
+    // Act
+    let recipe = Recipe::prepare(scratch.path().into(), PrepareArgs::default()).unwrap();
+
+    // Assert
+    assert_eq!(1, recipe.skeleton.manifests.len());
+    assert!(recipe.skeleton.manifests[0]
+        .contents
+        .contains("name = \"scratch\""));
+}
+
+fn prepare_split(directory: &TempDir) -> std::collections::BTreeMap<String, Recipe> {
+    Recipe::prepare_split(
+        directory.child("project").path().into(),
+        PrepareArgs::default(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_split_workspace_computes_one_recipe_per_member() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_for_manifest_path(&directory);
+
+    // Act
+    let recipes = prepare_split(&directory);
+
+    // Assert
+    let members: Vec<_> = recipes.keys().map(String::as_str).collect();
+    assert_eq!(vec!["api", "cli", "core"], members);
+    for (member, recipe) in recipes {
+        let expected = Recipe::prepare(
+            directory.child("project").path().into(),
+            PrepareArgs {
+                members: vec![member],
+                ..PrepareArgs::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(expected, recipe);
+    }
+}
+
+#[test]
+fn test_split_recipes_are_independent_of_the_other_members() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_for_manifest_path(&directory);
+    let before = prepare_split(&directory);
+
+    // Act
+    directory
+        .child("project/crates/cli/Cargo.toml")
+        .write_str(
+            "[package]\nname = \"cli\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n[dependencies]\nlog = \"0.4\"\n",
+        )
+        .unwrap();
+    let after = prepare_split(&directory);
+
+    // Assert
+    assert_eq!(before["api"], after["api"]);
+    assert_eq!(before["core"], after["core"]);
+    assert_ne!(before["cli"], after["cli"]);
+}
+
+#[test]
+fn test_split_recipes_cannot_be_scoped_to_some_members() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    workspace_for_manifest_path(&directory);
+
+    // Act
+    let result = Recipe::prepare_split(
+        directory.child("project").path().into(),
+        PrepareArgs {
+            members: vec!["api".to_owned()],
+            ..PrepareArgs::default()
+        },
+    );
+
+    // Assert
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_prepare_fails_without_any_manifest() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    directory
+        .child("docs")
+        .child("README.md")
+        .write_str("# Docs")
+        .unwrap();
+
+    // Act
+    let error = Recipe::prepare(directory.path().into(), PrepareArgs::default()).unwrap_err();
+
+    // Assert
+    let message = format!("{:#}", error);
+    assert!(
+        message.contains("No Cargo.toml was found in"),
+        "{}",
+        message
+    );
+    assert!(message.contains("(1 file scanned)"), "{}", message);
+    assert!(!message.contains("ignored"), "{}", message);
+}
+
+#[test]
+fn test_prepare_reports_the_manifests_that_are_ignored() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    directory
+        .child(".chefignore")
+        .write_str("generated/\n")
+        .unwrap();
+    directory
+        .child("generated")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "generated"
+version = "0.1.0"
+"#,
+        )
+        .unwrap();
+
+    // Act
+    let error = Recipe::prepare(directory.path().into(), PrepareArgs::default()).unwrap_err();
+
+    // Assert
+    let message = format!("{:#}", error);
+    assert!(
+        message.contains("1 Cargo.toml file is ignored by `.gitignore` or `.chefignore` files."),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn test_prepare_suggests_the_workspace_root_of_a_parent_directory() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    directory
+        .child("Cargo.toml")
+        .write_str("[workspace]\nmembers = [\"crates/*\"]\n")
+        .unwrap();
+    let docs = directory.child("docs");
+    docs.child("index.md").write_str("# Docs").unwrap();
+
+    // Act
+    let error = Recipe::prepare(docs.path().into(), PrepareArgs::default()).unwrap_err();
+
+    // Assert
+    let message = format!("{:#}", error);
+    let root_manifest = directory.child("Cargo.toml");
+    assert!(
+        message.contains(&format!(
+            "A parent directory contains the root of a workspace: {}.",
+            root_manifest.path().display()
+        )),
+        "{}",
+        message
+    );
+    assert!(
+        message.contains(&format!(
+            "--manifest-path {}",
+            root_manifest.path().display()
+        )),
+        "{}",
+        message
+    );
+}
@@ -0,0 +1,291 @@
+use assert_fs::prelude::{FileWriteStr, PathChild};
+use assert_fs::TempDir;
+use chef::{PrepareArgs, Recipe, Sbom, SbomFormat};
+use expect_test::expect;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn recipe_from_files(files: &[(&str, &str)]) -> Recipe {
+    let recipe_directory = TempDir::new().unwrap();
+    for (path, content) in files {
+        recipe_directory.child(path).write_str(content).unwrap();
+    }
+    Recipe::prepare(recipe_directory.path().into(), PrepareArgs::default()).unwrap()
+}
+
+const MANIFEST: &str = r#"
+[package]
+name = "app"
+version = "1.2.3"
+edition = "2018"
+
+[dependencies]
+serde = "1.0"
+spinners = { git = "https://github.com/FGRibreau/spinners", rev = "3b5c4ee" }
+"#;
+
+const LOCKFILE: &str = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "1.2.3"
+dependencies = [
+ "serde",
+ "spinners",
+]
+
+[[package]]
+name = "serde"
+version = "1.0.130"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "f12d06de37cf59146fbdecab66aa99f9fe4f78722e3607577a5375d66bd0c913"
+
+[[package]]
+name = "spinners"
+version = "3.0.1"
+source = "git+https://github.com/FGRibreau/spinners?rev=3b5c4ee#3b5c4eee38e4b9e1a9aa5ed1de1d0bd99bdb26d8"
+"#;
+
+fn recipe() -> Recipe {
+    recipe_from_files(&[
+        ("Cargo.toml", MANIFEST),
+        ("Cargo.lock", LOCKFILE),
+        ("src/main.rs", ""),
+    ])
+}
+
+#[test]
+fn cyclonedx() {
+    // Act
+    let sbom = Sbom::new(&recipe()).unwrap();
+
+    // Assert
+    let mut document = sbom.to_json(SbomFormat::CycloneDx, UNIX_EPOCH);
+    document["metadata"]["tools"]["components"][0]["version"] = "<version>".into();
+    expect![[r#"
+        {
+          "bomFormat": "CycloneDX",
+          "components": [
+            {
+              "bom-ref": "app@0.0.1",
+              "name": "app",
+              "properties": [
+                {
+                  "name": "cargo-chef:first-party",
+                  "value": "true"
+                },
+                {
+                  "name": "cargo-chef:masked-version",
+                  "value": "true"
+                }
+              ],
+              "type": "library",
+              "version": "0.0.1"
+            },
+            {
+              "bom-ref": "serde@1.0.130 (registry+https://github.com/rust-lang/crates.io-index)",
+              "externalReferences": [
+                {
+                  "type": "distribution",
+                  "url": "https://crates.io/api/v1/crates/serde/1.0.130/download"
+                }
+              ],
+              "hashes": [
+                {
+                  "alg": "SHA-256",
+                  "content": "f12d06de37cf59146fbdecab66aa99f9fe4f78722e3607577a5375d66bd0c913"
+                }
+              ],
+              "name": "serde",
+              "properties": [
+                {
+                  "name": "cargo-chef:source",
+                  "value": "registry+https://github.com/rust-lang/crates.io-index"
+                }
+              ],
+              "purl": "pkg:cargo/serde@1.0.130",
+              "type": "library",
+              "version": "1.0.130"
+            },
+            {
+              "bom-ref": "spinners@3.0.1 (git+https://github.com/FGRibreau/spinners?rev=3b5c4ee#3b5c4eee38e4b9e1a9aa5ed1de1d0bd99bdb26d8)",
+              "externalReferences": [
+                {
+                  "type": "distribution",
+                  "url": "git+https://github.com/FGRibreau/spinners@3b5c4eee38e4b9e1a9aa5ed1de1d0bd99bdb26d8"
+                }
+              ],
+              "name": "spinners",
+              "properties": [
+                {
+                  "name": "cargo-chef:source",
+                  "value": "git+https://github.com/FGRibreau/spinners?rev=3b5c4ee#3b5c4eee38e4b9e1a9aa5ed1de1d0bd99bdb26d8"
+                }
+              ],
+              "purl": "pkg:cargo/spinners@3.0.1?vcs_url=git+https://github.com/FGRibreau/spinners@3b5c4eee38e4b9e1a9aa5ed1de1d0bd99bdb26d8",
+              "type": "library",
+              "version": "3.0.1"
+            }
+          ],
+          "metadata": {
+            "tools": {
+              "components": [
+                {
+                  "name": "cargo-chef",
+                  "type": "application",
+                  "version": "<version>"
+                }
+              ]
+            }
+          },
+          "specVersion": "1.5",
+          "version": 1
+        }"#]]
+    .assert_eq(&serde_json::to_string_pretty(&document).unwrap());
+}
+
+#[test]
+fn spdx_json() {
+    // Act
+    let recipe = recipe();
+    let sbom = Sbom::new(&recipe).unwrap();
+
+    // Assert
+    let created = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let mut document = sbom.to_json(SbomFormat::SpdxJson, created);
+    assert_eq!(
+        format!(
+            "https://spdx.org/spdxdocs/cargo-chef-{}",
+            recipe.fingerprint().unwrap()
+        ),
+        document["documentNamespace"]
+    );
+    document["documentNamespace"] = "<namespace>".into();
+    document["creationInfo"]["creators"][0] = "<creator>".into();
+    expect![[r#"
+        {
+          "SPDXID": "SPDXRef-DOCUMENT",
+          "creationInfo": {
+            "created": "2023-11-14T22:13:20Z",
+            "creators": [
+              "<creator>"
+            ]
+          },
+          "dataLicense": "CC0-1.0",
+          "documentNamespace": "<namespace>",
+          "name": "cargo-chef recipe",
+          "packages": [
+            {
+              "SPDXID": "SPDXRef-Package-app-0.0.1",
+              "comment": "First-party crate of the workspace. Its version is masked by cargo-chef.",
+              "downloadLocation": "NOASSERTION",
+              "filesAnalyzed": false,
+              "name": "app",
+              "primaryPackagePurpose": "LIBRARY",
+              "versionInfo": "0.0.1"
+            },
+            {
+              "SPDXID": "SPDXRef-Package-serde-1.0.130",
+              "checksums": [
+                {
+                  "algorithm": "SHA256",
+                  "checksumValue": "f12d06de37cf59146fbdecab66aa99f9fe4f78722e3607577a5375d66bd0c913"
+                }
+              ],
+              "downloadLocation": "https://crates.io/api/v1/crates/serde/1.0.130/download",
+              "externalRefs": [
+                {
+                  "referenceCategory": "PACKAGE-MANAGER",
+                  "referenceLocator": "pkg:cargo/serde@1.0.130",
+                  "referenceType": "purl"
+                }
+              ],
+              "filesAnalyzed": false,
+              "name": "serde",
+              "primaryPackagePurpose": "LIBRARY",
+              "versionInfo": "1.0.130"
+            },
+            {
+              "SPDXID": "SPDXRef-Package-spinners-3.0.1",
+              "downloadLocation": "git+https://github.com/FGRibreau/spinners@3b5c4eee38e4b9e1a9aa5ed1de1d0bd99bdb26d8",
+              "externalRefs": [
+                {
+                  "referenceCategory": "PACKAGE-MANAGER",
+                  "referenceLocator": "pkg:cargo/spinners@3.0.1?vcs_url=git+https://github.com/FGRibreau/spinners@3b5c4eee38e4b9e1a9aa5ed1de1d0bd99bdb26d8",
+                  "referenceType": "purl"
+                }
+              ],
+              "filesAnalyzed": false,
+              "name": "spinners",
+              "primaryPackagePurpose": "LIBRARY",
+              "versionInfo": "3.0.1"
+            }
+          ],
+          "relationships": [
+            {
+              "relatedSpdxElement": "SPDXRef-Package-app-0.0.1",
+              "relationshipType": "DESCRIBES",
+              "spdxElementId": "SPDXRef-DOCUMENT"
+            }
+          ],
+          "spdxVersion": "SPDX-2.3"
+        }"#]].assert_eq(&serde_json::to_string_pretty(&document).unwrap());
+}
+
+#[test]
+fn checksums_of_legacy_lockfiles() {
+    // Arrange
+    let lockfile = r#"
+[[package]]
+name = "app"
+version = "1.2.3"
+dependencies = [
+ "serde 1.0.130 (registry+https://github.com/rust-lang/crates.io-index)",
+ "spinners 3.0.1 (git+https://github.com/FGRibreau/spinners?rev=3b5c4ee#3b5c4eee38e4b9e1a9aa5ed1de1d0bd99bdb26d8)",
+]
+
+[[package]]
+name = "serde"
+version = "1.0.130"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "spinners"
+version = "3.0.1"
+source = "git+https://github.com/FGRibreau/spinners?rev=3b5c4ee#3b5c4eee38e4b9e1a9aa5ed1de1d0bd99bdb26d8"
+
+[metadata]
+"checksum serde 1.0.130 (registry+https://github.com/rust-lang/crates.io-index)" = "f12d06de37cf59146fbdecab66aa99f9fe4f78722e3607577a5375d66bd0c913"
+"#;
+    let recipe = recipe_from_files(&[
+        ("Cargo.toml", MANIFEST),
+        ("Cargo.lock", lockfile),
+        ("src/main.rs", ""),
+    ]);
+
+    // Act
+    let sbom = Sbom::new(&recipe).unwrap();
+
+    // Assert
+    let document = sbom.to_json(SbomFormat::CycloneDx, UNIX_EPOCH);
+    assert_eq!(
+        "f12d06de37cf59146fbdecab66aa99f9fe4f78722e3607577a5375d66bd0c913",
+        document["components"][1]["hashes"][0]["content"]
+    );
+}
+
+#[test]
+fn recipes_without_a_lockfile_have_no_sbom() {
+    // Arrange
+    let recipe = recipe_from_files(&[("Cargo.toml", MANIFEST), ("src/main.rs", "")]);
+
+    // Act
+    let error = Sbom::new(&recipe).unwrap_err();
+
+    // Assert
+    assert!(
+        error.to_string().contains("The recipe has no Cargo.lock"),
+        "{}",
+        error
+    );
+}
@@ -2,7 +2,7 @@ use std::path::Path;
 
 use assert_fs::prelude::*;
 use assert_fs::TempDir;
-use chef::Skeleton;
+use chef::{OptimisationProfile, PrepareArgs, Recipe, RustToolchainFile, Skeleton};
 use expect_test::Expect;
 use predicates::prelude::*;
 
@@ -34,7 +34,7 @@ path = "src/main.rs"
         .unwrap();
 
     // Act
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), false)
@@ -53,7 +53,7 @@ path = "src/main.rs"
         .assert(predicate::path::exists());
 
     // Act (no_std)
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), true)
@@ -140,7 +140,7 @@ uuid = { version = "=0.8.0", features = ["v4"] }
     project_b.child("src").child("lib.rs").touch().unwrap();
 
     // Act
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), false)
@@ -162,7 +162,7 @@ uuid = { version = "=0.8.0", features = ["v4"] }
         .assert("");
 
     // Act (no_std)
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), true)
@@ -229,7 +229,7 @@ harness = false
         .unwrap();
 
     // Act
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), false)
@@ -277,7 +277,7 @@ name = "foo"
         .unwrap();
 
     // Act
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), false)
@@ -290,7 +290,7 @@ name = "foo"
     cook_directory.child("tests").child("foo.rs").assert("");
 
     // Act (no_std)
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), true)
@@ -349,7 +349,7 @@ name = "foo"
         .unwrap();
 
     // Act
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), false)
@@ -365,7 +365,7 @@ name = "foo"
         .assert("fn main() {}");
 
     // Act (no_std)
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), true)
@@ -409,14 +409,14 @@ edition = "2018"
     bin_dir.child("f.rs").touch().unwrap();
 
     // Act
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
 
     // What we're testing is that auto-directories come back in the same order.
     // Since it's possible that the directories just happen to come back in the
     // same order randomly, we'll run this a few times to increase the
     // likelihood of triggering the problem if it exists.
     for _ in 0..5 {
-        let skeleton2 = Skeleton::derive(recipe_directory.path(), None).unwrap();
+        let skeleton2 = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
         assert_eq!(
             skeleton, skeleton2,
             "Skeletons of equal directories are not equal. Check [[bin]] ordering in manifest?"
@@ -453,7 +453,7 @@ pub fn config_toml() {
         .unwrap();
 
     // Act
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), false)
@@ -473,6 +473,65 @@ pub fn config_toml() {
         .assert(predicate::path::exists());
 }
 
+#[test]
+pub fn config_toml_is_preserved_verbatim() {
+    // Arrange
+    let content = r#"
+        [package]
+        name = "test-dummy"
+        version = "0.1.0"
+        edition = "2018"
+            "#;
+    let config = r#"# Build against the vendored sources
+[source.crates-io]
+replace-with = "vendored-sources"
+
+[source.vendored-sources]
+directory = "../../vendor"
+
+[build]
+rustflags = ["-C", "target-cpu=native"]
+
+[target.x86_64-unknown-linux-gnu]
+linker = "clang"
+"#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    let manifest = recipe_directory.child("Cargo.toml");
+    manifest.write_str(content).unwrap();
+    recipe_directory
+        .child(".cargo")
+        .child("config.toml")
+        .write_str(config)
+        .unwrap();
+    recipe_directory.child("src").create_dir_all().unwrap();
+    recipe_directory
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    let args = PrepareArgs {
+        no_cargo_config: true,
+        ..Default::default()
+    };
+    let skeleton_without_config = Skeleton::derive(recipe_directory.path(), &args).unwrap();
+
+    // Assert
+    assert_eq!(Some(config), skeleton.config_file.as_deref());
+    cook_directory
+        .child(".cargo")
+        .child("config.toml")
+        .assert(config);
+    assert_eq!(None, skeleton_without_config.config_file);
+}
+
 #[test]
 pub fn version() {
     // Arrange
@@ -502,7 +561,7 @@ pub fn version() {
         .unwrap();
 
     // Act
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), false)
@@ -555,7 +614,7 @@ version = "1.2.3"
         .unwrap();
 
     // Act
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), false)
@@ -671,7 +730,7 @@ checksum = "bc5cf98d8186244414c848017f0e2676b3fcb46807f6668a97dfe67359a3c4b7"
     project_b.child("src").child("lib.rs").touch().unwrap();
 
     // Act
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), false)
@@ -728,69 +787,71 @@ version = "0.8.0"
         &second.contents,
         expect_test::expect![[r#"
             bench = []
-            test = []
             example = []
+            test = []
 
             [[bin]]
-            path = "src/main.rs"
-            name = "test-dummy"
-            test = true
-            doctest = true
             bench = true
             doc = true
+            doctest = true
+            harness = true
+            name = "test-dummy"
+            path = "src/main.rs"
             plugin = false
             proc-macro = false
-            harness = true
             required-features = []
+            test = true
+            [dependencies.uuid]
+            features = ["v4"]
+            version = "=0.8.0"
 
             [package]
-            name = "project-a"
-            edition = "2018"
-            version = "0.0.1"
+            autobenches = true
             autobins = true
             autoexamples = true
             autotests = true
-            autobenches = true
-            [dependencies.uuid]
-            version = "=0.8.0"
-            features = ["v4"]
+            edition = "2018"
+            name = "project-a"
+            version = "0.0.1"
         "#]],
     );
     let third = skeleton.manifests[2].clone();
     check(
         &third.contents,
         expect_test::expect![[r#"
-            bin = []
             bench = []
-            test = []
+            bin = []
             example = []
-
-            [package]
-            name = "project_b"
-            edition = "2018"
-            version = "0.0.1"
-            autobins = true
-            autoexamples = true
-            autotests = true
-            autobenches = true
+            test = []
             [dependencies.project_a]
-            version = "0.0.1"
             path = "../project_a"
+            version = "0.0.1"
 
             [dependencies.uuid]
-            version = "=0.8.0"
             features = ["v4"]
+            version = "=0.8.0"
 
             [lib]
-            test = true
-            doctest = true
             bench = true
+            crate-type = ["cdylib"]
             doc = true
+            doctest = true
+            harness = true
+            name = "project_b"
+            path = "src/lib.rs"
             plugin = false
             proc-macro = false
-            harness = true
             required-features = []
-            crate-type = ["cdylib"]
+            test = true
+
+            [package]
+            autobenches = true
+            autobins = true
+            autoexamples = true
+            autotests = true
+            edition = "2018"
+            name = "project_b"
+            version = "0.0.1"
         "#]],
     );
 }
@@ -874,7 +935,7 @@ description = "sample package representing all of rocket's dependencies""#;
         .unwrap();
 
     // Act
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
 
     // Assert
     assert_eq!(1, skeleton.manifests.len());
@@ -913,7 +974,7 @@ edition = "2018"
     backend.child("src").child("main.rs").touch().unwrap();
 
     // Act
-    let skeleton = Skeleton::derive(recipe_directory.path(), "backend".to_string().into()).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &members(&["backend"])).unwrap();
 
     let gold = r#"[workspace]
 members = ["backend"]
@@ -931,7 +992,7 @@ members = ["backend"]
         skeleton
             .manifests
             .iter()
-            .find(|manifest| manifest.relative_path == std::path::PathBuf::from("Cargo.toml"))
+            .find(|manifest| manifest.relative_path == Path::new("Cargo.toml"))
             .unwrap()
             .contents
             == gold
@@ -1010,7 +1071,7 @@ anyhow = { workspace = true }
     project_b.child("src").child("lib.rs").touch().unwrap();
 
     // Act
-    let skeleton = Skeleton::derive(recipe_directory.path(), None).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
     let cook_directory = TempDir::new().unwrap();
     skeleton
         .build_minimum_project(cook_directory.path(), false)
@@ -1020,67 +1081,63 @@ anyhow = { workspace = true }
     check(
         &first.contents,
         expect_test::expect![[r#"
-        [workspace]
-        members = ["project_a", "project_b"]
-
-        [workspace.dependencies]
-        anyhow = "1.0.66"
-
-        [workspace.dependencies.project_a]
-        version = "0.0.1"
-        path = "project_a"
-        
-        [workspace.dependencies.project_b]
-        version = "0.0.1"
-        path = "project_b"
-        
-        [workspace.package]
-        edition = "2021"
-        version = "0.0.1"
-        license = "Apache-2.0"
-    "#]],
+            [workspace]
+            members = ["project_a", "project_b"]
+
+            [workspace.dependencies]
+            anyhow = "1.0.66"
+
+            [workspace.dependencies.project_a]
+            path = "project_a"
+            version = "0.0.1"
+
+            [workspace.dependencies.project_b]
+            path = "project_b"
+            version = "0.0.1"
+
+            [workspace.package]
+            edition = "2021"
+            version = "0.0.1"
+        "#]],
     );
 
     let second = skeleton.manifests[1].clone();
     check(
         &second.contents,
         expect_test::expect![[r#"
-        bench = []
-        test = []
-        example = []
-        
-        [[bin]]
-        path = "src/main.rs"
-        name = "project_a"
-        test = true
-        doctest = true
-        bench = true
-        doc = true
-        plugin = false
-        proc-macro = false
-        harness = true
-        required-features = []
-        
-        [package]
-        name = "project_a"
-        autobins = true
-        autoexamples = true
-        autotests = true
-        autobenches = true
-        
-        [package.edition]
-        workspace = true
-        
-        [package.version]
-        workspace = true
-        
-        [package.license]
-        workspace = true
-        [dependencies.anyhow]
-        workspace = true
-        
-        [dependencies.project_b]
-        workspace = true
+            bench = []
+            example = []
+            test = []
+
+            [[bin]]
+            bench = true
+            doc = true
+            doctest = true
+            harness = true
+            name = "project_a"
+            path = "src/main.rs"
+            plugin = false
+            proc-macro = false
+            required-features = []
+            test = true
+            [dependencies.anyhow]
+            workspace = true
+
+            [dependencies.project_b]
+            workspace = true
+
+            [package]
+            autobenches = true
+            autobins = true
+            autoexamples = true
+            autotests = true
+            name = "project_a"
+
+            [package.edition]
+            workspace = true
+
+            [package.version]
+            workspace = true
         "#]],
     );
 
@@ -1088,47 +1145,5843 @@ anyhow = { workspace = true }
     check(
         &third.contents,
         expect_test::expect![[r#"
-        bin = []
-        bench = []
-        test = []
-        example = []
-        
-        [package]
-        name = "project_b"
-        autobins = true
-        autoexamples = true
-        autotests = true
-        autobenches = true
-        
-        [package.edition]
-        workspace = true
-        
-        [package.version]
-        workspace = true
-        
-        [package.license]
-        workspace = true
-        [dependencies.anyhow]
-        workspace = true
-        
-        [dependencies.project_a]
-        workspace = true
-        
-        [lib]
-        test = true
-        doctest = true
-        bench = true
-        doc = true
-        plugin = false
-        proc-macro = false
-        harness = true
-        required-features = []
-        crate-type = ["cdylib"]
+            bench = []
+            bin = []
+            example = []
+            test = []
+            [dependencies.anyhow]
+            workspace = true
+
+            [dependencies.project_a]
+            workspace = true
+
+            [lib]
+            bench = true
+            crate-type = ["cdylib"]
+            doc = true
+            doctest = true
+            harness = true
+            name = "project_b"
+            path = "src/lib.rs"
+            plugin = false
+            proc-macro = false
+            required-features = []
+            test = true
+
+            [package]
+            autobenches = true
+            autobins = true
+            autoexamples = true
+            autotests = true
+            name = "project_b"
+
+            [package.edition]
+            workspace = true
+
+            [package.version]
+            workspace = true
         "#]],
     );
 }
 
-fn check(actual: &str, expect: Expect) {
-    let actual = actual.to_string();
-    expect.assert_eq(&actual);
+#[test]
+pub fn mask_local_crates_in_patch_section() {
+    // Arrange
+    let content = r#"
+[package]
+name = "project_a"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+uuid = "1.2.0"
+foo = "0.3.0"
+
+[patch.crates-io]
+uuid = { path = "patches/uuid", version = "1.2.3" }
+
+[patch."https://my-registry"]
+my-fork = { package = "foo", path = "patches/foo", version = "0.3.1" }
+    "#;
+
+    let lockfile = r#"
+[[package]]
+name = "foo"
+version = "0.3.1"
+
+[[package]]
+name = "project_a"
+version = "0.1.0"
+dependencies = [
+ "foo",
+ "uuid",
+]
+
+[[package]]
+name = "uuid"
+version = "1.2.3"
+"#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(content)
+        .unwrap();
+    recipe_directory
+        .child("Cargo.lock")
+        .write_str(lockfile)
+        .unwrap();
+    recipe_directory.child("src").create_dir_all().unwrap();
+    recipe_directory
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &members(&["project_a"])).unwrap();
+
+    // Assert
+    let manifest = &skeleton.manifests[0];
+    assert!(manifest.contents.contains(
+        r#"[patch.crates-io.uuid]
+path = "patches/uuid"
+version = "0.0.1"
+"#
+    ));
+    assert!(manifest.contents.contains(
+        r#"[patch."https://my-registry".my-fork]
+package = "foo"
+path = "patches/foo"
+version = "0.0.1"
+"#
+    ));
+    let lock_file = skeleton.lock_file.expect("there should be a lock_file");
+    assert!(lock_file.contains(
+        r#"[[package]]
+name = "foo"
+version = "0.0.1"
+"#
+    ));
+    assert!(lock_file.contains(
+        r#"
+[[package]]
+name = "uuid"
+version = "0.0.1"
+"#
+    ));
+}
+
+#[test]
+pub fn mask_renamed_local_dependencies() {
+    // Arrange
+    let workspace_content = r#"
+[workspace]
+members = ["foo", "bar"]
+    "#;
+
+    let foo_content = r#"
+[package]
+name = "foo"
+version = "1.2.3"
+edition = "2018"
+    "#;
+
+    let bar_content = r#"
+[package]
+name = "bar"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+foo-renamed = { package = "foo", path = "../foo", version = "1.2.3" }
+
+[target.'cfg(unix)'.dependencies]
+foo-unix = { package = "foo", path = "../foo", version = "1.2.3" }
+    "#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(workspace_content)
+        .unwrap();
+    let foo = recipe_directory.child("foo");
+    foo.child("Cargo.toml").write_str(foo_content).unwrap();
+    foo.child("src").create_dir_all().unwrap();
+    foo.child("src").child("lib.rs").touch().unwrap();
+    let bar = recipe_directory.child("bar");
+    bar.child("Cargo.toml").write_str(bar_content).unwrap();
+    bar.child("src").create_dir_all().unwrap();
+    bar.child("src").child("main.rs").touch().unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let bar = skeleton
+        .manifests
+        .iter()
+        .find(|manifest| manifest.relative_path == Path::new("bar/Cargo.toml"))
+        .unwrap();
+    assert!(!bar.contents.contains("1.2.3"));
+    assert!(bar.contents.contains(
+        r#"[dependencies.foo-renamed]
+package = "foo"
+path = "../foo"
+version = "0.0.1"
+"#
+    ));
+    assert!(bar.contents.contains(
+        r#"[target."cfg(unix)".dependencies.foo-unix]
+package = "foo"
+path = "../foo"
+version = "0.0.1"
+"#
+    ));
+}
+
+#[test]
+pub fn specify_multiple_members_in_workspace() {
+    // Arrange
+    let workspace_content = r#"
+[workspace]
+
+members = [
+    "api",
+    "worker",
+    "ci",
+]
+    "#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(workspace_content)
+        .unwrap();
+    for member in ["api", "worker", "ci"] {
+        let directory = recipe_directory.child(member);
+        directory
+            .child("Cargo.toml")
+            .write_str(&format!(
+                r#"
+[package]
+name = "{}"
+version = "0.1.0"
+edition = "2018"
+            "#,
+                member
+            ))
+            .unwrap();
+        directory.child("src").create_dir_all().unwrap();
+        directory.child("src").child("main.rs").touch().unwrap();
+    }
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &members(&["worker", "api"])).unwrap();
+    let reversed = Skeleton::derive(recipe_directory.path(), &members(&["api", "worker"])).unwrap();
+    let missing = Skeleton::derive(recipe_directory.path(), &members(&["api", "frontend"]));
+
+    // Assert
+    assert_eq!(skeleton, reversed);
+    let root = skeleton
+        .manifests
+        .iter()
+        .find(|manifest| manifest.relative_path == Path::new("Cargo.toml"))
+        .unwrap();
+    assert_eq!(
+        root.contents,
+        r#"[workspace]
+members = ["api", "worker"]
+"#
+    );
+    let error = missing.unwrap_err().to_string();
+    assert!(error.contains("frontend"));
+    assert!(error.contains("api, ci, worker"));
+}
+
+#[test]
+pub fn specify_package_with_transitive_local_dependencies() {
+    // Arrange
+    let workspace_content = r#"
+[workspace]
+members = ["app", "util", "core", "other"]
+    "#;
+
+    let manifests = [
+        (
+            "app",
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+util = { path = "../util", version = "0.2.0" }
+    "#,
+        ),
+        (
+            "util",
+            r#"
+[package]
+name = "util"
+version = "0.2.0"
+edition = "2018"
+
+[dependencies]
+core = { path = "../core", version = "0.3.0" }
+    "#,
+        ),
+        (
+            "core",
+            r#"
+[package]
+name = "core"
+version = "0.3.0"
+edition = "2018"
+    "#,
+        ),
+        (
+            "other",
+            r#"
+[package]
+name = "other"
+version = "0.4.0"
+edition = "2018"
+    "#,
+        ),
+    ];
+
+    let lockfile = r#"
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "util",
+]
+
+[[package]]
+name = "core"
+version = "0.3.0"
+
+[[package]]
+name = "other"
+version = "0.4.0"
+
+[[package]]
+name = "util"
+version = "0.2.0"
+dependencies = [
+ "core",
+]
+"#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(workspace_content)
+        .unwrap();
+    recipe_directory
+        .child("Cargo.lock")
+        .write_str(lockfile)
+        .unwrap();
+    for (name, content) in manifests {
+        let directory = recipe_directory.child(name);
+        directory.child("Cargo.toml").write_str(content).unwrap();
+        directory.child("src").create_dir_all().unwrap();
+        directory.child("src").child("lib.rs").touch().unwrap();
+    }
+
+    // Act
+    // Keep the whole lockfile, to check that the members that were left out are not masked.
+    let args = PrepareArgs {
+        no_lockfile_pruning: true,
+        ..members(&["app"])
+    };
+    let skeleton = Skeleton::derive(recipe_directory.path(), &args).unwrap();
+    let pruned = Skeleton::derive(recipe_directory.path(), &members(&["app"])).unwrap();
+
+    // Assert
+    let lock_file = skeleton.lock_file.expect("there should be a lock_file");
+    for name in ["app", "core", "util"] {
+        assert!(lock_file.contains(&format!(
+            r#"name = "{}"
+version = "0.0.1"
+"#,
+            name
+        )));
+    }
+    assert!(lock_file.contains(
+        r#"name = "other"
+version = "0.4.0"
+"#
+    ));
+    assert!(!pruned.lock_file.unwrap().contains(r#"name = "other""#));
+}
+
+#[test]
+pub fn specify_member_with_nested_and_cyclic_local_dependencies() {
+    // Arrange
+    let workspace_content = r#"
+[workspace]
+members = ["foo", "common", "proto", "unrelated"]
+    "#;
+
+    let manifests = [
+        (
+            "foo",
+            r#"
+[package]
+name = "foo"
+version = "0.1.0"
+edition = "2018"
+
+[target.'cfg(unix)'.build-dependencies]
+common = { path = "../common", version = "0.2.0" }
+    "#,
+        ),
+        (
+            "common",
+            r#"
+[package]
+name = "common"
+version = "0.2.0"
+edition = "2018"
+
+[dev-dependencies]
+proto = { path = "../proto", version = "0.3.0" }
+    "#,
+        ),
+        (
+            "proto",
+            r#"
+[package]
+name = "proto"
+version = "0.3.0"
+edition = "2018"
+
+[dev-dependencies]
+common = { path = "../common", version = "0.2.0" }
+    "#,
+        ),
+        (
+            "unrelated",
+            r#"
+[package]
+name = "unrelated"
+version = "0.4.0"
+edition = "2018"
+    "#,
+        ),
+    ];
+
+    let lockfile = r#"
+[[package]]
+name = "common"
+version = "0.2.0"
+dependencies = [
+ "proto",
+]
+
+[[package]]
+name = "foo"
+version = "0.1.0"
+dependencies = [
+ "common",
+]
+
+[[package]]
+name = "proto"
+version = "0.3.0"
+dependencies = [
+ "common",
+]
+
+[[package]]
+name = "unrelated"
+version = "0.4.0"
+"#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(workspace_content)
+        .unwrap();
+    recipe_directory
+        .child("Cargo.lock")
+        .write_str(lockfile)
+        .unwrap();
+    for (name, content) in manifests {
+        let directory = recipe_directory.child(name);
+        directory.child("Cargo.toml").write_str(content).unwrap();
+        directory.child("src").create_dir_all().unwrap();
+        directory.child("src").child("lib.rs").touch().unwrap();
+    }
+
+    // Act
+    // Keep the whole lockfile, to check that the members that were left out are not masked.
+    let args = PrepareArgs {
+        no_lockfile_pruning: true,
+        ..members(&["foo"])
+    };
+    let skeleton = Skeleton::derive(recipe_directory.path(), &args).unwrap();
+    let pruned = Skeleton::derive(recipe_directory.path(), &members(&["foo"])).unwrap();
+
+    // Assert
+    let lock_file = skeleton.lock_file.expect("there should be a lock_file");
+    for name in ["common", "foo", "proto"] {
+        assert!(lock_file.contains(&format!(
+            r#"name = "{}"
+version = "0.0.1"
+"#,
+            name
+        )));
+    }
+    assert!(lock_file.contains(
+        r#"name = "unrelated"
+version = "0.4.0"
+"#
+    ));
+    assert!(!pruned.lock_file.unwrap().contains(r#"name = "unrelated""#));
+}
+
+#[test]
+pub fn mask_versions_inherited_from_workspace() {
+    fn skeleton_for_version(version: &str) -> Skeleton {
+        let workspace_content = format!(
+            r#"
+[workspace]
+members = ["project_a", "project_b"]
+
+[workspace.package]
+version = "{}"
+edition = "2021"
+            "#,
+            version
+        );
+        let lockfile = format!(
+            r#"
+[[package]]
+name = "project_a"
+version = "{0}"
+
+[[package]]
+name = "project_b"
+version = "{0}"
+dependencies = [
+ "project_a",
+]
+"#,
+            version
+        );
+
+        let recipe_directory = TempDir::new().unwrap();
+        recipe_directory
+            .child("Cargo.toml")
+            .write_str(&workspace_content)
+            .unwrap();
+        recipe_directory
+            .child("Cargo.lock")
+            .write_str(&lockfile)
+            .unwrap();
+        for (name, dependencies) in [
+            ("project_a", ""),
+            ("project_b", r#"project_a = { path = "../project_a" }"#),
+        ] {
+            let directory = recipe_directory.child(name);
+            directory
+                .child("Cargo.toml")
+                .write_str(&format!(
+                    r#"
+[package]
+name = "{}"
+version.workspace = true
+edition.workspace = true
+
+[dependencies]
+{}
+                    "#,
+                    name, dependencies
+                ))
+                .unwrap();
+            directory.child("src").create_dir_all().unwrap();
+            directory.child("src").child("lib.rs").touch().unwrap();
+        }
+        Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap()
+    }
+
+    // Act
+    let skeleton = skeleton_for_version("0.1.0");
+    let bumped = skeleton_for_version("0.2.0");
+
+    // Assert
+    assert_eq!(skeleton, bumped);
+    let root = &skeleton.manifests[0];
+    assert!(root.contents.contains(r#"version = "0.0.1""#));
+    assert!(!root.contents.contains("0.1.0"));
+    for member in &skeleton.manifests[1..] {
+        assert!(member.contents.contains(
+            r#"[package.version]
+workspace = true
+"#
+        ));
+    }
+}
+
+#[test]
+pub fn specify_member_with_workspace_inherited_local_dependencies() {
+    // Arrange
+    let workspace_content = r#"
+[workspace]
+members = ["app", "common", "codegen", "other"]
+
+[workspace.dependencies]
+common = { path = "common", version = "0.2.0" }
+codegen = { path = "codegen", version = "0.3.0" }
+other = { path = "other", version = "0.4.0" }
+    "#;
+
+    let manifests = [
+        (
+            "app",
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+common = { workspace = true }
+
+[target.'cfg(unix)'.build-dependencies]
+codegen = { workspace = true }
+    "#,
+        ),
+        (
+            "common",
+            r#"
+[package]
+name = "common"
+version = "0.2.0"
+edition = "2021"
+    "#,
+        ),
+        (
+            "codegen",
+            r#"
+[package]
+name = "codegen"
+version = "0.3.0"
+edition = "2021"
+    "#,
+        ),
+        (
+            "other",
+            r#"
+[package]
+name = "other"
+version = "0.4.0"
+edition = "2021"
+    "#,
+        ),
+    ];
+
+    let lockfile = r#"
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "codegen",
+ "common",
+]
+
+[[package]]
+name = "codegen"
+version = "0.3.0"
+
+[[package]]
+name = "common"
+version = "0.2.0"
+
+[[package]]
+name = "other"
+version = "0.4.0"
+"#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(workspace_content)
+        .unwrap();
+    recipe_directory
+        .child("Cargo.lock")
+        .write_str(lockfile)
+        .unwrap();
+    for (name, content) in manifests {
+        let directory = recipe_directory.child(name);
+        directory.child("Cargo.toml").write_str(content).unwrap();
+        directory.child("src").create_dir_all().unwrap();
+        directory.child("src").child("lib.rs").touch().unwrap();
+    }
+
+    // Act
+    // Keep the whole lockfile, to check that the members that were left out are not masked.
+    let args = PrepareArgs {
+        no_lockfile_pruning: true,
+        ..members(&["app"])
+    };
+    let skeleton = Skeleton::derive(recipe_directory.path(), &args).unwrap();
+    let pruned = Skeleton::derive(recipe_directory.path(), &members(&["app"])).unwrap();
+
+    // Assert
+    let root = &skeleton.manifests[0];
+    for (name, version) in [
+        ("codegen", "0.0.1"),
+        ("common", "0.0.1"),
+        ("other", "0.4.0"),
+    ] {
+        assert!(root.contents.contains(&format!(
+            r#"[workspace.dependencies.{0}]
+path = "{0}"
+version = "{1}"
+"#,
+            name, version
+        )));
+    }
+    let lock_file = skeleton.lock_file.expect("there should be a lock_file");
+    for (name, version) in [
+        ("app", "0.0.1"),
+        ("codegen", "0.0.1"),
+        ("common", "0.0.1"),
+        ("other", "0.4.0"),
+    ] {
+        assert!(lock_file.contains(&format!(
+            r#"name = "{}"
+version = "{}"
+"#,
+            name, version
+        )));
+    }
+    assert!(!pruned.lock_file.unwrap().contains(r#"name = "other""#));
+}
+
+#[test]
+pub fn mask_local_versions_in_lockfile_dependency_entries() {
+    // Arrange
+    let workspace_content = r#"
+[workspace]
+members = ["app", "common"]
+    "#;
+
+    let app_content = r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+common = { path = "../common", version = "0.3.1" }
+    "#;
+
+    let common_content = r#"
+[package]
+name = "common"
+version = "0.3.1"
+edition = "2018"
+    "#;
+
+    // The lockfile body is shared between the v2, v3 and v4 layouts, which only differ
+    // by the `version` header.
+    // `common 0.2.0` is pulled in by a remote dependency and must not be masked.
+    let packages = r#"
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "common 0.2.0",
+ "common 0.3.1",
+]
+
+[[package]]
+name = "common"
+version = "0.2.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "23f0a1a0d69c8cfe445ed2d19bb7b8bb5ee14fbf8bc8e4d5f2bb787cc052fe50"
+
+[[package]]
+name = "common"
+version = "0.3.1"
+checksum = "stale"
+
+[metadata]
+"checksum common 0.3.1" = "stale"
+"#;
+
+    for header in ["", "version = 3\n", "version = 4\n"] {
+        let recipe_directory = TempDir::new().unwrap();
+        recipe_directory
+            .child("Cargo.toml")
+            .write_str(workspace_content)
+            .unwrap();
+        recipe_directory
+            .child("Cargo.lock")
+            .write_str(&format!("{}{}", header, packages))
+            .unwrap();
+        let app = recipe_directory.child("app");
+        app.child("Cargo.toml").write_str(app_content).unwrap();
+        app.child("src").create_dir_all().unwrap();
+        app.child("src").child("main.rs").touch().unwrap();
+        let common = recipe_directory.child("common");
+        common
+            .child("Cargo.toml")
+            .write_str(common_content)
+            .unwrap();
+        common.child("src").create_dir_all().unwrap();
+        common.child("src").child("lib.rs").touch().unwrap();
+
+        // Act
+        let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+
+        // Assert
+        let lock_file = skeleton.lock_file.expect("there should be a lock_file");
+        assert!(!lock_file.contains("0.3.1"));
+        assert!(!lock_file.contains("stale"));
+        assert!(lock_file.contains(r#"dependencies = ["common 0.2.0", "common 0.0.1"]"#));
+        assert!(lock_file.contains(
+            r#"name = "common"
+version = "0.2.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "23f0a1a0d69c8cfe445ed2d19bb7b8bb5ee14fbf8bc8e4d5f2bb787cc052fe50"
+"#
+        ));
+    }
+}
+
+/// A package at version `1.2.3` with the given lockfile.
+fn package_with_lockfile(lock_file: &str) -> TempDir {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str("[package]\nname = \"app\"\nversion = \"1.2.3\"\nedition = \"2018\"\n")
+        .unwrap();
+    project.child("Cargo.lock").write_str(lock_file).unwrap();
+    project.child("src").child("main.rs").touch().unwrap();
+    project
+}
+
+#[test]
+pub fn lockfiles_in_an_unsupported_version_are_copied_as_is() {
+    // Arrange
+    let project = package_with_lockfile(
+        r#"version = 5
+
+[[package]]
+name = "app"
+version = "1.2.3"
+"#,
+    );
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let lock_file = skeleton.lock_file.unwrap();
+    assert!(lock_file.contains("version = 5"));
+    assert!(lock_file.contains(r#"version = "1.2.3""#));
+    // The manifests are masked nonetheless.
+    assert!(!skeleton.manifests[0].contents.contains("1.2.3"));
+}
+
+#[test]
+pub fn custom_mask_version() {
+    // Arrange
+    let content = r#"
+[package]
+name = "test-dummy"
+version = "1.2.3"
+edition = "2018"
+
+[dependencies]
+    "#;
+
+    let lockfile = r#"
+[[package]]
+name = "test-dummy"
+version = "1.2.3"
+"#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(content)
+        .unwrap();
+    recipe_directory
+        .child("Cargo.lock")
+        .write_str(lockfile)
+        .unwrap();
+    recipe_directory.child("src").create_dir_all().unwrap();
+    recipe_directory
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+
+    // Act
+    let args = PrepareArgs {
+        mask_version: "0.0.0-chef-masked".to_string(),
+        ..Default::default()
+    };
+    let skeleton = Skeleton::derive(recipe_directory.path(), &args).unwrap();
+
+    // Assert
+    assert_eq!("0.0.0-chef-masked", skeleton.mask_version);
+    assert!(skeleton.manifests[0]
+        .contents
+        .contains(r#"version = "0.0.0-chef-masked""#));
+    let lock_file = skeleton.lock_file.expect("there should be a lock_file");
+    assert!(lock_file.contains(r#"version = "0.0.0-chef-masked""#));
+    assert!(!lock_file.contains("1.2.3"));
+}
+
+#[test]
+pub fn keep_replaced_local_crates_consistent_with_their_keys() {
+    // Arrange
+    let content = r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+serde = { version = "1.0", features = ["derive"] }
+
+[replace]
+"serde:1.0.188" = { path = "forks/serde" }
+    "#;
+
+    let fork_content = r#"
+[package]
+name = "serde"
+version = "1.0.188"
+edition = "2018"
+    "#;
+
+    let lockfile = r#"
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "serde",
+]
+
+[[package]]
+name = "serde"
+version = "1.0.188"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+replace = "serde 1.0.188"
+
+[[package]]
+name = "serde"
+version = "1.0.188"
+"#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(content)
+        .unwrap();
+    recipe_directory
+        .child("Cargo.lock")
+        .write_str(lockfile)
+        .unwrap();
+    recipe_directory.child("src").create_dir_all().unwrap();
+    recipe_directory
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    let fork = recipe_directory.child("forks").child("serde");
+    fork.child("Cargo.toml").write_str(fork_content).unwrap();
+    fork.child("src").create_dir_all().unwrap();
+    fork.child("src").child("lib.rs").touch().unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let app = &skeleton.manifests[0];
+    assert!(app.contents.contains(
+        r#"[replace."serde:1.0.188"]
+path = "forks/serde"
+"#
+    ));
+    assert!(app.contents.contains(
+        r#"[dependencies.serde]
+features = ["derive"]
+version = "1.0"
+"#
+    ));
+    let fork = &skeleton.manifests[1];
+    assert!(fork.contents.contains(r#"version = "1.0.188""#));
+    let lock_file = skeleton.lock_file.expect("there should be a lock_file");
+    assert!(lock_file.contains(r#"replace = "serde 1.0.188""#));
+    assert!(lock_file.contains(
+        r#"[[package]]
+name = "serde"
+version = "1.0.188"
+"#
+    ));
+    assert!(lock_file.contains(
+        r#"[[package]]
+name = "app"
+version = "0.0.1"
+"#
+    ));
+}
+
+#[test]
+pub fn mask_target_specific_dependencies_declared_with_dotted_keys() {
+    // Arrange
+    let workspace_content = r#"
+[workspace]
+members = ["app", "codegen"]
+    "#;
+
+    // Dotted keys must come before any table header, otherwise they belong to that table.
+    let app_content = r#"
+target.'cfg(target_os = "linux")'.dependencies.codegen.path = "../codegen"
+target.'cfg(target_os = "linux")'.dependencies.codegen.version = "0.4.0"
+target.'cfg(target_os = "linux")'.dev-dependencies.codegen.path = "../codegen"
+target.'cfg(target_os = "linux")'.dev-dependencies.codegen.version = "0.4.0"
+target.'cfg(target_os = "linux")'.build-dependencies.codegen.path = "../codegen"
+target.'cfg(target_os = "linux")'.build-dependencies.codegen.version = "0.4.0"
+
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+    "#;
+
+    let codegen_content = r#"
+[package]
+name = "codegen"
+version = "0.4.0"
+edition = "2018"
+    "#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(workspace_content)
+        .unwrap();
+    let app = recipe_directory.child("app");
+    app.child("Cargo.toml").write_str(app_content).unwrap();
+    app.child("src").create_dir_all().unwrap();
+    app.child("src").child("main.rs").touch().unwrap();
+    let codegen = recipe_directory.child("codegen");
+    codegen
+        .child("Cargo.toml")
+        .write_str(codegen_content)
+        .unwrap();
+    codegen.child("src").create_dir_all().unwrap();
+    codegen.child("src").child("lib.rs").touch().unwrap();
+
+    for args in [PrepareArgs::default(), members(&["app"])] {
+        // Act
+        let skeleton = Skeleton::derive(recipe_directory.path(), &args).unwrap();
+
+        // Assert
+        let app = skeleton
+            .manifests
+            .iter()
+            .find(|manifest| manifest.relative_path == Path::new("app/Cargo.toml"))
+            .unwrap();
+        assert!(!app.contents.contains("0.4.0"));
+        for dependency_key in ["dependencies", "dev-dependencies", "build-dependencies"] {
+            assert!(app.contents.contains(&format!(
+                r#"[target."cfg(target_os = \"linux\")".{}.codegen]
+path = "../codegen"
+version = "0.0.1"
+"#,
+                dependency_key
+            )));
+        }
+    }
+}
+
+#[test]
+pub fn print_masked_does_not_change_the_skeleton() {
+    // Arrange
+    let project = r#"
+[package]
+name = "app"
+version = "1.2.3"
+edition = "2018"
+
+[dependencies]
+common = { path = "common", version = "0.3" }
+    "#;
+    let common = r#"
+[package]
+name = "common"
+version = "0.3.0"
+edition = "2018"
+    "#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(project)
+        .unwrap();
+    recipe_directory.child("src").create_dir_all().unwrap();
+    recipe_directory
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    let common_dir = recipe_directory.child("common");
+    common_dir.child("Cargo.toml").write_str(common).unwrap();
+    common_dir.child("src").create_dir_all().unwrap();
+    common_dir.child("src").child("lib.rs").touch().unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+    let args = PrepareArgs {
+        print_masked: true,
+        ..Default::default()
+    };
+    let debug_skeleton = Skeleton::derive(recipe_directory.path(), &args).unwrap();
+
+    // Assert
+    assert_eq!(skeleton, debug_skeleton);
+}
+
+#[test]
+pub fn virtual_workspace() {
+    // Arrange
+    let root = r#"
+[workspace]
+resolver = "2"
+members = ["crates/api", "crates/core", "crates/cli"]
+
+[workspace.package]
+version = "0.3.0"
+edition = "2021"
+
+[workspace.dependencies]
+core = { path = "crates/core", version = "0.3.0" }
+api = { path = "crates/api", version = "0.3.0" }
+uuid = { version = "0.8", features = ["v4"] }
+"#;
+    let member = |name: &str, dependencies: &str| {
+        format!(
+            r#"
+[package]
+name = "{}"
+version.workspace = true
+edition.workspace = true
+
+[dependencies]
+{}
+"#,
+            name, dependencies
+        )
+    };
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(root)
+        .unwrap();
+    for (name, dependencies, target) in [
+        ("api", "core = { workspace = true }", "lib.rs"),
+        ("core", "uuid.workspace = true", "lib.rs"),
+        (
+            "cli",
+            "api.workspace = true\ncore.workspace = true",
+            "main.rs",
+        ),
+    ] {
+        let crate_dir = recipe_directory.child("crates").child(name);
+        crate_dir
+            .child("Cargo.toml")
+            .write_str(&member(name, dependencies))
+            .unwrap();
+        crate_dir.child("src").create_dir_all().unwrap();
+        crate_dir.child("src").child(target).touch().unwrap();
+    }
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    let cli_skeleton = Skeleton::derive(recipe_directory.path(), &members(&["cli"])).unwrap();
+
+    // Assert
+    // The virtual root is preserved as is, without a `[package]` table.
+    let root = &skeleton.manifests[0];
+    assert_eq!(Path::new("Cargo.toml"), root.relative_path);
+    check(
+        &root.contents,
+        expect_test::expect![[r#"
+            [workspace]
+            members = ["crates/api", "crates/core", "crates/cli"]
+            resolver = "2"
+            [workspace.dependencies.api]
+            path = "crates/api"
+            version = "0.0.1"
+
+            [workspace.dependencies.core]
+            path = "crates/core"
+            version = "0.0.1"
+
+            [workspace.dependencies.uuid]
+            features = ["v4"]
+            version = "0.8"
+
+            [workspace.package]
+            edition = "2021"
+            version = "0.0.1"
+        "#]],
+    );
+    assert_eq!(4, skeleton.manifests.len());
+    for member in &skeleton.manifests[1..] {
+        assert!(member
+            .contents
+            .contains("[package.version]\nworkspace = true"));
+    }
+    cook_directory
+        .child("crates")
+        .child("cli")
+        .child("src")
+        .child("main.rs")
+        .assert("fn main() {}");
+
+    // Selecting a member keeps the members it depends on, as well as the workspace tables they
+    // inherit from.
+    let cli_root = &cli_skeleton.manifests[0];
+    assert_eq!(
+        root.contents.replace(
+            r#"members = ["crates/api", "crates/core", "crates/cli"]"#,
+            r#"members = ["crates/api", "crates/cli", "crates/core"]"#
+        ),
+        cli_root.contents
+    );
+    assert_eq!(4, cli_skeleton.manifests.len());
+}
+
+#[test]
+pub fn rust_toolchain_file() {
+    // Arrange
+    let content = r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+"#;
+    let toolchain = r#"[toolchain]
+channel = "1.70.0"
+components = ["clippy"]
+"#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(content)
+        .unwrap();
+    recipe_directory
+        .child("rust-toolchain.toml")
+        .write_str(toolchain)
+        .unwrap();
+    recipe_directory.child("src").create_dir_all().unwrap();
+    recipe_directory
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    recipe_directory
+        .child("rust-toolchain.toml")
+        .write_str(&toolchain.replace(r#"["clippy"]"#, r#"["clippy", "rustfmt"]"#))
+        .unwrap();
+    let updated_skeleton =
+        Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+    recipe_directory
+        .child("rust-toolchain")
+        .write_str("nightly-2023-01-01")
+        .unwrap();
+    let legacy_skeleton =
+        Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    assert_eq!(
+        Some((RustToolchainFile::Toml, toolchain.to_string())),
+        skeleton.rust_toolchain_file
+    );
+    cook_directory
+        .child("rust-toolchain.toml")
+        .assert(toolchain);
+    // Components affect the build: the recipe must change.
+    assert_ne!(skeleton, updated_skeleton);
+    // Like `rustup`, the legacy file takes precedence.
+    assert_eq!(
+        Some((RustToolchainFile::Bare, "nightly-2023-01-01".to_string())),
+        legacy_skeleton.rust_toolchain_file
+    );
+}
+
+#[test]
+pub fn build_scripts() {
+    // Arrange
+    let workspace = r#"
+[workspace]
+members = ["auto", "custom", "explicit"]
+"#;
+    let member = |name: &str, build: &str| {
+        format!(
+            r#"
+[package]
+name = "{}"
+version = "0.1.0"
+edition = "2018"
+{}
+
+[build-dependencies]
+cc = "1"
+"#,
+            name, build
+        )
+    };
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(workspace)
+        .unwrap();
+    for (name, build, build_path) in [
+        ("auto", "", "build.rs"),
+        ("custom", r#"build = "scripts/gen.rs""#, "scripts/gen.rs"),
+        ("explicit", "build = true", "build.rs"),
+    ] {
+        let crate_dir = recipe_directory.child(name);
+        crate_dir
+            .child("Cargo.toml")
+            .write_str(&member(name, build))
+            .unwrap();
+        crate_dir.child("src").create_dir_all().unwrap();
+        crate_dir.child("src").child("lib.rs").touch().unwrap();
+        crate_dir
+            .child(build_path)
+            .write_str("fn main() { println!(\"cargo:rerun-if-changed=build.rs\"); }")
+            .unwrap();
+    }
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    for (name, build_path) in [
+        ("auto", "build.rs"),
+        ("custom", "scripts/gen.rs"),
+        ("explicit", "build.rs"),
+    ] {
+        let manifest = skeleton
+            .manifests
+            .iter()
+            .find(|manifest| manifest.relative_path == Path::new(name).join("Cargo.toml"))
+            .unwrap();
+        // The build script is kept, so that build dependencies are compiled and cached.
+        assert!(manifest.contents.contains("[build-dependencies]"));
+        assert!(manifest.contents.contains("build = "));
+        cook_directory
+            .child(name)
+            .child(build_path)
+            .assert("fn main() {}");
+    }
+}
+
+#[test]
+pub fn targets_with_explicit_paths() {
+    // Arrange
+    let content = r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+
+[features]
+heavy = []
+
+[[bench]]
+name = "micro"
+path = "perf/micro/main.rs"
+harness = false
+
+[[bench]]
+name = "gated"
+path = "perf/gated.rs"
+required-features = ["heavy"]
+
+[[example]]
+name = "showcase"
+path = "examples/demo/showcase.rs"
+
+[[test]]
+name = "integration"
+path = "tests/it/main.rs"
+harness = false
+    "#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(content)
+        .unwrap();
+    for path in [
+        "src/lib.rs",
+        "perf/micro/main.rs",
+        "perf/gated.rs",
+        "examples/demo/showcase.rs",
+        "tests/it/main.rs",
+    ] {
+        recipe_directory.child(path).touch().unwrap();
+    }
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let manifest = &skeleton.manifests[0];
+    assert!(manifest
+        .contents
+        .contains(r#"required-features = ["heavy"]"#));
+    for path in [
+        "perf/micro/main.rs",
+        "perf/gated.rs",
+        "examples/demo/showcase.rs",
+        "tests/it/main.rs",
+    ] {
+        cook_directory.child(path).assert("fn main() {}");
+    }
+}
+
+#[test]
+pub fn discovered_binaries_are_kept_next_to_the_declared_ones() {
+    // Arrange
+    let content = r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+default-run = "worker"
+
+[features]
+admin = []
+
+[[bin]]
+name = "admin"
+path = "tools/admin.rs"
+required-features = ["admin"]
+
+[[bin]]
+name = "migrate"
+    "#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(content)
+        .unwrap();
+    for path in [
+        "src/main.rs",
+        "src/bin/worker.rs",
+        "src/bin/cli/main.rs",
+        "src/bin/migrate/main.rs",
+        "tools/admin.rs",
+    ] {
+        recipe_directory.child(path).touch().unwrap();
+    }
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let manifest: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    assert_eq!(manifest["package"]["default-run"].as_str(), Some("worker"));
+    let mut bins: Vec<(&str, &str)> = manifest["bin"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|bin| (bin["name"].as_str().unwrap(), bin["path"].as_str().unwrap()))
+        .collect();
+    bins.sort();
+    assert_eq!(
+        vec![
+            ("admin", "tools/admin.rs"),
+            ("app", "src/main.rs"),
+            ("cli", "src/bin/cli/main.rs"),
+            ("migrate", "src/bin/migrate/main.rs"),
+            ("worker", "src/bin/worker.rs"),
+        ],
+        bins
+    );
+    assert!(skeleton.manifests[0]
+        .contents
+        .contains(r#"required-features = ["admin"]"#));
+    for (_, path) in bins {
+        cook_directory.child(path).assert("fn main() {}");
+    }
+}
+
+#[test]
+pub fn declared_binaries_turn_the_discovery_off_on_the_2015_edition() {
+    // Arrange
+    let content = r#"
+[package]
+name = "legacy"
+version = "0.1.0"
+
+[[bin]]
+name = "cli"
+path = "src/cli.rs"
+    "#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(content)
+        .unwrap();
+    for path in ["src/main.rs", "src/cli.rs"] {
+        recipe_directory.child(path).touch().unwrap();
+    }
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let manifest: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    let bins = manifest["bin"].as_array().unwrap();
+    assert_eq!(1, bins.len());
+    assert_eq!(bins[0]["name"].as_str(), Some("cli"));
+}
+
+fn crate_with_discovered_targets() -> TempDir {
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "service"
+version = "0.1.0"
+edition = "2021"
+
+[[test]]
+name = "smoke"
+harness = false
+
+[[example]]
+name = "tour"
+path = "docs/tour.rs"
+"#,
+        )
+        .unwrap();
+    for path in [
+        "src/lib.rs",
+        "src/main.rs",
+        "tests/smoke/main.rs",
+        "tests/api.rs",
+        "examples/basic.rs",
+        "docs/tour.rs",
+        "benches/throughput.rs",
+    ] {
+        recipe_directory
+            .child(path)
+            .write_str("// The real sources.")
+            .unwrap();
+    }
+    recipe_directory
+}
+
+#[test]
+pub fn discovered_targets_are_recorded_in_the_recipe() {
+    // Arrange
+    let recipe_directory = crate_with_discovered_targets();
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let manifest: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    let targets = |section: &str| -> Vec<(String, String)> {
+        let mut targets: Vec<(String, String)> = manifest[section]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|target| {
+                (
+                    target["name"].as_str().unwrap().to_owned(),
+                    target["path"].as_str().unwrap().to_owned(),
+                )
+            })
+            .collect();
+        targets.sort();
+        targets
+    };
+    let pairs = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, path)| (name.to_string(), path.to_string()))
+            .collect()
+    };
+    assert_eq!(pairs(&[("service", "src/main.rs")]), targets("bin"));
+    assert_eq!(
+        pairs(&[("api", "tests/api.rs"), ("smoke", "tests/smoke/main.rs")]),
+        targets("test")
+    );
+    assert_eq!(
+        pairs(&[("basic", "examples/basic.rs"), ("tour", "docs/tour.rs")]),
+        targets("example")
+    );
+    assert_eq!(
+        pairs(&[("throughput", "benches/throughput.rs")]),
+        targets("bench")
+    );
+    assert_eq!(manifest["lib"]["path"].as_str(), Some("src/lib.rs"));
+}
+
+#[test]
+pub fn the_recipe_changes_only_when_the_set_of_targets_changes() {
+    // Arrange
+    let recipe_directory = crate_with_discovered_targets();
+    let prepare = || Recipe::prepare(recipe_directory.path().into(), PrepareArgs::default());
+    let before = prepare().unwrap().fingerprint().unwrap();
+
+    // Act
+    recipe_directory
+        .child("tests")
+        .child("api.rs")
+        .write_str("// Edited.")
+        .unwrap();
+    let edited = prepare().unwrap().fingerprint().unwrap();
+    recipe_directory
+        .child("tests")
+        .child("health.rs")
+        .touch()
+        .unwrap();
+    let added = prepare().unwrap().fingerprint().unwrap();
+
+    // Assert
+    assert_eq!(before, edited);
+    assert_ne!(before, added);
+}
+
+#[test]
+pub fn proc_macro_and_native_libraries() {
+    // Arrange
+    let workspace = r#"
+[workspace]
+members = ["macros", "ffi"]
+"#;
+    let macros = r#"
+[package]
+name = "my-macros"
+version = "0.1.0"
+edition = "2018"
+
+[lib]
+proc-macro = true
+
+[dependencies]
+quote = "1"
+syn = "2"
+"#;
+    let ffi = r#"
+[package]
+name = "ffi"
+version = "0.1.0"
+edition = "2018"
+
+[lib]
+name = "ffi_native"
+path = "lib/ffi.rs"
+crate-type = ["cdylib", "staticlib"]
+"#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(workspace)
+        .unwrap();
+    recipe_directory
+        .child("macros")
+        .child("Cargo.toml")
+        .write_str(macros)
+        .unwrap();
+    recipe_directory
+        .child("macros")
+        .child("src")
+        .child("lib.rs")
+        .touch()
+        .unwrap();
+    recipe_directory
+        .child("ffi")
+        .child("Cargo.toml")
+        .write_str(ffi)
+        .unwrap();
+    recipe_directory
+        .child("ffi")
+        .child("lib")
+        .child("ffi.rs")
+        .touch()
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), true)
+        .unwrap();
+
+    // Assert
+    let ffi = &skeleton.manifests[1];
+    assert_eq!(Path::new("ffi/Cargo.toml"), ffi.relative_path);
+    assert!(ffi
+        .contents
+        .contains(r#"crate-type = ["cdylib", "staticlib"]"#));
+    assert!(ffi.contents.contains(r#"name = "ffi_native""#));
+    assert!(ffi.contents.contains(r#"path = "lib/ffi.rs""#));
+    cook_directory
+        .child("ffi")
+        .child("lib")
+        .child("ffi.rs")
+        .assert("#![no_std]");
+
+    let macros = &skeleton.manifests[2];
+    assert_eq!(Path::new("macros/Cargo.toml"), macros.relative_path);
+    assert!(macros.contents.contains("proc-macro = true"));
+    assert!(macros.contents.contains(r#"name = "my_macros""#));
+    assert!(macros.contents.contains(r#"path = "src/lib.rs""#));
+    // Proc-macros always link to `std`, even for `no_std` projects.
+    cook_directory
+        .child("macros")
+        .child("src")
+        .child("lib.rs")
+        .assert("");
+}
+
+#[test]
+pub fn profiles_are_preserved() {
+    // Arrange
+    let content = r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+
+[profile.dev]
+split-debuginfo = "unpacked"
+debug = "line-tables-only"
+
+[profile.release-lto]
+inherits = "release"
+lto = "fat"
+codegen-units = 1
+strip = "symbols"
+
+[profile.release-lto.package."*"]
+opt-level = 3
+
+[profile.release-lto.build-override]
+opt-level = 0
+"#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(content)
+        .unwrap();
+    recipe_directory
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let manifest: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    let original: toml::Value = content.parse().unwrap();
+    assert_eq!(original.get("profile"), manifest.get("profile"));
+}
+
+/// A workspace whose member inherits the lints of the workspace.
+fn workspace_with_lints() -> TempDir {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["app"]
+
+[workspace.lints.rust]
+unsafe_code = "forbid"
+
+[workspace.lints.clippy]
+all = { level = "deny", priority = -1 }
+
+[workspace.metadata.release]
+shared-version = true
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[lints]
+workspace = true
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    project
+}
+
+#[test]
+pub fn lints_are_preserved() {
+    // Arrange
+    let project = workspace_with_lints();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let manifest = |path: &str| -> (toml::Value, toml::Value) {
+        let skeleton = skeleton
+            .manifests
+            .iter()
+            .find(|manifest| manifest.relative_path == Path::new(path))
+            .unwrap();
+        let original = std::fs::read_to_string(project.child(path).path()).unwrap();
+        (
+            skeleton.contents.parse().unwrap(),
+            original.parse().unwrap(),
+        )
+    };
+    let (root, original_root) = manifest("Cargo.toml");
+    assert_eq!(
+        original_root["workspace"].get("lints"),
+        root["workspace"].get("lints")
+    );
+    let (app, original_app) = manifest("app/Cargo.toml");
+    assert_eq!(original_app.get("lints"), app.get("lints"));
+}
+
+#[test]
+pub fn unknown_tables_are_preserved() {
+    // Arrange
+    let project = workspace_with_lints();
+    let root = project.child("Cargo.toml");
+    let contents = std::fs::read_to_string(root.path()).unwrap();
+    root.write_str(&format!(
+        "{}\n[workspace.future-key]\nkey = \"value\"\n\n[future-table]\nkey = \"value\"\n",
+        contents
+    ))
+    .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let manifest: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    let original: toml::Value = std::fs::read_to_string(root.path())
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert_eq!(original.get("future-table"), manifest.get("future-table"));
+    assert_eq!(
+        original["workspace"].get("future-key"),
+        manifest["workspace"].get("future-key")
+    );
+}
+
+/// A package with `[package.metadata]` tables, in a workspace with `[workspace.metadata]`.
+fn workspace_with_metadata(deb_revision: &str) -> TempDir {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["app"]
+
+[workspace.metadata.release]
+shared-version = true
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(&format!(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[package.metadata.deb]
+revision = "{}"
+assets = [["target/release/app", "usr/bin/", "755"]]
+
+[package.metadata.wasm-pack.profile.release]
+wasm-opt = false
+
+[dependencies]
+anyhow = "1"
+"#,
+            deb_revision
+        ))
+        .unwrap();
+    project
+        .child("app")
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    project
+}
+
+#[test]
+pub fn metadata_is_left_out_by_default() {
+    // Arrange
+    let project = workspace_with_metadata("1");
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    for manifest in &skeleton.manifests {
+        let contents: toml::Value = manifest.contents.parse().unwrap();
+        for key in ["package", "workspace"] {
+            assert!(
+                contents.get(key).and_then(|t| t.get("metadata")).is_none(),
+                "{}",
+                manifest.contents
+            );
+        }
+    }
+}
+
+#[test]
+pub fn editing_metadata_does_not_change_the_skeleton() {
+    // Arrange
+    let before = workspace_with_metadata("1");
+    let after = workspace_with_metadata("2");
+    let without_metadata = workspace_with_metadata("1");
+    for manifest in ["Cargo.toml", "app/Cargo.toml"] {
+        let manifest = without_metadata.child(manifest);
+        let contents = std::fs::read_to_string(manifest.path()).unwrap();
+        let mut contents: toml::Value = contents.parse().unwrap();
+        for key in ["package", "workspace"] {
+            if let Some(table) = contents.get_mut(key).and_then(|t| t.as_table_mut()) {
+                table.remove("metadata");
+            }
+        }
+        manifest
+            .write_str(&toml::to_string(&contents).unwrap())
+            .unwrap();
+    }
+
+    // Act
+    let derive = |project: &TempDir| Skeleton::derive(project.path(), &PrepareArgs::default());
+    let (before, after) = (derive(&before).unwrap(), derive(&after).unwrap());
+    let without_metadata = derive(&without_metadata).unwrap();
+
+    // Assert
+    assert_eq!(before, after);
+    assert_eq!(before, without_metadata);
+}
+
+#[test]
+pub fn metadata_is_kept_on_request() {
+    // Arrange
+    let project = workspace_with_metadata("1");
+    let args = PrepareArgs {
+        keep_metadata: true,
+        ..Default::default()
+    };
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &args).unwrap();
+
+    // Assert
+    let root: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    assert_eq!(
+        root["workspace"]["metadata"]["release"]["shared-version"].as_bool(),
+        Some(true)
+    );
+    let app: toml::Value = skeleton.manifests[1].contents.parse().unwrap();
+    assert_eq!(
+        app["package"]["metadata"]["deb"]["revision"].as_str(),
+        Some("1")
+    );
+}
+
+#[test]
+pub fn skip_build_is_kept_in_the_metadata() {
+    // Arrange
+    let project = workspace_with_metadata("1");
+    let manifest = project.child("app").child("Cargo.toml");
+    let contents = std::fs::read_to_string(manifest.path()).unwrap();
+    manifest
+        .write_str(&format!(
+            "{}\n[package.metadata.chef]\nskip-build = true\n",
+            contents
+        ))
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let app: toml::Value = skeleton.manifests[1].contents.parse().unwrap();
+    let metadata = app["package"]["metadata"].as_table().unwrap();
+    assert_eq!(vec!["chef"], metadata.keys().collect::<Vec<_>>());
+    assert_eq!(metadata["chef"]["skip-build"].as_bool(), Some(true));
+}
+
+#[test]
+pub fn skip_build_must_be_a_boolean() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "codegen"
+version = "0.1.0"
+edition = "2018"
+
+[package.metadata.chef]
+skip-build = "yes"
+"#,
+        )
+        .unwrap();
+    project.child("src").child("lib.rs").touch().unwrap();
+
+    // Act
+    let error = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap_err();
+
+    // Assert
+    assert_eq!(
+        "`package.metadata.chef.skip-build` must be a boolean.",
+        error.root_cause().to_string()
+    );
+}
+
+fn crate_with_includes(include: &str) -> TempDir {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(&format!(
+            r#"
+[package]
+name = "grpc"
+version = "0.1.0"
+edition = "2018"
+
+[package.metadata.chef]
+include = {}
+"#,
+            include
+        ))
+        .unwrap();
+    project.child("src").child("lib.rs").touch().unwrap();
+    project.child("build.rs").write_str("fn main() {}").unwrap();
+    project
+        .child("schema")
+        .child("api.proto")
+        .write_str("syntax = \"proto3\";\n")
+        .unwrap();
+    project
+        .child("schema")
+        .child("v1")
+        .child("user.proto")
+        .write_str("message User {}\n")
+        .unwrap();
+    project
+}
+
+#[test]
+pub fn included_files_are_embedded_in_the_skeleton() {
+    // Arrange
+    let project = crate_with_includes(r#"["schema/**", "build.rs"]"#);
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let included: Vec<(&Path, &str)> = skeleton
+        .included_files
+        .iter()
+        .map(|file| (file.relative_path.as_path(), file.contents.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            (Path::new("build.rs"), "fn main() {}"),
+            (Path::new("schema/api.proto"), "syntax = \"proto3\";\n"),
+            (Path::new("schema/v1/user.proto"), "message User {}\n"),
+        ],
+        included
+    );
+
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    cook_directory
+        .child("schema")
+        .child("v1")
+        .child("user.proto")
+        .assert("message User {}\n");
+}
+
+#[test]
+pub fn changing_an_included_file_changes_the_fingerprint() {
+    // Arrange
+    let project = crate_with_includes(r#"["schema/*.proto"]"#);
+    let before = Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap();
+
+    // Act
+    project
+        .child("schema")
+        .child("api.proto")
+        .write_str("syntax = \"proto2\";\n")
+        .unwrap();
+    let after = Recipe::prepare(project.path().into(), PrepareArgs::default()).unwrap();
+
+    // Assert
+    assert_eq!(1, after.skeleton.included_files.len());
+    assert_ne!(before.fingerprint().unwrap(), after.fingerprint().unwrap());
+}
+
+#[test]
+pub fn include_patterns_that_match_nothing_are_not_an_error() {
+    // Arrange
+    let project = crate_with_includes(r#"["migrations/*.sql"]"#);
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    assert!(skeleton.included_files.is_empty());
+}
+
+#[test]
+pub fn include_patterns_must_stay_within_the_crate() {
+    for pattern in ["../secrets/*", "/etc/passwd", "schema/../../*"].iter() {
+        // Arrange
+        let project = crate_with_includes(&format!("[{:?}]", pattern));
+
+        // Act
+        let error = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap_err();
+
+        // Assert
+        assert_eq!(
+            format!(
+                "`{}` must be relative to the directory of the crate, without `..`.",
+                pattern
+            ),
+            error.root_cause().to_string()
+        );
+    }
+}
+
+#[test]
+pub fn included_files_outside_of_the_project_are_not_restored() {
+    // Arrange
+    let project = crate_with_includes(r#"["schema/api.proto"]"#);
+    let mut skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+    skeleton.included_files[0].relative_path = "../escaped.proto".into();
+
+    // Act
+    let cook_directory = TempDir::new().unwrap();
+    let nested = cook_directory.child("project");
+    let error = skeleton
+        .build_minimum_project(nested.path(), false)
+        .unwrap_err();
+
+    // Assert
+    assert!(error.to_string().contains("outside of the project"));
+    cook_directory
+        .child("escaped.proto")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+pub fn sqlx_offline_data_is_embedded_in_the_skeleton() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["api", "legacy"]
+"#,
+        )
+        .unwrap();
+    for name in ["api", "legacy"].iter() {
+        let member = project.child(name);
+        member
+            .child("Cargo.toml")
+            .write_str(&format!(
+                r#"
+[package]
+name = "{}"
+version = "0.1.0"
+edition = "2018"
+"#,
+                name
+            ))
+            .unwrap();
+        member.child("src").child("lib.rs").touch().unwrap();
+    }
+    project
+        .child(".sqlx")
+        .child("query-1.json")
+        .write_str("{}")
+        .unwrap();
+    project
+        .child("api")
+        .child(".sqlx")
+        .child("query-2.json")
+        .write_str("{}")
+        .unwrap();
+    project
+        .child("legacy")
+        .child("sqlx-data.json")
+        .write_str("{}")
+        .unwrap();
+    // Only the query data is embedded, not the rest of the directories.
+    project
+        .child("api")
+        .child("migrations")
+        .child("1_init.sql")
+        .write_str("CREATE TABLE users (id INTEGER);")
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let included: Vec<&Path> = skeleton
+        .included_files
+        .iter()
+        .map(|file| file.relative_path.as_path())
+        .collect();
+    assert_eq!(
+        vec![
+            Path::new(".sqlx/query-1.json"),
+            Path::new("api/.sqlx/query-2.json"),
+            Path::new("legacy/sqlx-data.json"),
+        ],
+        included
+    );
+}
+
+fn workspace_with_build_scripts() -> TempDir {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["grpc", "tools"]
+"#,
+        )
+        .unwrap();
+    project
+        .child("grpc")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "grpc"
+version = "0.1.0"
+edition = "2018"
+
+[package.metadata.chef]
+keep-build-script = true
+
+[build-dependencies]
+tonic-build = "0.11"
+"#,
+        )
+        .unwrap();
+    project
+        .child("tools")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "tools"
+version = "0.1.0"
+edition = "2018"
+build = "scripts/generate.rs"
+"#,
+        )
+        .unwrap();
+    for name in ["grpc", "tools"].iter() {
+        project
+            .child(name)
+            .child("src")
+            .child("lib.rs")
+            .touch()
+            .unwrap();
+    }
+    project
+        .child("grpc")
+        .child("build.rs")
+        .write_str(r#"fn main() { tonic_build::compile_protos("proto/api.proto").unwrap(); }"#)
+        .unwrap();
+    project
+        .child("tools")
+        .child("scripts")
+        .child("generate.rs")
+        .write_str("fn main() { println!(\"cargo:rerun-if-changed=build.rs\"); }")
+        .unwrap();
+    project
+}
+
+#[test]
+pub fn build_scripts_marked_with_keep_build_script_are_embedded() {
+    // Arrange
+    let project = workspace_with_build_scripts();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    assert_eq!(1, skeleton.included_files.len());
+    assert_eq!(
+        Path::new("grpc/build.rs"),
+        skeleton.included_files[0].relative_path
+    );
+    assert!(skeleton.included_files[0]
+        .contents
+        .contains("tonic_build::compile_protos"));
+    let grpc: toml::Value = skeleton.manifests[1].contents.parse().unwrap();
+    assert!(grpc["build-dependencies"].get("tonic-build").is_some());
+
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+    cook_directory
+        .child("grpc")
+        .child("build.rs")
+        .assert(predicate::str::contains("tonic_build"));
+    cook_directory
+        .child("tools")
+        .child("scripts")
+        .child("generate.rs")
+        .assert("fn main() {}");
+}
+
+#[test]
+pub fn keep_build_scripts_embeds_all_of_them() {
+    // Arrange
+    let project = workspace_with_build_scripts();
+    let args = PrepareArgs {
+        keep_build_scripts: true,
+        ..Default::default()
+    };
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &args).unwrap();
+
+    // Assert
+    let included: Vec<&Path> = skeleton
+        .included_files
+        .iter()
+        .map(|file| file.relative_path.as_path())
+        .collect();
+    assert_eq!(
+        vec![
+            Path::new("grpc/build.rs"),
+            Path::new("tools/scripts/generate.rs")
+        ],
+        included
+    );
+}
+
+/// A workspace on the 2024 edition, whose members set keys that change how their dependencies
+/// are resolved and compiled, directly or through inheritance.
+fn workspace_with_resolution_keys() -> TempDir {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["sys", "app"]
+
+[workspace.package]
+version = "1.2.3"
+edition = "2024"
+rust-version = "1.85"
+license = "MIT"
+"#,
+        )
+        .unwrap();
+    let sys = project.child("sys");
+    sys.child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "sys"
+version.workspace = true
+edition.workspace = true
+rust-version.workspace = true
+license.workspace = true
+links = "foo"
+build = "build.rs"
+"#,
+        )
+        .unwrap();
+    sys.child("build.rs").write_str("fn main() {}").unwrap();
+    sys.child("src").child("lib.rs").touch().unwrap();
+    let app = project.child("app");
+    app.child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2024"
+rust-version = "1.88"
+
+[[bin]]
+name = "legacy"
+path = "src/bin/legacy.rs"
+edition = "2021"
+"#,
+        )
+        .unwrap();
+    app.child("src").child("lib.rs").touch().unwrap();
+    app.child("src")
+        .child("bin")
+        .child("legacy.rs")
+        .touch()
+        .unwrap();
+    project
+}
+
+/// The keys of `[package]` that are left out of the skeleton, since they cannot affect how
+/// dependencies are compiled.
+const COSMETIC_PACKAGE_KEYS: [&str; 10] = [
+    "authors",
+    "categories",
+    "description",
+    "documentation",
+    "homepage",
+    "keywords",
+    "license",
+    "license-file",
+    "readme",
+    "repository",
+];
+
+/// Every key of the table at `path` in the original manifest is in the skeleton, with the same
+/// value, except for `version`, which is masked, and the [`COSMETIC_PACKAGE_KEYS`].
+fn assert_table_is_preserved(
+    skeleton: &Skeleton,
+    project: &TempDir,
+    manifest: &str,
+    path: &[&str],
+) {
+    let contents = skeleton
+        .manifests
+        .iter()
+        .find(|candidate| candidate.relative_path == Path::new(manifest))
+        .unwrap()
+        .contents
+        .parse::<toml::Value>()
+        .unwrap();
+    let original = std::fs::read_to_string(project.child(manifest).path())
+        .unwrap()
+        .parse::<toml::Value>()
+        .unwrap();
+    let table = |manifest: &toml::Value| -> toml::value::Table {
+        let table = path
+            .iter()
+            .fold(manifest.clone(), |value, key| value[key].clone());
+        let mut table = table.as_table().unwrap().clone();
+        table.remove("version");
+        for key in COSMETIC_PACKAGE_KEYS {
+            table.remove(key);
+        }
+        table
+    };
+    let (original, contents) = (table(&original), table(&contents));
+    for (key, value) in &original {
+        assert_eq!(
+            Some(value),
+            contents.get(key),
+            "`{}` in {}:[{}]",
+            key,
+            manifest,
+            path.join(".")
+        );
+    }
+}
+
+#[test]
+pub fn resolution_keys_are_preserved() {
+    // Arrange
+    let project = workspace_with_resolution_keys();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    assert_table_is_preserved(&skeleton, &project, "Cargo.toml", &["workspace", "package"]);
+    assert_table_is_preserved(&skeleton, &project, "sys/Cargo.toml", &["package"]);
+    assert_table_is_preserved(&skeleton, &project, "app/Cargo.toml", &["package"]);
+}
+
+#[test]
+pub fn resolver_3_is_preserved() {
+    // Arrange
+    let project = workspace_with_resolution_keys();
+    let root = project.child("Cargo.toml");
+    let contents = std::fs::read_to_string(root.path()).unwrap();
+    root.write_str(&contents.replace("[workspace]\n", "[workspace]\nresolver = \"3\"\n"))
+        .unwrap();
+    let app = project.child("app").child("Cargo.toml");
+    let contents = std::fs::read_to_string(app.path()).unwrap();
+    app.write_str(&contents.replace("[package]\n", "[package]\nresolver = \"3\"\n"))
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let root: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    assert_eq!(root["workspace"]["resolver"].as_str(), Some("3"));
+    assert_table_is_preserved(&skeleton, &project, "Cargo.toml", &["workspace", "package"]);
+    assert_table_is_preserved(&skeleton, &project, "app/Cargo.toml", &["package"]);
+    assert_table_is_preserved(&skeleton, &project, "sys/Cargo.toml", &["package"]);
+}
+
+/// A workspace whose member sets the [`COSMETIC_PACKAGE_KEYS`], directly or through
+/// inheritance.
+fn workspace_with_cosmetic_keys(description: &str, repository: &str) -> TempDir {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(&format!(
+            r#"
+[workspace]
+members = ["app"]
+
+[workspace.package]
+authors = ["Jane Doe <jane@example.com>"]
+repository = "{}"
+license = "MIT"
+"#,
+            repository
+        ))
+        .unwrap();
+    project
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(&format!(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+rust-version = "1.70"
+links = "app"
+build = "build.rs"
+publish = false
+description = "{}"
+documentation = "https://docs.rs/app"
+homepage = "https://example.com"
+readme = "README.md"
+keywords = ["app"]
+categories = ["command-line-utilities"]
+license-file = "LICENSE"
+authors.workspace = true
+repository.workspace = true
+license.workspace = true
+
+[dependencies]
+anyhow = "1"
+"#,
+            description
+        ))
+        .unwrap();
+    project
+        .child("app")
+        .child("build.rs")
+        .write_str("fn main() {}")
+        .unwrap();
+    project
+        .child("app")
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    project
+}
+
+#[test]
+pub fn cosmetic_package_keys_are_left_out() {
+    // Arrange
+    let project = workspace_with_cosmetic_keys("An app.", "https://github.com/a/app");
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let root: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    let app: toml::Value = skeleton.manifests[1].contents.parse().unwrap();
+    for package in [&root["workspace"]["package"], &app["package"]] {
+        for key in COSMETIC_PACKAGE_KEYS {
+            assert!(package.get(key).is_none(), "`{}` in {}", key, package);
+        }
+    }
+    let kept = [
+        "name",
+        "version",
+        "edition",
+        "rust-version",
+        "links",
+        "build",
+        "publish",
+    ];
+    for key in kept {
+        assert!(app["package"].get(key).is_some(), "`{}` in {}", key, app);
+    }
+    assert_table_is_preserved(&skeleton, &project, "app/Cargo.toml", &["package"]);
+}
+
+#[test]
+pub fn editing_cosmetic_package_keys_does_not_change_the_recipe() {
+    // Arrange
+    let before = workspace_with_cosmetic_keys("An app.", "https://github.com/a/app");
+    let after = workspace_with_cosmetic_keys("A better app.", "https://gitlab.com/a/app");
+
+    // Act
+    let before = Recipe::prepare(before.path().into(), PrepareArgs::default()).unwrap();
+    let after = Recipe::prepare(after.path().into(), PrepareArgs::default()).unwrap();
+
+    // Assert
+    assert_eq!(
+        serde_json::to_string(&before).unwrap(),
+        serde_json::to_string(&after).unwrap()
+    );
+}
+
+#[test]
+pub fn artifact_dependencies_are_preserved() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["app", "tool"]
+
+[workspace.dependencies]
+tool = { path = "tool", version = "0.1.0", artifact = "bin" }
+"#,
+        )
+        .unwrap();
+    let app = r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+tool = { path = "../tool", version = "0.1.0", artifact = "bin", target = "wasm32-unknown-unknown", lib = true }
+
+[build-dependencies]
+tool = { path = "../tool", version = "0.1.0", artifact = ["bin:tool", "cdylib"], target = "target" }
+
+[target.'cfg(unix)'.dev-dependencies]
+tool = { workspace = true, artifact = "bin" }
+"#;
+    project
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(app)
+        .unwrap();
+    project
+        .child("app")
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    project
+        .child("tool")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "tool"
+version = "0.1.0"
+edition = "2021"
+"#,
+        )
+        .unwrap();
+    project
+        .child("tool")
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let root: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    let tool = &root["workspace"]["dependencies"]["tool"];
+    assert_eq!(tool["artifact"].as_str(), Some("bin"));
+    assert_eq!(tool["version"].as_str(), Some("0.0.1"));
+    let manifest: toml::Value = skeleton.manifests[1].contents.parse().unwrap();
+    let mut original: toml::Value = app.parse().unwrap();
+    // The local dependency is masked like any other.
+    for dependencies in ["dependencies", "build-dependencies"] {
+        original[dependencies]["tool"]["version"] = toml::Value::String("0.0.1".to_owned());
+        assert_eq!(original[dependencies], manifest[dependencies]);
+    }
+    let cfg_unix =
+        |manifest: &toml::Value| manifest["target"]["cfg(unix)"]["dev-dependencies"].clone();
+    assert_eq!(cfg_unix(&original), cfg_unix(&manifest));
+}
+
+#[test]
+pub fn targets_keep_their_edition() {
+    // Arrange
+    let project = workspace_with_resolution_keys();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let app = skeleton
+        .manifests
+        .iter()
+        .find(|manifest| manifest.relative_path == Path::new("app/Cargo.toml"))
+        .unwrap();
+    let app: toml::Value = app.contents.parse().unwrap();
+    assert_eq!(app["lib"]["edition"].as_str(), Some("2024"));
+    let bins = app["bin"].as_array().unwrap();
+    let legacy = bins
+        .iter()
+        .find(|bin| bin["name"].as_str() == Some("legacy"));
+    assert_eq!(legacy.unwrap()["edition"].as_str(), Some("2021"));
+}
+
+/// A workspace with a `workspace-hack` crate generated by `cargo hakari`, depending on
+/// `hack_dependencies`, that all the members depend on.
+fn workspace_with_workspace_hack(hack_version: &str, hack_dependencies: &str) -> TempDir {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["app", "workspace-hack"]
+
+[workspace.dependencies]
+workspace-hack = { version = "0.1", path = "workspace-hack" }
+"#,
+        )
+        .unwrap();
+    project
+        .child("workspace-hack")
+        .child("Cargo.toml")
+        .write_str(&format!(
+            r#"
+[package]
+name = "workspace-hack"
+version = "{}"
+edition = "2021"
+publish = false
+
+### BEGIN HAKARI SECTION
+{}
+### END HAKARI SECTION
+"#,
+            hack_version, hack_dependencies
+        ))
+        .unwrap();
+    project
+        .child("workspace-hack")
+        .child("src")
+        .child("lib.rs")
+        .write_str("// This is a stub lib.rs.\n")
+        .unwrap();
+    project
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+serde = "1"
+workspace-hack.workspace = true
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    project
+        .child("Cargo.lock")
+        .write_str(&format!(
+            r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "serde",
+ "workspace-hack",
+]
+
+[[package]]
+name = "serde"
+version = "1.0.197"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "3fb1c873e1b9b056a4dc4c0c198b24c3ffa059243875552b2bd0933b1aee4ce2"
+
+[[package]]
+name = "workspace-hack"
+version = "{}"
+dependencies = [
+ "serde",
+]
+"#,
+            hack_version
+        ))
+        .unwrap();
+    project
+}
+
+const HAKARI_DEPENDENCIES: &str = r#"
+[dependencies]
+serde = { version = "1", features = ["derive", "std"] }
+
+[build-dependencies]
+serde = { version = "1", default-features = false, features = ["std"] }
+
+[target.x86_64-unknown-linux-gnu.dependencies]
+serde = { version = "1", features = ["rc"] }
+"#;
+
+#[test]
+pub fn the_dependencies_of_a_workspace_hack_are_kept_and_its_version_masked() {
+    // Arrange
+    let project = workspace_with_workspace_hack("0.1.0", HAKARI_DEPENDENCIES);
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let hack = skeleton
+        .manifests
+        .iter()
+        .find(|manifest| manifest.relative_path == Path::new("workspace-hack/Cargo.toml"))
+        .unwrap();
+    let hack: toml::Value = hack.contents.parse().unwrap();
+    let original: toml::Value = HAKARI_DEPENDENCIES.parse().unwrap();
+    assert_eq!(original["dependencies"], hack["dependencies"]);
+    assert_eq!(original["build-dependencies"], hack["build-dependencies"]);
+    let linux = |manifest: &toml::Value| {
+        manifest["target"]["x86_64-unknown-linux-gnu"]["dependencies"].clone()
+    };
+    assert_eq!(linux(&original), linux(&hack));
+    assert_eq!(hack["package"]["version"].as_str(), Some("0.0.1"));
+
+    let root: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    let dependency = &root["workspace"]["dependencies"]["workspace-hack"];
+    assert_eq!(dependency["version"].as_str(), Some("0.0.1"));
+    let lock_file: toml::Value = skeleton.lock_file.unwrap().parse().unwrap();
+    let locked_hack = lock_file["package"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|package| package["name"].as_str() == Some("workspace-hack"))
+        .unwrap();
+    assert_eq!(locked_hack["version"].as_str(), Some("0.0.1"));
+}
+
+#[test]
+pub fn only_the_dependencies_of_a_workspace_hack_invalidate_the_skeleton() {
+    // Arrange
+    let hack = workspace_with_workspace_hack("0.1.0", HAKARI_DEPENDENCIES);
+    let bumped = workspace_with_workspace_hack("0.1.1", HAKARI_DEPENDENCIES);
+    let regenerated = workspace_with_workspace_hack(
+        "0.1.0",
+        &HAKARI_DEPENDENCIES.replace(r#"["derive", "std"]"#, r#"["derive", "std", "alloc"]"#),
+    );
+
+    // Act
+    let derive = |project: &TempDir| Skeleton::derive(project.path(), &PrepareArgs::default());
+    let hack = derive(&hack).unwrap();
+
+    // Assert
+    assert_eq!(hack, derive(&bumped).unwrap());
+    assert_ne!(hack, derive(&regenerated).unwrap());
+}
+
+#[test]
+pub fn a_workspace_hack_is_kept_when_scoping_to_a_member() {
+    // Arrange
+    let project = workspace_with_workspace_hack("0.1.0", HAKARI_DEPENDENCIES);
+    let args = PrepareArgs {
+        members: vec!["app".to_owned()],
+        ..Default::default()
+    };
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &args).unwrap();
+
+    // Assert
+    let paths: Vec<_> = skeleton
+        .manifests
+        .iter()
+        .map(|manifest| manifest.relative_path.clone())
+        .collect();
+    assert!(
+        paths.contains(&"workspace-hack/Cargo.toml".into()),
+        "{:?}",
+        paths
+    );
+    let lock_file = skeleton.lock_file.unwrap();
+    assert!(
+        lock_file.contains(r#"name = "workspace-hack""#),
+        "{}",
+        lock_file
+    );
+}
+
+#[test]
+pub fn locked_requires_a_lockfile() {
+    // Arrange
+    let content = r#"
+[package]
+name = "test-dummy"
+version = "0.1.0"
+edition = "2018"
+"#;
+
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(content)
+        .unwrap();
+    recipe_directory
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    let locked = PrepareArgs {
+        locked: true,
+        ..Default::default()
+    };
+
+    // Act
+    let without_lockfile = Skeleton::derive(recipe_directory.path(), &locked);
+    recipe_directory
+        .child("Cargo.lock")
+        .write_str(
+            r#"
+version = 3
+
+[[package]]
+name = "test-dummy"
+version = "0.1.0"
+"#,
+        )
+        .unwrap();
+    let with_lockfile = Skeleton::derive(recipe_directory.path(), &locked);
+
+    // Assert
+    let error = without_lockfile.unwrap_err().to_string();
+    assert!(error.contains("no Cargo.lock file was found"), "{}", error);
+    assert!(with_lockfile.unwrap().lock_file.is_some());
+    // Without `--locked`, the lockfile stays optional.
+    std::fs::remove_file(recipe_directory.child("Cargo.lock").path()).unwrap();
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+    assert!(skeleton.lock_file.is_none());
+}
+
+#[test]
+pub fn alternative_registries() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    project
+        .child(".cargo")
+        .child("config.toml")
+        .write_str(
+            r#"
+[registries.internal]
+index = "sparse+https://registry.internal/index/"
+"#,
+        )
+        .unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["app", "utils", "patched"]
+
+[workspace.dependencies]
+internal-ws = { version = "1.0", registry = "internal" }
+
+[patch.internal]
+patched = { path = "patched" }
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+internal-a = { version = "1.2", registry = "internal" }
+internal-b = { version = "2", registry-index = "sparse+https://registry.internal/index/" }
+internal-ws = { workspace = true }
+# Not the local `utils` crate: it comes from the alternative registry.
+utils = { version = "2.0", registry = "internal" }
+# The local `patched` crate, patched in for the alternative registry.
+patched = { version = "0.3", registry = "internal" }
+
+[target.'cfg(unix)'.dependencies]
+internal-c = { version = "3", registry = "internal" }
+"#,
+        )
+        .unwrap();
+    for (name, version) in [("utils", "0.1.0"), ("patched", "0.3.0")] {
+        let member = project.child(name);
+        member
+            .child("Cargo.toml")
+            .write_str(&format!(
+                r#"
+[package]
+name = "{}"
+version = "{}"
+edition = "2018"
+publish = ["internal"]
+"#,
+                name, version
+            ))
+            .unwrap();
+        member.child("src").child("lib.rs").touch().unwrap();
+    }
+    project
+        .child("app")
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    let registry = "sparse+https://registry.internal/index/";
+    project
+        .child("Cargo.lock")
+        .write_str(&format!(
+            r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "internal-a",
+ "internal-b",
+ "internal-c",
+ "internal-ws",
+ "patched",
+ "utils 2.0.1",
+]
+
+[[package]]
+name = "internal-a"
+version = "1.2.0"
+source = "{0}"
+checksum = "6f1b6dd01e1da6e8071e18e1b1c1c6fd2bbd7e0e6bc43b1b9b3d7c8a0c3b1b9a"
+
+[[package]]
+name = "internal-b"
+version = "2.0.0"
+source = "{0}"
+
+[[package]]
+name = "internal-c"
+version = "3.0.0"
+source = "registry+https://git.registry.internal/index"
+
+[[package]]
+name = "internal-ws"
+version = "1.0.4"
+source = "{0}"
+
+[[package]]
+name = "patched"
+version = "0.3.0"
+
+[[package]]
+name = "utils"
+version = "0.1.0"
+
+[[package]]
+name = "utils"
+version = "2.0.1"
+source = "{0}"
+"#,
+            registry
+        ))
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let manifest = |path: &str| -> toml::Value {
+        let contents = std::fs::read_to_string(cook_directory.child(path).path()).unwrap();
+        contents.parse().unwrap()
+    };
+    let app = manifest("app/Cargo.toml");
+    let dependency = |key: &str| app["dependencies"][key].clone();
+    let inline = |table: &str| -> toml::Value {
+        let document: toml::Value = format!("value = {}", table).parse().unwrap();
+        document["value"].clone()
+    };
+    assert_eq!(
+        inline(r#"{ registry = "internal", version = "1.2" }"#),
+        dependency("internal-a")
+    );
+    assert_eq!(
+        inline(r#"{ registry-index = "sparse+https://registry.internal/index/", version = "2" }"#),
+        dependency("internal-b")
+    );
+    assert_eq!(inline(r#"{ workspace = true }"#), dependency("internal-ws"));
+    assert_eq!(
+        inline(r#"{ registry = "internal", version = "2.0" }"#),
+        dependency("utils")
+    );
+    assert_eq!(
+        inline(r#"{ registry = "internal", version = "0.0.1" }"#),
+        dependency("patched")
+    );
+    assert_eq!(
+        inline(r#"{ registry = "internal", version = "3" }"#),
+        app["target"]["cfg(unix)"]["dependencies"]["internal-c"]
+    );
+    let root = manifest("Cargo.toml");
+    assert_eq!(
+        inline(r#"{ registry = "internal", version = "1.0" }"#),
+        root["workspace"]["dependencies"]["internal-ws"]
+    );
+    assert_eq!(
+        inline(r#"{ path = "patched" }"#),
+        root["patch"]["internal"]["patched"]
+    );
+    assert!(cook_directory
+        .child(".cargo")
+        .child("config.toml")
+        .path()
+        .exists());
+
+    let lock_file: toml::Value = std::fs::read_to_string(cook_directory.child("Cargo.lock").path())
+        .unwrap()
+        .parse()
+        .unwrap();
+    let packages: Vec<String> = lock_file["package"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|package| {
+            format!(
+                "{} {} {}",
+                package["name"].as_str().unwrap(),
+                package["version"].as_str().unwrap(),
+                package
+                    .get("source")
+                    .and_then(|source| source.as_str())
+                    .unwrap_or("(local)")
+            )
+        })
+        .collect();
+    check(
+        &packages.join("\n"),
+        expect_test::expect![[r#"
+        app 0.0.1 (local)
+        internal-a 1.2.0 sparse+https://registry.internal/index/
+        internal-b 2.0.0 sparse+https://registry.internal/index/
+        internal-c 3.0.0 registry+https://git.registry.internal/index
+        internal-ws 1.0.4 sparse+https://registry.internal/index/
+        patched 0.0.1 (local)
+        utils 0.0.1 (local)
+        utils 2.0.1 sparse+https://registry.internal/index/"#]],
+    );
+}
+
+#[test]
+pub fn git_dependencies() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["a", "b", "shared"]
+"#,
+        )
+        .unwrap();
+    let members = [
+        (
+            "a",
+            r#"
+[package]
+name = "a"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+tool = { git = "https://github.com/org/tool", rev = "1111111" }
+# Not the local `shared` crate: it comes from a git repository.
+shared = { git = "https://github.com/org/shared", tag = "v2.0.0", version = "2.0" }
+"#,
+        ),
+        (
+            "b",
+            r#"
+[package]
+name = "b"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+tool = { git = "https://github.com/org/tool", rev = "2222222" }
+helper = { git = "https://github.com/org/tool", branch = "next" }
+shared = { path = "../shared", version = "0.5.0" }
+"#,
+        ),
+        (
+            "shared",
+            r#"
+[package]
+name = "shared"
+version = "0.5.0"
+edition = "2018"
+"#,
+        ),
+    ];
+    for (name, manifest) in members {
+        let member = project.child(name);
+        member.child("Cargo.toml").write_str(manifest).unwrap();
+        member.child("src").child("lib.rs").touch().unwrap();
+    }
+    let lock_file = r#"
+version = 3
+
+[[package]]
+name = "a"
+version = "0.1.0"
+dependencies = [
+ "shared 2.0.0",
+ "tool 0.1.0 (git+https://github.com/org/tool?rev=1111111#1111111a3fa2b4f61b8d7d0b9e5b0e8e2f0a7c11)",
+]
+
+[[package]]
+name = "b"
+version = "0.1.0"
+dependencies = [
+ "helper",
+ "shared 0.5.0",
+ "tool 0.2.0 (git+https://github.com/org/tool?rev=2222222#2222222b4e1c9f0a7d3e6b8c5a9f1e0d2c4b6a22)",
+]
+
+[[package]]
+name = "helper"
+version = "0.3.0"
+source = "git+https://github.com/org/tool?branch=next#3333333c5f2d0a1b8e4f7c9d6b0a2f1e3d5c7b33"
+
+[[package]]
+name = "shared"
+version = "0.5.0"
+
+[[package]]
+name = "shared"
+version = "2.0.0"
+source = "git+https://github.com/org/shared?tag=v2.0.0#4444444d6a3e1b2c9f5a8d0e7c1b3a2f4e6d8c44"
+
+[[package]]
+name = "tool"
+version = "0.1.0"
+source = "git+https://github.com/org/tool?rev=1111111#1111111a3fa2b4f61b8d7d0b9e5b0e8e2f0a7c11"
+
+[[package]]
+name = "tool"
+version = "0.2.0"
+source = "git+https://github.com/org/tool?rev=2222222#2222222b4e1c9f0a7d3e6b8c5a9f1e0d2c4b6a22"
+"#;
+    project.child("Cargo.lock").write_str(lock_file).unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let dependencies = |path: &str| -> toml::Value {
+        let manifest = skeleton
+            .manifests
+            .iter()
+            .find(|manifest| manifest.relative_path == Path::new(path))
+            .unwrap();
+        let contents: toml::Value = manifest.contents.parse().unwrap();
+        contents["dependencies"].clone()
+    };
+    let original_dependencies = |name: &str| -> toml::Value {
+        let (_, manifest) = members.iter().find(|(member, _)| *member == name).unwrap();
+        let contents: toml::Value = manifest.parse().unwrap();
+        contents["dependencies"].clone()
+    };
+    // Git dependencies are carried over untouched, even if they share their name with a local
+    // crate.
+    assert_eq!(original_dependencies("a"), dependencies("a/Cargo.toml"));
+    let b = dependencies("b/Cargo.toml");
+    assert_eq!(original_dependencies("b")["tool"], b["tool"]);
+    assert_eq!(original_dependencies("b")["helper"], b["helper"]);
+    assert_eq!(Some("0.0.1"), b["shared"]["version"].as_str());
+
+    // The lockfile keeps all git pins, only the versions of local crates are masked.
+    let expected_lock_file = lock_file
+        .replace("\"a\"\nversion = \"0.1.0\"", "\"a\"\nversion = \"0.0.1\"")
+        .replace("\"b\"\nversion = \"0.1.0\"", "\"b\"\nversion = \"0.0.1\"")
+        .replace(
+            "\"shared\"\nversion = \"0.5.0\"",
+            "\"shared\"\nversion = \"0.0.1\"",
+        )
+        .replace("\"shared 0.5.0\"", "\"shared 0.0.1\"");
+    let expected_lock_file: toml::Value = expected_lock_file.parse().unwrap();
+    let actual_lock_file: toml::Value = skeleton.lock_file.unwrap().parse().unwrap();
+    assert_eq!(expected_lock_file, actual_lock_file);
+}
+
+#[test]
+pub fn no_dev_dependencies() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["app"]
+
+[workspace.dependencies]
+serde = "1"
+proptest = "1"
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+itoa = "1"
+rand = "0.7"
+serde = { workspace = true }
+
+[dev-dependencies]
+itoa = "1"
+rand = "0.8"
+proptest = { workspace = true }
+
+[target.'cfg(unix)'.dev-dependencies]
+memchr = "2"
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    let registry = "registry+https://github.com/rust-lang/crates.io-index";
+    project
+        .child("Cargo.lock")
+        .write_str(&format!(
+            r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "itoa",
+ "memchr",
+ "proptest",
+ "rand 0.7.3",
+ "rand 0.8.5",
+ "serde",
+]
+
+[[package]]
+name = "getrandom"
+version = "0.1.16"
+source = "{0}"
+
+[[package]]
+name = "getrandom"
+version = "0.2.10"
+source = "{0}"
+
+[[package]]
+name = "itoa"
+version = "1.0.9"
+source = "{0}"
+
+[[package]]
+name = "memchr"
+version = "2.6.3"
+source = "{0}"
+
+[[package]]
+name = "proptest"
+version = "1.2.0"
+source = "{0}"
+dependencies = [
+ "rand 0.8.5",
+]
+
+[[package]]
+name = "rand"
+version = "0.7.3"
+source = "{0}"
+dependencies = [
+ "getrandom 0.1.16",
+]
+
+[[package]]
+name = "rand"
+version = "0.8.5"
+source = "{0}"
+dependencies = [
+ "getrandom 0.2.10",
+]
+
+[[package]]
+name = "serde"
+version = "1.0.188"
+source = "{0}"
+"#,
+            registry
+        ))
+        .unwrap();
+    let args = PrepareArgs {
+        no_dev_dependencies: true,
+        ..Default::default()
+    };
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &args).unwrap();
+
+    // Assert
+    let manifest = |path: &str| -> toml::Value {
+        let manifest = skeleton
+            .manifests
+            .iter()
+            .find(|manifest| manifest.relative_path == Path::new(path))
+            .unwrap();
+        manifest.contents.parse().unwrap()
+    };
+    let app = manifest("app/Cargo.toml");
+    assert!(app.get("dev-dependencies").is_none());
+    assert!(app["target"]["cfg(unix)"].get("dev-dependencies").is_none());
+    assert_eq!(
+        vec!["itoa", "rand", "serde"],
+        app["dependencies"]
+            .as_table()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>()
+    );
+    // Only inherited by a dev-dependency.
+    let root = manifest("Cargo.toml");
+    assert_eq!(
+        vec!["serde"],
+        root["workspace"]["dependencies"]
+            .as_table()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>()
+    );
+    check(
+        skeleton.lock_file.as_ref().unwrap(),
+        expect_test::expect![[r#"
+            version = 3
+
+            [[package]]
+            name = "app"
+            version = "0.0.1"
+            dependencies = ["itoa", "rand 0.7.3", "serde"]
+
+            [[package]]
+            name = "getrandom"
+            version = "0.1.16"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "itoa"
+            version = "1.0.9"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "rand"
+            version = "0.7.3"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            dependencies = ["getrandom 0.1.16"]
+
+            [[package]]
+            name = "serde"
+            version = "1.0.188"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+        "#]],
+    );
+
+    // Dev-dependencies are kept by default.
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+    assert!(skeleton.lock_file.unwrap().contains("proptest"));
+}
+
+#[test]
+pub fn specify_member_leaves_out_unrelated_members() {
+    // Arrange
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["services/*", "libs/*"]
+default-members = ["services/api", "services/billing"]
+exclude = ["tools", "legacy"]
+
+[workspace.package]
+version = "0.3.0"
+edition = "2018"
+
+[workspace.dependencies]
+common = { path = "libs/common" }
+serde = "1"
+"#,
+        )
+        .unwrap();
+    let manifests = [
+        (
+            "services/api",
+            "api",
+            "common = { workspace = true }\ncodegen = { path = \"../../tools/codegen\" }",
+        ),
+        (
+            "services/billing",
+            "billing",
+            "ledger = { path = \"../../libs/ledger\" }",
+        ),
+        ("libs/common", "common", "serde = { workspace = true }"),
+        ("libs/ledger", "ledger", ""),
+    ];
+    for (directory, name, dependencies) in manifests {
+        let crate_dir = recipe_directory.child(directory);
+        crate_dir
+            .child("Cargo.toml")
+            .write_str(&format!(
+                r#"
+[package]
+name = "{}"
+version.workspace = true
+edition.workspace = true
+
+[dependencies]
+{}
+"#,
+                name, dependencies
+            ))
+            .unwrap();
+        crate_dir.child("src").child("lib.rs").touch().unwrap();
+    }
+    // Excluded from the workspace, it cannot inherit from it.
+    let codegen = recipe_directory.child("tools").child("codegen");
+    codegen
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "codegen"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .unwrap();
+    codegen.child("src").child("lib.rs").touch().unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &members(&["api"])).unwrap();
+
+    // Assert
+    let paths: Vec<_> = skeleton
+        .manifests
+        .iter()
+        .map(|manifest| manifest.relative_path.to_str().unwrap())
+        .collect();
+    assert_eq!(
+        vec![
+            "Cargo.toml",
+            "libs/common/Cargo.toml",
+            "services/api/Cargo.toml",
+            "tools/codegen/Cargo.toml"
+        ],
+        paths
+    );
+    check(
+        &skeleton.manifests[0].contents,
+        expect_test::expect![[r#"
+            [workspace]
+            default-members = ["services/api"]
+            exclude = ["tools"]
+            members = ["libs/common", "services/api"]
+
+            [workspace.dependencies]
+            serde = "1"
+
+            [workspace.dependencies.common]
+            path = "libs/common"
+
+            [workspace.package]
+            edition = "2018"
+            version = "0.0.1"
+        "#]],
+    );
+
+    // Editing an unrelated member does not change the skeleton.
+    recipe_directory
+        .child("libs")
+        .child("ledger")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "ledger"
+version = "0.4.0"
+edition = "2018"
+
+[dependencies]
+itoa = "1"
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        skeleton,
+        Skeleton::derive(recipe_directory.path(), &members(&["api"])).unwrap()
+    );
+}
+
+#[test]
+pub fn specify_member_reports_workspace_lists_that_are_not_arrays() {
+    for key in &["default-members", "exclude"] {
+        // Arrange
+        let recipe_directory = TempDir::new().unwrap();
+        recipe_directory
+            .child("Cargo.toml")
+            .write_str(&format!(
+                "[workspace]\nmembers = [\"api\"]\n{} = \"api\"\n",
+                key
+            ))
+            .unwrap();
+        recipe_directory
+            .child("api")
+            .child("Cargo.toml")
+            .write_str("[package]\nname = \"api\"\nversion = \"0.1.0\"\nedition = \"2018\"\n")
+            .unwrap();
+        recipe_directory
+            .child("api")
+            .child("src")
+            .child("lib.rs")
+            .touch()
+            .unwrap();
+
+        // Act
+        let result = Skeleton::derive(recipe_directory.path(), &members(&["api"]));
+
+        // Assert
+        let error = format!("{:#}", result.unwrap_err());
+        assert!(error.contains(&format!("`workspace.{}`", key)), "{}", error);
+    }
+}
+
+#[test]
+pub fn prune_lockfile_packages_of_unselected_members() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["app", "other", "shared"]
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+shared = { path = "../shared" }
+serde = { version = "1", features = ["derive"] }
+
+[build-dependencies]
+cc = "1"
+"#,
+        )
+        .unwrap();
+    project
+        .child("app")
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    project
+        .child("other")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "other"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+itoa = "1"
+memchr = "2"
+"#,
+        )
+        .unwrap();
+    project
+        .child("other")
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    project
+        .child("shared")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "shared"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+itoa = "1"
+"#,
+        )
+        .unwrap();
+    project
+        .child("shared")
+        .child("src")
+        .child("lib.rs")
+        .touch()
+        .unwrap();
+    let registry = "registry+https://github.com/rust-lang/crates.io-index";
+    project
+        .child("Cargo.lock")
+        .write_str(&format!(
+            r#"
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "cc",
+ "serde",
+ "shared",
+]
+
+[[package]]
+name = "cc"
+version = "1.0.83"
+source = "{0}"
+
+[[package]]
+name = "itoa"
+version = "1.0.9"
+source = "{0}"
+
+[[package]]
+name = "memchr"
+version = "2.6.3"
+source = "{0}"
+
+[[package]]
+name = "other"
+version = "0.1.0"
+dependencies = [
+ "itoa",
+ "memchr",
+]
+
+[[package]]
+name = "serde"
+version = "1.0.188"
+source = "{0}"
+dependencies = [
+ "serde_derive",
+]
+
+[[package]]
+name = "serde_derive"
+version = "1.0.188"
+source = "{0}"
+
+[[package]]
+name = "shared"
+version = "0.1.0"
+dependencies = [
+ "itoa",
+]
+
+[metadata]
+"checksum cc 1.0.83 ({0})" = "aaaa"
+"checksum itoa 1.0.9 ({0})" = "bbbb"
+"checksum memchr 2.6.3 ({0})" = "cccc"
+"checksum serde 1.0.188 ({0})" = "dddd"
+"checksum serde_derive 1.0.188 ({0})" = "eeee"
+"#,
+            registry
+        ))
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &members(&["app"])).unwrap();
+
+    // Assert
+    check(
+        skeleton.lock_file.as_ref().unwrap(),
+        expect_test::expect![[r#"
+            [[package]]
+            name = "app"
+            version = "0.0.1"
+            dependencies = ["cc", "serde", "shared"]
+
+            [[package]]
+            name = "cc"
+            version = "1.0.83"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "itoa"
+            version = "1.0.9"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "serde"
+            version = "1.0.188"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            dependencies = ["serde_derive"]
+
+            [[package]]
+            name = "serde_derive"
+            version = "1.0.188"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "shared"
+            version = "0.0.1"
+            dependencies = ["itoa"]
+
+            [metadata]
+            "checksum cc 1.0.83 (registry+https://github.com/rust-lang/crates.io-index)" = "aaaa"
+            "checksum itoa 1.0.9 (registry+https://github.com/rust-lang/crates.io-index)" = "bbbb"
+            "checksum serde 1.0.188 (registry+https://github.com/rust-lang/crates.io-index)" = "dddd"
+            "checksum serde_derive 1.0.188 (registry+https://github.com/rust-lang/crates.io-index)" = "eeee"
+        "#]],
+    );
+
+    // The whole lockfile is kept when pruning is disabled.
+    let args = PrepareArgs {
+        no_lockfile_pruning: true,
+        ..members(&["app"])
+    };
+    let skeleton = Skeleton::derive(project.path(), &args).unwrap();
+    let lock_file = skeleton.lock_file.unwrap();
+    assert!(lock_file.contains("memchr"));
+    assert!(lock_file.contains("name = \"other\""));
+}
+
+#[test]
+pub fn excluded_directories_and_nested_workspaces() {
+    // Arrange
+    let recipe_directory = TempDir::new().unwrap();
+    let package = |name: &str, edition: &str, extra: &str| {
+        format!(
+            r#"
+[package]
+name = "{}"
+version = "0.1.0"
+edition = "{}"
+{}
+"#,
+            name, edition, extra
+        )
+    };
+    let files = [
+        (
+            "Cargo.toml",
+            r#"
+[workspace]
+members = ["app"]
+exclude = ["tools", "third-party"]
+"#
+            .to_owned(),
+        ),
+        (
+            "app/Cargo.toml",
+            package(
+                "app",
+                "2018",
+                "[dependencies]\nfork = { path = \"../third-party/fork\" }",
+            ),
+        ),
+        // Excluded, but needed as a path dependency.
+        ("third-party/fork/Cargo.toml", package("fork", "2018", "")),
+        // An independent workspace: it is never parsed.
+        (
+            "tools/Cargo.toml",
+            "[workspace]\nmembers = [\"gen\"]\n".to_owned(),
+        ),
+        ("tools/gen/Cargo.toml", package("gen", "2024", "")),
+        // A nested workspace that is not explicitly excluded.
+        (
+            "examples/standalone/Cargo.toml",
+            package("standalone", "2018", "[workspace]"),
+        ),
+    ];
+    for (path, contents) in files.iter() {
+        recipe_directory.child(path).write_str(contents).unwrap();
+    }
+    for directory in [
+        "app",
+        "third-party/fork",
+        "tools/gen",
+        "examples/standalone",
+    ] {
+        recipe_directory
+            .child(directory)
+            .child("src")
+            .child("lib.rs")
+            .touch()
+            .unwrap();
+    }
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let paths: Vec<_> = skeleton
+        .manifests
+        .iter()
+        .map(|manifest| manifest.relative_path.to_str().unwrap())
+        .collect();
+    assert_eq!(
+        vec![
+            "Cargo.toml",
+            "app/Cargo.toml",
+            "third-party/fork/Cargo.toml"
+        ],
+        paths
+    );
+
+    // Preparing from the nested workspace itself is still possible.
+    let standalone_skeleton = Skeleton::derive(
+        recipe_directory.child("examples/standalone").path(),
+        &PrepareArgs::default(),
+    )
+    .unwrap();
+    assert_eq!(1, standalone_skeleton.manifests.len());
+}
+
+#[test]
+pub fn member_globs_are_expanded_into_a_sorted_list() {
+    // Arrange
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["services/*/app", "crates/*"]
+exclude = ["crates/legacy-*"]
+"#,
+        )
+        .unwrap();
+    for name in [
+        "crates/zeta",
+        "crates/alpha",
+        "crates/legacy-db",
+        "services/billing/app",
+    ] {
+        let package = recipe_directory.child(name);
+        package
+            .child("Cargo.toml")
+            .write_str(&format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+                name.replace('/', "-")
+            ))
+            .unwrap();
+        package.child("src").child("lib.rs").touch().unwrap();
+    }
+    // Not a crate: in the real project, cargo ignores it only because it has no manifest.
+    recipe_directory
+        .child("crates")
+        .child("docs")
+        .child("README.md")
+        .touch()
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let root: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    let members: Vec<&str> = root["workspace"]["members"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|member| member.as_str().unwrap())
+        .collect();
+    assert_eq!(
+        vec!["crates/alpha", "crates/zeta", "services/billing/app"],
+        members
+    );
+    assert_eq!(
+        "crates/legacy-*",
+        root["workspace"]["exclude"][0].as_str().unwrap()
+    );
+}
+
+#[test]
+pub fn path_dependencies_outside_of_the_project() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("repo").child("workspace");
+    let package = |name: &str, dependencies: &str| {
+        format!(
+            r#"
+[package]
+name = "{}"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+{}
+"#,
+            name, dependencies
+        )
+    };
+    project
+        .child("Cargo.toml")
+        .write_str("[workspace]\nmembers = [\"crates/app\"]\n")
+        .unwrap();
+    let absolute_path = directory.child("absolute");
+    project
+        .child("crates")
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(&package(
+            "app",
+            &format!(
+                "shared = {{ path = \"../../../../shared\" }}\nabsolute = {{ path = {:?} }}",
+                absolute_path.path()
+            ),
+        ))
+        .unwrap();
+    // `shared` depends on `utils`, also outside of the project.
+    directory
+        .child("shared")
+        .child("Cargo.toml")
+        .write_str(&package("shared", "utils = { path = \"../repo/utils\" }"))
+        .unwrap();
+    directory
+        .child("repo")
+        .child("utils")
+        .child("Cargo.toml")
+        .write_str(&package("utils", ""))
+        .unwrap();
+    absolute_path
+        .child("Cargo.toml")
+        .write_str(&package("absolute", ""))
+        .unwrap();
+    for crate_directory in [
+        project.child("crates").child("app"),
+        directory.child("shared"),
+        directory.child("repo").child("utils"),
+        absolute_path,
+    ] {
+        crate_directory
+            .child("src")
+            .child("lib.rs")
+            .touch()
+            .unwrap();
+    }
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let paths: Vec<_> = skeleton
+        .manifests
+        .iter()
+        .map(|manifest| manifest.relative_path.to_str().unwrap())
+        .collect();
+    assert_eq!(
+        vec![
+            ".cargo-chef/external/absolute/Cargo.toml",
+            ".cargo-chef/external/repo/utils/Cargo.toml",
+            ".cargo-chef/external/shared/Cargo.toml",
+            "Cargo.toml",
+            "crates/app/Cargo.toml",
+        ],
+        paths
+    );
+    let manifest = |path: &str| -> toml::Value {
+        let manifest = skeleton
+            .manifests
+            .iter()
+            .find(|manifest| manifest.relative_path == Path::new(path))
+            .unwrap();
+        manifest.contents.parse().unwrap()
+    };
+    let app = manifest("crates/app/Cargo.toml");
+    assert_eq!(
+        "../../.cargo-chef/external/shared",
+        app["dependencies"]["shared"]["path"].as_str().unwrap()
+    );
+    assert_eq!(
+        "../../.cargo-chef/external/absolute",
+        app["dependencies"]["absolute"]["path"].as_str().unwrap()
+    );
+    // The paths between external crates are left untouched.
+    let shared = manifest(".cargo-chef/external/shared/Cargo.toml");
+    assert_eq!(
+        "../repo/utils",
+        shared["dependencies"]["utils"]["path"].as_str().unwrap()
+    );
+    let root = manifest("Cargo.toml");
+    assert_eq!(
+        vec![".cargo-chef"],
+        root["workspace"]["exclude"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|excluded| excluded.as_str().unwrap())
+            .collect::<Vec<_>>()
+    );
+
+    // A missing external dependency is reported, instead of producing a broken recipe.
+    std::fs::remove_dir_all(directory.child("repo").child("utils").path()).unwrap();
+    let error = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap_err();
+    let message = error.to_string();
+    assert!(message.contains("`utils`"), "{}", message);
+    assert!(message.contains("../../shared/Cargo.toml"), "{}", message);
+}
+
+#[cfg(unix)]
+#[test]
+pub fn symlinked_members_are_followed() {
+    use std::os::unix::fs::symlink;
+
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("workspace");
+    project
+        .child("Cargo.toml")
+        .write_str("[workspace]\nmembers = [\"crates/*\"]\n")
+        .unwrap();
+    let package = |name: &str| {
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+            name
+        )
+    };
+    // A symlinked member directory...
+    let generated = directory.child("generated").child("proto");
+    generated
+        .child("Cargo.toml")
+        .write_str(&package("proto"))
+        .unwrap();
+    generated.child("src").child("lib.rs").touch().unwrap();
+    project.child("crates").create_dir_all().unwrap();
+    symlink(
+        generated.path(),
+        project.child("crates").child("proto").path(),
+    )
+    .unwrap();
+    // ...and a symlinked manifest.
+    let app = project.child("crates").child("app");
+    app.child("src").child("main.rs").touch().unwrap();
+    directory
+        .child("manifests")
+        .child("app.toml")
+        .write_str(&package("app"))
+        .unwrap();
+    symlink(
+        directory.child("manifests").child("app.toml").path(),
+        app.child("Cargo.toml").path(),
+    )
+    .unwrap();
+    // Broken symlinks are skipped.
+    symlink(
+        directory.child("nowhere").path(),
+        project.child("crates").child("broken").path(),
+    )
+    .unwrap();
+    project.child("junk").create_dir_all().unwrap();
+    symlink(
+        directory.child("missing.toml").path(),
+        project.child("junk").child("Cargo.toml").path(),
+    )
+    .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let paths: Vec<_> = skeleton
+        .manifests
+        .iter()
+        .map(|manifest| manifest.relative_path.to_str().unwrap())
+        .collect();
+    assert_eq!(
+        vec![
+            "Cargo.toml",
+            "crates/app/Cargo.toml",
+            "crates/proto/Cargo.toml"
+        ],
+        paths
+    );
+    // The targets are found through the links, and recreated as regular files.
+    for path in [
+        "crates/app/Cargo.toml",
+        "crates/app/src/main.rs",
+        "crates/proto/Cargo.toml",
+        "crates/proto/src/lib.rs",
+    ] {
+        let metadata = std::fs::symlink_metadata(cook_directory.child(path).path()).unwrap();
+        assert!(metadata.is_file(), "{} is not a regular file", path);
+    }
+    assert!(
+        std::fs::symlink_metadata(cook_directory.child("crates/proto").path())
+            .unwrap()
+            .is_dir()
+    );
+}
+
+/// A package, `my-app`, with a dependency whose name shares its prefix, `my-app-utils`.
+fn package_with_similarly_named_dependency() -> Skeleton {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "my-app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+my-app-utils = "1"
+"#,
+        )
+        .unwrap();
+    project.child("src").child("main.rs").touch().unwrap();
+    project.child("src").child("lib.rs").touch().unwrap();
+    Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap()
+}
+
+/// The artifacts cargo leaves in a profile directory after compiling a package, with both a
+/// library and a binary target, relative to the profile directory.
+fn artifacts(package: &str) -> Vec<String> {
+    let name = package.replace('-', "_");
+    vec![
+        format!(".fingerprint/{}-0123456789abcdef/lib-{}", package, name),
+        format!("build/{}-fedcba9876543210/build-script-build", package),
+        format!("deps/lib{}-0123456789abcdef.rlib", name),
+        format!("deps/lib{}-0123456789abcdef.rmeta", name),
+        format!("deps/{}-0123456789abcdef.d", name),
+        format!("deps/{}-0123456789abcdef", name),
+        format!("incremental/{}-0n1ab2cd3ef4g/s-file", name),
+        format!("lib{}.rlib", name),
+        format!("lib{}.d", name),
+        package.to_owned(),
+    ]
+}
+
+fn assert_only_dependency_artifacts_are_left(profile_directory: &assert_fs::fixture::ChildPath) {
+    for artifact in artifacts("my-app") {
+        profile_directory
+            .child(&artifact)
+            .assert(predicate::path::missing());
+    }
+    for artifact in artifacts("my-app-utils") {
+        profile_directory
+            .child(&artifact)
+            .assert(predicate::path::exists());
+    }
+}
+
+fn touch_artifacts(profile_directory: &assert_fs::fixture::ChildPath) {
+    for artifact in artifacts("my-app")
+        .into_iter()
+        .chain(artifacts("my-app-utils"))
+    {
+        profile_directory.child(artifact).touch().unwrap();
+    }
+}
+
+#[test]
+pub fn remove_compiled_dummies_in_debug() {
+    // Arrange
+    let skeleton = package_with_similarly_named_dependency();
+    let cook_directory = TempDir::new().unwrap();
+    let profile_directory = cook_directory.child("target").child("debug");
+    touch_artifacts(&profile_directory);
+
+    // Act
+    skeleton
+        .remove_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Debug,
+            None,
+            None,
+        )
+        .unwrap();
+
+    // Assert
+    assert_only_dependency_artifacts_are_left(&profile_directory);
+}
+
+#[test]
+pub fn remove_compiled_dummies_in_release_for_targets() {
+    // Arrange
+    let skeleton = package_with_similarly_named_dependency();
+    let cook_directory = TempDir::new().unwrap();
+    let target_directory = cook_directory.child("target");
+    let profile_directories = [
+        target_directory.child("release"),
+        target_directory
+            .child("x86_64-unknown-linux-musl")
+            .child("release"),
+        target_directory
+            .child("thumbv7em-none-eabihf")
+            .child("release"),
+    ];
+    for profile_directory in &profile_directories {
+        touch_artifacts(profile_directory);
+    }
+
+    // Act
+    skeleton
+        .remove_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Release,
+            Some(vec![
+                "x86_64-unknown-linux-musl".to_owned(),
+                // A custom target spec file.
+                "thumbv7em-none-eabihf.json".to_owned(),
+            ]),
+            None,
+        )
+        .unwrap();
+
+    // Assert
+    for profile_directory in &profile_directories {
+        assert_only_dependency_artifacts_are_left(profile_directory);
+    }
+}
+
+#[test]
+pub fn remove_compiled_dummies_in_custom_target_directory() {
+    // Arrange
+    let skeleton = package_with_similarly_named_dependency();
+    let cook_directory = TempDir::new().unwrap();
+    let target_directory = TempDir::new().unwrap();
+    let profile_directory = target_directory.child("ci");
+    touch_artifacts(&profile_directory);
+
+    // Act
+    skeleton
+        .remove_compiled_dummies(
+            cook_directory.path(),
+            OptimisationProfile::Other("ci".to_owned()),
+            None,
+            Some(target_directory.path().to_owned()),
+        )
+        .unwrap();
+
+    // Assert
+    assert_only_dependency_artifacts_are_left(&profile_directory);
+}
+
+/// The relative paths of the manifests of a skeleton.
+fn manifest_paths(skeleton: &Skeleton) -> Vec<&str> {
+    skeleton
+        .manifests
+        .iter()
+        .map(|manifest| manifest.relative_path.to_str().unwrap())
+        .collect()
+}
+
+/// A package with deliberately invalid manifests in its test fixtures.
+fn package_with_broken_fixtures() -> TempDir {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .unwrap();
+    project.child("src").child("main.rs").touch().unwrap();
+    project
+        .child("tests/fixtures/broken-workspace/Cargo.toml")
+        .write_str("[workspace\nmembers = ")
+        .unwrap();
+    project
+}
+
+#[test]
+pub fn chefignore_skips_broken_fixtures() {
+    // Arrange
+    let project = package_with_broken_fixtures();
+    project
+        .child(".chefignore")
+        .write_str("# Invalid on purpose\ntests/fixtures/\n")
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    assert_eq!(vec!["Cargo.toml"], manifest_paths(&skeleton));
+}
+
+#[test]
+pub fn broken_manifests_that_are_not_ignored_are_errors() {
+    // Arrange
+    let project = package_with_broken_fixtures();
+
+    // Act
+    let result = Skeleton::derive(project.path(), &PrepareArgs::default());
+
+    // Assert
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(
+        error.contains("tests/fixtures/broken-workspace/Cargo.toml:1:"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+pub fn all_the_invalid_manifests_are_reported_with_their_position() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["crates/*"]
+"#,
+        )
+        .unwrap();
+    project
+        .child("crates/api/Cargo.toml")
+        .write_str(
+            r#"[package]
+name = "api"
+version = 
+"#,
+        )
+        .unwrap();
+    project.child("crates/api/src/lib.rs").touch().unwrap();
+    project
+        .child("crates/cli/Cargo.toml")
+        .write_str(
+            r#"[package]
+name = "cli"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies
+"#,
+        )
+        .unwrap();
+    project.child("crates/cli/src/main.rs").touch().unwrap();
+    project
+        .child("crates/core/Cargo.toml")
+        .write_str(
+            r#"[package]
+name = "core"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .unwrap();
+    project.child("crates/core/src/lib.rs").touch().unwrap();
+
+    // Act
+    let result = Skeleton::derive(project.path(), &PrepareArgs::default());
+
+    // Assert
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(error.contains("Failed to parse 2 manifests"), "{}", error);
+    assert!(error.contains("crates/api/Cargo.toml:3:11: "), "{}", error);
+    assert!(
+        error.contains("3 | version = \n  |           ^"),
+        "{}",
+        error
+    );
+    assert!(error.contains("crates/cli/Cargo.toml:6:"), "{}", error);
+    assert!(error.contains("6 | [dependencies\n"), "{}", error);
+    assert!(!error.contains("crates/core"), "{}", error);
+}
+
+#[test]
+pub fn corrupt_lockfiles_are_errors() {
+    // Arrange
+    let project = package_with_broken_fixtures();
+    std::fs::remove_dir_all(project.child("tests").path()).unwrap();
+    project
+        .child("Cargo.lock")
+        .write_str("version = 3\n\n[[package]\nname = \"app\"\n")
+        .unwrap();
+
+    // Act
+    let result = Skeleton::derive(project.path(), &PrepareArgs::default());
+
+    // Assert
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(error.contains("Cargo.lock is corrupt"), "{}", error);
+    assert!(error.contains("Cargo.lock:3:"), "{}", error);
+    assert!(error.contains("3 | [[package]\n"), "{}", error);
+}
+
+#[test]
+pub fn lockfiles_with_an_unknown_version_are_errors_when_strict() {
+    // Arrange
+    let project = package_with_broken_fixtures();
+    std::fs::remove_dir_all(project.child("tests").path()).unwrap();
+    project
+        .child("Cargo.lock")
+        .write_str("version = 42\n\n[[package]]\nname = \"app\"\nversion = \"0.1.0\"\n")
+        .unwrap();
+    let args = PrepareArgs {
+        strict_lockfile: true,
+        ..Default::default()
+    };
+
+    // Act
+    let result = Skeleton::derive(project.path(), &args);
+
+    // Assert
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(
+        error.contains("Cargo.lock has an unsupported version (42)"),
+        "{}",
+        error
+    );
+
+    // The latest version is supported.
+    project
+        .child("Cargo.lock")
+        .write_str("version = 4\n\n[[package]]\nname = \"app\"\nversion = \"0.1.0\"\n")
+        .unwrap();
+    let skeleton = Skeleton::derive(project.path(), &args).unwrap();
+    assert!(skeleton.lock_file.unwrap().contains("version = 4"));
+}
+
+#[test]
+pub fn stale_lockfiles_are_errors_unless_allowed() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    let files = [
+        (
+            "Cargo.toml",
+            r#"
+[workspace]
+members = ["app"]
+
+[workspace.dependencies]
+serde = { version = "1.0.130", features = ["derive"] }
+"#,
+        ),
+        (
+            "app/Cargo.toml",
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+serde = { workspace = true }
+uuid = "0.8"
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+"#,
+        ),
+        (
+            "Cargo.lock",
+            r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["serde", "uuid"]
+
+[[package]]
+name = "serde"
+version = "1.0.100"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "uuid"
+version = "0.8.2"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#,
+        ),
+        ("app/src/main.rs", ""),
+    ];
+    for (path, contents) in &files {
+        project.child(path).write_str(contents).unwrap();
+    }
+
+    // Act
+    let result = Skeleton::derive(project.path(), &PrepareArgs::default());
+    let allowed = Skeleton::derive(
+        project.path(),
+        &PrepareArgs {
+            allow_stale_lockfile: true,
+            ..Default::default()
+        },
+    );
+
+    // Assert
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(
+        error.contains("Cargo.lock is out of sync with the manifests"),
+        "{}",
+        error
+    );
+    assert!(
+        error.ends_with(
+            "It pins no package for:\n  \
+            - `libc 0.2`, required by app/Cargo.toml\n  \
+            - `serde 1.0.130`, required by app/Cargo.toml"
+        ),
+        "{}",
+        error
+    );
+    assert!(allowed.is_ok());
+}
+
+#[test]
+pub fn optional_dependencies_of_disabled_features_are_not_required_in_the_lockfile() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+log = { version = "0.4", optional = true }
+tokio = { version = "1", optional = true }
+uuid = { version = "0.8", optional = true }
+
+[features]
+default = ["logging"]
+logging = ["log"]
+ids = ["uuid?/v4"]
+runtime = ["dep:tokio"]
+"#,
+        )
+        .unwrap();
+    project
+        .child("Cargo.lock")
+        .write_str("version = 3\n\n[[package]]\nname = \"app\"\nversion = \"0.1.0\"\n")
+        .unwrap();
+    project.child("src/main.rs").touch().unwrap();
+
+    // Act
+    let result = Skeleton::derive(project.path(), &PrepareArgs::default());
+
+    // Assert
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(
+        error.ends_with("It pins no package for:\n  - `log 0.4`, required by Cargo.toml"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+pub fn gitignore_is_respected_unless_disabled() {
+    // Arrange
+    let project = package_with_broken_fixtures();
+    project
+        .child(".gitignore")
+        .write_str("/tests/fixtures\n")
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+    let without_gitignore = Skeleton::derive(
+        project.path(),
+        &PrepareArgs {
+            no_gitignore: true,
+            ..Default::default()
+        },
+    );
+
+    // Assert
+    assert_eq!(vec!["Cargo.toml"], manifest_paths(&skeleton));
+    assert!(without_gitignore.is_err());
+}
+
+#[test]
+pub fn manifests_in_target_directories_are_skipped() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str("[workspace]\nmembers = [\"crates/*\"]\n")
+        .unwrap();
+    let api = project.child("crates").child("api");
+    api.child("Cargo.toml")
+        .write_str("[package]\nname = \"api\"\nversion = \"0.1.0\"\nedition = \"2018\"\n")
+        .unwrap();
+    api.child("src").child("lib.rs").touch().unwrap();
+    // Left behind by `cargo package`, at the root and in a member built on its own.
+    project
+        .child("target/package/api-0.1.0/Cargo.toml")
+        .write_str("[package]\nname = \"api\"\nversion = \"0.1.0\"\n")
+        .unwrap();
+    api.child("target").child("CACHEDIR.TAG").touch().unwrap();
+    api.child("target/package/api-0.1.0/Cargo.toml")
+        .write_str("[package]\nname = \"api\"\nversion = \"0.1.0\"\n")
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    assert_eq!(
+        vec!["Cargo.toml", "crates/api/Cargo.toml"],
+        manifest_paths(&skeleton)
+    );
+}
+
+#[test]
+pub fn ignored_crates_needed_by_cargo_are_kept() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["app", "generated/schema"]
+"#,
+        )
+        .unwrap();
+    project
+        .child("app/Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+bindings = { path = "../generated/bindings" }
+"#,
+        )
+        .unwrap();
+    project.child("app/src/main.rs").touch().unwrap();
+    for name in ["bindings", "schema", "unused"] {
+        let generated = project.child("generated").child(name);
+        generated
+            .child("Cargo.toml")
+            .write_str(&format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+                name
+            ))
+            .unwrap();
+        generated.child("src/lib.rs").touch().unwrap();
+    }
+    project
+        .child(".gitignore")
+        .write_str("generated/\n")
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let mut paths = manifest_paths(&skeleton);
+    paths.sort_unstable();
+    assert_eq!(
+        vec![
+            "Cargo.toml",
+            "app/Cargo.toml",
+            "generated/bindings/Cargo.toml",
+            "generated/schema/Cargo.toml"
+        ],
+        paths
+    );
+}
+
+fn members(members: &[&str]) -> PrepareArgs {
+    PrepareArgs {
+        members: members.iter().map(|member| member.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+fn check(actual: &str, expect: Expect) {
+    let actual = actual.to_string();
+    expect.assert_eq(&actual);
+}
+
+/// A workspace with `members` crates, `crate-0` to `crate-<members - 1>`, in `crates`.
+fn workspace_with_many_members(members: usize) -> TempDir {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str("[workspace]\nmembers = [\"crates/*\"]\n")
+        .unwrap();
+    for i in 0..members {
+        let member = project.child("crates").child(format!("crate-{}", i));
+        member
+            .child("Cargo.toml")
+            .write_str(&format!(
+                "[package]\nname = \"crate-{}\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+                i
+            ))
+            .unwrap();
+        member.child("src/lib.rs").touch().unwrap();
+    }
+    project
+}
+
+#[test]
+pub fn manifests_found_in_parallel_are_sorted_by_path() {
+    // Arrange
+    let project = workspace_with_many_members(300);
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+    let again = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    assert_eq!(301, skeleton.manifests.len());
+    let paths: Vec<_> = skeleton
+        .manifests
+        .iter()
+        .map(|manifest| manifest.relative_path.clone())
+        .collect();
+    let mut sorted = paths.clone();
+    sorted.sort();
+    assert_eq!(sorted, paths);
+    assert_eq!(skeleton, again);
+}
+
+#[test]
+pub fn invalid_manifests_found_in_parallel_are_all_reported_in_order() {
+    // Arrange
+    let project = workspace_with_many_members(300);
+    for i in [7, 150, 299] {
+        project
+            .child(format!("crates/crate-{}/Cargo.toml", i))
+            .write_str("[package\n")
+            .unwrap();
+    }
+
+    // Act
+    let result = Skeleton::derive(project.path(), &PrepareArgs::default());
+
+    // Assert
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(error.contains("Failed to parse 3 manifests"), "{}", error);
+    let positions: Vec<usize> = ["crate-150/", "crate-299/", "crate-7/"]
+        .iter()
+        .map(|path| error.find(path).expect(path))
+        .collect();
+    assert!(
+        positions.windows(2).all(|pair| pair[0] < pair[1]),
+        "{}",
+        error
+    );
+}
+
+/// A skeleton of a single crate, to tamper with as a malicious recipe would.
+fn single_crate_skeleton() -> Skeleton {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "single"
+version = "0.1.0"
+edition = "2018"
+"#,
+        )
+        .unwrap();
+    project.child("src").child("main.rs").touch().unwrap();
+    Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap()
+}
+
+/// Declare a benchmark at `path` in the manifest of `skeleton`.
+fn add_bench(skeleton: &mut Skeleton, path: &str) {
+    let mut contents: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    let bench: toml::Value =
+        toml::from_str(&format!("name = \"escape\"\npath = {:?}", path)).unwrap();
+    contents
+        .as_table_mut()
+        .unwrap()
+        .entry("bench")
+        .or_insert_with(|| toml::Value::Array(vec![]))
+        .as_array_mut()
+        .unwrap()
+        .push(bench);
+    skeleton.manifests[0].contents = toml::to_string(&contents).unwrap();
+}
+
+/// The names of the entries of `directory`, to check that nothing was written.
+fn entries(directory: &Path) -> Vec<std::ffi::OsString> {
+    std::fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect()
+}
+
+#[test]
+pub fn manifests_outside_of_the_project_are_not_written() {
+    let outside = TempDir::new().unwrap();
+    let relative_paths = vec![
+        std::path::PathBuf::from("../../evil/Cargo.toml"),
+        outside.child("Cargo.toml").path().to_path_buf(),
+    ];
+    for relative_path in relative_paths {
+        // Arrange
+        let mut skeleton = single_crate_skeleton();
+        skeleton.manifests[0].relative_path = relative_path;
+
+        // Act
+        let cook_directory = TempDir::new().unwrap();
+        let error = skeleton
+            .build_minimum_project(cook_directory.path(), false)
+            .unwrap_err();
+
+        // Assert
+        assert!(
+            error
+                .to_string()
+                .contains("the recipe points outside of the project."),
+            "{}",
+            error
+        );
+        assert!(entries(cook_directory.path()).is_empty());
+        assert!(entries(outside.path()).is_empty());
+    }
+}
+
+#[test]
+pub fn targets_outside_of_the_project_are_not_written() {
+    // Arrange
+    let mut skeleton = single_crate_skeleton();
+    add_bench(&mut skeleton, "../outside.rs");
+
+    // Act
+    let directory = TempDir::new().unwrap();
+    let cook_directory = directory.child("cook");
+    cook_directory.create_dir_all().unwrap();
+    let error = skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap_err();
+
+    // Assert
+    assert!(error.to_string().contains("outside of the project"));
+    assert!(entries(cook_directory.path()).is_empty());
+    directory
+        .child("outside.rs")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+pub fn targets_in_a_sibling_crate_are_written() {
+    // Arrange
+    let mut skeleton = single_crate_skeleton();
+    skeleton.manifests[0].relative_path = "crates/app/Cargo.toml".into();
+    add_bench(&mut skeleton, "../shared/bench.rs");
+
+    // Act
+    let cook_directory = TempDir::new().unwrap();
+    skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    cook_directory
+        .child("crates")
+        .child("shared")
+        .child("bench.rs")
+        .assert(predicate::path::exists());
+}
+
+#[cfg(unix)]
+#[test]
+pub fn links_to_the_outside_of_the_project_are_not_followed() {
+    // Arrange
+    let skeleton = single_crate_skeleton();
+    let outside = TempDir::new().unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    std::os::unix::fs::symlink(outside.path(), cook_directory.child("src").path()).unwrap();
+
+    // Act
+    let error = skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap_err();
+
+    // Assert
+    assert!(
+        error
+            .to_string()
+            .contains("is a link to the outside of the project."),
+        "{}",
+        error
+    );
+    assert!(entries(outside.path()).is_empty());
+    cook_directory
+        .child("Cargo.toml")
+        .assert(predicate::path::missing());
+}
+
+/// A workspace whose `app` enables optional local dependencies through its features, with
+/// the `[features]` of `app` declared in the given order.
+fn workspace_with_optional_local_dependencies(features: &str) -> TempDir {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["crates/*"]
+
+[workspace.dependencies]
+inherited = { path = "crates/inherited", version = "0.2.0" }
+"#,
+        )
+        .unwrap();
+    project
+        .child("crates")
+        .child("app")
+        .child("Cargo.toml")
+        .write_str(&format!(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[features]
+{}
+
+[dependencies]
+local-helper = {{ path = "../local-helper", version = "0.3.0", optional = true, default-features = false }}
+inherited = {{ workspace = true, optional = true }}
+
+[dev-dependencies]
+dev-helper = {{ path = "../dev-helper" }}
+"#,
+            features
+        ))
+        .unwrap();
+    for (name, version) in [
+        ("local-helper", "0.3.0"),
+        ("inherited", "0.2.0"),
+        ("dev-helper", "0.1.0"),
+    ] {
+        let member = project.child("crates").child(name);
+        member
+            .child("Cargo.toml")
+            .write_str(&format!(
+                "[package]\nname = \"{}\"\nversion = \"{}\"\nedition = \"2021\"\n\n[features]\nextra = []\n",
+                name, version
+            ))
+            .unwrap();
+        member.child("src").child("lib.rs").touch().unwrap();
+    }
+    project
+        .child("crates")
+        .child("app")
+        .child("src")
+        .child("lib.rs")
+        .touch()
+        .unwrap();
+    project
+}
+
+/// The manifest of `app` in `skeleton`.
+fn app_manifest(skeleton: &Skeleton) -> toml::Value {
+    skeleton
+        .manifests
+        .iter()
+        .find(|manifest| manifest.relative_path == Path::new("crates/app/Cargo.toml"))
+        .unwrap()
+        .contents
+        .parse()
+        .unwrap()
+}
+
+const APP_FEATURES: &str = r#"default = ["minimal"]
+minimal = []
+full = ["dep:local-helper", "local-helper/extra", "other"]
+other = ["inherited?/extra"]
+testing = ["dev-helper/extra", "minimal"]"#;
+
+#[test]
+pub fn features_and_optional_local_dependencies_are_kept_through_masking() {
+    // Arrange
+    let project = workspace_with_optional_local_dependencies(APP_FEATURES);
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let app = app_manifest(&skeleton);
+    let expected: toml::Value = toml::from_str(APP_FEATURES).unwrap();
+    assert_eq!(expected, app["features"]);
+    let local_helper = &app["dependencies"]["local-helper"];
+    assert_eq!(Some(true), local_helper["optional"].as_bool());
+    assert_eq!(Some(false), local_helper["default-features"].as_bool());
+    assert_eq!(Some("0.0.1"), local_helper["version"].as_str());
+    assert_eq!(
+        Some(true),
+        app["dependencies"]["inherited"]["optional"].as_bool()
+    );
+}
+
+#[test]
+pub fn the_order_of_the_features_does_not_change_the_skeleton() {
+    // Arrange
+    let mut reordered: Vec<&str> = APP_FEATURES.lines().collect();
+    reordered.reverse();
+    let project = workspace_with_optional_local_dependencies(APP_FEATURES);
+    let reordered_project = workspace_with_optional_local_dependencies(&reordered.join("\n"));
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+    let reordered_skeleton =
+        Skeleton::derive(reordered_project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    assert_eq!(skeleton, reordered_skeleton);
+}
+
+#[test]
+pub fn no_dev_dependencies_drops_the_feature_entries_enabling_them() {
+    // Arrange
+    let project = workspace_with_optional_local_dependencies(APP_FEATURES);
+    let args = PrepareArgs {
+        no_dev_dependencies: true,
+        ..Default::default()
+    };
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &args).unwrap();
+
+    // Assert
+    let features = &app_manifest(&skeleton)["features"];
+    assert_eq!(
+        vec![toml::Value::from("minimal")],
+        features["testing"].as_array().unwrap().clone()
+    );
+    assert_eq!(
+        3,
+        features["full"].as_array().unwrap().len(),
+        "{}",
+        features
+    );
+}
+
+/// A crate enabling the optional local dependency `local-helper`, at `helper_version`, through
+/// `[features]`, with the dependency declared by `dependency`.
+fn crate_with_an_optional_local_dependency(dependency: &str, helper_version: &str) -> TempDir {
+    let project = TempDir::new().unwrap();
+    project
+        .child("Cargo.toml")
+        .write_str(&format!(
+            r#"
+[workspace]
+members = ["helper"]
+
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+
+[features]
+helper = ["dep:local-helper"]
+
+{}
+"#,
+            dependency.replace("{version}", helper_version)
+        ))
+        .unwrap();
+    project.child("src").child("lib.rs").touch().unwrap();
+    project
+        .child("helper")
+        .child("Cargo.toml")
+        .write_str(&format!(
+            "[package]\nname = \"local-helper\"\nversion = \"{}\"\nedition = \"2021\"\n\n[features]\nextra = []\n",
+            helper_version
+        ))
+        .unwrap();
+    project
+        .child("helper")
+        .child("src")
+        .child("lib.rs")
+        .touch()
+        .unwrap();
+    project
+}
+
+#[test]
+pub fn optional_local_dependencies_are_masked_whatever_their_syntax() {
+    let syntaxes = vec![
+        r#"[dependencies]
+local-helper = { path = "helper", version = "{version}", optional = true, default-features = false, features = ["extra"] }"#,
+        r#"[dependencies]
+local-helper.path = "helper"
+local-helper.version = "{version}"
+local-helper.optional = true
+local-helper.default-features = false
+local-helper.features = ["extra"]"#,
+        r#"[dependencies.local-helper]
+path = "helper"
+version = "{version}"
+optional = true
+default-features = false
+features = ["extra"]"#,
+    ];
+    let mut skeletons = vec![];
+    for syntax in syntaxes {
+        for helper_version in ["0.5.0", "0.6.1"] {
+            // Arrange
+            let project = crate_with_an_optional_local_dependency(syntax, helper_version);
+
+            // Act
+            let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+            // Assert
+            let manifest: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+            let dependency = &manifest["dependencies"]["local-helper"];
+            assert_eq!(Some("0.0.1"), dependency["version"].as_str(), "{}", syntax);
+            assert_eq!(Some(true), dependency["optional"].as_bool(), "{}", syntax);
+            assert_eq!(
+                Some(false),
+                dependency["default-features"].as_bool(),
+                "{}",
+                syntax
+            );
+            assert_eq!(
+                toml::Value::from(vec!["extra"]),
+                dependency["features"],
+                "{}",
+                syntax
+            );
+            assert_eq!(
+                toml::Value::from(vec!["dep:local-helper"]),
+                manifest["features"]["helper"],
+                "{}",
+                syntax
+            );
+            skeletons.push(skeleton);
+        }
+    }
+    // Neither the syntax nor a version bump of the helper change the skeleton.
+    assert!(skeletons.windows(2).all(|pair| pair[0] == pair[1]));
+}
+
+/// Each dependency of `manifest`, without its `version`, by the key path of its table.
+fn dependencies_without_versions(
+    manifest: &toml::Value,
+) -> std::collections::BTreeMap<String, toml::Value> {
+    let mut tables = vec![];
+    for key in ["dependencies", "dev-dependencies", "build-dependencies"] {
+        tables.push((key.to_owned(), manifest.get(key)));
+        if let Some(targets) = manifest
+            .get("target")
+            .and_then(|targets| targets.as_table())
+        {
+            for (target, target_table) in targets {
+                tables.push((format!("target.{}.{}", target, key), target_table.get(key)));
+            }
+        }
+    }
+    tables.push((
+        "workspace.dependencies".to_owned(),
+        manifest
+            .get("workspace")
+            .and_then(|workspace| workspace.get("dependencies")),
+    ));
+    if let Some(patches) = manifest.get("patch").and_then(|patches| patches.as_table()) {
+        for (source, patch) in patches {
+            tables.push((format!("patch.{}", source), Some(patch)));
+        }
+    }
+    let mut dependencies = std::collections::BTreeMap::new();
+    for (path, table) in tables {
+        for (name, dependency) in table
+            .and_then(|table| table.as_table())
+            .into_iter()
+            .flatten()
+        {
+            let mut dependency = dependency.clone();
+            if let Some(dependency) = dependency.as_table_mut() {
+                dependency.remove("version");
+            }
+            dependencies.insert(format!("{}.{}", path, name), dependency);
+        }
+    }
+    dependencies
+}
+
+#[test]
+pub fn masking_keeps_the_keys_of_every_dependency() {
+    // Arrange
+    let project = TempDir::new().unwrap();
+    let root = r#"
+[workspace]
+members = ["crates/*"]
+
+[workspace.dependencies]
+core = { path = "crates/core", version = "1.2", default-features = false, features = ["serde"] }
+serde = { version = "1", default-features = false }
+
+[patch.crates-io]
+codegen = { path = "crates/codegen", version = "0.4.0" }
+"#;
+    let manifests = vec![
+        ("Cargo.toml", root),
+        (
+            "crates/app/Cargo.toml",
+            r#"
+[package]
+name = "app"
+version = "0.3.0"
+edition = "2021"
+
+[features]
+default = ["core/std"]
+
+[dependencies]
+core-renamed = { package = "core", path = "../core", version = "1.2", default-features = false, features = ["serde"] }
+inherited = { package = "core", workspace = true, features = ["extra"], optional = true }
+serde = { workspace = true, features = ["derive"], default-features = false }
+legacy = { package = "serde", workspace = true, default_features = false }
+itoa = { version = "1", default_features = false, features = ["alloc"], optional = true }
+
+[dependencies.codegen]
+version = "0.4.0"
+default-features = false
+features = ["fast"]
+
+[build-dependencies]
+codegen.version = "0.4.0"
+codegen.default-features = true
+codegen.optional = false
+
+[dev-dependencies]
+core = { path = "../core", version = "1.2", features = ["testing"] }
+
+[target.'cfg(unix)'.dependencies]
+core = { path = "../core", version = "1.2", default-features = false, features = ["serde", "unix"], optional = true }
+"#,
+        ),
+        (
+            "crates/core/Cargo.toml",
+            r#"
+[package]
+name = "core"
+version = "1.2.0"
+edition = "2021"
+
+[features]
+default = ["std"]
+std = []
+serde = []
+unix = []
+extra = []
+testing = []
+"#,
+        ),
+        (
+            "crates/codegen/Cargo.toml",
+            r#"
+[package]
+name = "codegen"
+version = "0.4.0"
+edition = "2021"
+
+[features]
+default = []
+fast = []
+"#,
+        ),
+    ];
+    for (relative_path, contents) in &manifests {
+        project.child(relative_path).write_str(contents).unwrap();
+    }
+    for name in ["app", "core", "codegen"] {
+        project
+            .child("crates")
+            .child(name)
+            .child("src")
+            .child("lib.rs")
+            .touch()
+            .unwrap();
+    }
+
+    // Act
+    let skeleton = Skeleton::derive(project.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    for (relative_path, contents) in &manifests {
+        let original: toml::Value = contents.parse().unwrap();
+        let masked: toml::Value = skeleton
+            .manifests
+            .iter()
+            .find(|manifest| manifest.relative_path == Path::new(relative_path))
+            .unwrap()
+            .contents
+            .parse()
+            .unwrap();
+        // Only the versions are masked: the other keys are kept, with their values.
+        assert_eq!(
+            dependencies_without_versions(&original),
+            dependencies_without_versions(&masked),
+            "{}",
+            relative_path
+        );
+    }
+}
+
+#[test]
+pub fn stubs_follow_the_target_paths_and_the_disabled_discovery() {
+    // Arrange
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str("[workspace]\nmembers = [\"legacy\", \"plugin\"]\n")
+        .unwrap();
+    recipe_directory
+        .child("legacy")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "legacy"
+version = "0.1.0"
+edition = "2018"
+autobins = false
+autotests = false
+
+[lib]
+path = "lib/mod.rs"
+
+[[bin]]
+name = "app"
+path = "app/main.rs"
+
+[[example]]
+name = "demo"
+path = "samples/demo/main.rs"
+"#,
+        )
+        .unwrap();
+    recipe_directory
+        .child("plugin")
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "plugin"
+version = "0.1.0"
+edition = "2018"
+autobins = false
+autolib = false
+
+[lib]
+path = "src/plugin.rs"
+"#,
+        )
+        .unwrap();
+    // Files cargo would discover if the discovery was not turned off.
+    for path in [
+        "legacy/lib/mod.rs",
+        "legacy/app/main.rs",
+        "legacy/samples/demo/main.rs",
+        "legacy/src/main.rs",
+        "legacy/src/bin/tool.rs",
+        "legacy/tests/smoke.rs",
+        "plugin/src/plugin.rs",
+        "plugin/src/main.rs",
+    ] {
+        recipe_directory.child(path).touch().unwrap();
+    }
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+    let cook_directory = TempDir::new().unwrap();
+    let written = skeleton
+        .build_minimum_project(cook_directory.path(), false)
+        .unwrap();
+
+    // Assert
+    let mut written: Vec<&str> = written.iter().map(|path| path.to_str().unwrap()).collect();
+    written.sort_unstable();
+    assert_eq!(
+        vec![
+            "Cargo.toml",
+            "legacy/Cargo.toml",
+            "legacy/app/main.rs",
+            "legacy/lib/mod.rs",
+            "legacy/samples/demo/main.rs",
+            "plugin/Cargo.toml",
+            "plugin/src/plugin.rs",
+        ],
+        written
+    );
+}
+
+#[test]
+pub fn no_library_is_inferred_when_autolib_is_disabled() {
+    // Arrange
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2021"
+autolib = false
+"#,
+        )
+        .unwrap();
+    recipe_directory
+        .child("src")
+        .child("main.rs")
+        .touch()
+        .unwrap();
+    // Not a library: e.g. a module of the binary.
+    recipe_directory
+        .child("src")
+        .child("lib.rs")
+        .touch()
+        .unwrap();
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &PrepareArgs::default()).unwrap();
+
+    // Assert
+    let manifest: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    assert!(manifest.get("lib").is_none());
+    assert_eq!("src/main.rs", manifest["bin"][0]["path"].as_str().unwrap());
+}
+
+#[test]
+pub fn git_patches_are_kept_verbatim_and_pinned_by_the_lockfile() {
+    // Arrange
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str(
+            r#"
+[workspace]
+members = ["app", "tool"]
+
+[patch.crates-io]
+uuid = { git = "https://github.com/example/uuid", branch = "fix-v4" }
+
+[patch."https://github.com/example/log"]
+log = { git = "https://github.com/fork/log", rev = "0123abc" }
+"#,
+        )
+        .unwrap();
+    for (name, dependencies) in [
+        ("app", "uuid = \"1\""),
+        ("tool", "log = { git = \"https://github.com/example/log\" }"),
+    ] {
+        recipe_directory
+            .child(name)
+            .child("Cargo.toml")
+            .write_str(&format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n\
+                [dependencies]\n{}\n",
+                name, dependencies
+            ))
+            .unwrap();
+        recipe_directory
+            .child(name)
+            .child("src")
+            .child("main.rs")
+            .touch()
+            .unwrap();
+    }
+    let uuid_source = "git+https://github.com/example/uuid?branch=fix-v4#\
+        4d3c2b1a4d3c2b1a4d3c2b1a4d3c2b1a4d3c2b1a";
+    recipe_directory
+        .child("Cargo.lock")
+        .write_str(&format!(
+            r#"version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["uuid"]
+
+[[package]]
+name = "log"
+version = "0.4.20"
+source = "git+https://github.com/fork/log?rev=0123abc#0123abc0123abc0123abc0123abc0123abc0123"
+
+[[package]]
+name = "tool"
+version = "0.1.0"
+dependencies = ["log"]
+
+[[package]]
+name = "uuid"
+version = "1.4.1"
+source = "{}"
+"#,
+            uuid_source
+        ))
+        .unwrap();
+    let args = PrepareArgs {
+        members: vec!["app".to_owned()],
+        ..Default::default()
+    };
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &args).unwrap();
+
+    // Assert
+    let original: toml::Value = std::fs::read_to_string(recipe_directory.child("Cargo.toml"))
+        .unwrap()
+        .parse()
+        .unwrap();
+    let root: toml::Value = skeleton.manifests[0].contents.parse().unwrap();
+    assert_eq!(original["patch"], root["patch"]);
+    // The commit of the branch comes from the lockfile, the only source of truth.
+    let lock_file: toml::Value = skeleton.lock_file.unwrap().parse().unwrap();
+    let sources: Vec<(&str, Option<&str>)> = lock_file["package"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|package| {
+            (
+                package["name"].as_str().unwrap(),
+                package.get("source").and_then(|source| source.as_str()),
+            )
+        })
+        .collect();
+    assert_eq!(vec![("app", None), ("uuid", Some(uuid_source))], sources);
+}
+
+#[test]
+pub fn target_specs_are_embedded_in_the_recipe() {
+    // Arrange
+    let recipe_directory = TempDir::new().unwrap();
+    recipe_directory
+        .child("Cargo.toml")
+        .write_str("[package]\nname = \"firmware\"\nversion = \"0.1.0\"\nedition = \"2021\"\n")
+        .unwrap();
+    recipe_directory
+        .child("src")
+        .child("lib.rs")
+        .touch()
+        .unwrap();
+    let spec = r#"{"llvm-target": "thumbv7em-none-eabihf", "arch": "arm"}"#;
+    recipe_directory
+        .child("targets")
+        .child("board.json")
+        .write_str(spec)
+        .unwrap();
+    let args = PrepareArgs {
+        target_specs: vec!["./targets/board.json".into()],
+        ..Default::default()
+    };
+
+    // Act
+    let skeleton = Skeleton::derive(recipe_directory.path(), &args).unwrap();
+
+    // Assert
+    assert_eq!(1, skeleton.included_files.len());
+    let included = &skeleton.included_files[0];
+    assert_eq!(Path::new("targets/board.json"), included.relative_path);
+    assert_eq!(spec, included.contents);
+    assert!(skeleton.violations().is_empty());
+}
+
+#[test]
+pub fn target_specs_must_be_json_files_of_the_project() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let project = directory.child("project");
+    project
+        .child("Cargo.toml")
+        .write_str("[package]\nname = \"firmware\"\nversion = \"0.1.0\"\nedition = \"2021\"\n")
+        .unwrap();
+    project.child("src").child("lib.rs").touch().unwrap();
+    directory.child("board.json").write_str("{}").unwrap();
+    project.child("board.toml").touch().unwrap();
+    let derive = |target_spec: &str| {
+        let args = PrepareArgs {
+            target_specs: vec![target_spec.into()],
+            ..Default::default()
+        };
+        Skeleton::derive(project.path(), &args)
+            .unwrap_err()
+            .to_string()
+    };
+
+    // Act & Assert
+    assert_eq!(
+        "The target specification \"../board.json\" must be relative to the root of the \
+        project, without `..`.",
+        derive("../board.json")
+    );
+    assert_eq!(
+        "The target specification \"board.toml\" is not a `.json` file.",
+        derive("board.toml")
+    );
+    assert_eq!(
+        "Failed to read the target specification \"missing.json\".",
+        derive("missing.json")
+    );
 }
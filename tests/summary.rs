@@ -0,0 +1,184 @@
+use assert_cmd::Command;
+use assert_fs::prelude::{FileTouch, FileWriteStr, PathChild};
+use assert_fs::TempDir;
+use chef::{PrepareArgs, Recipe, RecipeSummary};
+use expect_test::expect;
+
+/// A workspace with an `api` and a `worker` member: `api` depends on `worker` with a `path`,
+/// both on `serde` (one of them through `[workspace.dependencies]`), and `serde` on
+/// `serde_derive`.
+fn workspace() -> TempDir {
+    let directory = TempDir::new().unwrap();
+    let files = [
+        (
+            "Cargo.toml",
+            r#"
+[workspace]
+members = ["api", "worker"]
+
+[workspace.dependencies]
+serde = { version = "1.0.130", features = ["derive"] }
+"#,
+        ),
+        (
+            "api/Cargo.toml",
+            r#"
+[package]
+name = "api"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+worker = { path = "../worker" }
+serde = { workspace = true }
+log = { package = "tracing-log", version = "0.1" }
+spinners = { git = "https://github.com/FGRibreau/spinners", rev = "3b5c4ee" }
+
+[dev-dependencies]
+serde = "1"
+"#,
+        ),
+        (
+            "worker/Cargo.toml",
+            r#"
+[package]
+name = "worker"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+serde.workspace = true
+"#,
+        ),
+        (
+            "Cargo.lock",
+            r#"
+version = 3
+
+[[package]]
+name = "api"
+version = "0.1.0"
+dependencies = [
+ "serde",
+ "spinners",
+ "tracing-log",
+ "worker",
+]
+
+[[package]]
+name = "serde"
+version = "1.0.130"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = [
+ "serde_derive",
+]
+
+[[package]]
+name = "serde_derive"
+version = "1.0.130"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "spinners"
+version = "3.0.1"
+source = "git+https://github.com/FGRibreau/spinners?rev=3b5c4ee#3b5c4eee38e4b9e1a9aa5ed1de1d0bd99bdb26d8"
+
+[[package]]
+name = "tracing-log"
+version = "0.1.3"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "worker"
+version = "0.1.0"
+dependencies = [
+ "serde",
+]
+"#,
+        ),
+    ];
+    for (path, content) in &files {
+        directory.child(path).write_str(content).unwrap();
+    }
+    directory.child("api/src/main.rs").touch().unwrap();
+    directory.child("worker/src/lib.rs").touch().unwrap();
+    directory
+}
+
+#[test]
+fn summary() {
+    // Arrange
+    let directory = workspace();
+    let recipe = Recipe::prepare(directory.path().into(), PrepareArgs::default()).unwrap();
+
+    // Act
+    let summary = RecipeSummary::new(&recipe).unwrap();
+
+    // Assert
+    assert_eq!(recipe.fingerprint().unwrap(), summary.fingerprint);
+    let document = summary
+        .to_json()
+        .unwrap()
+        .replace(&summary.fingerprint, "<fingerprint>");
+    expect![[r#"
+        {
+          "summary_version": 1,
+          "fingerprint": "<fingerprint>",
+          "members": [
+            "api",
+            "worker"
+          ],
+          "remote_packages": {
+            "direct": 3,
+            "transitive": 1
+          },
+          "direct_dependencies": [
+            {
+              "name": "serde",
+              "requirement": "1",
+              "git": null
+            },
+            {
+              "name": "serde",
+              "requirement": "1.0.130",
+              "git": null
+            },
+            {
+              "name": "spinners",
+              "requirement": null,
+              "git": "https://github.com/FGRibreau/spinners"
+            },
+            {
+              "name": "tracing-log",
+              "requirement": "0.1",
+              "git": null
+            }
+          ]
+        }
+    "#]]
+    .assert_eq(&document);
+}
+
+#[test]
+fn prepare_writes_the_summary_next_to_the_recipe() {
+    // Arrange
+    let directory = workspace();
+
+    // Act
+    let assert = Command::cargo_bin("cargo-chef")
+        .unwrap()
+        .current_dir(directory.path())
+        .args(["chef", "prepare", "--summary-path", "summary.json"])
+        .assert();
+
+    // Assert
+    assert.success();
+    let recipe =
+        Recipe::from_json(&std::fs::read_to_string(directory.child("recipe.json")).unwrap())
+            .unwrap();
+    let summary = std::fs::read_to_string(directory.child("summary.json")).unwrap();
+    assert_eq!(
+        RecipeSummary::new(&recipe).unwrap().to_json().unwrap(),
+        summary
+    );
+}
@@ -0,0 +1,210 @@
+//! `cargo chef validate` (and `cook`) must catch recipes that were truncated or edited by hand
+//! before anything is built.
+use assert_cmd::Command;
+use assert_fs::prelude::{FileWriteStr, PathChild};
+use assert_fs::TempDir;
+use chef::{PrepareArgs, Recipe};
+use std::path::Path;
+
+fn recipe_from_files(files: &[(&str, &str)]) -> Recipe {
+    let recipe_directory = TempDir::new().unwrap();
+    for (path, content) in files {
+        recipe_directory.child(path).write_str(content).unwrap();
+    }
+    Recipe::prepare(recipe_directory.path().into(), PrepareArgs::default()).unwrap()
+}
+
+const WORKSPACE: &str = r#"
+[workspace]
+members = ["app", "common"]
+
+[workspace.dependencies]
+common = { path = "common" }
+"#;
+
+const APP: &str = r#"
+[package]
+name = "app"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+common = { workspace = true }
+uuid = "0.8"
+"#;
+
+const COMMON: &str = r#"
+[package]
+name = "common"
+version = "0.1.0"
+edition = "2018"
+"#;
+
+const LOCKFILE: &str = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["common", "uuid"]
+
+[[package]]
+name = "common"
+version = "0.1.0"
+
+[[package]]
+name = "uuid"
+version = "0.8.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+fn workspace() -> Recipe {
+    recipe_from_files(&[
+        ("Cargo.toml", WORKSPACE),
+        ("Cargo.lock", LOCKFILE),
+        ("app/Cargo.toml", APP),
+        ("app/src/main.rs", ""),
+        ("common/Cargo.toml", COMMON),
+        ("common/src/lib.rs", ""),
+    ])
+}
+
+fn violations(recipe: &Recipe) -> Vec<String> {
+    recipe
+        .skeleton
+        .violations()
+        .iter()
+        .map(|violation| violation.to_string())
+        .collect()
+}
+
+#[test]
+fn prepared_recipes_are_valid() {
+    // Act
+    let recipe = workspace();
+
+    // Assert
+    assert!(violations(&recipe).is_empty());
+    recipe.validate().unwrap();
+}
+
+#[test]
+fn missing_path_dependencies_are_reported() {
+    // Arrange
+    let mut recipe = workspace();
+    recipe
+        .skeleton
+        .manifests
+        .retain(|manifest| manifest.relative_path != Path::new("common/Cargo.toml"));
+
+    // Act
+    let violations = violations(&recipe);
+
+    // Assert
+    assert_eq!(
+        vec!["app/Cargo.toml: the `path` of `common` points to common/Cargo.toml, which is not in the recipe."],
+        violations
+    );
+}
+
+#[test]
+fn every_violation_is_reported_with_its_path() {
+    // Arrange
+    let mut recipe = workspace();
+    recipe.skeleton.manifests[0].contents.truncate(5);
+    recipe.skeleton.manifests[1].relative_path = "../app/Cargo.toml".into();
+    recipe.skeleton.lock_file = Some(LOCKFILE.replace("name = \"uuid\"", "name = \"other\""));
+
+    // Act
+    let violations = violations(&recipe);
+
+    // Assert
+    assert_eq!(4, violations.len(), "{:#?}", violations);
+    assert_eq!(
+        "../app/Cargo.toml: the path must be relative to the project, without `.` or `..`.",
+        violations[0]
+    );
+    assert!(violations[1].starts_with("Cargo.toml: invalid TOML: "));
+    assert_eq!(
+        &violations[2..],
+        &[
+            "../app/Cargo.toml: `uuid` is not pinned in Cargo.lock.",
+            "../app/Cargo.toml: `common` is inherited from `[workspace.dependencies]`, which does \
+            not declare it.",
+        ]
+    );
+}
+
+#[test]
+fn corrupt_lockfiles_are_reported() {
+    // Arrange
+    let mut recipe = workspace();
+    recipe.skeleton.lock_file = Some("[[package]\nname = ".into());
+
+    // Act
+    let violations = violations(&recipe);
+
+    // Assert
+    assert_eq!(1, violations.len(), "{:#?}", violations);
+    assert!(violations[0].starts_with("Cargo.lock: invalid TOML: "));
+}
+
+#[test]
+fn path_dependencies_must_stay_within_the_project() {
+    // Arrange
+    let mut recipe = workspace();
+    recipe.skeleton.manifests[0].contents = WORKSPACE.replace("\"common\" }", "\"../common\" }");
+
+    // Act
+    let violations = violations(&recipe);
+
+    // Assert
+    assert_eq!(
+        vec!["app/Cargo.toml: the `path` of `common` points outside of the project: `../common`."],
+        violations
+    );
+}
+
+#[test]
+fn validate_exits_with_an_error_listing_the_violations() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    let mut recipe = workspace();
+    recipe.skeleton.lock_file = Some(LOCKFILE.replace("name = \"uuid\"", "name = \"other\""));
+    directory
+        .child("recipe.json")
+        .write_str(&serde_json::to_string(&recipe).unwrap())
+        .unwrap();
+
+    // Act
+    let assert = Command::cargo_bin("cargo-chef")
+        .unwrap()
+        .current_dir(directory.path())
+        .args(["chef", "validate", "--recipe-path", "recipe.json"])
+        .assert();
+
+    // Assert
+    assert.failure().stderr(predicates::str::contains(
+        "The recipe is invalid, 1 violation was found:\n  - app/Cargo.toml: `uuid` is not pinned in Cargo.lock.",
+    ));
+}
+
+#[test]
+fn validate_accepts_valid_recipes() {
+    // Arrange
+    let directory = TempDir::new().unwrap();
+    directory
+        .child("recipe.json")
+        .write_str(&serde_json::to_string(&workspace()).unwrap())
+        .unwrap();
+
+    // Act
+    let assert = Command::cargo_bin("cargo-chef")
+        .unwrap()
+        .current_dir(directory.path())
+        .args(["chef", "validate"])
+        .assert();
+
+    // Assert
+    assert.success().stdout("The recipe is valid.\n");
+}